@@ -1,53 +1,133 @@
 pub mod controller;
+pub mod dispatch;
 pub mod hub;
+pub mod identity;
+pub mod jsonrpc;
+pub mod latency;
+pub mod presence;
+pub mod protocol;
+pub mod recording;
+#[cfg(test)]
+mod schema;
+pub mod tenant;
+pub mod trust;
 
-use crate::sessions::port::SessionStore;
-use serde::{Deserialize, Serialize};
-use socketioxide::{
-    extract::{SocketRef, TryData},
-    SocketIo,
+pub use crate::sessions::role::Role;
+use crate::{
+    actors::{
+        protocol::{ProtocolInfo, ProtocolVersion, SUPPORTED_VERSIONS},
+        tenant::TenantState,
+    },
+    events::port::{EventSink, SessionEvent, SessionEventKind},
+    sessions::port::SessionStore,
+    socket::{adapters::local, encoding::Encoding},
 };
-use tracing::{debug, warn};
-
-#[derive(Deserialize)]
-#[serde(rename_all = "snake_case")]
-#[cfg_attr(test, derive(serde::Serialize))]
-pub enum Role {
-    Hub,
-    Controller,
-}
+use serde::{Deserialize, Serialize};
+use socketioxide::extract::{SocketRef, TryData};
+use std::sync::Arc;
+use tracing::{debug, error, warn};
+use uuid::Uuid;
 
 #[derive(Deserialize)]
-#[cfg_attr(test, derive(serde::Serialize))]
+#[cfg_attr(test, derive(serde::Serialize, schemars::JsonSchema))]
 pub struct Auth {
     pub role: Role,
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Stable identity of the app installation, used to keep the audit log of a hub across its
+    /// sessions. Ignored unless the server has an identity secret to check `client_token` with.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Proof of the client id from the backend of the app, `<expires_at>.<signature>` where
+    /// `expires_at` is in seconds since the Unix epoch and `signature` is the hex HMAC-SHA256 of
+    /// `<client_id>.<expires_at>` keyed with the identity secret.
+    #[serde(default)]
+    pub client_token: Option<String>,
+    /// One of the auth keys of the tenant, if it has any.
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+/// Longest client id accepted.
+pub const MAX_CLIENT_ID_LEN: usize = 64;
+
+impl Auth {
+    /// Checks what can't be checked while deserializing, and drops the client id if there is no
+    /// secret to verify it with.
+    fn validate(mut self, identity_secret: Option<&str>) -> Result<Self, String> {
+        let Some(id) = &self.client_id else {
+            return Ok(self);
+        };
+        if id.is_empty() || id.len() > MAX_CLIENT_ID_LEN {
+            return Err(format!(
+                "Client id must have 1 to {MAX_CLIENT_ID_LEN} bytes"
+            ));
+        }
+        let Some(secret) = identity_secret else {
+            self.client_id = None;
+            return Ok(self);
+        };
+        match &self.client_token {
+            Some(token) if identity::verify(secret, id, token) => Ok(self),
+            _ => Err("Client id has a missing, invalid or expired token".to_owned()),
+        }
+    }
+
+    fn is_authorized(&self, auth_keys: &[String]) -> bool {
+        auth_keys.is_empty() || self.key.as_ref().is_some_and(|key| auth_keys.contains(key))
+    }
 }
 
 #[derive(Serialize)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 enum ConnectError {
     Unauthorized,
+    UnsupportedProtocolVersion,
 }
 
 #[derive(Serialize)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
 struct ConnectErrorResponse {
     reason: ConnectError,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(test, schemars(with = "Option<Vec<u32>>"))]
+    supported_versions: Option<&'static [ProtocolVersion]>,
 }
 
 impl ConnectErrorResponse {
     fn with_reason(reason: ConnectError) -> Self {
-        ConnectErrorResponse { reason }
+        ConnectErrorResponse {
+            reason,
+            supported_versions: None,
+        }
+    }
+
+    fn unsupported_protocol_version() -> Self {
+        ConnectErrorResponse {
+            reason: ConnectError::UnsupportedProtocolVersion,
+            supported_versions: Some(SUPPORTED_VERSIONS),
+        }
     }
 }
 
-pub fn on_connect<T>(socket: SocketRef, io: SocketIo, TryData(auth): TryData<Auth>)
-where
+pub fn on_connect<T, E>(
+    socket: SocketRef,
+    TryData(auth): TryData<Auth>,
+    state: Arc<TenantState<T, E>>,
+) where
     T: SessionStore + 'static,
+    E: EventSink + 'static,
 {
     debug!("Client connected: {:?}", socket.id);
 
-    let role = match auth {
-        Ok(data) => data.role,
+    let auth = match auth
+        .map_err(|error| error.to_string())
+        .and_then(|auth| auth.validate(state.config.identity_secret.as_deref()))
+    {
+        Ok(data) => data,
         Err(error) => {
             warn!(%error, "Client provided invalid auth data");
             socket
@@ -61,8 +141,69 @@ where
         }
     };
 
+    if !auth.is_authorized(&state.config.auth_keys) {
+        warn!("Client provided a missing or unknown auth key");
+        socket
+            .emit(
+                "connect_error",
+                ConnectErrorResponse::with_reason(ConnectError::Unauthorized),
+            )
+            .ok();
+        socket.disconnect().ok();
+        return;
+    }
+
+    let version = match ProtocolVersion::negotiate(auth.protocol_version) {
+        Ok(version) => version,
+        Err(version) => {
+            warn!(version, "Client requested an unsupported protocol version");
+            socket
+                .emit(
+                    "connect_error",
+                    ConnectErrorResponse::unsupported_protocol_version(),
+                )
+                .ok();
+            socket.disconnect().ok();
+            return;
+        }
+    };
+
+    // The handshake is always in JSON, the negotiated encoding applies to what comes after it
+    if let Err(error) = socket.emit("protocol", ProtocolInfo::new(version, auth.encoding)) {
+        error!(%error, "Failed to send protocol info to client");
+    }
+    local::set_encoding(&socket, auth.encoding);
+    local::set_protocol_version(&socket, version);
+    if let Some(client_id) = auth.client_id {
+        local::set_client_id(&socket, client_id);
+    }
+
+    let (sid, role) = (socket.id, auth.role);
+    tokio::spawn({
+        let state = state.clone();
+        async move {
+            if let Some(client) = state.local_socket_of(sid) {
+                trust::go_online(&client, role, &state.sessions).await;
+            }
+            let client = || state.local_socket_of(sid);
+            presence::track(client, role, &state.sessions, &state.config).await
+        }
+    });
+
     match role {
-        Role::Hub => hub::on_connect::<T>(socket),
-        Role::Controller => controller::on_connect::<T>(socket, io),
+        Role::Hub => hub::on_connect(socket, state),
+        Role::Controller => controller::on_connect(socket, state),
     };
 }
+
+async fn publish_event<E>(events: &E, session_id: Uuid, role: Role, kind: SessionEventKind)
+where
+    E: EventSink,
+{
+    if let Err(error) = events
+        .publish(SessionEvent::new(session_id, role, kind))
+        .await
+    {
+        error!(%error, "Failed to publish session event");
+    }
+}
@@ -1,62 +1,248 @@
 mod handlers;
-mod messages;
+pub mod messages;
 
 use crate::{
-    configuration::Config,
+    actors::{
+        dispatch::{self, on, params, Caller, Handlers, Reply},
+        latency, presence, recording,
+        tenant::TenantState,
+        trust, Role,
+    },
+    events::port::EventSink,
     sessions::port::SessionStore,
-    socket::adapters::local::{ClientSocketImpl, GlobalSocketImpl},
+    socket::adapters::jsonrpc::ErrorObject,
 };
-use handlers::{on_disconnect, on_join_session, on_vibrate_command};
-pub use messages::*;
-use socketioxide::{
-    extract::{AckSender, Data, SocketRef, State},
-    SocketIo,
+use futures_util::FutureExt;
+use handlers::{
+    check_join_request, on_acked_vibrate_command, on_cancel_join, on_disconnect, on_join_session,
+    on_resolve_room, on_vibrate_command,
 };
-use tracing::error;
+pub use messages::*;
+use serde_json::{json, Value};
+use socketioxide::extract::{Data, SocketRef};
+use std::sync::Arc;
 
-pub fn on_connect<T>(socket: SocketRef, io: SocketIo)
+/// Events controllers can send, through either transport.
+pub fn handlers<T, E, C>() -> Handlers<T, E, C>
 where
     T: SessionStore + 'static,
+    E: EventSink + 'static,
+    C: Caller,
 {
-    socket.on(
-        "join_session",
-        |socket: SocketRef,
-         data: Data<JoinSessionRequest>,
-         ack: AckSender,
-         sessions: State<T>,
-         config: State<Config>| async move {
-            if let Err(error) = ack.send(
-                on_join_session(
-                    ClientSocketImpl::from(socket),
-                    GlobalSocketImpl::from(io),
-                    data,
-                    sessions.0,
-                    config.0,
-                )
-                .await,
-            ) {
-                error!(%error, "Failed to send acknowledgment to client");
-            }
-        },
-    );
-    socket.on("vibrate", |socket: SocketRef, data: Data<VibrateCmd>| {
-        on_vibrate_command(ClientSocketImpl::from(socket), data)
-    });
-    socket.on_disconnect(|socket: SocketRef, sessions: State<T>| async move {
-        on_disconnect(ClientSocketImpl::from(socket), sessions.0).await
-    });
+    vec![
+        on(event_names::JOIN_SESSION, |caller, data, state| {
+            join_session(caller, data, state).boxed()
+        }),
+        on(event_names::CANCEL_JOIN, |caller, _, state| {
+            cancel_join(caller, state).boxed()
+        }),
+        on(event_names::RESOLVE_ROOM, |_, data, state| {
+            resolve_room(data, state).boxed()
+        }),
+        on(event_names::HUBS_ONLINE, |caller, data, state| {
+            hubs_online(caller, data, state).boxed()
+        }),
+        on(event_names::VIBRATE, |caller, data, state| {
+            vibrate(caller, data, state).boxed()
+        }),
+        on(
+            recording::messages::event_names::RECORDING_CONSENT,
+            |caller, data, state| recording::consent(caller, data, Role::Controller, state).boxed(),
+        ),
+    ]
+}
+
+pub fn on_connect<T, E>(socket: SocketRef, state: Arc<TenantState<T, E>>)
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+{
+    dispatch::register(&socket, handlers(), &state);
+    socket.on_disconnect(move |socket: SocketRef| async move { disconnect(socket, &state).await });
+}
+
+/// Cleans up after a controller connected through either transport.
+pub async fn disconnect<T, E, C>(caller: C, state: &TenantState<T, E>)
+where
+    T: SessionStore,
+    E: EventSink,
+    C: Caller,
+{
+    let socket = caller.socket(state);
+    presence::forget(&socket, Role::Controller, &state.sessions).await;
+    trust::go_offline(&socket, Role::Controller, &state.sessions).await;
+    on_disconnect(
+        socket,
+        &state.sessions,
+        &state.recordings,
+        &state.commands,
+        &state.pending_joins,
+        &state.events,
+    )
+    .await
+}
+
+async fn join_session<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+    C: Caller,
+{
+    let request: JoinSessionRequest = params(data)?;
+    let session_id = request.session_id;
+    let id = caller.id();
+    let socket = caller.socket(&state);
+    if let Err(kind) =
+        check_join_request(&socket, &request, &state.sessions, &state.join_guard).await
+    {
+        return Ok(Reply::new(json!(JoinSessionResponse::with_err(kind))));
+    }
+    let response = on_join_session(
+        socket,
+        state.global_socket(),
+        Data(request),
+        &state.sessions,
+        &state.activity,
+        &state.pending_joins,
+        &state.events,
+        &state.config,
+    )
+    .await;
+
+    let reply = Reply::new(json!(response));
+    let JoinSessionResponse::Ok {} = response else {
+        return Ok(reply);
+    };
+    Ok(reply.then(
+        async move {
+            let socket = || C::socket_of(id, &state);
+            latency::monitor(
+                socket,
+                session_id,
+                Role::Controller,
+                &state.latency,
+                &state.config,
+            )
+            .await
+        }
+        .boxed(),
+    ))
+}
+
+async fn cancel_join<T, E, C>(
+    caller: C,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    C: Caller,
+{
+    let response = on_cancel_join(caller.socket(&state), &state.pending_joins);
+    Ok(Reply::new(json!(response)))
+}
+
+async fn resolve_room<T, E>(
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+{
+    let request: ResolveRoomRequest = params(data)?;
+    let response = on_resolve_room(Data(request), &state.sessions).await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn hubs_online<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let request: HubsOnlineRequest = params(data)?;
+    let response = presence::on_hubs_online(
+        &caller.socket(&state),
+        Data(request),
+        &state.sessions,
+        &state.hubs_online_limiter,
+    )
+    .await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn vibrate<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    C: Caller,
+{
+    let cmd: VibrateCmd = params(data)?;
+    if cmd.seq.is_none() {
+        on_vibrate_command(
+            caller.socket(&state),
+            Data(cmd),
+            &state.activity,
+            &state.latency,
+            &state.recordings,
+        );
+        return Ok(Reply::new(Value::Null));
+    }
+
+    let result = on_acked_vibrate_command(
+        caller.socket(&state),
+        &state.global_socket(),
+        Data(cmd),
+        &state.activity,
+        &state.latency,
+        &state.recordings,
+        &state.commands,
+        &state.config,
+    )
+    .await;
+    Ok(Reply::new(json!(result)))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::on_join_session;
+    use super::{
+        check_join_request, on_acked_vibrate_command, on_join_session, on_resolve_room,
+        on_vibrate_command,
+    };
     use crate::{
-        actors::controller::{
-            JoinSessionErrorKind, JoinSessionPermissionRequest, JoinSessionPermissionResponse,
-            JoinSessionRequest, JoinSessionResponse,
+        actors::{
+            controller::{
+                CommandAck, CommandResult, CommandStatus, ControllerErrorMsg, JoinPending,
+                JoinSessionErrorKind, JoinSessionPermissionRequest, JoinSessionPermissionResponse,
+                JoinSessionRequest, JoinSessionResponse, ResolveRoomErrorKind, ResolveRoomRequest,
+                ResolveRoomResponse, VibrateCmd,
+            },
+            hub::messages::DeviceStatus,
+            latency::unix_millis,
+            protocol::ProtocolVersion,
+            Role,
         },
         configuration::Config,
-        sessions::port::{MockSessionStore, SessionState},
+        events::port::{JoinOutcome, MockEventSink, SessionEventKind},
+        moderation::{adapters::word_list::WordListFilter, guard::JoinGuard},
+        sessions::{
+            activity::SessionActivity,
+            audit::JoinAttemptOutcome,
+            commands::CommandSequences,
+            latency::{Measurement, SessionLatency},
+            pending::PendingJoins,
+            policy::JoinPolicy,
+            port::{MockSessionStore, SessionState},
+            recording::SessionRecordings,
+            schedule::{Invitation, SessionWindow},
+        },
         socket::port::{DummyMockError, MockClientSocket, MockGlobalSocket},
     };
     use futures_util::FutureExt;
@@ -70,24 +256,123 @@ mod tests {
         client_socket: MockClientSocket,
         session_store: MockSessionStore,
         global_socket: MockGlobalSocket,
+        activity: SessionActivity,
+        pending_joins: PendingJoins,
+        events: MockEventSink,
+        recordings: SessionRecordings,
+        commands: CommandSequences,
     }
 
     impl AsyncTestContext for Context {
         async fn setup() -> Self {
+            let mut client_socket = MockClientSocket::new();
+            client_socket
+                .expect_connection_id()
+                .return_const("controller".to_string());
+            client_socket
+                .expect_protocol_version()
+                .return_const(ProtocolVersion::V1);
             Self {
-                client_socket: MockClientSocket::new(),
+                client_socket,
                 global_socket: MockGlobalSocket::new(),
                 session_store: MockSessionStore::new(),
+                activity: SessionActivity::default(),
+                pending_joins: PendingJoins::default(),
+                events: MockEventSink::new(),
+                recordings: SessionRecordings::default(),
+                commands: CommandSequences::default(),
             }
         }
     }
 
+    /// Expects the join attempt to be appended to the audit log of the session's hub.
+    fn expect_audited(ctx: &mut Context, hub_id: Option<&str>, outcome: JoinAttemptOutcome) {
+        let key = match hub_id {
+            Some(hub_id) => format!("hub:{hub_id}"),
+            None => format!("session:{}", Uuid::nil()),
+        };
+        let hub_id = hub_id.map(str::to_owned);
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("controller".to_string()));
+        ctx.session_store
+            .expect_session_owner()
+            .times(1)
+            .returning(move |_| {
+                let hub_id = hub_id.clone();
+                async { Ok(hub_id) }.boxed()
+            });
+        ctx.session_store
+            .expect_append_join_attempt()
+            .times(1)
+            .withf(move |audit_key, attempt| {
+                *audit_key == key
+                    && attempt.outcome == outcome
+                    && attempt.controller_id.as_deref() == Some("controller")
+                    && attempt.message == "hello world"
+            })
+            .returning(|_, _| async { Ok(()) }.boxed());
+    }
+
+    /// Expects the session to be looked up for a window, and not to have one.
+    fn expect_unscheduled(ctx: &mut Context) {
+        ctx.session_store
+            .expect_session_window()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+    }
+
+    /// Expects the hub of the session to be asked whether it trusts the controller, and not to.
+    fn expect_untrusted(ctx: &mut Context) {
+        ctx.session_store
+            .expect_session_owner()
+            .times(1)
+            .returning(|_| async { Ok(Some("hub".to_string())) }.boxed());
+        ctx.session_store
+            .expect_trusted_controllers()
+            .with(eq("hub".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(["someone-else".to_string()].into()) }.boxed());
+    }
+
+    fn default_timeout(config: &Config) -> Duration {
+        Duration::from_secs(config.controller.session_join_request_timeout)
+    }
+
+    /// Matches the join request of "hello world" sent to the hub of the nil session.
+    fn asks_the_hub(
+        timeout: Duration,
+    ) -> impl Fn(&String, &String, &JoinSessionPermissionRequest, &Duration) -> bool {
+        move |room, event, request, request_timeout| {
+            let deadline = unix_millis() + timeout.as_millis() as i64;
+            *room == Uuid::nil().to_string()
+                && event == "join_request"
+                && request.message == "hello world"
+                && request.timeout_ms == timeout.as_millis() as u64
+                && (deadline - 1000..=deadline).contains(&request.deadline)
+                && *request_timeout == timeout
+        }
+    }
+
+    /// Expects the controller to be told its join request is waiting for the hub.
+    fn expect_join_pending(ctx: &mut Context, timeout: Duration) {
+        ctx.client_socket
+            .expect_emit::<JoinPending>()
+            .times(1..)
+            .withf(move |event, pending| {
+                event == "join_pending" && pending.remaining_ms <= timeout.as_millis() as u64
+            })
+            .return_const(Ok(()));
+    }
+
     #[test_context(Context, skip_teardown)]
     #[tokio::test]
     async fn can_not_join_a_session_if_already_in_a_session(mut ctx: Context) {
         let join_request = Data(JoinSessionRequest {
             session_id: Uuid::nil(),
             message: "hello world".into(),
+            invitation: None,
         });
         let config = Config::load();
 
@@ -96,11 +381,16 @@ mod tests {
             .times(1)
             .return_const(Some(Uuid::nil()));
 
+        ctx.events.expect_publish().never();
+
         let result = on_join_session(
             ctx.client_socket,
             ctx.global_socket,
             join_request,
             &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
             &config,
         )
         .await;
@@ -117,6 +407,7 @@ mod tests {
         let join_request = Data(JoinSessionRequest {
             session_id: Uuid::nil(),
             message: "hello world".into(),
+            invitation: None,
         });
         let config = Config::load();
 
@@ -130,11 +421,17 @@ mod tests {
             .times(1)
             .returning(|_| async { Ok(None) }.boxed());
 
+        ctx.events.expect_publish().never();
+        ctx.session_store.expect_append_join_attempt().never();
+
         let result = on_join_session(
             ctx.client_socket,
             ctx.global_socket,
             join_request,
             &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
             &config,
         )
         .await;
@@ -151,6 +448,7 @@ mod tests {
         let join_request = Data(JoinSessionRequest {
             session_id: Uuid::nil(),
             message: "hello world".into(),
+            invitation: None,
         });
         let config = Config::load();
 
@@ -164,11 +462,18 @@ mod tests {
             .times(1)
             .returning(|_| async { Ok(Some(SessionState::InProgress)) }.boxed());
 
+        ctx.events.expect_publish().never();
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::SessionFull);
+
         let result = on_join_session(
             ctx.client_socket,
             ctx.global_socket,
             join_request,
             &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
             &config,
         )
         .await;
@@ -185,6 +490,7 @@ mod tests {
         let join_request = JoinSessionRequest {
             session_id: Uuid::nil(),
             message: "hello world".into(),
+            invitation: None,
         };
         let config = Config::load();
 
@@ -198,19 +504,24 @@ mod tests {
             .times(1)
             .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
 
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_untrusted(&mut ctx);
+        ctx.session_store
+            .expect_join_request_timeout()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_join_pending(&mut ctx, default_timeout(&config));
+
         ctx.global_socket
             .expect_emit_to_room_with_ack()
             .times(1)
-            .with(
-                eq(join_request.session_id.to_string()),
-                eq("join_request".to_string()),
-                eq(JoinSessionPermissionRequest {
-                    message: join_request.message.clone(),
-                }),
-                eq(Duration::from_secs(
-                    config.controller.session_join_request_timeout,
-                )),
-            )
+            .withf(asks_the_hub(default_timeout(&config)))
             .returning(|_, _, _, _| async { Ok(JoinSessionPermissionResponse::Accept) }.boxed());
 
         ctx.client_socket
@@ -240,16 +551,54 @@ mod tests {
             .with(eq(join_request.session_id))
             .return_const(());
 
+        ctx.session_store
+            .expect_device_statuses()
+            .times(1)
+            .with(eq(join_request.session_id))
+            .returning(|_| {
+                async { Ok(vec![r#"{"device_id":"toy","battery":0.5}"#.into()]) }.boxed()
+            });
+
+        ctx.client_socket
+            .expect_emit()
+            .times(1)
+            .with(
+                eq("device_status".to_string()),
+                eq(DeviceStatus {
+                    device_id: "toy".into(),
+                    battery: Some(0.5),
+                    ..Default::default()
+                }),
+            )
+            .return_const(Ok(()));
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::JoinAttempt {
+                        outcome: JoinOutcome::Accepted,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Accepted);
+
         let result = on_join_session(
             ctx.client_socket,
             ctx.global_socket,
             Data(join_request),
             &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
             &config,
         )
         .await;
 
         assert_eq!(result, JoinSessionResponse::Ok {});
+        assert!(ctx.activity.idle_for(Uuid::nil()).is_some());
     }
 
     #[test_context(Context, skip_teardown)]
@@ -258,6 +607,7 @@ mod tests {
         let join_request = JoinSessionRequest {
             session_id: Uuid::nil(),
             message: "hello world".into(),
+            invitation: None,
         };
         let config = Config::load();
 
@@ -271,19 +621,102 @@ mod tests {
             .times(1)
             .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
 
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_untrusted(&mut ctx);
+        ctx.session_store
+            .expect_join_request_timeout()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_join_pending(&mut ctx, default_timeout(&config));
+
         ctx.global_socket
             .expect_emit_to_room_with_ack()
             .times(1)
-            .with(
-                eq(join_request.session_id.to_string()),
-                eq("join_request".to_string()),
-                eq(JoinSessionPermissionRequest {
-                    message: join_request.message.clone(),
-                }),
-                eq(Duration::from_secs(
-                    config.controller.session_join_request_timeout,
-                )),
-            )
+            .withf(asks_the_hub(default_timeout(&config)))
+            .returning(|_, _, _, _| async { Ok(JoinSessionPermissionResponse::Reject) }.boxed());
+
+        ctx.client_socket.expect_join().never();
+        ctx.session_store.expect_update_session_state().never();
+        ctx.client_socket.expect_emit_to_room::<()>().never();
+        ctx.client_socket.expect_store_value().never();
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::JoinAttempt {
+                        outcome: JoinOutcome::Rejected,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Rejected);
+
+        let result = on_join_session(
+            ctx.client_socket,
+            ctx.global_socket,
+            Data(join_request),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            JoinSessionResponse::Error {
+                kind: JoinSessionErrorKind::Rejected
+            }
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn hubs_have_the_join_request_timeout_of_their_session(mut ctx: Context) {
+        let join_request = JoinSessionRequest {
+            session_id: Uuid::nil(),
+            message: "hello world".into(),
+            invitation: None,
+        };
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_untrusted(&mut ctx);
+        ctx.session_store
+            .expect_join_request_timeout()
+            .times(1)
+            .returning(|_| async { Ok(Some(Duration::from_secs(30))) }.boxed());
+
+        expect_join_pending(&mut ctx, Duration::from_secs(30));
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack()
+            .times(1)
+            .withf(asks_the_hub(Duration::from_secs(30)))
             .returning(|_, _, _, _| async { Ok(JoinSessionPermissionResponse::Reject) }.boxed());
 
         ctx.client_socket.expect_join().never();
@@ -291,11 +724,27 @@ mod tests {
         ctx.client_socket.expect_emit_to_room::<()>().never();
         ctx.client_socket.expect_store_value().never();
 
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::JoinAttempt {
+                        outcome: JoinOutcome::Rejected,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Rejected);
+
         let result = on_join_session(
             ctx.client_socket,
             ctx.global_socket,
             Data(join_request),
             &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
             &config,
         )
         .await;
@@ -314,6 +763,7 @@ mod tests {
         let join_request = JoinSessionRequest {
             session_id: Uuid::nil(),
             message: "hello world".into(),
+            invitation: None,
         };
         let config = Config::load();
 
@@ -327,19 +777,24 @@ mod tests {
             .times(1)
             .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
 
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_untrusted(&mut ctx);
+        ctx.session_store
+            .expect_join_request_timeout()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_join_pending(&mut ctx, default_timeout(&config));
+
         ctx.global_socket
             .expect_emit_to_room_with_ack()
             .times(1)
-            .with(
-                eq(join_request.session_id.to_string()),
-                eq("join_request".to_string()),
-                eq(JoinSessionPermissionRequest {
-                    message: join_request.message.clone(),
-                }),
-                eq(Duration::from_secs(
-                    config.controller.session_join_request_timeout,
-                )),
-            )
+            .withf(asks_the_hub(default_timeout(&config)))
             .returning(|_, _, _, _| async { Err(DummyMockError) }.boxed());
 
         ctx.client_socket.expect_join().never();
@@ -347,11 +802,27 @@ mod tests {
         ctx.client_socket.expect_emit_to_room::<()>().never();
         ctx.client_socket.expect_store_value().never();
 
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::JoinAttempt {
+                        outcome: JoinOutcome::HubResponseTimeout,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::TimedOut);
+
         let result = on_join_session(
             ctx.client_socket,
             ctx.global_socket,
             Data(join_request),
             &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
             &config,
         )
         .await;
@@ -363,4 +834,785 @@ mod tests {
             }
         );
     }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn cancelled_join_requests_are_withdrawn_from_the_hub(mut ctx: Context) {
+        let join_request = JoinSessionRequest {
+            session_id: Uuid::nil(),
+            message: "hello world".into(),
+            invitation: None,
+        };
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_untrusted(&mut ctx);
+        ctx.session_store
+            .expect_join_request_timeout()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        expect_join_pending(&mut ctx, default_timeout(&config));
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<JoinSessionPermissionRequest>()
+            .times(1)
+            .returning(|_, _, _, _| futures_util::future::pending().boxed());
+
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(join_request.session_id.to_string()),
+                eq("join_request_cancelled".to_string()),
+                eq(()),
+            )
+            .return_const(Ok(()));
+
+        ctx.client_socket.expect_join().never();
+        ctx.session_store.expect_update_session_state().never();
+        ctx.client_socket.expect_store_value().never();
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::JoinAttempt {
+                        outcome: JoinOutcome::Cancelled,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Cancelled);
+
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            ctx.pending_joins.cancel("controller")
+        };
+        let (result, cancelled) = tokio::join!(
+            on_join_session(
+                ctx.client_socket,
+                ctx.global_socket,
+                Data(join_request),
+                &ctx.session_store,
+                &ctx.activity,
+                &ctx.pending_joins,
+                &ctx.events,
+                &config,
+            ),
+            cancel
+        );
+
+        assert_eq!(cancelled, Some(Uuid::nil()));
+        assert_eq!(
+            result,
+            JoinSessionResponse::with_err(JoinSessionErrorKind::Cancelled)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn can_not_send_a_join_request_while_another_is_pending(mut ctx: Context) {
+        let join_request = Data(JoinSessionRequest {
+            session_id: Uuid::nil(),
+            message: "hello world".into(),
+            invitation: None,
+        });
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store.expect_session_state().never();
+        ctx.events.expect_publish().never();
+
+        let _pending = ctx
+            .pending_joins
+            .register("controller".into(), Uuid::new_v4())
+            .unwrap();
+
+        let result = on_join_session(
+            ctx.client_socket,
+            ctx.global_socket,
+            join_request,
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            JoinSessionResponse::with_err(JoinSessionErrorKind::JoinRequestPending)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn allowlisted_controllers_join_without_asking_the_hub(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| {
+                let policy = JoinPolicy::Allowlist {
+                    controllers: ["controller".to_string()].into(),
+                };
+                async { Ok(Some(policy)) }.boxed()
+            });
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<JoinSessionPermissionRequest>()
+            .never();
+
+        ctx.session_store
+            .expect_update_session_state()
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+        ctx.client_socket
+            .expect_join()
+            .times(1)
+            .return_const(Ok(()));
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("controller_joined".to_string()),
+                eq(()),
+            )
+            .return_const(Ok(()));
+        ctx.client_socket
+            .expect_store_value()
+            .times(1)
+            .return_const(());
+        ctx.session_store
+            .expect_device_statuses()
+            .times(1)
+            .returning(|_| async { Ok(vec![]) }.boxed());
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Accepted);
+
+        let result = on_join_session(
+            ctx.client_socket,
+            ctx.global_socket,
+            Data(JoinSessionRequest {
+                session_id: Uuid::nil(),
+                message: "hello world".into(),
+                invitation: None,
+            }),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(result, JoinSessionResponse::Ok {});
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn controllers_trusted_by_the_hub_join_without_asking_it(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        expect_unscheduled(&mut ctx);
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        ctx.session_store
+            .expect_session_owner()
+            .times(1)
+            .returning(|_| async { Ok(Some("hub".to_string())) }.boxed());
+        ctx.session_store
+            .expect_trusted_controllers()
+            .with(eq("hub".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(["controller".to_string()].into()) }.boxed());
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<JoinSessionPermissionRequest>()
+            .never();
+
+        ctx.session_store
+            .expect_update_session_state()
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+        ctx.client_socket
+            .expect_join()
+            .times(1)
+            .return_const(Ok(()));
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("controller_joined".to_string()),
+                eq(()),
+            )
+            .return_const(Ok(()));
+        ctx.client_socket
+            .expect_store_value()
+            .times(1)
+            .return_const(());
+        ctx.session_store
+            .expect_device_statuses()
+            .times(1)
+            .returning(|_| async { Ok(vec![]) }.boxed());
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Accepted);
+
+        let result = on_join_session(
+            ctx.client_socket,
+            ctx.global_socket,
+            Data(JoinSessionRequest {
+                session_id: Uuid::nil(),
+                message: "hello world".into(),
+                invitation: None,
+            }),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(result, JoinSessionResponse::Ok {});
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn invited_controllers_join_without_asking_the_hub(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        ctx.session_store
+            .expect_session_window()
+            .times(1)
+            .returning(|_| {
+                let now = unix_millis();
+                async move {
+                    Ok(Some(SessionWindow {
+                        starts_at: now - 1_000,
+                        ends_at: now + 60_000,
+                    }))
+                }
+                .boxed()
+            });
+        ctx.session_store
+            .expect_invitation()
+            .with(eq("token".to_string()))
+            .times(1)
+            .returning(|_| {
+                async {
+                    Ok(Some(Invitation {
+                        session_id: Uuid::nil(),
+                        controller_id: "controller".into(),
+                    }))
+                }
+                .boxed()
+            });
+        ctx.session_store
+            .expect_join_policy()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<JoinSessionPermissionRequest>()
+            .never();
+
+        ctx.session_store
+            .expect_update_session_state()
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+        ctx.client_socket
+            .expect_join()
+            .times(1)
+            .return_const(Ok(()));
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("controller_joined".to_string()),
+                eq(()),
+            )
+            .return_const(Ok(()));
+        ctx.client_socket
+            .expect_store_value()
+            .times(1)
+            .return_const(());
+        ctx.session_store
+            .expect_device_statuses()
+            .times(1)
+            .returning(|_| async { Ok(vec![]) }.boxed());
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::Accepted);
+
+        let result = on_join_session(
+            ctx.client_socket,
+            ctx.global_socket,
+            Data(JoinSessionRequest {
+                session_id: Uuid::nil(),
+                message: "hello world".into(),
+                invitation: Some("token".into()),
+            }),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(result, JoinSessionResponse::Ok {});
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn scheduled_sessions_can_not_be_joined_before_their_window(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        ctx.session_store
+            .expect_session_window()
+            .times(1)
+            .returning(|_| {
+                let now = unix_millis();
+                async move {
+                    Ok(Some(SessionWindow {
+                        starts_at: now + 60_000,
+                        ends_at: now + 120_000,
+                    }))
+                }
+                .boxed()
+            });
+        ctx.session_store.expect_join_policy().never();
+
+        expect_audited(
+            &mut ctx,
+            Some("hub"),
+            JoinAttemptOutcome::OutsideSessionWindow,
+        );
+
+        let result = on_join_session(
+            ctx.client_socket,
+            ctx.global_socket,
+            Data(JoinSessionRequest {
+                session_id: Uuid::nil(),
+                message: "hello world".into(),
+                invitation: None,
+            }),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.pending_joins,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            JoinSessionResponse::with_err(JoinSessionErrorKind::OutsideSessionWindow)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn rate_limited_join_requests_are_audited(mut ctx: Context) {
+        let guard = JoinGuard::new(WordListFilter::default(), &Config::load().controller);
+        let request = JoinSessionRequest {
+            session_id: Uuid::nil(),
+            message: "hello world".into(),
+            invitation: None,
+        };
+
+        ctx.client_socket.expect_ip().return_const(None);
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(Some(SessionState::WaitingForController)) }.boxed());
+
+        expect_audited(&mut ctx, Some("hub"), JoinAttemptOutcome::RateLimited);
+
+        let first = check_join_request(&ctx.client_socket, &request, &ctx.session_store, &guard);
+        assert_eq!(first.await, Ok(()));
+        let second = check_join_request(&ctx.client_socket, &request, &ctx.session_store, &guard);
+        assert_eq!(second.await, Err(JoinSessionErrorKind::RateLimited));
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn refused_join_requests_for_unknown_sessions_are_not_audited(mut ctx: Context) {
+        let guard = JoinGuard::new(WordListFilter::new(["spam"]), &Config::load().controller);
+        let request = JoinSessionRequest {
+            session_id: Uuid::nil(),
+            message: "spam".into(),
+            invitation: None,
+        };
+
+        ctx.client_socket.expect_ip().return_const(None);
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("controller".to_string()));
+        ctx.session_store
+            .expect_session_state()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+        ctx.session_store.expect_append_join_attempt().never();
+
+        let result =
+            check_join_request(&ctx.client_socket, &request, &ctx.session_store, &guard).await;
+        assert_eq!(result, Err(JoinSessionErrorKind::MessageRejected));
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn rooms_resolve_to_the_live_session_of_their_hub(mut ctx: Context) {
+        ctx.session_store
+            .expect_room_session()
+            .with(eq("hub-room".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(Some(Uuid::nil())) }.boxed());
+        ctx.session_store
+            .expect_room_session()
+            .with(eq("idle-room".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        let resolve = |room: &str| {
+            on_resolve_room(
+                Data(ResolveRoomRequest { room: room.into() }),
+                &ctx.session_store,
+            )
+        };
+        assert_eq!(
+            resolve("hub-room").await,
+            ResolveRoomResponse::Ok {
+                session_id: Uuid::nil()
+            }
+        );
+        let not_found = ResolveRoomResponse::Error {
+            kind: ResolveRoomErrorKind::RoomNotFound,
+        };
+        assert_eq!(resolve("idle-room").await, not_found);
+        assert_eq!(resolve("Not A Room").await, not_found);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn timed_commands_are_relayed_in_the_hub_clock(mut ctx: Context) {
+        let latency = SessionLatency::default();
+        let rtt = Duration::from_millis(40);
+        latency.record(
+            Uuid::nil(),
+            Role::Controller,
+            Measurement {
+                rtt,
+                clock_offset: 500,
+            },
+        );
+        latency.record(
+            Uuid::nil(),
+            Role::Hub,
+            Measurement {
+                rtt,
+                clock_offset: -200,
+            },
+        );
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("vibrate".to_string()),
+                eq(VibrateCmd {
+                    value: 0.5,
+                    execute_at: Some(9_300),
+                    seq: None,
+                    timeout_ms: None,
+                }),
+            )
+            .return_const(Ok(()));
+
+        on_vibrate_command(
+            ctx.client_socket,
+            Data(VibrateCmd {
+                value: 0.5,
+                execute_at: Some(10_000),
+                seq: None,
+                timeout_ms: None,
+            }),
+            &ctx.activity,
+            &latency,
+            &ctx.recordings,
+        );
+    }
+
+    fn acked_command(timeout_ms: Option<u64>) -> VibrateCmd {
+        VibrateCmd {
+            value: 0.5,
+            execute_at: None,
+            seq: Some(7),
+            timeout_ms,
+        }
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn acked_commands_report_what_the_hub_answered(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack()
+            .times(1)
+            .with(
+                eq(format!("{}:hub", Uuid::nil())),
+                eq("vibrate".to_string()),
+                eq(acked_command(None)),
+                eq(Duration::from_millis(500)),
+            )
+            .returning(|_, _, _, _| async { Ok(CommandAck::Rejected) }.boxed());
+
+        let result = on_acked_vibrate_command(
+            ctx.client_socket,
+            &ctx.global_socket,
+            Data(acked_command(Some(500))),
+            &ctx.activity,
+            &SessionLatency::default(),
+            &ctx.recordings,
+            &ctx.commands,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            CommandResult {
+                seq: 7,
+                status: CommandStatus::Rejected
+            }
+        );
+        assert!(ctx.activity.idle_for(Uuid::nil()).is_some());
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn replayed_commands_do_not_reach_the_hub(mut ctx: Context) {
+        let config = Config::load();
+
+        let mut retry_socket = MockClientSocket::new();
+        for socket in [&mut ctx.client_socket, &mut retry_socket] {
+            socket
+                .expect_get_stored_value()
+                .times(1)
+                .return_const(Some(Uuid::nil()));
+        }
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<VibrateCmd>()
+            .times(1)
+            .returning(|_, _, _, _| async { Ok(CommandAck::Applied) }.boxed());
+
+        for socket in [ctx.client_socket, retry_socket] {
+            let result = on_acked_vibrate_command(
+                socket,
+                &ctx.global_socket,
+                Data(acked_command(None)),
+                &ctx.activity,
+                &SessionLatency::default(),
+                &ctx.recordings,
+                &ctx.commands,
+                &config,
+            )
+            .await;
+
+            assert_eq!(
+                result,
+                CommandResult {
+                    seq: 7,
+                    status: CommandStatus::Applied
+                }
+            );
+        }
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn acked_commands_time_out_if_the_hub_does_not_answer(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        // Commands can't wait for longer than the configured timeout
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<VibrateCmd>()
+            .times(1)
+            .with(
+                eq(format!("{}:hub", Uuid::nil())),
+                eq("vibrate".to_string()),
+                eq(acked_command(None)),
+                eq(Duration::from_secs(config.controller.command_ack_timeout)),
+            )
+            .returning(|_, _, _, _| async { Err(DummyMockError) }.boxed());
+
+        let result = on_acked_vibrate_command(
+            ctx.client_socket,
+            &ctx.global_socket,
+            Data(acked_command(Some(u64::MAX))),
+            &ctx.activity,
+            &SessionLatency::default(),
+            &ctx.recordings,
+            &ctx.commands,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            CommandResult {
+                seq: 7,
+                status: CommandStatus::TimedOut
+            }
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn acked_commands_are_rejected_outside_of_a_session(mut ctx: Context) {
+        let config = Config::load();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.global_socket
+            .expect_emit_to_room_with_ack::<VibrateCmd>()
+            .never();
+
+        ctx.client_socket
+            .expect_emit::<ControllerErrorMsg>()
+            .times(1)
+            .withf(|event, _| event == "error")
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_disconnect()
+            .times(1)
+            .return_const(());
+
+        let result = on_acked_vibrate_command(
+            ctx.client_socket,
+            &ctx.global_socket,
+            Data(acked_command(None)),
+            &ctx.activity,
+            &SessionLatency::default(),
+            &ctx.recordings,
+            &ctx.commands,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            CommandResult {
+                seq: 7,
+                status: CommandStatus::NotInSession
+            }
+        );
+    }
 }
@@ -1,27 +1,100 @@
 use super::messages::*;
 use crate::{
+    actors::{
+        hub::{hub_room, messages as hub_messages},
+        latency::unix_millis,
+        publish_event, Role,
+    },
     configuration::Config,
-    sessions::port::{SessionState, SessionStore},
+    events::port::{EventSink, JoinOutcome, SessionEventKind},
+    moderation::{
+        guard::{JoinGuard, JoinRefusal},
+        port::MessageFilter,
+    },
+    sessions::{
+        activity::SessionActivity,
+        audit::{audit_key, JoinAttempt, JoinAttemptOutcome},
+        commands::{CommandSequences, Sequenced},
+        latency::SessionLatency,
+        pending::{Cancelled, PendingJoins},
+        port::{SessionState, SessionStore},
+        recording::SessionRecordings,
+        room::is_valid_room,
+    },
     socket::port::{ClientSocket, GlobalSocket},
 };
 use socketioxide::extract::Data;
-use std::time::Duration;
+use std::{future::Future, time::Duration};
+use tokio::time::Instant;
 use tracing::{debug, error, warn};
 use uuid::Uuid;
 
-pub async fn on_join_session<T, S, G>(
+/// How often controllers are told their join request is still waiting for the hub.
+const JOIN_PENDING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Refuses join requests that come too often or carry a message that isn't allowed, before
+/// they reach the hub.
+pub async fn check_join_request<T, S, F>(
+    socket: &S,
+    request: &JoinSessionRequest,
+    sessions: &T,
+    guard: &JoinGuard<F>,
+) -> Result<(), JoinSessionErrorKind>
+where
+    T: SessionStore,
+    S: ClientSocket<StoreItem = Uuid>,
+    F: MessageFilter,
+{
+    let (kind, outcome) = match guard.check(socket.ip(), socket.client_id(), &request.message) {
+        Ok(()) => return Ok(()),
+        Err(JoinRefusal::RateLimited) => {
+            warn!(ip = ?socket.ip(), "Join request rate limited");
+            (
+                JoinSessionErrorKind::RateLimited,
+                JoinAttemptOutcome::RateLimited,
+            )
+        }
+        Err(JoinRefusal::MessageRejected) => (
+            JoinSessionErrorKind::MessageRejected,
+            JoinAttemptOutcome::MessageRejected,
+        ),
+    };
+
+    // Refused requests may name any session, only those of an existing one are audited
+    match sessions.session_state(request.session_id).await {
+        Ok(Some(_)) => {
+            let attempt = JoinAttempt::new(
+                request.session_id,
+                socket.client_id(),
+                &request.message,
+                outcome,
+            );
+            audit_join_attempt(sessions, attempt).await;
+        }
+        Ok(None) => {}
+        Err(error) => error!(%error, "Failed to check session state"),
+    }
+    Err(kind)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn on_join_session<T, S, G, E>(
     socket: S,
     global_socket: G,
     Data(request): Data<JoinSessionRequest>,
     sessions: &T,
+    activity: &SessionActivity,
+    pending_joins: &PendingJoins,
+    events: &E,
     config: &Config,
 ) -> JoinSessionResponse
 where
     T: SessionStore,
     S: ClientSocket<StoreItem = Uuid>,
     G: GlobalSocket,
+    E: EventSink,
 {
-    debug!("Received join_session command");
+    debug!(version = ?socket.protocol_version(), "Received join_session command");
 
     let session_id = request.session_id;
 
@@ -29,8 +102,22 @@ where
         return JoinSessionResponse::with_err(JoinSessionErrorKind::AlreadyInASession);
     }
 
+    let Some(mut pending) = pending_joins.register(socket.connection_id(), session_id) else {
+        return JoinSessionResponse::with_err(JoinSessionErrorKind::JoinRequestPending);
+    };
+
+    // The state of the session is only checked once the requests before this one are answered
+    if pending.wait_turn().await.is_err() {
+        debug!("Join request cancelled while waiting for its turn");
+        return JoinSessionResponse::with_err(JoinSessionErrorKind::Cancelled);
+    }
+
+    let attempt =
+        |outcome| JoinAttempt::new(session_id, socket.client_id(), &request.message, outcome);
+
     match sessions.session_state(session_id).await {
         Ok(Some(SessionState::InProgress)) => {
+            audit_join_attempt(sessions, attempt(JoinAttemptOutcome::SessionFull)).await;
             return JoinSessionResponse::with_err(JoinSessionErrorKind::SessionFull);
         }
         Ok(Some(SessionState::WaitingForController)) => (),
@@ -43,26 +130,126 @@ where
         }
     };
 
-    let response = global_socket
-        .emit_to_room_with_ack(
+    match sessions.session_window(session_id).await {
+        Ok(Some(window)) if !window.contains(unix_millis()) => {
+            audit_join_attempt(sessions, attempt(JoinAttemptOutcome::OutsideSessionWindow)).await;
+            return JoinSessionResponse::with_err(JoinSessionErrorKind::OutsideSessionWindow);
+        }
+        Ok(_) => (),
+        Err(error) => {
+            error!(%error, "Failed to get the session window");
+            return JoinSessionResponse::with_err(JoinSessionErrorKind::ServerError);
+        }
+    };
+
+    let invited = match request.invitation.clone() {
+        Some(token) => match sessions.invitation(token).await {
+            Ok(Some(invitation))
+                if invitation.admits(session_id, socket.client_id().as_deref()) =>
+            {
+                true
+            }
+            Ok(_) => {
+                audit_join_attempt(sessions, attempt(JoinAttemptOutcome::InvalidInvitation)).await;
+                return JoinSessionResponse::with_err(JoinSessionErrorKind::InvalidInvitation);
+            }
+            Err(error) => {
+                error!(%error, "Failed to get the invitation");
+                return JoinSessionResponse::with_err(JoinSessionErrorKind::ServerError);
+            }
+        },
+        None => false,
+    };
+
+    let policy = match sessions.join_policy(session_id).await {
+        Ok(policy) => policy.unwrap_or_default(),
+        Err(error) => {
+            error!(%error, "Failed to get the join policy");
+            return JoinSessionResponse::with_err(JoinSessionErrorKind::ServerError);
+        }
+    };
+
+    let outcome = if policy.auto_accepts(socket.client_id().as_deref()) {
+        debug!("Controller joins without asking the hub");
+        JoinOutcome::Accepted
+    } else if invited {
+        debug!("Invited controller joins without asking the hub");
+        JoinOutcome::Accepted
+    } else if is_trusted(sessions, session_id, socket.client_id()).await {
+        debug!("Controller trusted by the hub joins without asking it");
+        JoinOutcome::Accepted
+    } else {
+        let timeout = match sessions.join_request_timeout(session_id).await {
+            Ok(timeout) => timeout.unwrap_or(Duration::from_secs(
+                config.controller.session_join_request_timeout,
+            )),
+            Err(error) => {
+                error!(%error, "Failed to get the join request timeout");
+                return JoinSessionResponse::with_err(JoinSessionErrorKind::ServerError);
+            }
+        };
+        let deadline = unix_millis() + timeout.as_millis() as i64;
+        let answer = global_socket.emit_to_room_with_ack(
             session_id.into(),
-            "join_request".into(),
+            event_names::JOIN_REQUEST.into(),
             JoinSessionPermissionRequest {
-                message: request.message,
+                message: request.message.clone(),
+                deadline,
+                timeout_ms: timeout.as_millis() as u64,
             },
-            Duration::from_secs(config.controller.session_join_request_timeout),
-        )
-        .await;
+            timeout,
+        );
+        let response = pending
+            .until_cancelled(wait_for_hub(&socket, answer, deadline, timeout))
+            .await;
+
+        match response {
+            Ok(Ok(JoinSessionPermissionResponse::Accept)) => JoinOutcome::Accepted,
+            Ok(Ok(JoinSessionPermissionResponse::Reject)) => JoinOutcome::Rejected,
+            Ok(Err(error)) => {
+                error!(%error, "Failed to ask client if controller can join the session");
+                JoinOutcome::HubResponseTimeout
+            }
+            Err(Cancelled) => {
+                if let Err(error) = socket.emit_to_room(
+                    session_id.into(),
+                    event_names::JOIN_REQUEST_CANCELLED.into(),
+                    (),
+                ) {
+                    error!(%error, "Failed to tell the hub the join request was cancelled");
+                }
+                JoinOutcome::Cancelled
+            }
+        }
+    };
 
-    match response {
-        Ok(JoinSessionPermissionResponse::Accept) => (),
-        Ok(JoinSessionPermissionResponse::Reject) => {
+    let audited = match outcome {
+        JoinOutcome::Accepted => JoinAttemptOutcome::Accepted,
+        JoinOutcome::Rejected => JoinAttemptOutcome::Rejected,
+        JoinOutcome::HubResponseTimeout => JoinAttemptOutcome::TimedOut,
+        JoinOutcome::Cancelled => JoinAttemptOutcome::Cancelled,
+    };
+    audit_join_attempt(sessions, attempt(audited)).await;
+
+    publish_event(
+        events,
+        session_id,
+        Role::Controller,
+        SessionEventKind::JoinAttempt { outcome },
+    )
+    .await;
+
+    match outcome {
+        JoinOutcome::Accepted => (),
+        JoinOutcome::Rejected => {
             return JoinSessionResponse::with_err(JoinSessionErrorKind::Rejected);
         }
-        Err(error) => {
-            error!(%error, "Failed to ask client if controller can join the session");
+        JoinOutcome::HubResponseTimeout => {
             return JoinSessionResponse::with_err(JoinSessionErrorKind::HubResponseTimeout);
         }
+        JoinOutcome::Cancelled => {
+            return JoinSessionResponse::with_err(JoinSessionErrorKind::Cancelled);
+        }
     };
 
     if let Err(error) = sessions
@@ -78,40 +265,229 @@ where
         return JoinSessionResponse::with_err(JoinSessionErrorKind::ServerError);
     }
 
-    if let Err(error) = socket.emit_to_room(session_id.into(), "controller_joined".into(), ()) {
+    if let Err(error) =
+        socket.emit_to_room(session_id.into(), event_names::CONTROLLER_JOINED.into(), ())
+    {
         error!(%error, "Controller failed to send join confirmation to hub");
         return JoinSessionResponse::with_err(JoinSessionErrorKind::ServerError);
     }
 
-    socket.store_value(session_id);
+    // A controller that disconnected meanwhile would never leave the session
+    if pending.finish(|| socket.store_value(session_id)).is_err() {
+        debug!("Join request cancelled after the hub accepted it");
+        undo_join(&socket, session_id, sessions, events).await;
+        return JoinSessionResponse::with_err(JoinSessionErrorKind::Cancelled);
+    }
+
+    activity.record(session_id);
+    send_device_statuses(&socket, session_id, sessions).await;
     JoinSessionResponse::Ok {}
 }
 
-pub fn on_vibrate_command<S>(socket: S, Data(cmd): Data<VibrateCmd>)
+/// Waits for the hub to answer a join request, telling the controller how long is left on the
+/// way.
+async fn wait_for_hub<S, F>(socket: &S, answer: F, deadline: i64, timeout: Duration) -> F::Output
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    F: Future,
+{
+    let expires_at = Instant::now() + timeout;
+    let mut updates = tokio::time::interval_at(
+        Instant::now() + JOIN_PENDING_INTERVAL,
+        JOIN_PENDING_INTERVAL,
+    );
+    tokio::pin!(answer);
+    loop {
+        let remaining = expires_at.saturating_duration_since(Instant::now());
+        let update = JoinPending {
+            deadline,
+            remaining_ms: remaining.as_millis() as u64,
+        };
+        if let Err(error) = socket.emit(event_names::JOIN_PENDING.into(), update) {
+            warn!(%error, "Failed to tell the controller its join request is pending");
+        }
+
+        tokio::select! {
+            output = &mut answer => return output,
+            _ = updates.tick() => (),
+        }
+    }
+}
+
+/// Leaves a session joined by a request that was cancelled at the last moment.
+async fn undo_join<T, S, E>(socket: &S, session_id: Uuid, sessions: &T, events: &E)
+where
+    T: SessionStore,
+    S: ClientSocket<StoreItem = Uuid>,
+    E: EventSink,
+{
+    if let Err(error) = socket.leave(session_id.into()) {
+        error!(%error, "Controller failed to leave session");
+    }
+
+    if let Err(error) = socket.emit_to_room(
+        session_id.into(),
+        event_names::CONTROLLER_DISCONNECTED.into(),
+        (),
+    ) {
+        error!(%error, "Failed to send controller_disconnected event");
+    }
+
+    if let Err(error) = sessions
+        .update_session_state(session_id, SessionState::WaitingForController)
+        .await
+    {
+        error!(%error, "Failed to update session state");
+    }
+
+    publish_event(
+        events,
+        session_id,
+        Role::Controller,
+        SessionEventKind::ControllerLeft,
+    )
+    .await;
+}
+
+/// Cancels the join request the controller is waiting an answer for.
+pub fn on_cancel_join<S>(socket: S, pending_joins: &PendingJoins) -> CancelJoinResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    debug!("Received cancel_join command");
+    match pending_joins.cancel(&socket.connection_id()) {
+        Some(_) => CancelJoinResponse::Ok {},
+        None => CancelJoinResponse::Error {
+            kind: CancelJoinErrorKind::NoPendingRequest,
+        },
+    }
+}
+
+/// Looks up the session a hub is running in its persistent room, so controllers can join it.
+pub async fn on_resolve_room<T>(
+    Data(request): Data<ResolveRoomRequest>,
+    sessions: &T,
+) -> ResolveRoomResponse
+where
+    T: SessionStore,
+{
+    debug!("Received resolve_room command");
+    if !is_valid_room(&request.room) {
+        return ResolveRoomResponse::Error {
+            kind: ResolveRoomErrorKind::RoomNotFound,
+        };
+    }
+
+    match sessions.room_session(request.room).await {
+        Ok(Some(session_id)) => ResolveRoomResponse::Ok { session_id },
+        Ok(None) => ResolveRoomResponse::Error {
+            kind: ResolveRoomErrorKind::RoomNotFound,
+        },
+        Err(error) => {
+            error!(%error, "Failed to get the session of the room");
+            ResolveRoomResponse::Error {
+                kind: ResolveRoomErrorKind::ServerError,
+            }
+        }
+    }
+}
+
+/// Whether the hub that owns the session trusts the client id the controller proved, in which
+/// case it isn't asked.
+async fn is_trusted<T>(sessions: &T, session_id: Uuid, controller_id: Option<String>) -> bool
+where
+    T: SessionStore,
+{
+    let Some(controller_id) = controller_id else {
+        return false;
+    };
+    let hub_id = match sessions.session_owner(session_id).await {
+        Ok(Some(hub_id)) => hub_id,
+        Ok(None) => return false,
+        Err(error) => {
+            error!(%error, "Failed to get the session owner");
+            return false;
+        }
+    };
+    match sessions.trusted_controllers(hub_id).await {
+        Ok(controllers) => controllers.contains(&controller_id),
+        Err(error) => {
+            error!(%error, "Failed to get the controllers trusted by the hub");
+            false
+        }
+    }
+}
+
+/// Appends a join attempt to the audit log of the hub that owns the session.
+async fn audit_join_attempt<T>(sessions: &T, attempt: JoinAttempt)
+where
+    T: SessionStore,
+{
+    let hub_id = match sessions.session_owner(attempt.session_id).await {
+        Ok(hub_id) => hub_id,
+        Err(error) => {
+            error!(%error, "Failed to get the session owner");
+            None
+        }
+    };
+    let Some(key) = audit_key(hub_id, Some(attempt.session_id)) else {
+        return;
+    };
+    if let Err(error) = sessions.append_join_attempt(key, attempt).await {
+        error!(%error, "Failed to audit join attempt");
+    }
+}
+
+/// Sends the latest status of each device of the session to a controller that just joined.
+async fn send_device_statuses<T, S>(socket: &S, session_id: Uuid, sessions: &T)
 where
+    T: SessionStore,
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    let statuses = match sessions.device_statuses(session_id).await {
+        Ok(statuses) => statuses,
+        Err(error) => {
+            error!(%error, "Failed to get device statuses");
+            return;
+        }
+    };
+
+    for status in statuses {
+        let status = match serde_json::from_str::<hub_messages::DeviceStatus>(&status) {
+            Ok(status) => status,
+            Err(error) => {
+                warn!(%error, "Ignoring invalid cached device status");
+                continue;
+            }
+        };
+        if let Err(error) = socket.emit(hub_messages::event_names::DEVICE_STATUS.into(), status) {
+            error!(%error, "Failed to send device status to controller");
+        }
+    }
+}
+
+pub fn on_vibrate_command<S>(
+    socket: S,
+    Data(cmd): Data<VibrateCmd>,
+    activity: &SessionActivity,
+    latency: &SessionLatency,
+    recordings: &SessionRecordings,
+) where
     S: ClientSocket<StoreItem = Uuid>,
 {
     debug!("Received vibrate command");
     let Some(session_id) = socket.get_stored_value() else {
-        warn!("Client sent vibrate command without being in a session");
-        socket
-            .emit(
-                "error".into(),
-                ControllerErrorMsg::new(
-                    ControllerErrorKind::Permissions,
-                    "Can't send a vibrate command if not in a session",
-                ),
-            )
-            .ok();
-        socket.disconnect();
+        reject_command(socket);
         return;
     };
 
-    if let Err(error) = socket.emit_to_room(session_id.into(), "vibrate".into(), cmd) {
+    let cmd = prepare_command(cmd, session_id, activity, latency, recordings);
+
+    if let Err(error) = socket.emit_to_room(session_id.into(), event_names::VIBRATE.into(), cmd) {
         error!(%error, "Failed to emit vibrate command");
         socket
             .emit(
-                "error".into(),
+                event_names::ERROR.into(),
                 ControllerErrorMsg::new(
                     ControllerErrorKind::VibrateCmdSendError,
                     "Failed to send vibration command",
@@ -121,26 +497,149 @@ where
     }
 }
 
-pub async fn on_disconnect<T, S>(socket: S, sessions: &T)
+/// Relays a vibrate command with a sequence number and waits for the hub to acknowledge it.
+///
+/// Replayed sequence numbers are answered without reaching the hub.
+#[allow(clippy::too_many_arguments)]
+pub async fn on_acked_vibrate_command<S, G>(
+    socket: S,
+    global_socket: &G,
+    Data(cmd): Data<VibrateCmd>,
+    activity: &SessionActivity,
+    latency: &SessionLatency,
+    recordings: &SessionRecordings,
+    commands: &CommandSequences,
+    config: &Config,
+) -> CommandResult
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    G: GlobalSocket,
+{
+    debug!("Received acknowledged vibrate command");
+    let seq = cmd.seq.unwrap_or_default();
+    let Some(session_id) = socket.get_stored_value() else {
+        reject_command(socket);
+        return CommandResult {
+            seq,
+            status: CommandStatus::NotInSession,
+        };
+    };
+
+    if let Sequenced::Answer(status) = commands.check(session_id, seq) {
+        debug!(seq, "Dropping replayed vibrate command");
+        return CommandResult { seq, status };
+    }
+
+    let max_timeout = Duration::from_secs(config.controller.command_ack_timeout);
+    let timeout = cmd.timeout_ms.map_or(max_timeout, |timeout| {
+        Duration::from_millis(timeout).min(max_timeout)
+    });
+    let cmd = prepare_command(cmd, session_id, activity, latency, recordings);
+
+    let status = match global_socket
+        .emit_to_room_with_ack(
+            hub_room(session_id),
+            event_names::VIBRATE.into(),
+            cmd,
+            timeout,
+        )
+        .await
+    {
+        Ok(CommandAck::Applied) => CommandStatus::Applied,
+        Ok(CommandAck::Rejected) => CommandStatus::Rejected,
+        Err(error) => {
+            warn!(%error, seq, "Hub did not acknowledge vibrate command");
+            CommandStatus::TimedOut
+        }
+    };
+    commands.record(session_id, seq, status);
+
+    CommandResult { seq, status }
+}
+
+fn reject_command<S>(socket: S)
 where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    warn!("Client sent vibrate command without being in a session");
+    socket
+        .emit(
+            event_names::ERROR.into(),
+            ControllerErrorMsg::new(
+                ControllerErrorKind::Permissions,
+                "Can't send a vibrate command if not in a session",
+            ),
+        )
+        .ok();
+    socket.disconnect();
+}
+
+/// Records the command as session activity and turns it into what the hub receives.
+fn prepare_command(
+    mut cmd: VibrateCmd,
+    session_id: Uuid,
+    activity: &SessionActivity,
+    latency: &SessionLatency,
+    recordings: &SessionRecordings,
+) -> VibrateCmd {
+    activity.record(session_id);
+    recordings.record(session_id, cmd.value);
+
+    // Hubs schedule timed commands against their own clock
+    cmd.execute_at = cmd.execute_at.map(|timestamp| {
+        latency.convert_timestamp(session_id, timestamp, Role::Controller, Role::Hub)
+    });
+    cmd.timeout_ms = None;
+    cmd
+}
+
+pub async fn on_disconnect<T, S, E>(
+    socket: S,
+    sessions: &T,
+    recordings: &SessionRecordings,
+    commands: &CommandSequences,
+    pending_joins: &PendingJoins,
+    events: &E,
+) where
     T: SessionStore,
     S: ClientSocket<StoreItem = Uuid>,
+    E: EventSink,
 {
     debug!("Controller disconnected");
 
+    // The join request notifies the hub and cleans up after itself
+    if pending_joins.cancel(&socket.connection_id()).is_some() {
+        debug!("Cancelled the join request of the controller");
+    }
+
     let Some(session_id) = socket.get_stored_value() else {
         return;
     };
 
-    if let Err(error) = socket.emit_to_room(session_id.into(), "controller_disconnected".into(), ())
-    {
+    if let Err(error) = socket.emit_to_room(
+        session_id.into(),
+        event_names::CONTROLLER_DISCONNECTED.into(),
+        (),
+    ) {
         error!(%error, "Failed to send controller_disconnected event");
     }
 
+    // Consent is given by each controller, the next one has to agree again
+    recordings.set_consent(session_id, Role::Controller, false);
+    commands.remove(session_id);
+
     if let Err(error) = sessions
         .update_session_state(session_id, SessionState::WaitingForController)
         .await
     {
         error!(%error, "Failed to update session state");
     }
+
+    publish_event(
+        events,
+        session_id,
+        Role::Controller,
+        SessionEventKind::ControllerLeft,
+    )
+    .await;
 }
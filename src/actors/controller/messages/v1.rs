@@ -0,0 +1,575 @@
+use crate::socket::port::MessageWithAck;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Names of the events exchanged with controllers.
+pub mod event_names {
+    pub const JOIN_SESSION: &str = "join_session";
+    pub const VIBRATE: &str = "vibrate";
+    pub const CANCEL_JOIN: &str = "cancel_join";
+    pub const RESOLVE_ROOM: &str = "resolve_room";
+    pub const HUB_PRESENCE: &str = "hub_presence";
+    pub const HUBS_ONLINE: &str = "hubs_online";
+    pub const JOIN_REQUEST: &str = "join_request";
+    pub const JOIN_REQUEST_CANCELLED: &str = "join_request_cancelled";
+    pub const JOIN_PENDING: &str = "join_pending";
+    pub const CONTROLLER_JOINED: &str = "controller_joined";
+    pub const CONTROLLER_DISCONNECTED: &str = "controller_disconnected";
+    pub const ERROR: &str = "error";
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct JoinSessionRequest {
+    pub session_id: Uuid,
+    pub message: String,
+    /// Token of an invitation to a scheduled session, to join it without asking the hub.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invitation: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum JoinSessionErrorKind {
+    AlreadyInASession,
+    SessionNotFound,
+    SessionFull,
+    ServerError,
+    HubResponseTimeout,
+    Rejected,
+    RateLimited,
+    MessageRejected,
+    JoinRequestPending,
+    Cancelled,
+    OutsideSessionWindow,
+    InvalidInvitation,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum JoinSessionResponse {
+    Error { kind: JoinSessionErrorKind },
+    Ok {},
+}
+
+impl JoinSessionResponse {
+    pub fn with_err(kind: JoinSessionErrorKind) -> Self {
+        Self::Error { kind }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum CancelJoinErrorKind {
+    NoPendingRequest,
+}
+
+/// Acknowledgment of `cancel_join`, the cancelled `join_session` is answered with `cancelled`.
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum CancelJoinResponse {
+    Error { kind: CancelJoinErrorKind },
+    Ok {},
+}
+
+/// Sent to controllers when a hub that trusts them connects or disconnects.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct HubPresence {
+    pub hub_id: String,
+    pub online: bool,
+}
+
+/// Asks which of the hubs are connected to the server. Hubs that don't trust the controller are
+/// always reported offline.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct HubsOnlineRequest {
+    /// At most 50 client ids of hubs.
+    pub hub_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum HubsOnlineErrorKind {
+    /// Only controllers that connected with a client id can ask.
+    RequiresClientId,
+    TooManyHubs,
+    /// Asked again too soon.
+    RateLimited,
+    ServerError,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum HubsOnlineResponse {
+    Error { kind: HubsOnlineErrorKind },
+    Ok { hubs: Vec<HubPresence> },
+}
+
+/// Looks up the live session of the persistent room of a hub.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct ResolveRoomRequest {
+    pub room: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum ResolveRoomErrorKind {
+    /// The room doesn't exist or its hub has no session going on.
+    RoomNotFound,
+    ServerError,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum ResolveRoomResponse {
+    Error { kind: ResolveRoomErrorKind },
+    Ok { session_id: Uuid },
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, Default, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct JoinSessionPermissionRequest {
+    pub message: String,
+    /// When the request times out, in milliseconds since the Unix epoch of the server clock.
+    pub deadline: i64,
+    /// How long the hub has to answer, to count down without relying on its clock.
+    pub timeout_ms: u64,
+}
+
+/// Sent to a controller every now and then while the hub hasn't answered its join request.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct JoinPending {
+    /// When the request times out, in milliseconds since the Unix epoch of the server clock.
+    pub deadline: i64,
+    pub remaining_ms: u64,
+}
+
+#[derive(Deserialize, Debug)]
+#[cfg_attr(
+    test,
+    derive(PartialEq, Default, serde::Serialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum JoinSessionPermissionResponse {
+    #[cfg_attr(test, default)]
+    Accept,
+    Reject,
+}
+
+impl MessageWithAck for JoinSessionPermissionRequest {
+    type Ack = JoinSessionPermissionResponse;
+}
+
+// TODO: Refactor this command once we know what is the preferred way clients should receive
+// vibration data
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq, schemars::JsonSchema))]
+pub struct VibrateCmd {
+    pub value: f32,
+    /// When the hub should run the command, in milliseconds since the Unix epoch.
+    ///
+    /// Controllers send it with their clock and the server converts it to the hub's clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execute_at: Option<i64>,
+    /// Asks for the command to be acknowledged by the hub.
+    ///
+    /// Sequence numbers grow with every command of a session. The server answers replayed ones
+    /// itself, but relays a command again when the hub didn't acknowledge it in time, so hubs
+    /// must not apply a sequence number twice and acknowledge it again instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// How long to wait for the hub to acknowledge the command, capped by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl MessageWithAck for VibrateCmd {
+    type Ack = CommandAck;
+}
+
+/// Answer of a hub to an acknowledged command.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(
+    test,
+    derive(PartialEq, Default, serde::Serialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum CommandAck {
+    #[cfg_attr(test, default)]
+    Applied,
+    Rejected,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum CommandStatus {
+    Applied,
+    Rejected,
+    TimedOut,
+    NotInSession,
+}
+
+/// Acknowledgment sent to controllers for commands with a sequence number.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct CommandResult {
+    pub seq: u64,
+    pub status: CommandStatus,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ControllerErrorKind {
+    Permissions,
+    VibrateCmdSendError,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct ControllerErrorMsg {
+    kind: ControllerErrorKind,
+    message: String,
+}
+
+impl ControllerErrorMsg {
+    pub fn new(kind: ControllerErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        actors::controller::{ControllerErrorKind, ControllerErrorMsg},
+        socket::encoding::assert_msgpack_round_trip,
+    };
+
+    use super::{
+        CancelJoinErrorKind, CancelJoinResponse, CommandAck, CommandResult, CommandStatus,
+        HubPresence, HubsOnlineErrorKind, HubsOnlineResponse, JoinPending, JoinSessionErrorKind,
+        JoinSessionPermissionRequest, JoinSessionPermissionResponse, JoinSessionRequest,
+        JoinSessionResponse, ResolveRoomErrorKind, ResolveRoomResponse, VibrateCmd,
+    };
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_serialize_join_session_request() {
+        let request = JoinSessionRequest {
+            session_id: Uuid::nil(),
+            message: "hello world".into(),
+            invitation: None,
+        };
+
+        let serialized = format!(
+            r#"{{"message":"hello world","session_id":"{}"}}"#,
+            Uuid::nil()
+        );
+
+        assert_eq!(
+            serde_json::from_str::<JoinSessionRequest>(&serialized).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn test_serialize_join_session_ok_response() {
+        let response = JoinSessionResponse::Ok {};
+        assert_eq!(json!(response).to_string(), r#"{"type":"ok"}"#);
+    }
+
+    #[test]
+    fn test_serialize_join_session_err_response() {
+        let response = JoinSessionResponse::Error {
+            kind: JoinSessionErrorKind::SessionFull,
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"kind":"session_full","type":"error"}"#
+        )
+    }
+
+    #[test]
+    fn test_serialize_resolve_room_response() {
+        let response = ResolveRoomResponse::Ok {
+            session_id: Uuid::nil(),
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            format!(r#"{{"session_id":"{}","type":"ok"}}"#, Uuid::nil())
+        );
+        let response = ResolveRoomResponse::Error {
+            kind: ResolveRoomErrorKind::RoomNotFound,
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"kind":"room_not_found","type":"error"}"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_cancel_join_response() {
+        assert_eq!(
+            json!(CancelJoinResponse::Ok {}).to_string(),
+            r#"{"type":"ok"}"#
+        );
+        let response = CancelJoinResponse::Error {
+            kind: CancelJoinErrorKind::NoPendingRequest,
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"kind":"no_pending_request","type":"error"}"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_join_session_permission_request() {
+        let response = JoinSessionPermissionRequest {
+            message: "hello!".into(),
+            deadline: 1000,
+            timeout_ms: 500,
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"deadline":1000,"message":"hello!","timeout_ms":500}"#
+        )
+    }
+
+    #[test]
+    fn test_serialize_join_pending() {
+        let pending = JoinPending {
+            deadline: 1000,
+            remaining_ms: 500,
+        };
+        assert_eq!(
+            json!(pending).to_string(),
+            r#"{"deadline":1000,"remaining_ms":500}"#
+        )
+    }
+
+    #[test]
+    fn test_deserialize_join_session_permission_response() {
+        let serialized = r#"{"type":"accept"}"#;
+        assert_eq!(
+            serde_json::from_str::<JoinSessionPermissionResponse>(serialized).unwrap(),
+            JoinSessionPermissionResponse::Accept
+        );
+        let serialized = r#"{"type":"reject"}"#;
+        assert_eq!(
+            serde_json::from_str::<JoinSessionPermissionResponse>(serialized).unwrap(),
+            JoinSessionPermissionResponse::Reject
+        );
+    }
+
+    #[test]
+    fn test_serialize_vibrate_command() {
+        let command = VibrateCmd {
+            value: 12.0,
+            execute_at: None,
+            seq: None,
+            timeout_ms: None,
+        };
+        assert_eq!(json!(command).to_string(), r#"{"value":12.0}"#);
+    }
+
+    #[test]
+    fn test_serialize_timed_vibrate_command() {
+        let command = VibrateCmd {
+            value: 12.0,
+            execute_at: Some(1000),
+            seq: None,
+            timeout_ms: None,
+        };
+        assert_eq!(
+            json!(command).to_string(),
+            r#"{"execute_at":1000,"value":12.0}"#
+        );
+    }
+
+    #[test]
+    fn test_deserialize_command_ack() {
+        assert_eq!(
+            serde_json::from_str::<CommandAck>(r#"{"type":"applied"}"#).unwrap(),
+            CommandAck::Applied
+        );
+        assert_eq!(
+            serde_json::from_str::<CommandAck>(r#"{"type":"rejected"}"#).unwrap(),
+            CommandAck::Rejected
+        );
+    }
+
+    #[test]
+    fn test_serialize_command_result() {
+        let result = CommandResult {
+            seq: 7,
+            status: CommandStatus::TimedOut,
+        };
+        assert_eq!(
+            json!(result).to_string(),
+            r#"{"seq":7,"status":"timed_out"}"#
+        );
+    }
+
+    #[test]
+    fn test_serialize_controller_error_msg() {
+        let msg = ControllerErrorMsg::new(ControllerErrorKind::Permissions, "bro no");
+        assert_eq!(
+            json!(msg).to_string(),
+            r#"{"kind":"permissions","message":"bro no"}"#
+        );
+    }
+
+    #[test]
+    fn messages_round_trip_through_msgpack() {
+        assert_msgpack_round_trip(JoinSessionRequest {
+            session_id: Uuid::new_v4(),
+            message: "hello world".into(),
+            invitation: None,
+        });
+        assert_msgpack_round_trip(JoinSessionResponse::Ok {});
+        for kind in [
+            JoinSessionErrorKind::AlreadyInASession,
+            JoinSessionErrorKind::SessionNotFound,
+            JoinSessionErrorKind::SessionFull,
+            JoinSessionErrorKind::ServerError,
+            JoinSessionErrorKind::HubResponseTimeout,
+            JoinSessionErrorKind::Rejected,
+            JoinSessionErrorKind::JoinRequestPending,
+            JoinSessionErrorKind::Cancelled,
+        ] {
+            assert_msgpack_round_trip(JoinSessionResponse::with_err(kind));
+        }
+        assert_msgpack_round_trip(CancelJoinResponse::Ok {});
+        assert_msgpack_round_trip(HubsOnlineResponse::Ok {
+            hubs: vec![HubPresence {
+                hub_id: "hub".into(),
+                online: true,
+            }],
+        });
+        assert_msgpack_round_trip(HubsOnlineResponse::Error {
+            kind: HubsOnlineErrorKind::TooManyHubs,
+        });
+        assert_msgpack_round_trip(HubPresence {
+            hub_id: "hub".into(),
+            online: false,
+        });
+        assert_msgpack_round_trip(ResolveRoomResponse::Ok {
+            session_id: Uuid::new_v4(),
+        });
+        assert_msgpack_round_trip(ResolveRoomResponse::Error {
+            kind: ResolveRoomErrorKind::ServerError,
+        });
+        assert_msgpack_round_trip(CancelJoinResponse::Error {
+            kind: CancelJoinErrorKind::NoPendingRequest,
+        });
+        assert_msgpack_round_trip(JoinSessionPermissionRequest {
+            message: "hello world".into(),
+            deadline: 1000,
+            timeout_ms: 500,
+        });
+        assert_msgpack_round_trip(JoinPending {
+            deadline: 1000,
+            remaining_ms: 500,
+        });
+        assert_msgpack_round_trip(JoinSessionPermissionResponse::Accept);
+        assert_msgpack_round_trip(JoinSessionPermissionResponse::Reject);
+        assert_msgpack_round_trip(VibrateCmd {
+            value: 0.5,
+            execute_at: None,
+            seq: None,
+            timeout_ms: None,
+        });
+        assert_msgpack_round_trip(VibrateCmd {
+            value: 0.5,
+            execute_at: Some(1000),
+            seq: Some(1),
+            timeout_ms: Some(500),
+        });
+        assert_msgpack_round_trip(CommandAck::Applied);
+        assert_msgpack_round_trip(CommandAck::Rejected);
+        for status in [
+            CommandStatus::Applied,
+            CommandStatus::Rejected,
+            CommandStatus::TimedOut,
+            CommandStatus::NotInSession,
+        ] {
+            assert_msgpack_round_trip(CommandResult { seq: 1, status });
+        }
+        assert_msgpack_round_trip(ControllerErrorMsg::new(
+            ControllerErrorKind::Permissions,
+            "bro no",
+        ));
+        assert_msgpack_round_trip(ControllerErrorMsg::new(
+            ControllerErrorKind::VibrateCmdSendError,
+            "bro no",
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+//! Events clients can send, shared by the socket.io and JSON-RPC transports.
+//!
+//! Each role has a table mapping the name of its events to their handler. Handlers take the
+//! event data as JSON and answer with the acknowledgment, so the same table registers the
+//! socket.io events and resolves the JSON-RPC methods.
+
+use super::tenant::TenantState;
+use crate::socket::{
+    adapters::{
+        jsonrpc::{self, ErrorObject, PeerId},
+        local::{self, Ack, Payload},
+    },
+    port::ClientSocket,
+};
+use futures_util::future::BoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use socketioxide::{extract::SocketRef, socket::Sid};
+use std::sync::Arc;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+/// Client that sent an event, through either transport.
+pub trait Caller: Send + Sync + 'static {
+    type Socket: ClientSocket<StoreItem = Uuid> + Send + Sync + 'static;
+    /// Finds the client again later, without keeping its connection alive.
+    type Id: Copy + Send + Sync + 'static;
+
+    fn id(&self) -> Self::Id;
+
+    fn socket<T, E>(self, state: &TenantState<T, E>) -> Self::Socket;
+
+    /// Socket of the client, `None` once it has disconnected.
+    fn socket_of<T, E>(id: Self::Id, state: &TenantState<T, E>) -> Option<Self::Socket>;
+}
+
+impl Caller for SocketRef {
+    type Socket = local::ClientSocketImpl;
+    type Id = Sid;
+
+    fn id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn socket<T, E>(self, state: &TenantState<T, E>) -> Self::Socket {
+        state.local_socket(self)
+    }
+
+    fn socket_of<T, E>(id: Self::Id, state: &TenantState<T, E>) -> Option<Self::Socket> {
+        state.local_socket_of(id)
+    }
+}
+
+impl Caller for PeerId {
+    type Socket = jsonrpc::ClientSocketImpl;
+    type Id = PeerId;
+
+    fn id(&self) -> Self::Id {
+        *self
+    }
+
+    fn socket<T, E>(self, state: &TenantState<T, E>) -> Self::Socket {
+        state.client_socket(self)
+    }
+
+    fn socket_of<T, E>(id: Self::Id, state: &TenantState<T, E>) -> Option<Self::Socket> {
+        state
+            .peers
+            .is_connected(id)
+            .then(|| state.client_socket(id))
+    }
+}
+
+/// Acknowledgment of an event.
+pub struct Reply {
+    pub result: Value,
+    /// Started once the client got the result, so it knows about what it's told after.
+    pub then: Option<BoxFuture<'static, ()>>,
+}
+
+impl Reply {
+    pub fn new(result: Value) -> Self {
+        Self { result, then: None }
+    }
+
+    pub fn then(mut self, task: BoxFuture<'static, ()>) -> Self {
+        self.then = Some(task);
+        self
+    }
+}
+
+pub type Handler<T, E, C> =
+    fn(C, Value, Arc<TenantState<T, E>>) -> BoxFuture<'static, Result<Reply, ErrorObject>>;
+
+/// Handlers of the events of a role, by event name.
+pub type Handlers<T, E, C> = Vec<(&'static str, Handler<T, E, C>)>;
+
+/// Entry of a table of [`Handlers`].
+pub fn on<T, E, C>(
+    event: &'static str,
+    handler: Handler<T, E, C>,
+) -> (&'static str, Handler<T, E, C>) {
+    (event, handler)
+}
+
+/// Reads the data of an event, a mismatch is reported as invalid JSON-RPC params.
+pub fn params<P: DeserializeOwned>(params: Value) -> Result<P, ErrorObject> {
+    serde_json::from_value(params).map_err(ErrorObject::invalid_params)
+}
+
+/// Registers the handlers as socket.io events of the socket.
+pub fn register<T, E>(
+    socket: &SocketRef,
+    handlers: Handlers<T, E, SocketRef>,
+    state: &Arc<TenantState<T, E>>,
+) where
+    T: Send + Sync + 'static,
+    E: Send + Sync + 'static,
+{
+    for (event, handler) in handlers {
+        let state = state.clone();
+        socket.on(
+            event,
+            move |socket: SocketRef, Payload(data): Payload<Value>, ack: Ack| async move {
+                let reply = match handler(socket, data, state).await {
+                    Ok(reply) => reply,
+                    Err(error) => {
+                        warn!(
+                            event,
+                            error = error.message,
+                            "Client sent invalid event data"
+                        );
+                        return;
+                    }
+                };
+                if let Err(error) = ack.send(reply.result) {
+                    error!(%error, "Failed to send acknowledgment to client");
+                }
+                if let Some(task) = reply.then {
+                    tokio::spawn(task);
+                }
+            },
+        );
+    }
+}
+
+/// Handles a JSON-RPC request of a peer and answers it if it has an id.
+pub async fn call<T, E>(
+    handlers: Handlers<T, E, PeerId>,
+    method: &str,
+    params: Value,
+    id: Option<Value>,
+    peer: PeerId,
+    state: Arc<TenantState<T, E>>,
+) {
+    let handler = handlers
+        .into_iter()
+        .find(|(event, _)| *event == method)
+        .map(|(_, handler)| handler);
+    let Some(handler) = handler else {
+        if let Some(id) = id {
+            state
+                .peers
+                .respond(peer, id, Err(ErrorObject::method_not_found()));
+        }
+        return;
+    };
+
+    let (result, then) = match handler(peer, params, state.clone()).await {
+        Ok(reply) => (Ok(reply.result), reply.then),
+        Err(error) => (Err(error), None),
+    };
+    if let Some(id) = id {
+        state.peers.respond(peer, id, result);
+    }
+    if let Some(task) = then {
+        tokio::spawn(task);
+    }
+}
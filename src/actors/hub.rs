@@ -1,52 +1,402 @@
 mod handlers;
-mod messages;
+pub mod messages;
 
-use crate::{sessions::port::SessionStore, socket::adapters::local::ClientSocketImpl};
-use handlers::{on_disconnect, on_start_session};
-use socketioxide::extract::{AckSender, SocketRef, State};
-use tracing::error;
+use crate::{
+    actors::{
+        dispatch::{self, on, params, Caller, Handlers, Reply},
+        latency, presence, recording,
+        tenant::TenantState,
+        trust, Role,
+    },
+    configuration::Config,
+    events::port::EventSink,
+    sessions::{activity::SessionActivity, port::SessionStore, recording::SessionRecordings},
+    socket::{adapters::jsonrpc::ErrorObject, port::ClientSocket},
+};
+use futures_util::FutureExt;
+pub use handlers::{hub_room, DeviceStatusLimiter};
+use handlers::{
+    on_device_status, on_disconnect, on_heartbeat, on_join_attempts, on_release_room,
+    on_start_replay, on_start_session, on_trust_controller, on_trusted_controllers,
+    on_untrust_controller, Heartbeat,
+};
+use messages::{
+    event_names, DeviceStatus, JoinAttemptsRequest, ReleaseRoomRequest, StartReplayRequest,
+    StartSessionRequest, StartSessionResponse, TrustControllerRequest,
+};
+use serde_json::{json, Value};
+use socketioxide::extract::SocketRef;
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
 
-pub fn on_connect<T>(socket: SocketRef)
+/// Events hubs can send, through either transport.
+pub fn handlers<T, E, C>() -> Handlers<T, E, C>
 where
     T: SessionStore + 'static,
+    E: EventSink + 'static,
+    C: Caller,
 {
-    socket.on(
-        "start_session",
-        |socket: SocketRef, ack: AckSender, sessions: State<T>| async move {
-            if let Err(error) =
-                ack.send(on_start_session(ClientSocketImpl::from(socket), sessions.0).await)
-            {
-                error!(%error, "Failed to send acknowledgment to client.");
-            }
-        },
-    );
-    socket.on_disconnect(|socket: SocketRef, sessions: State<T>| async move {
-        on_disconnect(ClientSocketImpl::from(socket), sessions.0).await
-    });
+    vec![
+        on(event_names::START_SESSION, |caller, data, state| {
+            start_session(caller, data, state).boxed()
+        }),
+        on(event_names::START_REPLAY, |caller, data, state| {
+            start_replay(caller, data, state).boxed()
+        }),
+        on(
+            recording::messages::event_names::RECORDING_CONSENT,
+            |caller, data, state| recording::consent(caller, data, Role::Hub, state).boxed(),
+        ),
+        on(event_names::DEVICE_STATUS, |caller, data, state| {
+            device_status(caller, data, state).boxed()
+        }),
+        on(event_names::JOIN_ATTEMPTS, |caller, data, state| {
+            join_attempts(caller, data, state).boxed()
+        }),
+        on(event_names::RELEASE_ROOM, |caller, data, state| {
+            release_room(caller, data, state).boxed()
+        }),
+        on(event_names::TRUST_CONTROLLER, |caller, data, state| {
+            trust_controller(caller, data, state).boxed()
+        }),
+        on(event_names::UNTRUST_CONTROLLER, |caller, data, state| {
+            untrust_controller(caller, data, state).boxed()
+        }),
+        on(event_names::TRUSTED_CONTROLLERS, |caller, _, state| {
+            trusted_controllers(caller, state).boxed()
+        }),
+    ]
+}
+
+pub fn on_connect<T, E>(socket: SocketRef, state: Arc<TenantState<T, E>>)
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+{
+    dispatch::register(&socket, handlers(), &state);
+    socket.on_disconnect(move |socket: SocketRef| async move { disconnect(socket, &state).await });
+}
+
+/// Cleans up after a hub connected through either transport.
+pub async fn disconnect<T, E, C>(caller: C, state: &TenantState<T, E>)
+where
+    T: SessionStore,
+    E: EventSink,
+    C: Caller,
+{
+    let socket = caller.socket(state);
+    presence::forget(&socket, Role::Hub, &state.sessions).await;
+    trust::go_offline(&socket, Role::Hub, &state.sessions).await;
+    on_disconnect(
+        socket,
+        &state.sessions,
+        &state.activity,
+        &state.recordings,
+        &state.events,
+    )
+    .await
+}
+
+async fn start_session<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+    C: Caller,
+{
+    let request: Option<StartSessionRequest> = params(data)?;
+    let id = caller.id();
+    let response = on_start_session(
+        caller.socket(&state),
+        request.unwrap_or_default(),
+        &state.sessions,
+        &state.activity,
+        &state.events,
+        &state.config,
+    )
+    .await;
+
+    let reply = Reply::new(json!(response));
+    let StartSessionResponse::Ok { session_id, .. } = response else {
+        return Ok(reply);
+    };
+    Ok(reply.then(
+        async move {
+            let socket = || C::socket_of(id, &state);
+            tokio::join!(
+                latency::monitor(socket, session_id, Role::Hub, &state.latency, &state.config,),
+                keep_alive(
+                    socket,
+                    session_id,
+                    &state.sessions,
+                    &state.activity,
+                    &state.recordings,
+                    &state.events,
+                    &state.config,
+                )
+            );
+        }
+        .boxed(),
+    ))
+}
+
+async fn start_replay<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+    C: Caller,
+{
+    let request: StartReplayRequest = params(data)?;
+    let id = caller.id();
+    let result = on_start_replay(
+        caller.socket(&state),
+        request,
+        &state.sessions,
+        &state.activity,
+        &state.events,
+    )
+    .await;
+
+    let (session_id, recording) = match result {
+        Ok(started) => started,
+        Err(kind) => return Ok(Reply::new(json!(StartSessionResponse::error(kind)))),
+    };
+
+    // Acknowledged first so the hub knows about the session before getting commands
+    let reply = Reply::new(json!(StartSessionResponse::Ok {
+        session_id,
+        invitation: None,
+    }));
+    Ok(reply.then(
+        async move {
+            let socket = || C::socket_of(id, &state);
+            tokio::join!(
+                recording::play(socket, session_id, recording, &state.activity),
+                keep_alive(
+                    socket,
+                    session_id,
+                    &state.sessions,
+                    &state.activity,
+                    &state.recordings,
+                    &state.events,
+                    &state.config,
+                )
+            );
+        }
+        .boxed(),
+    ))
+}
+
+async fn device_status<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let status: DeviceStatus = params(data)?;
+    let response = on_device_status(
+        caller.socket(&state),
+        status,
+        &state.sessions,
+        &state.device_status_limiter,
+        Duration::from_millis(state.config.hub.device_status_min_interval_ms),
+    )
+    .await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn join_attempts<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let request: JoinAttemptsRequest = params(data)?;
+    let response = on_join_attempts(caller.socket(&state), request, &state.sessions).await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn release_room<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let request: ReleaseRoomRequest = params(data)?;
+    let response = on_release_room(caller.socket(&state), request, &state.sessions).await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn trust_controller<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let request: TrustControllerRequest = params(data)?;
+    let response = on_trust_controller(caller.socket(&state), request, &state.sessions).await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn untrust_controller<T, E, C>(
+    caller: C,
+    data: Value,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let request: TrustControllerRequest = params(data)?;
+    let response = on_untrust_controller(caller.socket(&state), request, &state.sessions).await;
+    Ok(Reply::new(json!(response)))
+}
+
+async fn trusted_controllers<T, E, C>(
+    caller: C,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    T: SessionStore,
+    C: Caller,
+{
+    let response = on_trusted_controllers(caller.socket(&state), &state.sessions).await;
+    Ok(Reply::new(json!(response)))
+}
+
+/// Periodically refreshes the session while the hub that started it is still in it.
+///
+/// `socket` returns the hub's socket, or `None` once it has disconnected.
+async fn keep_alive<S, T, E>(
+    socket: impl Fn() -> Option<S>,
+    session_id: Uuid,
+    sessions: &T,
+    activity: &SessionActivity,
+    recordings: &SessionRecordings,
+    events: &E,
+    config: &Config,
+) where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+    E: EventSink,
+{
+    let idle_timeout = config.session_idle_timeout.map(Duration::from_secs);
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(config.session_heartbeat_interval));
+    // The first tick completes immediately and the session has just been created
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let Some(socket) = socket() else {
+            break;
+        };
+        let heartbeat = on_heartbeat(
+            socket,
+            session_id,
+            sessions,
+            activity,
+            recordings,
+            events,
+            idle_timeout,
+        )
+        .await;
+        if matches!(heartbeat, Heartbeat::Stop) {
+            break;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{on_disconnect, on_start_session};
+    use super::{
+        on_device_status, on_disconnect, on_heartbeat, on_join_attempts, on_release_room,
+        on_start_replay, on_start_session, on_trust_controller, on_trusted_controllers,
+        DeviceStatusLimiter, Heartbeat,
+    };
     use crate::{
-        actors::hub::messages::{StartSessionError, StartSessionResponse},
-        sessions::port::MockSessionStore,
+        actors::{
+            hub::messages::{
+                DeviceStatus, DeviceStatusError, DeviceStatusResponse, JoinAttemptsRequest,
+                JoinAttemptsResponse, ReleaseRoomError, ReleaseRoomRequest, ReleaseRoomResponse,
+                SessionSchedule, StartReplayRequest, StartSessionError, StartSessionRequest,
+                StartSessionResponse, TrustControllerRequest, TrustedController,
+                TrustedControllersError, TrustedControllersResponse,
+            },
+            latency::unix_millis,
+            protocol::ProtocolVersion,
+            Role,
+        },
+        configuration::Config,
+        events::port::{FinishReason, MockEventSink, SessionEventKind},
+        sessions::{
+            activity::SessionActivity,
+            audit::{JoinAttempt, JoinAttemptOutcome},
+            policy::JoinPolicy,
+            port::{
+                CreateSessionError, MockSessionStore, RoomClaim, SessionState, TouchSessionError,
+            },
+            recording::{RecordedCommand, Recording, SessionRecordings},
+            schedule::SessionWindow,
+        },
         socket::port::MockClientSocket,
     };
+    use anyhow::anyhow;
+    use futures_util::FutureExt;
     use mockall::predicate::eq;
+    use std::time::Duration;
     use test_context::{test_context, AsyncTestContext};
     use uuid::Uuid;
 
     struct Context {
         client_socket: MockClientSocket,
         session_store: MockSessionStore,
+        activity: SessionActivity,
+        events: MockEventSink,
+        limiter: DeviceStatusLimiter,
+        recordings: SessionRecordings,
+    }
+
+    fn battery_status(battery: f64) -> DeviceStatus {
+        DeviceStatus {
+            device_id: "toy".into(),
+            battery: Some(battery),
+            ..Default::default()
+        }
     }
 
     impl AsyncTestContext for Context {
         async fn setup() -> Self {
+            let mut client_socket = MockClientSocket::new();
+            client_socket
+                .expect_protocol_version()
+                .return_const(ProtocolVersion::V1);
             Self {
-                client_socket: MockClientSocket::new(),
+                client_socket,
                 session_store: MockSessionStore::new(),
+                activity: SessionActivity::default(),
+                events: MockEventSink::new(),
+                limiter: DeviceStatusLimiter::default(),
+                recordings: SessionRecordings::default(),
             }
         }
     }
@@ -70,15 +420,107 @@ mod tests {
             .times(1)
             .return_const(Ok(()));
 
+        ctx.client_socket
+            .expect_join()
+            .with(eq(format!("{}:hub", Uuid::nil())))
+            .times(1)
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+
+        ctx.session_store
+            .expect_set_session_owner()
+            .with(eq(Uuid::nil()), eq("hub".to_string()))
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+
         ctx.client_socket
             .expect_store_value()
             .with(eq(Uuid::nil()))
             .times(1)
             .return_const(());
 
-        let result = on_start_session(ctx.client_socket, &ctx.session_store).await;
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| event.kind == SessionEventKind::SessionStarted)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        ctx.session_store
+            .expect_set_join_policy()
+            .with(eq(Uuid::nil()), eq(JoinPolicy::Open))
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+
+        ctx.session_store
+            .expect_set_join_request_timeout()
+            .with(eq(Uuid::nil()), eq(Duration::from_secs(30)))
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+
+        ctx.session_store
+            .expect_claim_room()
+            .withf(|room, hub_id, _| room == "hub-room" && hub_id == "hub")
+            .times(1)
+            .returning(|_, _, _| async { Ok(RoomClaim::Claimed) }.boxed());
+
+        ctx.session_store
+            .expect_set_room_session()
+            .with(eq("hub-room".to_string()), eq(Uuid::nil()))
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
 
-        assert!(matches!(result, StartSessionResponse::Ok { session_id: _ }));
+        let now = unix_millis();
+        let window = SessionWindow {
+            starts_at: now,
+            ends_at: now + 60_000,
+        };
+        ctx.session_store
+            .expect_set_session_window()
+            .with(eq(Uuid::nil()), eq(window))
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+
+        ctx.session_store
+            .expect_create_invitation()
+            .withf(|_, invitation, ttl| {
+                invitation.session_id == Uuid::nil()
+                    && invitation.controller_id == "alice"
+                    && *ttl <= Duration::from_secs(60)
+            })
+            .times(1)
+            .returning(|_, _, _| async { Ok(()) }.boxed());
+
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest {
+                join_policy: JoinPolicy::Open,
+                join_request_timeout: Some(30),
+                room: Some("hub-room".into()),
+                schedule: Some(SessionSchedule {
+                    starts_at: window.starts_at,
+                    ends_at: window.ends_at,
+                    controller_id: "alice".into(),
+                }),
+                resume: None,
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &Config::load(),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            StartSessionResponse::Ok {
+                invitation: Some(_),
+                ..
+            }
+        ));
+        assert!(ctx.activity.idle_for(Uuid::nil()).is_some());
     }
 
     #[test_context(Context, skip_teardown)]
@@ -92,8 +534,17 @@ mod tests {
         ctx.session_store.expect_create_session().never();
         ctx.client_socket.expect_join().never();
         ctx.client_socket.expect_store_value().never();
+        ctx.events.expect_publish().never();
 
-        let result = on_start_session(ctx.client_socket, &ctx.session_store).await;
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest::default(),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &Config::load(),
+        )
+        .await;
 
         assert_eq!(
             result,
@@ -101,6 +552,287 @@ mod tests {
         );
     }
 
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn does_not_create_a_session_with_an_invalid_join_policy(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store.expect_create_session().never();
+        ctx.events.expect_publish().never();
+
+        let join_policy = JoinPolicy::Allowlist {
+            controllers: ["x".repeat(65)].into(),
+        };
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest {
+                join_policy,
+                ..Default::default()
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &Config::load(),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            StartSessionResponse::error(StartSessionError::InvalidJoinPolicy)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn does_not_create_a_session_with_a_join_request_timeout_out_of_bounds(mut ctx: Context) {
+        let config = Config::load();
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store.expect_create_session().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest {
+                join_request_timeout: Some(config.controller.max_session_join_request_timeout + 1),
+                ..Default::default()
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &config,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            StartSessionResponse::error(StartSessionError::InvalidJoinRequestTimeout)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn does_not_create_a_session_scheduled_in_the_past(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store.expect_create_session().never();
+
+        let now = unix_millis();
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest {
+                schedule: Some(SessionSchedule {
+                    starts_at: now - 120_000,
+                    ends_at: now - 60_000,
+                    controller_id: "alice".into(),
+                }),
+                ..Default::default()
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &Config::load(),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            StartSessionResponse::error(StartSessionError::InvalidSchedule)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn does_not_create_a_session_in_a_room_owned_by_another_hub(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+
+        ctx.session_store
+            .expect_claim_room()
+            .withf(|room, hub_id, _| room == "hub-room" && hub_id == "hub")
+            .times(1)
+            .returning(|_, _, _| async { Ok(RoomClaim::Taken) }.boxed());
+
+        ctx.session_store.expect_create_session().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest {
+                room: Some("hub-room".into()),
+                ..Default::default()
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &Config::load(),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            StartSessionResponse::error(StartSessionError::RoomTaken)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn gives_back_the_room_when_the_session_cannot_be_created(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+
+        ctx.session_store
+            .expect_claim_room()
+            .withf(|room, hub_id, _| room == "hub-room" && hub_id == "hub")
+            .times(1)
+            .returning(|_, _, _| async { Ok(RoomClaim::Claimed) }.boxed());
+
+        ctx.session_store
+            .expect_create_session()
+            .times(1)
+            .returning(|| {
+                async { Err(CreateSessionError::IoError(anyhow!("unavailable"))) }.boxed()
+            });
+
+        ctx.session_store
+            .expect_release_room()
+            .with(eq("hub-room".to_string()), eq("hub".to_string()))
+            .times(1)
+            .returning(|_, _| async { Ok(true) }.boxed());
+
+        ctx.session_store.expect_delete_session().never();
+        ctx.client_socket.expect_store_value().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_start_session(
+            ctx.client_socket,
+            StartSessionRequest {
+                room: Some("hub-room".into()),
+                ..Default::default()
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+            &Config::load(),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            StartSessionResponse::error(StartSessionError::ServerError)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn hubs_can_only_release_their_own_rooms(mut ctx: Context) {
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+
+        ctx.session_store
+            .expect_release_room()
+            .with(eq("other-room".to_string()), eq("hub".to_string()))
+            .times(1)
+            .returning(|_, _| async { Ok(false) }.boxed());
+
+        let result = on_release_room(
+            ctx.client_socket,
+            ReleaseRoomRequest {
+                room: "other-room".into(),
+            },
+            &ctx.session_store,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            ReleaseRoomResponse::error(ReleaseRoomError::RoomNotOwned)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn hubs_trust_controllers_by_client_id(mut ctx: Context) {
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+        ctx.session_store
+            .expect_online()
+            .with(eq(Role::Controller), eq(vec!["alice".to_string()]))
+            .times(1)
+            .returning(|_, ids| async move { Ok(ids.into_iter().collect()) }.boxed());
+
+        ctx.session_store
+            .expect_trusted_controllers()
+            .with(eq("hub".to_string()))
+            .times(1)
+            .returning(|_| async { Ok([].into()) }.boxed());
+        ctx.session_store
+            .expect_trust_controller()
+            .with(eq("hub".to_string()), eq("alice".to_string()))
+            .times(1)
+            .returning(|_, _| async { Ok(()) }.boxed());
+        ctx.session_store
+            .expect_trusted_controllers()
+            .times(1)
+            .returning(|_| async { Ok(["alice".to_string()].into()) }.boxed());
+
+        let result = on_trust_controller(
+            ctx.client_socket,
+            TrustControllerRequest {
+                controller_id: "alice".into(),
+            },
+            &ctx.session_store,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            TrustedControllersResponse::Ok {
+                controllers: vec![TrustedController {
+                    controller_id: "alice".into(),
+                    online: true,
+                }],
+            }
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn hubs_without_a_client_id_can_not_trust_controllers(mut ctx: Context) {
+        ctx.client_socket.expect_client_id().return_const(None);
+        ctx.session_store.expect_trusted_controllers().never();
+
+        let result = on_trusted_controllers(ctx.client_socket, &ctx.session_store).await;
+
+        assert_eq!(
+            result,
+            TrustedControllersResponse::error(TrustedControllersError::RequiresClientId)
+        );
+    }
+
     #[test_context(Context, skip_teardown)]
     #[tokio::test]
     async fn deletes_session_and_sends_message_on_disconnect_if_in_a_session(mut ctx: Context) {
@@ -109,6 +841,8 @@ mod tests {
             .times(1)
             .return_const(Some(Uuid::nil()));
 
+        ctx.client_socket.expect_client_id().return_const(None);
+
         ctx.client_socket
             .expect_emit_to_room()
             .times(1)
@@ -130,7 +864,25 @@ mod tests {
             .with(eq(Uuid::nil()))
             .returning(|_| Box::pin(async { Ok(()) }));
 
-        on_disconnect(ctx.client_socket, &ctx.session_store).await;
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::SessionFinished {
+                        reason: FinishReason::HubDisconnected,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        on_disconnect(
+            ctx.client_socket,
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+        )
+        .await;
     }
 
     #[test_context(Context, skip_teardown)]
@@ -144,7 +896,639 @@ mod tests {
         ctx.client_socket.expect_emit_to_room::<()>().never();
         ctx.client_socket.expect_remove_value().never();
         ctx.session_store.expect_delete_session().never();
+        ctx.events.expect_publish().never();
+
+        on_disconnect(
+            ctx.client_socket,
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+        )
+        .await;
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn heartbeat_refreshes_an_active_session(mut ctx: Context) {
+        ctx.activity.record(Uuid::nil());
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.session_store
+            .expect_touch()
+            .times(1)
+            .with(eq(Uuid::nil()))
+            .returning(|_| async { Ok(()) }.boxed());
+
+        ctx.session_store.expect_delete_session().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_heartbeat(
+            ctx.client_socket,
+            Uuid::nil(),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+            Some(Duration::from_secs(60)),
+        )
+        .await;
+
+        assert_eq!(result, Heartbeat::Continue);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn heartbeat_stops_once_the_hub_left_the_session(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store.expect_touch().never();
+        ctx.session_store.expect_delete_session().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_heartbeat(
+            ctx.client_socket,
+            Uuid::nil(),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+            None,
+        )
+        .await;
+
+        assert_eq!(result, Heartbeat::Stop);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn heartbeat_finishes_an_idle_session(mut ctx: Context) {
+        ctx.activity.record(Uuid::nil());
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.client_socket.expect_client_id().return_const(None);
+
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("session_finished".to_string()),
+                eq(()),
+            )
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_emit()
+            .times(1)
+            .with(eq("session_finished".to_string()), eq(()))
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_leave()
+            .times(1)
+            .with(eq(Uuid::nil().to_string()))
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_leave()
+            .times(1)
+            .with(eq(format!("{}:hub", Uuid::nil())))
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_remove_value()
+            .times(1)
+            .return_const(());
+
+        ctx.session_store.expect_touch().never();
+
+        ctx.session_store
+            .expect_session_window()
+            .returning(|_| async { Ok(None) }.boxed());
+
+        ctx.session_store
+            .expect_delete_session()
+            .times(1)
+            .with(eq(Uuid::nil()))
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::SessionFinished {
+                        reason: FinishReason::Idle,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let result = on_heartbeat(
+            ctx.client_socket,
+            Uuid::nil(),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+            Some(Duration::ZERO),
+        )
+        .await;
+
+        assert_eq!(result, Heartbeat::Stop);
+        assert!(ctx.activity.idle_for(Uuid::nil()).is_none());
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn heartbeat_keeps_an_idle_session_until_its_window_ends(mut ctx: Context) {
+        ctx.activity.record(Uuid::nil());
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.session_store
+            .expect_session_window()
+            .with(eq(Uuid::nil()))
+            .returning(|_| {
+                let window = SessionWindow {
+                    starts_at: unix_millis() + 60_000,
+                    ends_at: unix_millis() + 120_000,
+                };
+                async move { Ok(Some(window)) }.boxed()
+            });
+
+        ctx.session_store
+            .expect_touch()
+            .times(1)
+            .returning(|_| async { Ok(()) }.boxed());
+        ctx.session_store.expect_delete_session().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_heartbeat(
+            ctx.client_socket,
+            Uuid::nil(),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+            Some(Duration::ZERO),
+        )
+        .await;
+
+        assert_eq!(result, Heartbeat::Continue);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn heartbeat_finishes_a_session_that_expired(mut ctx: Context) {
+        ctx.activity.record(Uuid::nil());
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.client_socket.expect_client_id().return_const(None);
+
+        ctx.session_store
+            .expect_touch()
+            .times(1)
+            .returning(|id| async move { Err(TouchSessionError::UnknownSession(id)) }.boxed());
+
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("session_finished".to_string()),
+                eq(()),
+            )
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_emit()
+            .times(1)
+            .with(eq("session_finished".to_string()), eq(()))
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_leave()
+            .times(2)
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_remove_value()
+            .times(1)
+            .return_const(());
+
+        ctx.session_store
+            .expect_delete_session()
+            .times(1)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| {
+                event.kind
+                    == SessionEventKind::SessionFinished {
+                        reason: FinishReason::Expired,
+                    }
+            })
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let result = on_heartbeat(
+            ctx.client_socket,
+            Uuid::nil(),
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.recordings,
+            &ctx.events,
+            None,
+        )
+        .await;
+
+        assert_eq!(result, Heartbeat::Stop);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn caches_and_relays_device_statuses(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.session_store
+            .expect_set_device_status()
+            .times(1)
+            .with(
+                eq(Uuid::nil()),
+                eq("toy".to_string()),
+                eq(r#"{"device_id":"toy","battery":0.5}"#.to_string()),
+                eq(16),
+            )
+            .returning(|_, _, _, _| async { Ok(true) }.boxed());
+
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("device_status".to_string()),
+                eq(battery_status(0.5)),
+            )
+            .return_const(Ok(()));
+
+        let result = on_device_status(
+            ctx.client_socket,
+            battery_status(0.5),
+            &ctx.session_store,
+            &ctx.limiter,
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(result, DeviceStatusResponse::Ok {});
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn refuses_devices_over_the_limit_of_the_session(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.session_store
+            .expect_set_device_status()
+            .times(1)
+            .returning(|_, _, _, _| async { Ok(false) }.boxed());
+        ctx.client_socket
+            .expect_emit_to_room::<DeviceStatus>()
+            .never();
+
+        let result = on_device_status(
+            ctx.client_socket,
+            battery_status(0.5),
+            &ctx.session_store,
+            &ctx.limiter,
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            DeviceStatusResponse::error(DeviceStatusError::TooManyDevices)
+        );
+        assert!(ctx
+            .limiter
+            .allows(&(Uuid::nil(), "toy".into()), Duration::from_secs(60)));
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn rejects_invalid_device_statuses(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.session_store.expect_set_device_status().never();
+        ctx.client_socket
+            .expect_emit_to_room::<DeviceStatus>()
+            .never();
+
+        let result = on_device_status(
+            ctx.client_socket,
+            battery_status(2.0),
+            &ctx.session_store,
+            &ctx.limiter,
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            DeviceStatusResponse::error(DeviceStatusError::InvalidStatus)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn rate_limits_device_statuses(mut ctx: Context) {
+        ctx.limiter
+            .check((Uuid::nil(), "toy".into()), Duration::from_secs(60));
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.session_store.expect_set_device_status().never();
+        ctx.client_socket
+            .expect_emit_to_room::<DeviceStatus>()
+            .never();
+
+        let result = on_device_status(
+            ctx.client_socket,
+            battery_status(0.5),
+            &ctx.session_store,
+            &ctx.limiter,
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            DeviceStatusResponse::error(DeviceStatusError::RateLimited)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn ignores_device_statuses_outside_of_a_session(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store.expect_set_device_status().never();
+
+        let result = on_device_status(
+            ctx.client_socket,
+            battery_status(0.5),
+            &ctx.session_store,
+            &ctx.limiter,
+            Duration::from_secs(60),
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            DeviceStatusResponse::error(DeviceStatusError::NotInASession)
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn starts_a_session_that_can_not_be_joined_to_replay_a_recording(mut ctx: Context) {
+        let recording = Recording {
+            id: Uuid::nil(),
+            started_at: 1000,
+            commands: vec![RecordedCommand(0, 0.5)],
+            owner: Some("hub".into()),
+        };
+        let session_id = Uuid::new_v4();
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+
+        let stored = recording.clone();
+        ctx.session_store
+            .expect_recording()
+            .times(1)
+            .with(eq(Uuid::nil()))
+            .returning(move |_| {
+                let recording = stored.clone();
+                async move { Ok(Some(recording)) }.boxed()
+            });
+
+        ctx.session_store
+            .expect_create_session()
+            .times(1)
+            .returning(move || async move { Ok(session_id) }.boxed());
+
+        ctx.session_store
+            .expect_update_session_state()
+            .times(1)
+            .with(eq(session_id), eq(SessionState::InProgress))
+            .returning(|_, _| async { Ok(()) }.boxed());
+
+        ctx.client_socket
+            .expect_join()
+            .with(eq(session_id.to_string()))
+            .times(1)
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_join()
+            .with(eq(format!("{session_id}:hub")))
+            .times(1)
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_store_value()
+            .with(eq(session_id))
+            .times(1)
+            .return_const(());
+
+        ctx.events
+            .expect_publish()
+            .times(1)
+            .withf(|event| event.kind == SessionEventKind::SessionStarted)
+            .returning(|_| Box::pin(async { Ok(()) }));
+
+        let result = on_start_replay(
+            ctx.client_socket,
+            StartReplayRequest {
+                recording_id: Uuid::nil(),
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), (session_id, recording));
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn hubs_can_not_replay_the_recordings_of_other_hubs(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("other-hub".to_string()));
+
+        ctx.session_store
+            .expect_recording()
+            .times(1)
+            .returning(|_| {
+                async {
+                    Ok(Some(Recording {
+                        id: Uuid::nil(),
+                        started_at: 1000,
+                        commands: vec![RecordedCommand(0, 0.5)],
+                        owner: Some("hub".into()),
+                    }))
+                }
+                .boxed()
+            });
+
+        ctx.session_store.expect_create_session().never();
+
+        let result = on_start_replay(
+            ctx.client_socket,
+            StartReplayRequest {
+                recording_id: Uuid::nil(),
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err(), StartSessionError::RecordingNotFound);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn can_not_replay_an_unknown_recording(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.session_store
+            .expect_recording()
+            .times(1)
+            .returning(|_| async { Ok(None) }.boxed());
+
+        ctx.session_store.expect_create_session().never();
+        ctx.events.expect_publish().never();
+
+        let result = on_start_replay(
+            ctx.client_socket,
+            StartReplayRequest {
+                recording_id: Uuid::nil(),
+            },
+            &ctx.session_store,
+            &ctx.activity,
+            &ctx.events,
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err(), StartSessionError::RecordingNotFound);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn returns_the_latest_join_attempts_on_the_sessions_of_the_hub(mut ctx: Context) {
+        let attempt = JoinAttempt::new(Uuid::nil(), None, "hi", JoinAttemptOutcome::Rejected);
+        let attempts = vec![attempt.clone()];
+
+        ctx.client_socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+        ctx.client_socket
+            .expect_get_stored_value()
+            .return_const(None);
+
+        ctx.session_store
+            .expect_join_attempts()
+            .with(eq("hub:hub".to_string()), eq(200))
+            .times(1)
+            .returning(move |_, _| {
+                let attempts = attempts.clone();
+                async { Ok(attempts) }.boxed()
+            });
+
+        let result = on_join_attempts(
+            ctx.client_socket,
+            JoinAttemptsRequest { limit: Some(1000) },
+            &ctx.session_store,
+        )
+        .await;
+
+        assert_eq!(
+            result,
+            JoinAttemptsResponse::Ok {
+                attempts: vec![attempt]
+            }
+        );
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn hubs_without_client_id_only_see_attempts_on_their_session(mut ctx: Context) {
+        ctx.client_socket.expect_client_id().return_const(None);
+        ctx.client_socket
+            .expect_get_stored_value()
+            .return_const(None);
+        ctx.session_store.expect_join_attempts().never();
+
+        let result = on_join_attempts(
+            ctx.client_socket,
+            JoinAttemptsRequest::default(),
+            &ctx.session_store,
+        )
+        .await;
 
-        on_disconnect(ctx.client_socket, &ctx.session_store).await;
+        assert_eq!(result, JoinAttemptsResponse::Ok { attempts: vec![] });
     }
 }
@@ -1,53 +1,801 @@
 use super::messages::*;
-use crate::{sessions::port::SessionStore, socket::port::ClientSocket};
-use tracing::{debug, error};
+use crate::{
+    actors::{
+        latency::unix_millis, publish_event, recording::save_recording, Role, MAX_CLIENT_ID_LEN,
+    },
+    configuration::Config,
+    events::port::{EventSink, FinishReason, SessionEventKind},
+    sessions::{
+        activity::SessionActivity,
+        audit::audit_key,
+        policy::JoinPolicy,
+        port::{RoomClaim, SessionState, SessionStore, TouchSessionError},
+        rate_limit::RateLimiter,
+        recording::{Recording, SessionRecordings},
+        room::is_valid_room,
+        schedule::{invitation_token, Invitation, SessionWindow},
+    },
+    socket::port::ClientSocket,
+};
+use std::time::Duration;
+use tracing::{debug, error, warn};
 use uuid::Uuid;
 
-pub async fn on_start_session<T, S>(socket: S, sessions: &T) -> StartSessionResponse
+/// Most rooms a hub can own.
+const MAX_ROOMS_PER_HUB: usize = 10;
+
+/// Most controllers a hub can trust.
+const MAX_TRUSTED_CONTROLLERS: usize = 100;
+
+/// Join attempts returned when the hub doesn't ask for a number of them.
+const DEFAULT_JOIN_ATTEMPTS: usize = 50;
+const MAX_JOIN_ATTEMPTS: usize = 200;
+
+/// Most devices a session can report statuses for.
+const MAX_DEVICES_PER_SESSION: usize = 16;
+
+/// Limits the statuses each device of a session can send.
+///
+/// Sessions have a bounded number of devices, which bounds the statuses of each session.
+pub type DeviceStatusLimiter = RateLimiter<(Uuid, String)>;
+
+/// Room with only the hub of a session, for the commands it has to acknowledge.
+pub fn hub_room(session_id: Uuid) -> String {
+    format!("{session_id}:hub")
+}
+
+fn join_session<S>(socket: &S, session_id: Uuid) -> Result<(), S::Error>
+where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    socket.join(session_id.into())?;
+    socket.join(hub_room(session_id))
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum Heartbeat {
+    Continue,
+    Stop,
+}
+
+pub async fn on_start_session<T, S, E>(
+    socket: S,
+    request: StartSessionRequest,
+    sessions: &T,
+    activity: &SessionActivity,
+    events: &E,
+    config: &Config,
+) -> StartSessionResponse
 where
     S: ClientSocket<StoreItem = Uuid>,
     T: SessionStore,
+    E: EventSink,
 {
-    debug!("Received start_session command");
+    debug!(version = ?socket.protocol_version(), "Received start_session command");
 
     if socket.get_stored_value().is_some() {
         return StartSessionResponse::error(StartSessionError::AlreadyInASession);
     }
 
+    if let Some(session_id) = request.resume {
+        return resume_session(&socket, session_id, sessions, activity).await;
+    }
+
+    if !request.join_policy.is_valid() {
+        return StartSessionResponse::error(StartSessionError::InvalidJoinPolicy);
+    }
+
+    if request
+        .join_request_timeout
+        .is_some_and(|timeout| !config.controller.allows_join_request_timeout(timeout))
+    {
+        return StartSessionResponse::error(StartSessionError::InvalidJoinRequestTimeout);
+    }
+
+    if let Some(schedule) = &request.schedule {
+        if !schedule.window().is_valid(unix_millis())
+            || schedule.controller_id.is_empty()
+            || schedule.controller_id.len() > MAX_CLIENT_ID_LEN
+        {
+            return StartSessionResponse::error(StartSessionError::InvalidSchedule);
+        }
+    }
+
+    if let Some(room) = &request.room {
+        if !is_valid_room(room) {
+            return StartSessionResponse::error(StartSessionError::InvalidRoom);
+        }
+        // Rooms belong to an identity, which anonymous hubs lack
+        let Some(hub_id) = socket.client_id() else {
+            return StartSessionResponse::error(StartSessionError::RoomRequiresClientId);
+        };
+        match sessions
+            .claim_room(room.clone(), hub_id, MAX_ROOMS_PER_HUB)
+            .await
+        {
+            Ok(RoomClaim::Claimed) => {}
+            Ok(RoomClaim::Taken) => {
+                return StartSessionResponse::error(StartSessionError::RoomTaken)
+            }
+            Ok(RoomClaim::TooManyRooms) => {
+                return StartSessionResponse::error(StartSessionError::TooManyRooms)
+            }
+            Err(error) => {
+                error!(%error, "Failed to claim the room");
+                return StartSessionResponse::error(StartSessionError::ServerError);
+            }
+        }
+    }
+
+    // A failed start gives back the room it claimed
+    let room = request.room.clone();
+    let (session_id, invitation) = match set_up_session(&socket, request, sessions).await {
+        Ok(session) => session,
+        Err((session_id, kind)) => {
+            abandon_session(&socket, session_id, room, sessions).await;
+            return StartSessionResponse::error(kind);
+        }
+    };
+
+    // Join attempts are audited by hub, so it can see them across its sessions
+    if let Some(hub_id) = socket.client_id() {
+        if let Err(error) = sessions.set_session_owner(session_id, hub_id).await {
+            error!(%error, "Failed to store the session owner");
+        }
+    }
+
+    socket.store_value(session_id);
+    activity.record(session_id);
+
+    publish_event(
+        events,
+        session_id,
+        Role::Hub,
+        SessionEventKind::SessionStarted,
+    )
+    .await;
+
+    StartSessionResponse::Ok {
+        session_id,
+        invitation,
+    }
+}
+
+/// Stores a new session with the settings of the request and puts the hub in it. Failures come
+/// with the id of the session if it was already created.
+async fn set_up_session<T, S>(
+    socket: &S,
+    request: StartSessionRequest,
+    sessions: &T,
+) -> Result<(Uuid, Option<String>), (Option<Uuid>, StartSessionError)>
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
     let session_id = match sessions.create_session().await {
         Ok(session_id) => session_id,
         Err(error) => {
             error!(%error, "Failed to create session on session store");
-            return StartSessionResponse::error(StartSessionError::ServerError);
+            return Err((None, StartSessionError::ServerError));
         }
     };
 
-    if let Err(error) = socket.join(session_id.into()) {
+    // Sessions without a stored policy are manual
+    if request.join_policy != JoinPolicy::Manual {
+        if let Err(error) = sessions
+            .set_join_policy(session_id, request.join_policy)
+            .await
+        {
+            error!(%error, "Failed to store the join policy");
+            return Err((Some(session_id), StartSessionError::ServerError));
+        }
+    }
+
+    if let Some(timeout) = request.join_request_timeout {
+        if let Err(error) = sessions
+            .set_join_request_timeout(session_id, Duration::from_secs(timeout))
+            .await
+        {
+            error!(%error, "Failed to store the join request timeout");
+            return Err((Some(session_id), StartSessionError::ServerError));
+        }
+    }
+
+    let invitation = match request.schedule {
+        Some(schedule) => {
+            match schedule_session(
+                sessions,
+                session_id,
+                schedule.window(),
+                schedule.controller_id,
+            )
+            .await
+            {
+                Ok(token) => Some(token),
+                Err(kind) => return Err((Some(session_id), kind)),
+            }
+        }
+        None => None,
+    };
+
+    if let Some(room) = request.room {
+        if let Err(error) = sessions.set_room_session(room, session_id).await {
+            error!(%error, "Failed to point the room to the session");
+            return Err((Some(session_id), StartSessionError::ServerError));
+        }
+    }
+
+    if let Err(error) = join_session(socket, session_id) {
+        error!(%error, "Socket failed to join session");
+        return Err((Some(session_id), StartSessionError::ServerError));
+    }
+
+    Ok((session_id, invitation))
+}
+
+/// Undoes what a failed start left behind, the session it created and the room it claimed.
+async fn abandon_session<T, S>(
+    socket: &S,
+    session_id: Option<Uuid>,
+    room: Option<String>,
+    sessions: &T,
+) where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    if let Some(session_id) = session_id {
+        if let Err(error) = sessions.delete_session(session_id).await {
+            error!(%error, "Failed to delete session");
+        }
+    }
+    if let (Some(room), Some(hub_id)) = (room, socket.client_id()) {
+        if let Err(error) = sessions.release_room(room, hub_id).await {
+            error!(%error, "Failed to release the room");
+        }
+    }
+}
+
+/// Puts the hub back in a scheduled session it left, see [`on_disconnect`].
+async fn resume_session<T, S>(
+    socket: &S,
+    session_id: Uuid,
+    sessions: &T,
+    activity: &SessionActivity,
+) -> StartSessionResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    let Some(hub_id) = socket.client_id() else {
+        return StartSessionResponse::error(StartSessionError::SessionNotFound);
+    };
+    match sessions.session_owner(session_id).await {
+        Ok(Some(owner)) if owner == hub_id => {}
+        Ok(_) => return StartSessionResponse::error(StartSessionError::SessionNotFound),
+        Err(error) => {
+            error!(%error, "Failed to get the session owner");
+            return StartSessionResponse::error(StartSessionError::ServerError);
+        }
+    }
+    // Only scheduled sessions outlive their hub, the others may be going on elsewhere
+    match sessions.session_window(session_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return StartSessionResponse::error(StartSessionError::SessionNotFound),
+        Err(error) => {
+            error!(%error, "Failed to get the session window");
+            return StartSessionResponse::error(StartSessionError::ServerError);
+        }
+    }
+
+    if let Err(error) = join_session(socket, session_id) {
         error!(%error, "Socket failed to join session");
         return StartSessionResponse::error(StartSessionError::ServerError);
     }
+    socket.store_value(session_id);
+    activity.record(session_id);
+
+    StartSessionResponse::Ok {
+        session_id,
+        invitation: None,
+    }
+}
+
+/// Whether a session is scheduled and its window hasn't ended, which keeps it going while its
+/// hub is idle or away.
+async fn is_scheduled_ahead<T>(sessions: &T, session_id: Uuid) -> bool
+where
+    T: SessionStore,
+{
+    match sessions.session_window(session_id).await {
+        Ok(window) => window.is_some_and(|window| unix_millis() < window.ends_at),
+        Err(error) => {
+            error!(%error, "Failed to get the session window");
+            false
+        }
+    }
+}
+
+/// Limits a session to its window and invites a controller to it, returning the token of the
+/// invitation.
+async fn schedule_session<T>(
+    sessions: &T,
+    session_id: Uuid,
+    window: SessionWindow,
+    controller_id: String,
+) -> Result<String, StartSessionError>
+where
+    T: SessionStore,
+{
+    if let Err(error) = sessions.set_session_window(session_id, window).await {
+        error!(%error, "Failed to store the session window");
+        return Err(StartSessionError::ServerError);
+    }
+
+    let token = invitation_token();
+    let invitation = Invitation {
+        session_id,
+        controller_id,
+    };
+    let ttl = Duration::from_millis((window.ends_at - unix_millis()).max(0) as u64);
+    if let Err(error) = sessions
+        .create_invitation(token.clone(), invitation, ttl)
+        .await
+    {
+        error!(%error, "Failed to store the invitation");
+        return Err(StartSessionError::ServerError);
+    }
+    Ok(token)
+}
+
+/// Starts a session that plays back a recording, returning the new session and the recording.
+///
+/// Controllers can't join replay sessions.
+pub async fn on_start_replay<T, S, E>(
+    socket: S,
+    request: StartReplayRequest,
+    sessions: &T,
+    activity: &SessionActivity,
+    events: &E,
+) -> Result<(Uuid, Recording), StartSessionError>
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+    E: EventSink,
+{
+    debug!("Received start_replay command");
+
+    if socket.get_stored_value().is_some() {
+        return Err(StartSessionError::AlreadyInASession);
+    }
+
+    let recording = match sessions.recording(request.recording_id).await {
+        Ok(Some(recording)) => recording,
+        Ok(None) => return Err(StartSessionError::RecordingNotFound),
+        Err(error) => {
+            error!(%error, "Failed to get recording");
+            return Err(StartSessionError::ServerError);
+        }
+    };
+
+    // Recordings are private to the hub of the recorded session
+    if recording.owner.is_none() || recording.owner != socket.client_id() {
+        return Err(StartSessionError::RecordingNotFound);
+    }
+
+    let session_id = match sessions.create_session().await {
+        Ok(session_id) => session_id,
+        Err(error) => {
+            error!(%error, "Failed to create session on session store");
+            return Err(StartSessionError::ServerError);
+        }
+    };
+
+    if let Err(error) = sessions
+        .update_session_state(session_id, SessionState::InProgress)
+        .await
+    {
+        error!(%error, "Failed to update session state");
+        return Err(StartSessionError::ServerError);
+    }
+
+    if let Err(error) = join_session(&socket, session_id) {
+        error!(%error, "Socket failed to join session");
+        return Err(StartSessionError::ServerError);
+    }
 
     socket.store_value(session_id);
+    activity.record(session_id);
 
-    StartSessionResponse::Ok { session_id }
+    publish_event(
+        events,
+        session_id,
+        Role::Hub,
+        SessionEventKind::SessionStarted,
+    )
+    .await;
+
+    Ok((session_id, recording))
 }
 
-pub async fn on_disconnect<T, S>(socket: S, sessions: &T)
+pub async fn on_heartbeat<T, S, E>(
+    socket: S,
+    session_id: Uuid,
+    sessions: &T,
+    activity: &SessionActivity,
+    recordings: &SessionRecordings,
+    events: &E,
+    idle_timeout: Option<Duration>,
+) -> Heartbeat
+where
+    T: SessionStore,
+    S: ClientSocket<StoreItem = Uuid>,
+    E: EventSink,
+{
+    if socket.get_stored_value() != Some(session_id) {
+        return Heartbeat::Stop;
+    }
+
+    let idle_for = activity.idle_for(session_id).unwrap_or_default();
+    if idle_timeout.is_some_and(|timeout| idle_for >= timeout)
+        && !is_scheduled_ahead(sessions, session_id).await
+    {
+        debug!(%session_id, "Finishing idle session");
+        finish_session(socket, session_id, sessions, activity, recordings).await;
+        publish_event(
+            events,
+            session_id,
+            Role::Hub,
+            SessionEventKind::SessionFinished {
+                reason: FinishReason::Idle,
+            },
+        )
+        .await;
+        return Heartbeat::Stop;
+    }
+
+    match sessions.touch(session_id).await {
+        Ok(()) => Heartbeat::Continue,
+        Err(TouchSessionError::UnknownSession(_)) => {
+            warn!(%session_id, "Session expired while the hub was still connected");
+            finish_session(socket, session_id, sessions, activity, recordings).await;
+            publish_event(
+                events,
+                session_id,
+                Role::Hub,
+                SessionEventKind::SessionFinished {
+                    reason: FinishReason::Expired,
+                },
+            )
+            .await;
+            Heartbeat::Stop
+        }
+        Err(error) => {
+            error!(%error, "Failed to refresh session TTL");
+            Heartbeat::Continue
+        }
+    }
+}
+
+pub async fn on_device_status<T, S>(
+    socket: S,
+    status: DeviceStatus,
+    sessions: &T,
+    limiter: &DeviceStatusLimiter,
+    min_interval: Duration,
+) -> DeviceStatusResponse
 where
     T: SessionStore,
     S: ClientSocket<StoreItem = Uuid>,
+{
+    let Some(session_id) = socket.get_stored_value() else {
+        return DeviceStatusResponse::error(DeviceStatusError::NotInASession);
+    };
+
+    if !status.is_valid() {
+        return DeviceStatusResponse::error(DeviceStatusError::InvalidStatus);
+    }
+
+    let limited = (session_id, status.device_id.clone());
+    if !limiter.allows(&limited, min_interval) {
+        return DeviceStatusResponse::error(DeviceStatusError::RateLimited);
+    }
+
+    // Cached so controllers joining later get the current status right away
+    let serialized = match serde_json::to_string(&status) {
+        Ok(serialized) => serialized,
+        Err(error) => {
+            error!(%error, "Failed to serialize device status");
+            return DeviceStatusResponse::error(DeviceStatusError::ServerError);
+        }
+    };
+    match sessions
+        .set_device_status(
+            session_id,
+            status.device_id.clone(),
+            serialized,
+            MAX_DEVICES_PER_SESSION,
+        )
+        .await
+    {
+        // Only devices within the limit of the session are recorded, which bounds the limiter
+        Ok(true) => limiter.record(limited, min_interval),
+        Ok(false) => {
+            warn!(%session_id, "Refused the status of a device over the limit of the session");
+            return DeviceStatusResponse::error(DeviceStatusError::TooManyDevices);
+        }
+        Err(error) => {
+            error!(%error, "Failed to store device status");
+            return DeviceStatusResponse::error(DeviceStatusError::ServerError);
+        }
+    }
+
+    if let Err(error) =
+        socket.emit_to_room(session_id.into(), event_names::DEVICE_STATUS.into(), status)
+    {
+        error!(%error, "Failed to relay device status");
+    }
+
+    DeviceStatusResponse::Ok {}
+}
+
+pub async fn on_disconnect<T, S, E>(
+    socket: S,
+    sessions: &T,
+    activity: &SessionActivity,
+    recordings: &SessionRecordings,
+    events: &E,
+) where
+    T: SessionStore,
+    S: ClientSocket<StoreItem = Uuid>,
+    E: EventSink,
 {
     let Some(session_id) = socket.get_stored_value() else {
         return;
     };
 
-    if let Err(error) = socket.emit_to_room(session_id.into(), "session_finished".into(), ()) {
+    // Hubs that proved their client id can resume their scheduled sessions until they end
+    if socket.client_id().is_some() && is_scheduled_ahead(sessions, session_id).await {
+        debug!(%session_id, "Keeping the scheduled session of the hub");
+        socket.remove_value();
+        activity.remove(session_id);
+        save_recording(session_id, socket.client_id(), sessions, recordings).await;
+        // Heartbeats stop with the hub, this makes the session last until its window ends
+        if let Err(error) = sessions.touch(session_id).await {
+            error!(%error, "Failed to refresh session TTL");
+        }
+        return;
+    }
+
+    if let Err(error) =
+        socket.emit_to_room(session_id.into(), event_names::SESSION_FINISHED.into(), ())
+    {
+        error!(%error, "Failed to send session_finished event");
+    }
+
+    socket.remove_value();
+    activity.remove(session_id);
+    save_recording(session_id, socket.client_id(), sessions, recordings).await;
+
+    if let Err(error) = sessions.delete_session(session_id).await {
+        error!(%error, "Failed to delete session");
+    }
+
+    publish_event(
+        events,
+        session_id,
+        Role::Hub,
+        SessionEventKind::SessionFinished {
+            reason: FinishReason::HubDisconnected,
+        },
+    )
+    .await;
+}
+
+async fn finish_session<T, S>(
+    socket: S,
+    session_id: Uuid,
+    sessions: &T,
+    activity: &SessionActivity,
+    recordings: &SessionRecordings,
+) where
+    T: SessionStore,
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    if let Err(error) =
+        socket.emit_to_room(session_id.into(), event_names::SESSION_FINISHED.into(), ())
+    {
         error!(%error, "Failed to send session_finished event");
     }
 
+    if let Err(error) = socket.emit(event_names::SESSION_FINISHED.into(), ()) {
+        error!(%error, "Failed to send session_finished event to hub");
+    }
+
+    for room in [session_id.into(), hub_room(session_id)] {
+        if let Err(error) = socket.leave(room) {
+            error!(%error, "Hub failed to leave session");
+        }
+    }
+
     socket.remove_value();
+    activity.remove(session_id);
+    save_recording(session_id, socket.client_id(), sessions, recordings).await;
 
     if let Err(error) = sessions.delete_session(session_id).await {
         error!(%error, "Failed to delete session");
     }
 }
+
+pub async fn on_join_attempts<T, S>(
+    socket: S,
+    request: JoinAttemptsRequest,
+    sessions: &T,
+) -> JoinAttemptsResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    debug!("Received join_attempts command");
+
+    let Some(key) = audit_key(socket.client_id(), socket.get_stored_value()) else {
+        return JoinAttemptsResponse::Ok { attempts: vec![] };
+    };
+    let limit = request
+        .limit
+        .unwrap_or(DEFAULT_JOIN_ATTEMPTS)
+        .min(MAX_JOIN_ATTEMPTS);
+
+    match sessions.join_attempts(key, limit).await {
+        Ok(attempts) => JoinAttemptsResponse::Ok { attempts },
+        Err(error) => {
+            error!(%error, "Failed to get join attempts");
+            JoinAttemptsResponse::Error {
+                kind: JoinAttemptsError::ServerError,
+            }
+        }
+    }
+}
+
+/// Gives up a room of the hub, its session goes on but can't be resolved from the room anymore.
+pub async fn on_release_room<T, S>(
+    socket: S,
+    request: ReleaseRoomRequest,
+    sessions: &T,
+) -> ReleaseRoomResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    debug!("Received release_room command");
+
+    let Some(hub_id) = socket.client_id() else {
+        return ReleaseRoomResponse::error(ReleaseRoomError::RequiresClientId);
+    };
+    match sessions.release_room(request.room, hub_id).await {
+        Ok(true) => ReleaseRoomResponse::Ok,
+        Ok(false) => ReleaseRoomResponse::error(ReleaseRoomError::RoomNotOwned),
+        Err(error) => {
+            error!(%error, "Failed to release the room");
+            ReleaseRoomResponse::error(ReleaseRoomError::ServerError)
+        }
+    }
+}
+
+/// Lets a controller join the sessions of the hub without asking it.
+pub async fn on_trust_controller<T, S>(
+    socket: S,
+    request: TrustControllerRequest,
+    sessions: &T,
+) -> TrustedControllersResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    debug!("Received trust_controller command");
+
+    let Some(hub_id) = socket.client_id() else {
+        return TrustedControllersResponse::error(TrustedControllersError::RequiresClientId);
+    };
+    let controller_id = request.controller_id;
+    if controller_id.is_empty() || controller_id.len() > MAX_CLIENT_ID_LEN {
+        return TrustedControllersResponse::error(TrustedControllersError::InvalidControllerId);
+    }
+
+    match sessions.trusted_controllers(hub_id.clone()).await {
+        Ok(controllers)
+            if controllers.len() >= MAX_TRUSTED_CONTROLLERS
+                && !controllers.contains(&controller_id) =>
+        {
+            return TrustedControllersResponse::error(
+                TrustedControllersError::TooManyTrustedControllers,
+            );
+        }
+        Ok(_) => (),
+        Err(error) => {
+            error!(%error, "Failed to get the trusted controllers");
+            return TrustedControllersResponse::error(TrustedControllersError::ServerError);
+        }
+    }
+
+    if let Err(error) = sessions
+        .trust_controller(hub_id.clone(), controller_id)
+        .await
+    {
+        error!(%error, "Failed to trust the controller");
+        return TrustedControllersResponse::error(TrustedControllersError::ServerError);
+    }
+
+    trusted_controllers(hub_id, sessions).await
+}
+
+pub async fn on_untrust_controller<T, S>(
+    socket: S,
+    request: TrustControllerRequest,
+    sessions: &T,
+) -> TrustedControllersResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    debug!("Received untrust_controller command");
+
+    let Some(hub_id) = socket.client_id() else {
+        return TrustedControllersResponse::error(TrustedControllersError::RequiresClientId);
+    };
+    if let Err(error) = sessions
+        .untrust_controller(hub_id.clone(), request.controller_id)
+        .await
+    {
+        error!(%error, "Failed to stop trusting the controller");
+        return TrustedControllersResponse::error(TrustedControllersError::ServerError);
+    }
+
+    trusted_controllers(hub_id, sessions).await
+}
+
+pub async fn on_trusted_controllers<T, S>(socket: S, sessions: &T) -> TrustedControllersResponse
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    debug!("Received trusted_controllers command");
+
+    let Some(hub_id) = socket.client_id() else {
+        return TrustedControllersResponse::error(TrustedControllersError::RequiresClientId);
+    };
+    trusted_controllers(hub_id, sessions).await
+}
+
+/// Lists the controllers the hub trusts, along with whether they are connected.
+async fn trusted_controllers<T>(hub_id: String, sessions: &T) -> TrustedControllersResponse
+where
+    T: SessionStore,
+{
+    let controllers = match sessions.trusted_controllers(hub_id).await {
+        Ok(controllers) => controllers,
+        Err(error) => {
+            error!(%error, "Failed to get the trusted controllers");
+            return TrustedControllersResponse::error(TrustedControllersError::ServerError);
+        }
+    };
+    // Controllers may be connected to any instance
+    let online = match sessions
+        .online(Role::Controller, controllers.iter().cloned().collect())
+        .await
+    {
+        Ok(online) => online,
+        Err(error) => {
+            error!(%error, "Failed to get the presence of the trusted controllers");
+            return TrustedControllersResponse::error(TrustedControllersError::ServerError);
+        }
+    };
+    TrustedControllersResponse::Ok {
+        controllers: controllers
+            .into_iter()
+            .map(|controller_id| TrustedController {
+                online: online.contains(&controller_id),
+                controller_id,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,524 @@
+use crate::sessions::{audit::JoinAttempt, policy::JoinPolicy, schedule::SessionWindow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+const MAX_DEVICE_ID_LEN: usize = 64;
+const MAX_SENSORS: usize = 32;
+const MAX_SENSOR_NAME_LEN: usize = 64;
+
+/// Names of the events exchanged with hubs.
+pub mod event_names {
+    pub const START_SESSION: &str = "start_session";
+    pub const SESSION_FINISHED: &str = "session_finished";
+    pub const DEVICE_STATUS: &str = "device_status";
+    pub const START_REPLAY: &str = "start_replay";
+    pub const REPLAY_FINISHED: &str = "replay_finished";
+    pub const JOIN_ATTEMPTS: &str = "join_attempts";
+    pub const RELEASE_ROOM: &str = "release_room";
+    pub const TRUST_CONTROLLER: &str = "trust_controller";
+    pub const UNTRUST_CONTROLLER: &str = "untrust_controller";
+    pub const TRUSTED_CONTROLLERS: &str = "trusted_controllers";
+    pub const CONTROLLER_PRESENCE: &str = "controller_presence";
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum StartSessionError {
+    AlreadyInASession,
+    RecordingNotFound,
+    InvalidJoinPolicy,
+    InvalidJoinRequestTimeout,
+    InvalidRoom,
+    RoomRequiresClientId,
+    RoomTaken,
+    /// The hub owns as many rooms as it can, it has to release one first.
+    TooManyRooms,
+    InvalidSchedule,
+    /// The session to resume is over, or isn't a scheduled session of the hub.
+    SessionNotFound,
+    ServerError,
+}
+
+/// Data of `start_session`, which can also be sent without any.
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct StartSessionRequest {
+    #[serde(default)]
+    pub join_policy: JoinPolicy,
+    /// Seconds the hub has to answer join requests, within bounds set by the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub join_request_timeout: Option<u64>,
+    /// Persistent room of the hub, which controllers can resolve to this session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// Makes the session joinable only within a window, by the invited controller without
+    /// asking the hub.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<SessionSchedule>,
+    /// Scheduled session the hub left before its window ended, to take back instead of starting
+    /// a new one. The other fields are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume: Option<Uuid>,
+}
+
+/// Window of a scheduled session in Unix milliseconds, and the client id of the controller
+/// invited to it.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct SessionSchedule {
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub controller_id: String,
+}
+
+impl SessionSchedule {
+    pub fn window(&self) -> SessionWindow {
+        SessionWindow {
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum StartSessionResponse {
+    Error {
+        kind: StartSessionError,
+    },
+    Ok {
+        session_id: Uuid,
+        /// Token the invited controller joins a scheduled session with.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        invitation: Option<String>,
+    },
+}
+
+impl StartSessionResponse {
+    pub fn error(kind: StartSessionError) -> Self {
+        Self::Error { kind }
+    }
+}
+
+/// Starts a session playing back a recording, answered like `start_session`.
+///
+/// Hubs can only replay the recordings of their own sessions, other ones are not found.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct StartReplayRequest {
+    pub recording_id: Uuid,
+}
+
+/// Latest readings of a device connected to a hub, relayed to the controller of its session.
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq, Default, schemars::JsonSchema))]
+pub struct DeviceStatus {
+    pub device_id: String,
+    /// Battery level, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery: Option<f64>,
+    /// Signal strength, in dBm.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rssi: Option<i16>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sensors: BTreeMap<String, f64>,
+}
+
+impl DeviceStatus {
+    pub fn is_valid(&self) -> bool {
+        let valid_name = |name: &str| !name.is_empty() && name.len() <= MAX_DEVICE_ID_LEN;
+        valid_name(&self.device_id)
+            && self
+                .battery
+                .is_none_or(|battery| (0.0..=1.0).contains(&battery))
+            && self.rssi.is_none_or(|rssi| rssi <= 0)
+            && self.sensors.len() <= MAX_SENSORS
+            && self.sensors.iter().all(|(name, value)| {
+                !name.is_empty() && name.len() <= MAX_SENSOR_NAME_LEN && value.is_finite()
+            })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum DeviceStatusError {
+    NotInASession,
+    InvalidStatus,
+    RateLimited,
+    /// The session already has as many devices as it can, statuses of other devices are
+    /// refused.
+    TooManyDevices,
+    ServerError,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceStatusResponse {
+    Error { kind: DeviceStatusError },
+    Ok {},
+}
+
+impl DeviceStatusResponse {
+    pub fn error(kind: DeviceStatusError) -> Self {
+        Self::Error { kind }
+    }
+}
+
+/// Asks for the latest join attempts on the sessions of the hub, newest first.
+///
+/// Hubs that connected with a client id get the attempts on all their sessions, others only
+/// those on their current session. Only the latest 1000 attempts of each hub are kept.
+#[derive(Deserialize, Debug, Default)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct JoinAttemptsRequest {
+    /// Defaults to 50, at most 200.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum JoinAttemptsError {
+    ServerError,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum JoinAttemptsResponse {
+    Error { kind: JoinAttemptsError },
+    Ok { attempts: Vec<JoinAttempt> },
+}
+
+/// Data of `release_room`, which lets any hub claim a room of this one.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct ReleaseRoomRequest {
+    pub room: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum ReleaseRoomError {
+    /// Rooms are owned by client id, which the hub didn't prove when connecting.
+    RequiresClientId,
+    /// The hub doesn't own the room.
+    RoomNotOwned,
+    ServerError,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseRoomResponse {
+    Error { kind: ReleaseRoomError },
+    Ok,
+}
+
+impl ReleaseRoomResponse {
+    pub fn error(kind: ReleaseRoomError) -> Self {
+        Self::Error { kind }
+    }
+}
+
+/// Data of `trust_controller` and `untrust_controller`.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct TrustControllerRequest {
+    pub controller_id: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub enum TrustedControllersError {
+    /// Trust is kept by client id, which the hub didn't prove when connecting.
+    RequiresClientId,
+    InvalidControllerId,
+    TooManyTrustedControllers,
+    ServerError,
+}
+
+/// A controller the hub trusts, and whether it is connected to the server.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct TrustedController {
+    pub controller_id: String,
+    pub online: bool,
+}
+
+/// Acknowledgment of the trust events, with the controllers the hub trusts after it.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum TrustedControllersResponse {
+    Error { kind: TrustedControllersError },
+    Ok { controllers: Vec<TrustedController> },
+}
+
+impl TrustedControllersResponse {
+    pub fn error(kind: TrustedControllersError) -> Self {
+        Self::Error { kind }
+    }
+}
+
+/// Sent to hubs when a controller they trust connects or disconnects.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct ControllerPresence {
+    pub controller_id: String,
+    pub online: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ControllerPresence, DeviceStatus, DeviceStatusError, DeviceStatusResponse,
+        JoinAttemptsResponse, StartReplayRequest, StartSessionError, StartSessionResponse,
+        TrustedController, TrustedControllersError, TrustedControllersResponse,
+    };
+    use crate::{
+        sessions::audit::{JoinAttempt, JoinAttemptOutcome},
+        socket::encoding::assert_msgpack_round_trip,
+    };
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_serialize_start_ression_response_ok_to_json() {
+        let response = StartSessionResponse::Ok {
+            session_id: Uuid::nil(),
+            invitation: None,
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            format!(r#"{{"session_id":"{}","type":"ok"}}"#, Uuid::nil())
+        )
+    }
+
+    #[test]
+    fn test_serialize_start_ression_response_err_to_json() {
+        let response = StartSessionResponse::Error {
+            kind: StartSessionError::AlreadyInASession,
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"kind":"already_in_a_session","type":"error"}"#
+        )
+    }
+
+    #[test]
+    fn test_serialize_device_status() {
+        let status = DeviceStatus {
+            device_id: "toy".into(),
+            battery: Some(0.5),
+            rssi: None,
+            sensors: [("temperature".to_string(), 36.5)].into(),
+        };
+        assert_eq!(
+            json!(status).to_string(),
+            r#"{"battery":0.5,"device_id":"toy","sensors":{"temperature":36.5}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<DeviceStatus>(r#"{"device_id":"toy"}"#).unwrap(),
+            DeviceStatus {
+                device_id: "toy".into(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn validates_device_status() {
+        let status = DeviceStatus {
+            device_id: "toy".into(),
+            battery: Some(1.0),
+            rssi: Some(-60),
+            sensors: [("temperature".to_string(), 36.5)].into(),
+        };
+        assert!(status.is_valid());
+
+        for invalid in [
+            DeviceStatus {
+                device_id: "".into(),
+                ..status.clone()
+            },
+            DeviceStatus {
+                device_id: "a".repeat(65),
+                ..status.clone()
+            },
+            DeviceStatus {
+                battery: Some(1.5),
+                ..status.clone()
+            },
+            DeviceStatus {
+                battery: Some(f64::NAN),
+                ..status.clone()
+            },
+            DeviceStatus {
+                rssi: Some(10),
+                ..status.clone()
+            },
+            DeviceStatus {
+                sensors: [("temperature".to_string(), f64::INFINITY)].into(),
+                ..status.clone()
+            },
+            DeviceStatus {
+                sensors: (0..33).map(|i| (i.to_string(), 0.0)).collect(),
+                ..status.clone()
+            },
+        ] {
+            assert!(!invalid.is_valid(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_serialize_device_status_err_response() {
+        let response = DeviceStatusResponse::error(DeviceStatusError::RateLimited);
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"kind":"rate_limited","type":"error"}"#
+        )
+    }
+
+    #[test]
+    fn test_serialize_join_attempts_response() {
+        let response = JoinAttemptsResponse::Ok {
+            attempts: vec![JoinAttempt {
+                session_id: Uuid::nil(),
+                timestamp: 1000,
+                controller_id: None,
+                message: "hi".into(),
+                outcome: JoinAttemptOutcome::SessionFull,
+            }],
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            format!(
+                r#"{{"attempts":[{{"controller_id":null,"message":"hi","outcome":"session_full","session_id":"{}","timestamp":1000}}],"type":"ok"}}"#,
+                Uuid::nil()
+            )
+        )
+    }
+
+    #[test]
+    fn test_serialize_trusted_controllers_response_to_json() {
+        let response = TrustedControllersResponse::Ok {
+            controllers: vec![TrustedController {
+                controller_id: "alice".into(),
+                online: true,
+            }],
+        };
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"controllers":[{"controller_id":"alice","online":true}],"type":"ok"}"#
+        );
+        let response = TrustedControllersResponse::error(TrustedControllersError::RequiresClientId);
+        assert_eq!(
+            json!(response).to_string(),
+            r#"{"kind":"requires_client_id","type":"error"}"#
+        );
+    }
+
+    #[test]
+    fn messages_round_trip_through_msgpack() {
+        assert_msgpack_round_trip(TrustedControllersResponse::Ok {
+            controllers: vec![TrustedController {
+                controller_id: "alice".into(),
+                online: false,
+            }],
+        });
+        assert_msgpack_round_trip(TrustedControllersResponse::error(
+            TrustedControllersError::TooManyTrustedControllers,
+        ));
+        assert_msgpack_round_trip(ControllerPresence {
+            controller_id: "alice".into(),
+            online: true,
+        });
+        assert_msgpack_round_trip(StartSessionResponse::Ok {
+            session_id: Uuid::new_v4(),
+            invitation: None,
+        });
+        assert_msgpack_round_trip(StartSessionResponse::Ok {
+            session_id: Uuid::new_v4(),
+            invitation: Some("token".into()),
+        });
+        assert_msgpack_round_trip(StartSessionResponse::error(
+            StartSessionError::AlreadyInASession,
+        ));
+        assert_msgpack_round_trip(StartSessionResponse::error(StartSessionError::ServerError));
+        assert_msgpack_round_trip(StartSessionResponse::error(
+            StartSessionError::RecordingNotFound,
+        ));
+        assert_msgpack_round_trip(StartSessionResponse::error(StartSessionError::RoomTaken));
+        assert_msgpack_round_trip(StartSessionResponse::error(
+            StartSessionError::InvalidSchedule,
+        ));
+        assert_msgpack_round_trip(StartReplayRequest {
+            recording_id: Uuid::new_v4(),
+        });
+        assert_msgpack_round_trip(DeviceStatus {
+            device_id: "toy".into(),
+            battery: Some(0.5),
+            rssi: Some(-60),
+            sensors: [("temperature".to_string(), 36.5)].into(),
+        });
+        assert_msgpack_round_trip(DeviceStatusResponse::Ok {});
+        for kind in [
+            DeviceStatusError::NotInASession,
+            DeviceStatusError::InvalidStatus,
+            DeviceStatusError::RateLimited,
+            DeviceStatusError::ServerError,
+        ] {
+            assert_msgpack_round_trip(DeviceStatusResponse::error(kind));
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Client ids are only trusted when the backend of the app vouches for them.
+//!
+//! The backend hands its clients a token `<expires_at>.<signature>` along with their client id,
+//! where `expires_at` is in seconds since the Unix epoch and `signature` is the hex HMAC-SHA256
+//! of `<client_id>.<expires_at>` keyed with the identity secret.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn mac(secret: &str, client_id: &str, expires_at: u64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{client_id}.{expires_at}").as_bytes());
+    mac
+}
+
+/// Token proving `client_id` until `expires_at`, in seconds since the Unix epoch. The backends
+/// sign them, the server only does it in tests.
+#[cfg(test)]
+pub fn sign(secret: &str, client_id: &str, expires_at: u64) -> String {
+    let signature = mac(secret, client_id, expires_at).finalize().into_bytes();
+    format!("{expires_at}.{}", hex::encode(signature))
+}
+
+/// Whether `token` was signed for `client_id` with `secret` and has not expired.
+pub fn verify(secret: &str, client_id: &str, token: &str) -> bool {
+    let Some((expires_at, signature)) = token.split_once('.') else {
+        return false;
+    };
+    let (Ok(expires_at), Ok(signature)) = (expires_at.parse::<u64>(), hex::decode(signature))
+    else {
+        return false;
+    };
+    expires_at > now_secs()
+        && mac(secret, client_id, expires_at)
+            .verify_slice(&signature)
+            .is_ok()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{now_secs, sign, verify};
+
+    #[test]
+    fn tokens_prove_the_client_id_they_were_signed_for() {
+        let token = sign("secret", "alice", now_secs() + 60);
+
+        assert!(verify("secret", "alice", &token));
+        assert!(!verify("secret", "bob", &token));
+        assert!(!verify("other-secret", "alice", &token));
+    }
+
+    #[test]
+    fn expired_or_malformed_tokens_prove_nothing() {
+        assert!(!verify(
+            "secret",
+            "alice",
+            &sign("secret", "alice", now_secs() - 1)
+        ));
+        assert!(!verify("secret", "alice", ""));
+        assert!(!verify("secret", "alice", "soon.abcd"));
+        assert!(!verify(
+            "secret",
+            "alice",
+            &format!("{}.zz", now_secs() + 60)
+        ));
+    }
+}
@@ -0,0 +1,200 @@
+//! Plain WebSocket transport speaking JSON-RPC 2.0, for clients that can't run a socket.io
+//! client.
+//!
+//! Clients connect to `/rpc?role=<hub|controller>[&protocol_version=<n>][&encoding=<json|msgpack>]`.
+//! The query is validated like the socket.io handshake, a bad one is rejected with
+//! `400 Bad Request` and a `{ "reason": ... }` body. Clients that pick `msgpack` exchange
+//! MessagePack encoded messages in binary frames instead of JSON in text frames.
+//!
+//! Every socket.io event maps to a JSON-RPC method with the same name and payload:
+//!
+//! - Events sent by clients are requests, `params` is the event data and `result` is the
+//!   acknowledgment (`null` for events without one). They can also be sent as notifications.
+//! - Events sent by the server are notifications, `params` is the event data.
+//! - Events the server expects an acknowledgment for (`join_request`, `ping`) are requests
+//!   with a numeric `id`, clients must answer them with the acknowledgment as `result`.
+//!
+//! ```text
+//! --> {"jsonrpc":"2.0","id":1,"method":"start_session"}
+//! <-- {"jsonrpc":"2.0","id":1,"result":{"type":"ok","session_id":"..."}}
+//! <-- {"jsonrpc":"2.0","id":1,"method":"join_request","params":{"message":"hi","deadline":1700000060000,"timeout_ms":60000}}
+//! --> {"jsonrpc":"2.0","id":1,"result":{"type":"accept"}}
+//! <-- {"jsonrpc":"2.0","method":"controller_joined","params":null}
+//! ```
+//!
+//! Sessions are shared with socket.io clients, a hub connected through JSON-RPC can be
+//! joined by a controller connected through socket.io and the other way around.
+
+use super::{
+    controller, dispatch, hub, presence,
+    protocol::{ProtocolInfo, ProtocolVersion},
+    tenant::TenantState,
+    trust, Auth, ConnectError, ConnectErrorResponse, Role,
+};
+use crate::{
+    events::port::EventSink,
+    sessions::port::SessionStore,
+    socket::{
+        adapters::jsonrpc::{self, ErrorObject, Incoming, Outgoing, PeerId, Request},
+        encoding,
+        ip::client_ip,
+    },
+};
+use axum::{
+    extract::{
+        rejection::QueryRejection,
+        ws::{Message, WebSocket},
+        ConnectInfo, Query, State, WebSocketUpgrade,
+    },
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+use tracing::{debug, warn};
+
+pub async fn on_upgrade<T, E>(
+    ws: WebSocketUpgrade,
+    auth: Result<Query<Auth>, QueryRejection>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    State(state): State<Arc<TenantState<T, E>>>,
+) -> Response
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+{
+    let auth = auth
+        .map_err(|error| error.to_string())
+        .and_then(|Query(auth)| auth.validate(state.config.identity_secret.as_deref()));
+    let auth = match auth {
+        Ok(auth) => auth,
+        Err(error) => {
+            warn!(%error, "Client provided invalid auth data");
+            let response = ConnectErrorResponse::with_reason(ConnectError::Unauthorized);
+            return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+        }
+    };
+
+    if !auth.is_authorized(&state.config.auth_keys) {
+        warn!("Client provided a missing or unknown auth key");
+        let response = ConnectErrorResponse::with_reason(ConnectError::Unauthorized);
+        return (StatusCode::UNAUTHORIZED, Json(response)).into_response();
+    }
+
+    let Ok(version) = ProtocolVersion::negotiate(auth.protocol_version) else {
+        warn!("Client requested an unsupported protocol version");
+        let response = ConnectErrorResponse::unsupported_protocol_version();
+        return (StatusCode::BAD_REQUEST, Json(response)).into_response();
+    };
+
+    let ip = client_ip(&headers, connect_info.map(|info| info.0));
+    ws.on_upgrade(move |socket| on_connect(socket, auth, ip, version, state))
+}
+
+async fn on_connect<T, E>(
+    socket: WebSocket,
+    auth: Auth,
+    ip: Option<IpAddr>,
+    version: ProtocolVersion,
+    state: Arc<TenantState<T, E>>,
+) where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+{
+    let role = auth.role;
+    let (peer, mut outgoing) = state.peers.connect(auth.encoding, version);
+    state.peers.identify(peer, auth.client_id.clone(), ip);
+    debug!(peer, "JSON-RPC client connected");
+    trust::go_online(&state.client_socket(peer), role, &state.sessions).await;
+    tokio::spawn(presence::track_rpc_peer(state.clone(), peer, role));
+
+    let (mut sink, mut stream) = socket.split();
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            let message = match message {
+                Outgoing::Text(text) => Message::Text(text),
+                Outgoing::Binary(bytes) => Message::Binary(bytes),
+                Outgoing::Close => break,
+            };
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+        sink.close().await.ok();
+    });
+
+    state
+        .peers
+        .notify(
+            peer,
+            "protocol",
+            &json!(ProtocolInfo::new(version, auth.encoding)),
+        )
+        .ok();
+
+    while let Some(Ok(message)) = stream.next().await {
+        let message = match message {
+            Message::Text(text) => serde_json::from_str::<Value>(&text).ok(),
+            Message::Binary(bytes) => encoding::from_msgpack::<Value>(&bytes).ok(),
+            _ => continue,
+        };
+
+        let Some(message) = message else {
+            state
+                .peers
+                .respond(peer, Value::Null, Err(ErrorObject::parse_error()));
+            continue;
+        };
+
+        match serde_json::from_value(message) {
+            Ok(Incoming::Request(request)) => {
+                tokio::spawn(on_request(request, role, peer, state.clone()));
+            }
+            Ok(Incoming::Response(response)) => state.peers.resolve(peer, response),
+            Err(_) => {
+                state
+                    .peers
+                    .respond(peer, Value::Null, Err(ErrorObject::invalid_request()));
+            }
+        }
+    }
+
+    debug!(peer, "JSON-RPC client disconnected");
+
+    match role {
+        Role::Hub => hub::disconnect(peer, &state).await,
+        Role::Controller => controller::disconnect(peer, &state).await,
+    }
+
+    state.peers.disconnect(peer);
+}
+
+async fn on_request<T, E>(request: Request, role: Role, peer: PeerId, state: Arc<TenantState<T, E>>)
+where
+    T: SessionStore + 'static,
+    E: EventSink + 'static,
+{
+    if request.jsonrpc != jsonrpc::VERSION {
+        if let Some(id) = request.id {
+            state
+                .peers
+                .respond(peer, id, Err(ErrorObject::invalid_request()));
+        }
+        return;
+    }
+
+    let handlers = match role {
+        Role::Hub => hub::handlers(),
+        Role::Controller => controller::handlers(),
+    };
+    let Request {
+        id, method, params, ..
+    } = request;
+    dispatch::call(handlers, &method, params, id, peer, state).await
+}
@@ -0,0 +1,290 @@
+//! Latency and clock offset measurement.
+//!
+//! While in a session, hubs and controllers are sent a `ping` every `latency_probe_interval`
+//! seconds and answer with their clock. The round trip time and the offset of their clock
+//! are estimated from it, assuming the trip takes as long both ways, and the stats of both
+//! participants are sent to the session with `session_stats`.
+
+pub mod messages;
+
+use super::Role;
+use crate::{
+    configuration::Config,
+    sessions::latency::{Measurement, SessionLatency},
+    socket::port::ClientSocket,
+};
+use messages::{event_names, PeerStats, Ping, Pong, SessionStats};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq)]
+pub enum Probe {
+    Continue,
+    Stop,
+}
+
+/// Milliseconds since the Unix epoch, the unit of every timestamp in the protocol.
+pub fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+pub fn session_stats(latency: &SessionLatency, session_id: Uuid) -> SessionStats {
+    SessionStats {
+        hub: latency.get(session_id, Role::Hub).map(PeerStats::from),
+        controller: latency
+            .get(session_id, Role::Controller)
+            .map(PeerStats::from),
+    }
+}
+
+/// Pings a participant and shares the updated stats with the session.
+pub async fn on_probe<S>(
+    socket: S,
+    session_id: Uuid,
+    role: Role,
+    latency: &SessionLatency,
+    timeout: Duration,
+) -> Probe
+where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    if socket.get_stored_value() != Some(session_id) {
+        latency.remove(session_id, role);
+        return Probe::Stop;
+    }
+
+    let sent_at = Instant::now();
+    let server_time = unix_millis();
+    let ping = socket.emit_with_ack(event_names::PING.into(), Ping { server_time }, timeout);
+    let Pong { client_time } = match ping.await {
+        Ok(pong) => pong,
+        Err(error) => {
+            warn!(%error, "Client did not answer ping");
+            return Probe::Continue;
+        }
+    };
+
+    let rtt = sent_at.elapsed();
+    let clock_offset = client_time - (server_time + rtt.as_millis() as i64 / 2);
+    latency.record(session_id, role, Measurement { rtt, clock_offset });
+
+    let stats = session_stats(latency, session_id);
+    if let Err(error) = socket
+        .emit_to_room(session_id.into(), event_names::SESSION_STATS.into(), stats)
+        .and_then(|_| socket.emit(event_names::SESSION_STATS.into(), stats))
+    {
+        error!(%error, "Failed to send session stats");
+    }
+
+    Probe::Continue
+}
+
+/// Periodically measures the latency of a participant while it stays in the session.
+///
+/// `socket` returns the participant's socket, or `None` once it has disconnected.
+pub async fn monitor<S>(
+    socket: impl Fn() -> Option<S>,
+    session_id: Uuid,
+    role: Role,
+    latency: &SessionLatency,
+    config: &Config,
+) where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    let Some(interval) = config.latency_probe_interval.map(Duration::from_secs) else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, give the client time to get the session ack
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        let Some(socket) = socket() else {
+            latency.remove(session_id, role);
+            break;
+        };
+        if on_probe(socket, session_id, role, latency, interval).await == Probe::Stop {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        messages::{PeerStats, Ping, Pong, SessionStats},
+        on_probe, session_stats, Probe,
+    };
+    use crate::{
+        actors::Role,
+        sessions::latency::{Measurement, SessionLatency},
+        socket::port::{DummyMockError, MockClientSocket},
+    };
+    use futures_util::FutureExt;
+    use mockall::predicate::{always, eq};
+    use std::time::Duration;
+    use test_context::{test_context, AsyncTestContext};
+    use uuid::Uuid;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    struct Context {
+        client_socket: MockClientSocket,
+        latency: SessionLatency,
+    }
+
+    impl AsyncTestContext for Context {
+        async fn setup() -> Self {
+            Self {
+                client_socket: MockClientSocket::new(),
+                latency: SessionLatency::default(),
+            }
+        }
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn measures_the_participant_and_shares_the_stats(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        // The client clock is a minute ahead
+        ctx.client_socket
+            .expect_emit_with_ack()
+            .times(1)
+            .with(eq("ping".to_string()), always(), eq(TIMEOUT))
+            .returning(|_, ping: Ping, _| {
+                async move {
+                    Ok(Pong {
+                        client_time: ping.server_time + 60_000,
+                    })
+                }
+                .boxed()
+            });
+
+        ctx.client_socket
+            .expect_emit_to_room()
+            .times(1)
+            .withf(|room, event, stats: &SessionStats| {
+                room == &Uuid::nil().to_string()
+                    && event == "session_stats"
+                    && stats.hub.is_some()
+                    && stats.controller.is_none()
+            })
+            .return_const(Ok(()));
+
+        ctx.client_socket
+            .expect_emit::<SessionStats>()
+            .times(1)
+            .withf(|event, _| event == "session_stats")
+            .return_const(Ok(()));
+
+        let result = on_probe(
+            ctx.client_socket,
+            Uuid::nil(),
+            Role::Hub,
+            &ctx.latency,
+            TIMEOUT,
+        )
+        .await;
+
+        assert_eq!(result, Probe::Continue);
+        let measurement = ctx.latency.get(Uuid::nil(), Role::Hub).unwrap();
+        assert!((59_000..=60_000).contains(&measurement.clock_offset));
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn keeps_probing_if_the_participant_does_not_answer(mut ctx: Context) {
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+
+        ctx.client_socket
+            .expect_emit_with_ack::<Ping>()
+            .times(1)
+            .returning(|_, _, _| async { Err(DummyMockError) }.boxed());
+
+        ctx.client_socket
+            .expect_emit_to_room::<SessionStats>()
+            .never();
+        ctx.client_socket.expect_emit::<SessionStats>().never();
+
+        let result = on_probe(
+            ctx.client_socket,
+            Uuid::nil(),
+            Role::Controller,
+            &ctx.latency,
+            TIMEOUT,
+        )
+        .await;
+
+        assert_eq!(result, Probe::Continue);
+        assert_eq!(ctx.latency.get(Uuid::nil(), Role::Controller), None);
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn stops_once_the_participant_left_the_session(mut ctx: Context) {
+        ctx.latency.record(
+            Uuid::nil(),
+            Role::Controller,
+            Measurement {
+                rtt: Duration::from_millis(40),
+                clock_offset: 0,
+            },
+        );
+
+        ctx.client_socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(None);
+
+        ctx.client_socket.expect_emit_with_ack::<Ping>().never();
+
+        let result = on_probe(
+            ctx.client_socket,
+            Uuid::nil(),
+            Role::Controller,
+            &ctx.latency,
+            TIMEOUT,
+        )
+        .await;
+
+        assert_eq!(result, Probe::Stop);
+        assert_eq!(ctx.latency.get(Uuid::nil(), Role::Controller), None);
+    }
+
+    #[test]
+    fn stats_include_the_measured_participants() {
+        let latency = SessionLatency::default();
+        latency.record(
+            Uuid::nil(),
+            Role::Controller,
+            Measurement {
+                rtt: Duration::from_millis(40),
+                clock_offset: 0,
+            },
+        );
+
+        let stats = session_stats(&latency, Uuid::nil());
+
+        assert_eq!(stats.hub, None);
+        assert!(matches!(
+            stats.controller,
+            Some(PeerStats {
+                rtt_ms: 40,
+                latency_ms: 20,
+                ..
+            })
+        ));
+    }
+}
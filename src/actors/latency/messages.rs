@@ -0,0 +1,5 @@
+//! Messages used to measure latency, one module per protocol version.
+
+pub mod v1;
+
+pub use v1::*;
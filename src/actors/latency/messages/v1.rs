@@ -0,0 +1,117 @@
+use crate::{sessions::latency::Measurement, socket::port::MessageWithAck};
+use serde::{Deserialize, Serialize};
+
+/// Names of the events used to measure latency, exchanged with hubs and controllers.
+pub mod event_names {
+    pub const PING: &str = "ping";
+    pub const SESSION_STATS: &str = "session_stats";
+}
+
+/// Sent by the server, `server_time` is in milliseconds since the Unix epoch.
+#[derive(Serialize)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct Ping {
+    pub server_time: i64,
+}
+
+/// Answer to a ping, `client_time` is the time the client received it in milliseconds since
+/// the Unix epoch.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct Pong {
+    pub client_time: i64,
+}
+
+impl MessageWithAck for Ping {
+    type Ack = Pong;
+}
+
+/// Latency of a participant, `clock_offset_ms` is how far its clock is ahead of the server's.
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct PeerStats {
+    pub rtt_ms: u64,
+    pub latency_ms: u64,
+    pub clock_offset_ms: i64,
+}
+
+impl From<Measurement> for PeerStats {
+    fn from(measurement: Measurement) -> Self {
+        let rtt_ms = measurement.rtt.as_millis() as u64;
+        Self {
+            rtt_ms,
+            latency_ms: rtt_ms / 2,
+            clock_offset_ms: measurement.clock_offset,
+        }
+    }
+}
+
+/// Sent to both participants of a session every time one of them is measured.
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct SessionStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hub: Option<PeerStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub controller: Option<PeerStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeerStats, Ping, Pong, SessionStats};
+    use crate::{sessions::latency::Measurement, socket::encoding::assert_msgpack_round_trip};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_serialize_ping() {
+        let ping = Ping { server_time: 1000 };
+        assert_eq!(json!(ping).to_string(), r#"{"server_time":1000}"#);
+    }
+
+    #[test]
+    fn test_deserialize_pong() {
+        assert_eq!(
+            serde_json::from_str::<Pong>(r#"{"client_time":1000}"#).unwrap(),
+            Pong { client_time: 1000 }
+        );
+    }
+
+    #[test]
+    fn test_serialize_session_stats() {
+        let stats = SessionStats {
+            hub: Some(PeerStats::from(Measurement {
+                rtt: Duration::from_millis(41),
+                clock_offset: -7,
+            })),
+            controller: None,
+        };
+        assert_eq!(
+            json!(stats).to_string(),
+            r#"{"hub":{"clock_offset_ms":-7,"latency_ms":20,"rtt_ms":41}}"#
+        );
+    }
+
+    #[test]
+    fn messages_round_trip_through_msgpack() {
+        assert_msgpack_round_trip(Ping { server_time: 1000 });
+        assert_msgpack_round_trip(Pong { client_time: -1000 });
+        assert_msgpack_round_trip(SessionStats {
+            hub: Some(PeerStats {
+                rtt_ms: 40,
+                latency_ms: 20,
+                clock_offset_ms: -7,
+            }),
+            controller: None,
+        });
+    }
+}
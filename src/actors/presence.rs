@@ -0,0 +1,322 @@
+//! Presence of the clients that connect with a client id, shared by every server instance
+//! through the session store.
+//!
+//! Each connection marks its client online every `presence_heartbeat_interval` seconds and is
+//! forgotten when it disconnects. Connections that stop sending heartbeats, like those of an
+//! instance that crashed, expire after missing a few of them.
+
+pub mod api;
+
+use super::{
+    controller::{HubPresence, HubsOnlineErrorKind, HubsOnlineRequest, HubsOnlineResponse},
+    tenant::TenantState,
+    Role,
+};
+use crate::{
+    configuration::Config,
+    sessions::{port::SessionStore, rate_limit::RateLimiter},
+    socket::{adapters::jsonrpc::PeerId, port::ClientSocket},
+};
+use socketioxide::extract::Data;
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+/// Heartbeats a connection can miss before it stops counting as online.
+const MISSED_HEARTBEATS: u32 = 3;
+const MAX_HUBS_QUERIED: usize = 50;
+/// Time a controller has to wait between two `hubs_online` requests.
+const HUBS_ONLINE_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rate limits the `hubs_online` requests by client id.
+pub type HubsOnlineLimiter = RateLimiter<String>;
+
+/// Tells apart the connections of this instance from those of the others.
+static INSTANCE_ID: LazyLock<Uuid> = LazyLock::new(Uuid::new_v4);
+
+fn presence_id<S: ClientSocket>(socket: &S) -> String {
+    format!("{}:{}", *INSTANCE_ID, socket.connection_id())
+}
+
+/// Marks the client of a connection online for a few more heartbeats.
+pub async fn on_heartbeat<S, T>(socket: &S, role: Role, sessions: &T, config: &Config)
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    let Some(client_id) = socket.client_id() else {
+        return;
+    };
+    let ttl = Duration::from_secs(config.presence_heartbeat_interval) * MISSED_HEARTBEATS;
+    if let Err(error) = sessions
+        .set_online(role, client_id, presence_id(socket), ttl)
+        .await
+    {
+        error!(%error, "Failed to mark the client online");
+    }
+}
+
+/// Sends the presence heartbeats of a connection until it disconnects.
+///
+/// `socket` returns the connection's socket, or `None` once it has disconnected.
+pub async fn track<S, T>(socket: impl Fn() -> Option<S>, role: Role, sessions: &T, config: &Config)
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    let mut ticker = tokio::time::interval(Duration::from_secs(config.presence_heartbeat_interval));
+    loop {
+        ticker.tick().await;
+        let Some(socket) = socket() else {
+            break;
+        };
+        if socket.client_id().is_none() {
+            break;
+        }
+        on_heartbeat(&socket, role, sessions, config).await;
+    }
+}
+
+/// Same as [`track`], for a client connected through JSON-RPC.
+pub async fn track_rpc_peer<T, E>(state: Arc<TenantState<T, E>>, peer: PeerId, role: Role)
+where
+    T: SessionStore,
+{
+    let socket = || {
+        state
+            .peers
+            .is_connected(peer)
+            .then(|| state.client_socket(peer))
+    };
+    track(socket, role, &state.sessions, &state.config).await
+}
+
+/// Stops counting a connection that disconnected as online.
+pub async fn forget<S, T>(socket: &S, role: Role, sessions: &T)
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    let Some(client_id) = socket.client_id() else {
+        return;
+    };
+    if let Err(error) = sessions
+        .set_offline(role, client_id, presence_id(socket))
+        .await
+    {
+        error!(%error, "Failed to mark the client offline");
+    }
+}
+
+/// Tells an identified controller which of the hubs that trust it are online, so presence
+/// can't be used to track hubs.
+pub async fn on_hubs_online<S, T>(
+    socket: &S,
+    Data(request): Data<HubsOnlineRequest>,
+    sessions: &T,
+    limiter: &HubsOnlineLimiter,
+) -> HubsOnlineResponse
+where
+    S: ClientSocket,
+    T: SessionStore,
+{
+    debug!("Received hubs_online command");
+
+    let Some(controller_id) = socket.client_id() else {
+        return HubsOnlineResponse::Error {
+            kind: HubsOnlineErrorKind::RequiresClientId,
+        };
+    };
+    if request.hub_ids.len() > MAX_HUBS_QUERIED {
+        return HubsOnlineResponse::Error {
+            kind: HubsOnlineErrorKind::TooManyHubs,
+        };
+    }
+    if !limiter.check(controller_id.clone(), HUBS_ONLINE_MIN_INTERVAL) {
+        return HubsOnlineResponse::Error {
+            kind: HubsOnlineErrorKind::RateLimited,
+        };
+    }
+
+    let trusting = match sessions.trusting_hubs(controller_id).await {
+        Ok(trusting) => trusting,
+        Err(error) => {
+            error!(%error, "Failed to get the hubs trusting the controller");
+            return HubsOnlineResponse::Error {
+                kind: HubsOnlineErrorKind::ServerError,
+            };
+        }
+    };
+    let trusted_hub_ids = request
+        .hub_ids
+        .iter()
+        .filter(|hub_id| trusting.contains(*hub_id))
+        .cloned()
+        .collect();
+
+    match sessions.online(Role::Hub, trusted_hub_ids).await {
+        Ok(online) => HubsOnlineResponse::Ok {
+            hubs: request
+                .hub_ids
+                .into_iter()
+                .map(|hub_id| HubPresence {
+                    online: online.contains(&hub_id),
+                    hub_id,
+                })
+                .collect(),
+        },
+        Err(error) => {
+            error!(%error, "Failed to get the presence of the hubs");
+            HubsOnlineResponse::Error {
+                kind: HubsOnlineErrorKind::ServerError,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{on_heartbeat, on_hubs_online, HubsOnlineLimiter};
+    use crate::{
+        actors::{
+            controller::{HubPresence, HubsOnlineErrorKind, HubsOnlineRequest, HubsOnlineResponse},
+            Role,
+        },
+        configuration::Config,
+        sessions::port::MockSessionStore,
+        socket::port::MockClientSocket,
+    };
+    use futures_util::FutureExt;
+    use mockall::predicate::eq;
+    use socketioxide::extract::Data;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn connections_stay_online_for_a_few_heartbeats() {
+        let config = Config::load();
+        let mut socket = MockClientSocket::new();
+        let mut sessions = MockSessionStore::new();
+        socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+        socket
+            .expect_connection_id()
+            .return_const("connection".to_string());
+        let ttl = Duration::from_secs(config.presence_heartbeat_interval * 3);
+        sessions
+            .expect_set_online()
+            .withf(move |role, client_id, connection_id, set_ttl| {
+                *role == Role::Hub
+                    && client_id == "hub"
+                    && connection_id.ends_with(":connection")
+                    && *set_ttl == ttl
+            })
+            .times(1)
+            .returning(|_, _, _, _| async { Ok(()) }.boxed());
+
+        on_heartbeat(&socket, Role::Hub, &sessions, &config).await;
+    }
+
+    fn controller(client_id: Option<&str>) -> MockClientSocket {
+        let mut socket = MockClientSocket::new();
+        socket
+            .expect_client_id()
+            .return_const(client_id.map(str::to_owned));
+        socket
+    }
+
+    #[tokio::test]
+    async fn controllers_only_see_the_hubs_that_trust_them() {
+        let mut sessions = MockSessionStore::new();
+        sessions
+            .expect_trusting_hubs()
+            .with(eq("carol".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(["alice".to_string(), "bob".to_string()].into()) }.boxed());
+        sessions
+            .expect_online()
+            .with(
+                eq(Role::Hub),
+                eq(vec!["alice".to_string(), "bob".to_string()]),
+            )
+            .times(1)
+            .returning(|_, _| async { Ok(["bob".to_string()].into()) }.boxed());
+
+        let request = HubsOnlineRequest {
+            hub_ids: vec!["alice".into(), "bob".into(), "dave".into()],
+        };
+        assert_eq!(
+            on_hubs_online(
+                &controller(Some("carol")),
+                Data(request),
+                &sessions,
+                &HubsOnlineLimiter::default()
+            )
+            .await,
+            HubsOnlineResponse::Ok {
+                hubs: vec![
+                    HubPresence {
+                        hub_id: "alice".into(),
+                        online: false,
+                    },
+                    HubPresence {
+                        hub_id: "bob".into(),
+                        online: true,
+                    },
+                    HubPresence {
+                        hub_id: "dave".into(),
+                        online: false,
+                    },
+                ],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn only_identified_controllers_can_ask_once_in_a_while_about_a_few_hubs() {
+        let sessions = MockSessionStore::new();
+        let limiter = HubsOnlineLimiter::default();
+        let request = || HubsOnlineRequest {
+            hub_ids: vec!["hub".into()],
+        };
+
+        assert_eq!(
+            on_hubs_online(&controller(None), Data(request()), &sessions, &limiter).await,
+            HubsOnlineResponse::Error {
+                kind: HubsOnlineErrorKind::RequiresClientId,
+            }
+        );
+        let too_many = HubsOnlineRequest {
+            hub_ids: vec!["hub".into(); 51],
+        };
+        assert_eq!(
+            on_hubs_online(
+                &controller(Some("carol")),
+                Data(too_many),
+                &sessions,
+                &limiter
+            )
+            .await,
+            HubsOnlineResponse::Error {
+                kind: HubsOnlineErrorKind::TooManyHubs,
+            }
+        );
+
+        limiter.check("carol".into(), std::time::Duration::from_secs(60));
+        assert_eq!(
+            on_hubs_online(
+                &controller(Some("carol")),
+                Data(request()),
+                &sessions,
+                &limiter
+            )
+            .await,
+            HubsOnlineResponse::Error {
+                kind: HubsOnlineErrorKind::RateLimited,
+            }
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! HTTP API to check whether a hub is online, for the backends of the apps.
+//!
+//! Every request must carry the configured token as `Authorization: Bearer <token>`.
+
+use crate::{
+    actors::{controller::HubPresence, Role},
+    configuration::PresenceConfig,
+    http,
+    sessions::port::SessionStore,
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+use tracing::error;
+
+struct ApiState<T> {
+    sessions: T,
+    config: PresenceConfig,
+}
+
+pub fn router<T>(sessions: T, config: PresenceConfig) -> Router
+where
+    T: SessionStore + 'static,
+{
+    Router::new()
+        .route("/presence/hubs/:hub_id", get(hub_presence::<T>))
+        .with_state(Arc::new(ApiState { sessions, config }))
+}
+
+async fn hub_presence<T: SessionStore>(
+    State(state): State<Arc<ApiState<T>>>,
+    Path(hub_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Json<HubPresence>, StatusCode> {
+    http::authorize(&headers, &state.config.api_token)?;
+    let online = state
+        .sessions
+        .online(Role::Hub, vec![hub_id.clone()])
+        .await
+        .map_err(|error| {
+            error!(%error, "Failed to get the presence of the hub");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(Json(HubPresence {
+        online: online.contains(&hub_id),
+        hub_id,
+    }))
+}
@@ -0,0 +1,98 @@
+use crate::socket::encoding::Encoding;
+use serde::Serialize;
+
+/// Versions of the wire protocol spoken with clients.
+///
+/// Event names and message payloads are a contract with already shipped apps,
+/// so any breaking change to them must go into a new version.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(into = "u32")]
+pub enum ProtocolVersion {
+    V1,
+}
+
+/// Versions the server can speak, from oldest to newest.
+pub const SUPPORTED_VERSIONS: &[ProtocolVersion] = &[ProtocolVersion::V1];
+
+/// Version assumed for clients that don't send one, which predate versioning.
+pub const DEFAULT_VERSION: ProtocolVersion = ProtocolVersion::V1;
+
+impl From<ProtocolVersion> for u32 {
+    fn from(version: ProtocolVersion) -> Self {
+        match version {
+            ProtocolVersion::V1 => 1,
+        }
+    }
+}
+
+impl TryFrom<u32> for ProtocolVersion {
+    type Error = u32;
+
+    fn try_from(version: u32) -> Result<Self, Self::Error> {
+        SUPPORTED_VERSIONS
+            .iter()
+            .copied()
+            .find(|supported| u32::from(*supported) == version)
+            .ok_or(version)
+    }
+}
+
+impl ProtocolVersion {
+    /// Picks the version to speak with a client that requested `requested`.
+    pub fn negotiate(requested: Option<u32>) -> Result<Self, u32> {
+        requested.map_or(Ok(DEFAULT_VERSION), Self::try_from)
+    }
+}
+
+/// Sent to clients right after they connect to let them know which version is in use.
+#[derive(Serialize)]
+#[cfg_attr(test, derive(Debug, PartialEq, schemars::JsonSchema))]
+pub struct ProtocolInfo {
+    #[cfg_attr(test, schemars(with = "u32"))]
+    pub version: ProtocolVersion,
+    #[cfg_attr(test, schemars(with = "Vec<u32>"))]
+    pub supported_versions: &'static [ProtocolVersion],
+    pub encoding: Encoding,
+}
+
+impl ProtocolInfo {
+    pub fn new(version: ProtocolVersion, encoding: Encoding) -> Self {
+        Self {
+            version,
+            supported_versions: SUPPORTED_VERSIONS,
+            encoding,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProtocolInfo, ProtocolVersion};
+    use crate::socket::encoding::Encoding;
+    use serde_json::json;
+
+    #[test]
+    fn clients_without_a_version_speak_v1() {
+        assert_eq!(ProtocolVersion::negotiate(None), Ok(ProtocolVersion::V1));
+    }
+
+    #[test]
+    fn can_negotiate_a_supported_version() {
+        assert_eq!(ProtocolVersion::negotiate(Some(1)), Ok(ProtocolVersion::V1));
+    }
+
+    #[test]
+    fn can_not_negotiate_an_unsupported_version() {
+        assert_eq!(ProtocolVersion::negotiate(Some(2)), Err(2));
+        assert_eq!(ProtocolVersion::negotiate(Some(0)), Err(0));
+    }
+
+    #[test]
+    fn test_serialize_protocol_info_to_json() {
+        let info = ProtocolInfo::new(ProtocolVersion::V1, Encoding::MessagePack);
+        assert_eq!(
+            json!(info).to_string(),
+            r#"{"encoding":"msgpack","supported_versions":[1],"version":1}"#
+        )
+    }
+}
@@ -0,0 +1,217 @@
+//! Opt-in recording of sessions and their replay.
+//!
+//! Hubs and controllers send `recording_consent` to agree to record their session or to
+//! withdraw their consent, and the vibrate commands relayed while both agree are recorded
+//! with their timing. The recording is saved when the session finishes, keyed by the session
+//! id. Hubs can play it back later by starting a session with `start_replay`.
+
+pub mod api;
+pub mod messages;
+
+use super::{
+    controller::messages::{event_names::VIBRATE, VibrateCmd},
+    dispatch::{params, Caller, Reply},
+    hub::messages::event_names::REPLAY_FINISHED,
+    tenant::TenantState,
+    Role,
+};
+use crate::{
+    sessions::{
+        activity::SessionActivity,
+        port::SessionStore,
+        recording::{Consents, RecordedCommand, Recording, SessionRecordings},
+    },
+    socket::{adapters::jsonrpc::ErrorObject, port::ClientSocket},
+};
+use messages::{event_names, RecordingConsent, RecordingStatus};
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tracing::{debug, error, info};
+use uuid::Uuid;
+
+/// Handles `recording_consent` from a hub or a controller, through either transport.
+pub async fn consent<T, E, C>(
+    caller: C,
+    data: Value,
+    role: Role,
+    state: Arc<TenantState<T, E>>,
+) -> Result<Reply, ErrorObject>
+where
+    C: Caller,
+{
+    let consent: RecordingConsent = params(data)?;
+    let status = on_recording_consent(caller.socket(&state), role, consent, &state.recordings);
+    Ok(Reply::new(json!(status)))
+}
+
+pub fn on_recording_consent<S>(
+    socket: S,
+    role: Role,
+    RecordingConsent { consent }: RecordingConsent,
+    recordings: &SessionRecordings,
+) -> RecordingStatus
+where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    let Some(session_id) = socket.get_stored_value() else {
+        return RecordingStatus::from(Consents::default());
+    };
+
+    debug!(%session_id, ?role, consent, "Recording consent changed");
+    let status = RecordingStatus::from(recordings.set_consent(session_id, role, consent));
+    if let Err(error) = socket.emit_to_room(
+        session_id.into(),
+        event_names::RECORDING_STATUS.into(),
+        status,
+    ) {
+        error!(%error, "Failed to send recording status");
+    }
+    status
+}
+
+/// Saves what was recorded of a session that is finishing.
+/// Saves what was recorded of a session, owned by the hub with the client id `owner`.
+pub async fn save_recording<T>(
+    session_id: Uuid,
+    owner: Option<String>,
+    sessions: &T,
+    recordings: &SessionRecordings,
+) where
+    T: SessionStore,
+{
+    let Some(recording) = recordings.finish(session_id) else {
+        return;
+    };
+    let recording = Recording { owner, ..recording };
+
+    info!(%session_id, commands = recording.commands.len(), "Saving session recording");
+    if let Err(error) = sessions.save_recording(recording).await {
+        error!(%error, "Failed to save session recording");
+    }
+}
+
+/// Sends the commands of a recording to the hub of a replay session, with their original timing.
+///
+/// `socket` returns the hub's socket, or `None` once it has disconnected.
+pub async fn play<S>(
+    socket: impl Fn() -> Option<S>,
+    session_id: Uuid,
+    recording: Recording,
+    activity: &SessionActivity,
+) where
+    S: ClientSocket<StoreItem = Uuid>,
+{
+    let in_session = || socket().filter(|socket| socket.get_stored_value() == Some(session_id));
+    let started = tokio::time::Instant::now();
+
+    for RecordedCommand(offset, value) in recording.commands {
+        tokio::time::sleep_until(started + Duration::from_millis(offset)).await;
+        let Some(socket) = in_session() else {
+            return;
+        };
+
+        activity.record(session_id);
+        let cmd = VibrateCmd {
+            value,
+            execute_at: None,
+            seq: None,
+            timeout_ms: None,
+        };
+        if let Err(error) = socket.emit(VIBRATE.into(), cmd) {
+            error!(%error, "Failed to send replayed command");
+        }
+    }
+
+    if let Some(socket) = in_session() {
+        if let Err(error) = socket.emit(REPLAY_FINISHED.into(), ()) {
+            error!(%error, "Failed to send replay_finished event");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{messages::RecordingStatus, on_recording_consent, save_recording};
+    use crate::{
+        actors::{recording::messages::RecordingConsent, Role},
+        sessions::{port::MockSessionStore, recording::SessionRecordings},
+        socket::port::MockClientSocket,
+    };
+    use futures_util::FutureExt;
+    use mockall::predicate::eq;
+    use uuid::Uuid;
+
+    #[test]
+    fn shares_consent_changes_with_the_session() {
+        let recordings = SessionRecordings::default();
+        recordings.set_consent(Uuid::nil(), Role::Hub, true);
+        let status = RecordingStatus {
+            recording: true,
+            hub: true,
+            controller: true,
+        };
+
+        let mut socket = MockClientSocket::new();
+        socket
+            .expect_get_stored_value()
+            .times(1)
+            .return_const(Some(Uuid::nil()));
+        socket
+            .expect_emit_to_room()
+            .times(1)
+            .with(
+                eq(Uuid::nil().to_string()),
+                eq("recording_status".to_string()),
+                eq(status),
+            )
+            .return_const(Ok(()));
+
+        let result = on_recording_consent(
+            socket,
+            Role::Controller,
+            RecordingConsent { consent: true },
+            &recordings,
+        );
+
+        assert_eq!(result, status);
+    }
+
+    #[test]
+    fn ignores_consent_outside_of_a_session() {
+        let recordings = SessionRecordings::default();
+        let mut socket = MockClientSocket::new();
+        socket.expect_get_stored_value().times(1).return_const(None);
+        socket.expect_emit_to_room::<RecordingStatus>().never();
+
+        let result = on_recording_consent(
+            socket,
+            Role::Hub,
+            RecordingConsent { consent: true },
+            &recordings,
+        );
+
+        assert!(!result.hub);
+    }
+
+    #[tokio::test]
+    async fn saves_recorded_sessions() {
+        let recordings = SessionRecordings::default();
+        recordings.set_consent(Uuid::nil(), Role::Hub, true);
+        recordings.set_consent(Uuid::nil(), Role::Controller, true);
+        recordings.record(Uuid::nil(), 0.5);
+
+        let mut sessions = MockSessionStore::new();
+        sessions
+            .expect_save_recording()
+            .times(1)
+            .withf(|recording| {
+                recording.id == Uuid::nil()
+                    && recording.commands.len() == 1
+                    && recording.owner.as_deref() == Some("hub")
+            })
+            .returning(|_| async { Ok(()) }.boxed());
+
+        save_recording(Uuid::nil(), Some("hub".into()), &sessions, &recordings).await;
+        save_recording(Uuid::nil(), Some("hub".into()), &sessions, &recordings).await;
+    }
+}
@@ -0,0 +1,68 @@
+//! HTTP API to list and download recordings, meant for the backends of the apps.
+//!
+//! Every request must carry the configured token as `Authorization: Bearer <token>`.
+
+use crate::{
+    configuration::RecordingsConfig,
+    http,
+    sessions::{
+        port::SessionStore,
+        recording::{Recording, RecordingSummary},
+    },
+};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+struct ApiState<T> {
+    sessions: T,
+    config: RecordingsConfig,
+}
+
+pub fn router<T>(sessions: T, config: RecordingsConfig) -> Router
+where
+    T: SessionStore + 'static,
+{
+    Router::new()
+        .route("/recordings", get(list::<T>))
+        .route("/recordings/:id", get(download::<T>))
+        .with_state(Arc::new(ApiState { sessions, config }))
+}
+
+fn authorize(headers: &HeaderMap, config: &RecordingsConfig) -> Result<(), StatusCode> {
+    http::authorize(headers, &config.api_token)
+}
+
+async fn list<T: SessionStore>(
+    State(state): State<Arc<ApiState<T>>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<RecordingSummary>>, StatusCode> {
+    authorize(&headers, &state.config)?;
+    let recordings = state.sessions.recordings().await.map_err(|error| {
+        error!(%error, "Failed to list recordings");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(recordings))
+}
+
+async fn download<T: SessionStore>(
+    State(state): State<Arc<ApiState<T>>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<Recording>, StatusCode> {
+    authorize(&headers, &state.config)?;
+    match state.sessions.recording(id).await {
+        Ok(Some(recording)) => Ok(Json(recording)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(error) => {
+            error!(%error, "Failed to get recording");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
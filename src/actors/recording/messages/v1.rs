@@ -0,0 +1,65 @@
+use crate::sessions::recording::Consents;
+use serde::{Deserialize, Serialize};
+
+/// Names of the events used to record sessions, exchanged with hubs and controllers.
+pub mod event_names {
+    pub const RECORDING_CONSENT: &str = "recording_consent";
+    pub const RECORDING_STATUS: &str = "recording_status";
+}
+
+/// Sent by a participant to agree to record the session, or to withdraw its consent.
+#[derive(Deserialize, Debug)]
+#[cfg_attr(test, derive(PartialEq, serde::Serialize, schemars::JsonSchema))]
+pub struct RecordingConsent {
+    pub consent: bool,
+}
+
+/// Sent to both participants when a consent changes, the session is recorded while both agree.
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(
+    test,
+    derive(Debug, PartialEq, serde::Deserialize, schemars::JsonSchema)
+)]
+pub struct RecordingStatus {
+    pub recording: bool,
+    pub hub: bool,
+    pub controller: bool,
+}
+
+impl From<Consents> for RecordingStatus {
+    fn from(consents: Consents) -> Self {
+        Self {
+            recording: consents.recording(),
+            hub: consents.hub,
+            controller: consents.controller,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordingConsent, RecordingStatus};
+    use crate::{sessions::recording::Consents, socket::encoding::assert_msgpack_round_trip};
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize_recording_status() {
+        let status = RecordingStatus::from(Consents {
+            hub: true,
+            controller: false,
+        });
+        assert_eq!(
+            json!(status).to_string(),
+            r#"{"controller":false,"hub":true,"recording":false}"#
+        );
+    }
+
+    #[test]
+    fn messages_round_trip_through_msgpack() {
+        assert_msgpack_round_trip(RecordingConsent { consent: true });
+        assert_msgpack_round_trip(RecordingStatus::from(Consents {
+            hub: true,
+            controller: true,
+        }));
+    }
+}
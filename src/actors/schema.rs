@@ -0,0 +1,226 @@
+//! JSON Schema of the wire protocol, derived from the message types.
+//!
+//! The schema of each protocol version is committed under `schema/` so client teams can
+//! generate their types from it. Run `cargo make update-schema` after changing a message.
+
+use super::{
+    controller::messages::v1 as controller_v1,
+    hub::messages::v1 as hub_v1,
+    latency::messages::v1 as latency_v1,
+    protocol::{ProtocolInfo, ProtocolVersion},
+    recording::messages::v1 as recording_v1,
+    Auth, ConnectErrorResponse,
+};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Peer {
+    Server,
+    Hub,
+    Controller,
+}
+
+#[derive(Serialize)]
+struct EventSchema {
+    name: &'static str,
+    from: Peer,
+    to: Peer,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<Schema>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ack: Option<Schema>,
+}
+
+#[derive(Serialize)]
+struct ProtocolSchema {
+    version: ProtocolVersion,
+    auth: Schema,
+    events: Vec<EventSchema>,
+    definitions: BTreeMap<String, Schema>,
+}
+
+struct Events {
+    generator: SchemaGenerator,
+    events: Vec<EventSchema>,
+}
+
+impl Events {
+    fn new() -> Self {
+        Self {
+            generator: SchemaSettings::draft07().into_generator(),
+            events: vec![],
+        }
+    }
+
+    fn event(mut self, name: &'static str, from: Peer, to: Peer) -> Self {
+        self.events.push(EventSchema {
+            name,
+            from,
+            to,
+            payload: None,
+            ack: None,
+        });
+        self
+    }
+
+    fn with_payload<T: JsonSchema>(mut self) -> Self {
+        let schema = self.generator.subschema_for::<T>();
+        self.events.last_mut().unwrap().payload = Some(schema);
+        self
+    }
+
+    fn with_ack<T: JsonSchema>(mut self) -> Self {
+        let schema = self.generator.subschema_for::<T>();
+        self.events.last_mut().unwrap().ack = Some(schema);
+        self
+    }
+
+    fn into_schema(mut self, version: ProtocolVersion) -> ProtocolSchema {
+        let auth = self.generator.subschema_for::<Auth>();
+        ProtocolSchema {
+            version,
+            auth,
+            events: self.events,
+            definitions: self.generator.take_definitions().into_iter().collect(),
+        }
+    }
+}
+
+fn v1() -> Events {
+    use controller_v1::event_names::*;
+    use hub_v1::event_names::*;
+    use latency_v1::event_names::*;
+    use recording_v1::event_names::*;
+
+    Events::new()
+        .event("protocol", Peer::Server, Peer::Hub)
+        .with_payload::<ProtocolInfo>()
+        .event("protocol", Peer::Server, Peer::Controller)
+        .with_payload::<ProtocolInfo>()
+        .event("connect_error", Peer::Server, Peer::Hub)
+        .with_payload::<ConnectErrorResponse>()
+        .event("connect_error", Peer::Server, Peer::Controller)
+        .with_payload::<ConnectErrorResponse>()
+        .event(START_SESSION, Peer::Hub, Peer::Server)
+        .with_payload::<Option<hub_v1::StartSessionRequest>>()
+        .with_ack::<hub_v1::StartSessionResponse>()
+        .event(SESSION_FINISHED, Peer::Server, Peer::Hub)
+        .event(SESSION_FINISHED, Peer::Server, Peer::Controller)
+        .event(DEVICE_STATUS, Peer::Hub, Peer::Server)
+        .with_payload::<hub_v1::DeviceStatus>()
+        .with_ack::<hub_v1::DeviceStatusResponse>()
+        .event(DEVICE_STATUS, Peer::Server, Peer::Controller)
+        .with_payload::<hub_v1::DeviceStatus>()
+        .event(JOIN_SESSION, Peer::Controller, Peer::Server)
+        .with_payload::<controller_v1::JoinSessionRequest>()
+        .with_ack::<controller_v1::JoinSessionResponse>()
+        .event(JOIN_REQUEST, Peer::Server, Peer::Hub)
+        .with_payload::<controller_v1::JoinSessionPermissionRequest>()
+        .with_ack::<controller_v1::JoinSessionPermissionResponse>()
+        .event(CANCEL_JOIN, Peer::Controller, Peer::Server)
+        .with_ack::<controller_v1::CancelJoinResponse>()
+        .event(JOIN_REQUEST_CANCELLED, Peer::Server, Peer::Hub)
+        .event(RESOLVE_ROOM, Peer::Controller, Peer::Server)
+        .with_payload::<controller_v1::ResolveRoomRequest>()
+        .with_ack::<controller_v1::ResolveRoomResponse>()
+        .event(HUBS_ONLINE, Peer::Controller, Peer::Server)
+        .with_payload::<controller_v1::HubsOnlineRequest>()
+        .with_ack::<controller_v1::HubsOnlineResponse>()
+        .event(JOIN_PENDING, Peer::Server, Peer::Controller)
+        .with_payload::<controller_v1::JoinPending>()
+        .event(CONTROLLER_JOINED, Peer::Server, Peer::Hub)
+        .event(CONTROLLER_DISCONNECTED, Peer::Server, Peer::Hub)
+        .event(VIBRATE, Peer::Controller, Peer::Server)
+        .with_payload::<controller_v1::VibrateCmd>()
+        .with_ack::<controller_v1::CommandResult>()
+        .event(VIBRATE, Peer::Server, Peer::Hub)
+        .with_payload::<controller_v1::VibrateCmd>()
+        .with_ack::<controller_v1::CommandAck>()
+        .event(ERROR, Peer::Server, Peer::Controller)
+        .with_payload::<controller_v1::ControllerErrorMsg>()
+        .event(PING, Peer::Server, Peer::Hub)
+        .with_payload::<latency_v1::Ping>()
+        .with_ack::<latency_v1::Pong>()
+        .event(PING, Peer::Server, Peer::Controller)
+        .with_payload::<latency_v1::Ping>()
+        .with_ack::<latency_v1::Pong>()
+        .event(SESSION_STATS, Peer::Server, Peer::Hub)
+        .with_payload::<latency_v1::SessionStats>()
+        .event(SESSION_STATS, Peer::Server, Peer::Controller)
+        .with_payload::<latency_v1::SessionStats>()
+        .event(RECORDING_CONSENT, Peer::Hub, Peer::Server)
+        .with_payload::<recording_v1::RecordingConsent>()
+        .with_ack::<recording_v1::RecordingStatus>()
+        .event(RECORDING_CONSENT, Peer::Controller, Peer::Server)
+        .with_payload::<recording_v1::RecordingConsent>()
+        .with_ack::<recording_v1::RecordingStatus>()
+        .event(RECORDING_STATUS, Peer::Server, Peer::Hub)
+        .with_payload::<recording_v1::RecordingStatus>()
+        .event(RECORDING_STATUS, Peer::Server, Peer::Controller)
+        .with_payload::<recording_v1::RecordingStatus>()
+        .event(START_REPLAY, Peer::Hub, Peer::Server)
+        .with_payload::<hub_v1::StartReplayRequest>()
+        .with_ack::<hub_v1::StartSessionResponse>()
+        .event(REPLAY_FINISHED, Peer::Server, Peer::Hub)
+        .event(JOIN_ATTEMPTS, Peer::Hub, Peer::Server)
+        .with_payload::<hub_v1::JoinAttemptsRequest>()
+        .with_ack::<hub_v1::JoinAttemptsResponse>()
+        .event(RELEASE_ROOM, Peer::Hub, Peer::Server)
+        .with_payload::<hub_v1::ReleaseRoomRequest>()
+        .with_ack::<hub_v1::ReleaseRoomResponse>()
+        .event(TRUST_CONTROLLER, Peer::Hub, Peer::Server)
+        .with_payload::<hub_v1::TrustControllerRequest>()
+        .with_ack::<hub_v1::TrustedControllersResponse>()
+        .event(UNTRUST_CONTROLLER, Peer::Hub, Peer::Server)
+        .with_payload::<hub_v1::TrustControllerRequest>()
+        .with_ack::<hub_v1::TrustedControllersResponse>()
+        .event(TRUSTED_CONTROLLERS, Peer::Hub, Peer::Server)
+        .with_ack::<hub_v1::TrustedControllersResponse>()
+        .event(CONTROLLER_PRESENCE, Peer::Server, Peer::Hub)
+        .with_payload::<hub_v1::ControllerPresence>()
+        .event(HUB_PRESENCE, Peer::Server, Peer::Controller)
+        .with_payload::<controller_v1::HubPresence>()
+}
+
+fn generate(version: ProtocolVersion) -> ProtocolSchema {
+    match version {
+        ProtocolVersion::V1 => v1().into_schema(version),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+    use crate::actors::protocol::SUPPORTED_VERSIONS;
+    use std::{env, fs, path::Path};
+
+    #[test]
+    fn committed_schemas_match_the_message_types() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema");
+
+        for version in SUPPORTED_VERSIONS {
+            let path = dir.join(format!("v{}.json", u32::from(*version)));
+            let schema = serde_json::to_string_pretty(&generate(*version)).unwrap() + "\n";
+
+            if env::var_os("UPDATE_SCHEMA").is_some() {
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(&path, schema).unwrap();
+                continue;
+            }
+
+            let committed = fs::read_to_string(&path).unwrap_or_default();
+            assert!(
+                committed == schema,
+                "{} is out of date, run `cargo make update-schema` to regenerate it",
+                path.display()
+            );
+        }
+    }
+}
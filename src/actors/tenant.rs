@@ -0,0 +1,58 @@
+use super::{hub, presence};
+use crate::{
+    configuration::Config,
+    moderation::{adapters::word_list::WordListFilter, guard::JoinGuard},
+    sessions::{
+        activity::SessionActivity, commands::CommandSequences, latency::SessionLatency,
+        pending::PendingJoins, recording::SessionRecordings,
+    },
+    socket::adapters::{
+        jsonrpc::{self, PeerId, Peers},
+        local::{self, GlobalSocketImpl},
+    },
+};
+use socketioxide::{extract::SocketRef, socket::Sid, SocketIo};
+
+/// State of a tenant, owned by the app and handed to the handlers of its socket.io clients
+/// and JSON-RPC peers.
+pub struct TenantState<T, E> {
+    pub sessions: T,
+    pub activity: SessionActivity,
+    pub latency: SessionLatency,
+    pub device_status_limiter: hub::DeviceStatusLimiter,
+    pub hubs_online_limiter: presence::HubsOnlineLimiter,
+    pub join_guard: JoinGuard<WordListFilter>,
+    pub pending_joins: PendingJoins,
+    pub recordings: SessionRecordings,
+    pub commands: CommandSequences,
+    pub events: E,
+    pub config: Config,
+    pub io: SocketIo,
+    /// Namespace of the tenant, whose socket.io clients share rooms with the peers.
+    pub namespace: String,
+    pub peers: Peers,
+}
+
+impl<T, E> TenantState<T, E> {
+    pub fn client_socket(&self, peer: PeerId) -> jsonrpc::ClientSocketImpl {
+        jsonrpc::ClientSocketImpl::new(
+            self.peers.clone(),
+            self.io.clone(),
+            self.namespace.clone(),
+            peer,
+        )
+    }
+
+    pub fn local_socket(&self, socket: SocketRef) -> local::ClientSocketImpl {
+        local::ClientSocketImpl::new(socket, self.peers.clone())
+    }
+
+    /// Socket of a socket.io client of the tenant, `None` once it has disconnected.
+    pub fn local_socket_of(&self, sid: Sid) -> Option<local::ClientSocketImpl> {
+        local::socket_of(&self.io, &self.namespace, sid).map(|socket| self.local_socket(socket))
+    }
+
+    pub fn global_socket(&self) -> GlobalSocketImpl {
+        GlobalSocketImpl::new(self.io.clone(), self.namespace.clone(), self.peers.clone())
+    }
+}
@@ -0,0 +1,221 @@
+//! Tells hubs and the controllers they trust when the other side connects or disconnects.
+//!
+//! Only clients that proved their client id have one, so only they join identity rooms.
+
+use crate::{
+    actors::{
+        controller::{event_names as controller_events, HubPresence},
+        hub::messages::{event_names as hub_events, ControllerPresence},
+        Role,
+    },
+    sessions::port::SessionStore,
+    socket::port::ClientSocket,
+};
+use tracing::error;
+use uuid::Uuid;
+
+/// Room with every connection of a client id, so it can be reached without being in a session.
+pub fn identity_room(role: Role, client_id: &str) -> String {
+    match role {
+        Role::Hub => format!("hub:{client_id}"),
+        Role::Controller => format!("controller:{client_id}"),
+    }
+}
+
+/// Puts an identified client in its room and tells its trusted peers it is online.
+pub async fn go_online<S, T>(socket: &S, role: Role, sessions: &T)
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    let Some(client_id) = socket.client_id() else {
+        return;
+    };
+    if let Err(error) = socket.join(identity_room(role, &client_id)) {
+        error!(%error, "Socket failed to join its identity room");
+        return;
+    }
+    announce(socket, role, client_id, true, sessions).await;
+}
+
+/// Tells the trusted peers of an identified client it went offline, once its last connection
+/// is gone from every instance.
+///
+/// The connection must have been forgotten by [`presence`](super::presence) already.
+pub async fn go_offline<S, T>(socket: &S, role: Role, sessions: &T)
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    let Some(client_id) = socket.client_id() else {
+        return;
+    };
+    let room = identity_room(role, &client_id);
+    if let Err(error) = socket.leave(room.clone()) {
+        error!(%error, "Socket failed to leave its identity room");
+    }
+    match sessions.online(role, vec![client_id.clone()]).await {
+        Ok(online) if online.contains(&client_id) => return,
+        Ok(_) => {}
+        Err(error) => {
+            error!(%error, "Failed to get the presence of the client");
+            return;
+        }
+    }
+    announce(socket, role, client_id, false, sessions).await;
+}
+
+async fn announce<S, T>(socket: &S, role: Role, client_id: String, online: bool, sessions: &T)
+where
+    S: ClientSocket<StoreItem = Uuid>,
+    T: SessionStore,
+{
+    match role {
+        Role::Hub => {
+            let controllers = match sessions.trusted_controllers(client_id.clone()).await {
+                Ok(controllers) => controllers,
+                Err(error) => {
+                    error!(%error, "Failed to get the controllers trusted by the hub");
+                    return;
+                }
+            };
+            for controller_id in controllers {
+                let presence = HubPresence {
+                    hub_id: client_id.clone(),
+                    online,
+                };
+                if let Err(error) = socket.emit_to_room(
+                    identity_room(Role::Controller, &controller_id),
+                    controller_events::HUB_PRESENCE.into(),
+                    presence,
+                ) {
+                    error!(%error, "Failed to tell a controller the hub presence");
+                }
+            }
+        }
+        Role::Controller => {
+            let hubs = match sessions.trusting_hubs(client_id.clone()).await {
+                Ok(hubs) => hubs,
+                Err(error) => {
+                    error!(%error, "Failed to get the hubs trusting the controller");
+                    return;
+                }
+            };
+            for hub_id in hubs {
+                let presence = ControllerPresence {
+                    controller_id: client_id.clone(),
+                    online,
+                };
+                if let Err(error) = socket.emit_to_room(
+                    identity_room(Role::Hub, &hub_id),
+                    hub_events::CONTROLLER_PRESENCE.into(),
+                    presence,
+                ) {
+                    error!(%error, "Failed to tell a hub the controller presence");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{go_offline, go_online};
+    use crate::{
+        actors::{controller::HubPresence, hub::messages::ControllerPresence, Role},
+        sessions::port::MockSessionStore,
+        socket::port::MockClientSocket,
+    };
+    use futures_util::FutureExt;
+    use mockall::predicate::eq;
+
+    #[tokio::test]
+    async fn trusted_controllers_are_told_when_the_hub_comes_online() {
+        let mut socket = MockClientSocket::new();
+        let mut sessions = MockSessionStore::new();
+        socket
+            .expect_client_id()
+            .return_const(Some("hub".to_string()));
+        socket
+            .expect_join()
+            .with(eq("hub:hub".to_string()))
+            .times(1)
+            .return_const(Ok(()));
+        sessions
+            .expect_trusted_controllers()
+            .with(eq("hub".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(["alice".to_string(), "bob".to_string()].into()) }.boxed());
+        for controller_id in ["alice", "bob"] {
+            socket
+                .expect_emit_to_room::<HubPresence>()
+                .with(
+                    eq(format!("controller:{controller_id}")),
+                    eq("hub_presence".to_string()),
+                    eq(HubPresence {
+                        hub_id: "hub".into(),
+                        online: true,
+                    }),
+                )
+                .times(1)
+                .return_const(Ok(()));
+        }
+
+        go_online(&socket, Role::Hub, &sessions).await;
+    }
+
+    #[tokio::test]
+    async fn controllers_stay_online_while_they_have_another_connection() {
+        let mut socket = MockClientSocket::new();
+        let mut sessions = MockSessionStore::new();
+        socket
+            .expect_client_id()
+            .return_const(Some("alice".to_string()));
+        socket.expect_leave().times(1).return_const(Ok(()));
+        sessions
+            .expect_online()
+            .with(eq(Role::Controller), eq(vec!["alice".to_string()]))
+            .times(1)
+            .returning(|_, ids| async move { Ok(ids.into_iter().collect()) }.boxed());
+        sessions.expect_trusting_hubs().never();
+
+        go_offline(&socket, Role::Controller, &sessions).await;
+    }
+
+    #[tokio::test]
+    async fn trusting_hubs_are_told_when_the_controller_goes_offline() {
+        let mut socket = MockClientSocket::new();
+        let mut sessions = MockSessionStore::new();
+        socket
+            .expect_client_id()
+            .return_const(Some("alice".to_string()));
+        socket
+            .expect_leave()
+            .with(eq("controller:alice".to_string()))
+            .times(1)
+            .return_const(Ok(()));
+        sessions
+            .expect_online()
+            .times(1)
+            .returning(|_, _| async { Ok(Default::default()) }.boxed());
+        sessions
+            .expect_trusting_hubs()
+            .with(eq("alice".to_string()))
+            .times(1)
+            .returning(|_| async { Ok(["hub".to_string()].into()) }.boxed());
+        socket
+            .expect_emit_to_room::<ControllerPresence>()
+            .with(
+                eq("hub:hub".to_string()),
+                eq("controller_presence".to_string()),
+                eq(ControllerPresence {
+                    controller_id: "alice".into(),
+                    online: false,
+                }),
+            )
+            .times(1)
+            .return_const(Ok(()));
+
+        go_offline(&socket, Role::Controller, &sessions).await;
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{
+    actors::{
+        self, hub::DeviceStatusLimiter, presence, presence::HubsOnlineLimiter, recording,
+        tenant::TenantState, Auth,
+    },
+    configuration::Config,
+    events::{adapters::tenant::TenantEventSink, port::EventSink},
+    moderation::{adapters::word_list::WordListFilter, guard::JoinGuard},
+    sessions::{
+        activity::SessionActivity, commands::CommandSequences, latency::SessionLatency,
+        pending::PendingJoins, port::SessionStore, recording::SessionRecordings,
+    },
+    socket::adapters::jsonrpc::Peers,
+};
+use axum::{routing::get, Router};
+use socketioxide::{
+    extract::{SocketRef, TryData},
+    SocketIo,
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// State shared by the clients of every tenant.
+///
+/// Only holds state keyed by session ids, which are unique across tenants.
+#[derive(Clone, Default)]
+struct Shared {
+    activity: SessionActivity,
+    latency: SessionLatency,
+    recordings: SessionRecordings,
+    commands: CommandSequences,
+}
+
+/// Mounts the socket.io server, the JSON-RPC endpoint and the health check on top of `router`.
+///
+/// The root namespace is served with `sessions` and every tenant of `config` with its store in
+/// `tenant_sessions`.
+pub fn build<T, E>(
+    router: Router,
+    sessions: T,
+    mut tenant_sessions: BTreeMap<String, T>,
+    events: E,
+    config: Config,
+) -> Router
+where
+    T: SessionStore + Clone + 'static,
+    E: EventSink + Clone + 'static,
+{
+    let shared = Shared::default();
+
+    let (layer, io) = SocketIo::new_layer();
+
+    let mut router = router.merge(serve_tenant(
+        &io,
+        "/",
+        sessions,
+        TenantEventSink::new(None, events.clone()),
+        config.clone(),
+        &shared,
+    ));
+
+    for name in config.tenants.keys() {
+        let sessions = tenant_sessions
+            .remove(name)
+            .unwrap_or_else(|| panic!("Missing session store for tenant '{name}'"));
+        let tenant = serve_tenant(
+            &io,
+            &format!("/{name}"),
+            sessions,
+            TenantEventSink::new(Some(name.clone()), events.clone()),
+            config.for_tenant(name),
+            &shared,
+        );
+        router = router.nest(&format!("/{name}"), tenant);
+    }
+
+    router
+        .route("/health-check", get(|| async { "ok" }))
+        .layer(layer)
+}
+
+/// Serves the socket.io clients of `namespace` and returns the routes of its JSON-RPC
+/// endpoint and HTTP APIs.
+fn serve_tenant<T, E>(
+    io: &SocketIo,
+    namespace: &str,
+    sessions: T,
+    events: E,
+    config: Config,
+    shared: &Shared,
+) -> Router
+where
+    T: SessionStore + Clone + 'static,
+    E: EventSink + Clone + 'static,
+{
+    let router = match config.recordings.clone() {
+        Some(recordings_config) => {
+            Router::new().merge(recording::api::router(sessions.clone(), recordings_config))
+        }
+        None => Router::new(),
+    };
+
+    let router = match config.presence.clone() {
+        Some(presence_config) => {
+            router.merge(presence::api::router(sessions.clone(), presence_config))
+        }
+        None => router,
+    };
+
+    let state = Arc::new(TenantState {
+        sessions,
+        activity: shared.activity.clone(),
+        latency: shared.latency.clone(),
+        device_status_limiter: DeviceStatusLimiter::default(),
+        hubs_online_limiter: HubsOnlineLimiter::default(),
+        join_guard: JoinGuard::new(
+            WordListFilter::new(&config.blocked_words),
+            &config.controller,
+        ),
+        pending_joins: PendingJoins::default(),
+        recordings: shared.recordings.clone(),
+        commands: shared.commands.clone(),
+        events,
+        config,
+        io: io.clone(),
+        namespace: namespace.to_owned(),
+        peers: Peers::default(),
+    });
+
+    // Both transports of the tenant share its state, the clients of other tenants never see it
+    io.ns(namespace.to_owned(), {
+        let state = state.clone();
+        move |socket: SocketRef, auth: TryData<Auth>| actors::on_connect(socket, auth, state)
+    });
+
+    let rpc = Router::new()
+        .route("/rpc", get(actors::jsonrpc::on_upgrade::<T, E>))
+        .with_state(state);
+
+    router.merge(rpc)
+}
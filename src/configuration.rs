@@ -1,16 +1,148 @@
-use serde::Deserialize;
+use crate::{
+    events::adapters::{redis as redis_events, webhook},
+    sessions::adapters::{
+        redis::pool::{ClusterConfig, SentinelConfig},
+        resilient,
+    },
+};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
 
 #[derive(Clone, Deserialize)]
 pub struct Config {
     pub controller: ControllerConfig,
+    pub hub: HubConfig,
     #[serde(default)]
     pub redis: deadpool_redis::Config,
+    /// Connects to the primary the sentinels point to instead of `redis.url`.
+    pub redis_sentinel: Option<SentinelConfig>,
+    /// Spreads the keys over a cluster instead of using `redis.url` or `redis_sentinel`.
+    pub redis_cluster: Option<ClusterConfig>,
+    /// Retries and circuit breaker around the calls to the session store.
+    #[serde(default)]
+    pub session_store: resilient::Config,
+    /// Prepended to the keys of the sessions, tenants add their name to it.
+    #[serde(default)]
+    pub redis_key_prefix: String,
+    /// Moves the keys written before `redis_key_prefix` was set under it on startup.
+    #[serde(default)]
+    pub migrate_legacy_redis_keys: bool,
     pub session_ttl: Option<i64>,
+    pub session_heartbeat_interval: u64,
+    /// Seconds between the presence heartbeats of each connection of an identified client.
+    pub presence_heartbeat_interval: u64,
+    pub session_idle_timeout: Option<u64>,
+    pub latency_probe_interval: Option<u64>,
+    pub events: Option<redis_events::Config>,
+    pub webhooks: Option<webhook::Config>,
+    pub recordings: Option<RecordingsConfig>,
+    pub presence: Option<PresenceConfig>,
+    pub metrics: Option<MetricsConfig>,
+    /// Words that can't appear in join requests.
+    #[serde(default)]
+    pub blocked_words: Vec<String>,
+    /// Keys clients send to connect, anyone can connect if there are none.
+    #[serde(default)]
+    pub auth_keys: Vec<String>,
+    /// Key the backend of the app signs client ids with, clients can't claim one without it.
+    pub identity_secret: Option<String>,
+    /// Apps served from their own namespace besides the root one, by name.
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantConfig>,
+}
+
+/// A white-label app whose clients never see those of the other apps.
+///
+/// Its clients connect to the `/<name>` namespace, or to `/<name>/rpc`, and its sessions are
+/// stored apart from the others.
+#[derive(Clone, Deserialize)]
+pub struct TenantConfig {
+    /// Keys the clients of the tenant send to connect, anyone can connect if there are none.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub auth_keys: Vec<String>,
+    /// Key the backend of the tenant signs client ids with, its clients can't claim one without
+    /// it.
+    pub identity_secret: Option<String>,
+    /// Endpoints the webhooks of the tenant's sessions are sent to instead of the top level ones.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub webhook_urls: Vec<String>,
+    /// Limits of the tenant's controllers, the top level ones if missing.
+    pub controller: Option<ControllerConfig>,
+    /// Limits of the tenant's hubs, the top level ones if missing.
+    pub hub: Option<HubConfig>,
+}
+
+/// Reads a list from a comma separated string, since the environment can't tell the lists of
+/// tenants apart before knowing their names.
+fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let list = String::deserialize(deserializer)?;
+    Ok(list
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Whether `name` can name a tenant, and so a namespace and a key prefix.
+fn is_valid_tenant_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
 }
 
 #[derive(Clone, Copy, Deserialize)]
 pub struct ControllerConfig {
+    /// Seconds hubs have to answer join requests, unless they choose their own.
     pub session_join_request_timeout: u64,
+    /// Shortest join request timeout hubs can choose, in seconds.
+    pub min_session_join_request_timeout: u64,
+    /// Longest join request timeout hubs can choose, in seconds.
+    pub max_session_join_request_timeout: u64,
+    pub command_ack_timeout: u64,
+    /// Longest message of a join request, in characters.
+    pub join_message_max_len: usize,
+    /// Minimum time between two join attempts from the same address or client id, in
+    /// milliseconds.
+    pub join_attempt_min_interval_ms: u64,
+}
+
+impl ControllerConfig {
+    pub fn allows_join_request_timeout(&self, seconds: u64) -> bool {
+        (self.min_session_join_request_timeout..=self.max_session_join_request_timeout)
+            .contains(&seconds)
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct HubConfig {
+    /// Minimum time between two statuses of the same device, in milliseconds.
+    pub device_status_min_interval_ms: u64,
+}
+
+/// Enables the HTTP API to list and download recordings.
+#[derive(Clone, Deserialize)]
+pub struct RecordingsConfig {
+    /// Bearer token required by every request
+    pub api_token: String,
+}
+
+/// Enables the HTTP API to check whether hubs are online.
+#[derive(Clone, Deserialize)]
+pub struct PresenceConfig {
+    /// Bearer token required by every request
+    pub api_token: String,
+}
+
+/// Enables the HTTP API to read the metrics of the session store.
+#[derive(Clone, Deserialize)]
+pub struct MetricsConfig {
+    /// Bearer token required by every request
+    pub api_token: String,
 }
 
 impl Config {
@@ -19,11 +151,115 @@ impl Config {
             .add_source(
                 config::Environment::default()
                     .try_parsing(true)
-                    .separator("__"),
+                    .separator("__")
+                    .list_separator(",")
+                    .with_list_parse_key("webhooks.urls")
+                    .with_list_parse_key("blocked_words")
+                    .with_list_parse_key("auth_keys")
+                    .with_list_parse_key("redis_sentinel.urls")
+                    .with_list_parse_key("redis_cluster.urls"),
             )
             .build()
             .expect("Failed to load app configuration")
-            .try_deserialize()
+            .try_deserialize::<Self>()
             .expect("Cannot deserialize configuration")
+            .validate()
+    }
+
+    fn validate(self) -> Self {
+        if let Some(name) = self.tenants.keys().find(|name| !is_valid_tenant_name(name)) {
+            panic!("Invalid tenant name '{name}', use lowercase letters, digits, '-' and '_'");
+        }
+        if self.redis_sentinel.is_some() && self.redis_cluster.is_some() {
+            panic!("Configure either REDIS_SENTINEL or REDIS_CLUSTER, not both");
+        }
+        if self.session_heartbeat_interval == 0 {
+            panic!("SESSION_HEARTBEAT_INTERVAL must be at least 1 second");
+        }
+        if self.presence_heartbeat_interval == 0 {
+            panic!("PRESENCE_HEARTBEAT_INTERVAL must be at least 1 second");
+        }
+        if self.latency_probe_interval == Some(0) {
+            panic!("LATENCY_PROBE_INTERVAL must be at least 1 second, leave it unset to disable probes");
+        }
+        self
+    }
+
+    /// Configuration the clients of a tenant are served with.
+    pub fn for_tenant(&self, name: &str) -> Self {
+        let tenant = &self.tenants[name];
+        Self {
+            controller: tenant.controller.unwrap_or(self.controller),
+            hub: tenant.hub.unwrap_or(self.hub),
+            auth_keys: tenant.auth_keys.clone(),
+            identity_secret: tenant.identity_secret.clone(),
+            tenants: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_tenant_name, Config, HubConfig, TenantConfig};
+
+    #[test]
+    fn tenants_override_the_top_level_limits() {
+        let mut config = Config::load();
+        config.auth_keys = vec!["root-key".into()];
+        config.tenants.insert(
+            "acme".into(),
+            TenantConfig {
+                auth_keys: vec!["acme-key".into()],
+                identity_secret: None,
+                webhook_urls: vec![],
+                controller: None,
+                hub: Some(HubConfig {
+                    device_status_min_interval_ms: 5,
+                }),
+            },
+        );
+
+        let tenant = config.for_tenant("acme");
+        assert_eq!(tenant.auth_keys, vec!["acme-key".to_string()]);
+        assert_eq!(tenant.hub.device_status_min_interval_ms, 5);
+        assert_eq!(
+            tenant.controller.command_ack_timeout,
+            config.controller.command_ack_timeout
+        );
+        assert!(tenant.tenants.is_empty());
+    }
+
+    #[test]
+    fn tenant_names_can_be_namespaces() {
+        assert!(is_valid_tenant_name("acme"));
+        assert!(is_valid_tenant_name("white_label-2"));
+        assert!(!is_valid_tenant_name(""));
+        assert!(!is_valid_tenant_name("Acme"));
+        assert!(!is_valid_tenant_name("acme/app"));
+    }
+
+    #[test]
+    #[should_panic(expected = "SESSION_HEARTBEAT_INTERVAL")]
+    fn heartbeats_need_an_interval() {
+        let mut config = Config::load();
+        config.session_heartbeat_interval = 0;
+        config.validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "PRESENCE_HEARTBEAT_INTERVAL")]
+    fn presence_heartbeats_need_an_interval() {
+        let mut config = Config::load();
+        config.presence_heartbeat_interval = 0;
+        config.validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "LATENCY_PROBE_INTERVAL")]
+    fn latency_probes_need_an_interval() {
+        let mut config = Config::load();
+        config.latency_probe_interval = Some(0);
+        config.validate();
     }
 }
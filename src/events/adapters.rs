@@ -0,0 +1,5 @@
+pub mod fanout;
+pub mod noop;
+pub mod redis;
+pub mod tenant;
+pub mod webhook;
@@ -0,0 +1,16 @@
+use crate::events::port::{EventSink, PublishEventError, SessionEvent};
+
+/// Publishes every event to two sinks. Nest it to fan out to more than two.
+#[derive(Clone)]
+pub struct FanoutEventSink<A, B>(pub A, pub B);
+
+impl<A, B> EventSink for FanoutEventSink<A, B>
+where
+    A: EventSink,
+    B: EventSink,
+{
+    async fn publish(&self, event: SessionEvent) -> Result<(), PublishEventError> {
+        let (first, second) = tokio::join!(self.0.publish(event.clone()), self.1.publish(event));
+        first.and(second)
+    }
+}
@@ -0,0 +1,11 @@
+use crate::events::port::{EventSink, PublishEventError, SessionEvent};
+
+/// Event sink used when no event stream is configured. Every event is discarded.
+#[derive(Clone, Default)]
+pub struct NoopEventSink;
+
+impl EventSink for NoopEventSink {
+    async fn publish(&self, _event: SessionEvent) -> Result<(), PublishEventError> {
+        Ok(())
+    }
+}
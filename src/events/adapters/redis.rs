@@ -0,0 +1,130 @@
+use crate::{
+    events::port::{EventSink, PublishEventError, SessionEvent},
+    sessions::adapters::redis::pool::{self, RedisPool},
+};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// Events are sent through `PUBLISH` and only reach currently connected subscribers
+    #[default]
+    PubSub,
+    /// Events are appended to a stream so consumers can read them at their own pace
+    Stream,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Config {
+    /// Name of the channel or stream events are sent to. Events of a tenant go to
+    /// `<key>:<tenant>` instead.
+    pub key: String,
+    #[serde(default)]
+    pub transport: Transport,
+    /// Approximate number of entries kept in the stream, only used by the `stream` transport
+    pub stream_max_len: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct RedisEventSink {
+    pool: RedisPool,
+    config: Config,
+}
+
+impl RedisEventSink {
+    pub fn new(pool: RedisPool, config: Config) -> Self {
+        Self { pool, config }
+    }
+}
+
+impl RedisEventSink {
+    fn key(&self, event: &SessionEvent) -> String {
+        match &event.tenant {
+            Some(tenant) => format!("{}:{tenant}", self.config.key),
+            None => self.config.key.clone(),
+        }
+    }
+}
+
+impl EventSink for RedisEventSink {
+    async fn publish(&self, event: SessionEvent) -> Result<(), PublishEventError> {
+        let key = self.key(&event);
+        let payload = serde_json::to_string(&event)?;
+        match self.config.transport {
+            Transport::PubSub => pool::publish(&self.pool, key, payload)
+                .await
+                .map_err(Into::into)
+                .map_err(PublishEventError::IoError)?,
+            Transport::Stream => pool::stream_add(
+                &self.pool,
+                key,
+                &[("event", payload)],
+                self.config.stream_max_len,
+            )
+            .await
+            .map_err(Into::into)
+            .map_err(PublishEventError::IoError)?,
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, RedisEventSink, Transport};
+    use crate::{
+        configuration,
+        events::port::{EventSink, SessionEvent, SessionEventKind},
+        sessions::adapters::redis::pool,
+        sessions::role::Role,
+    };
+    use test_context::{test_context, AsyncTestContext};
+    use uuid::Uuid;
+
+    struct Context {
+        pool: pool::RedisPool,
+    }
+
+    impl AsyncTestContext for Context {
+        async fn setup() -> Self {
+            let config = configuration::Config::load();
+            let pool = pool::connect(
+                &config.redis,
+                config.redis_sentinel.as_ref(),
+                config.redis_cluster.as_ref(),
+            )
+            .expect("Can't connect to redis");
+            Self { pool }
+        }
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn can_publish_events_to_a_channel(ctx: Context) {
+        let sink = RedisEventSink::new(
+            ctx.pool.clone(),
+            Config {
+                key: "test:events:pubsub".into(),
+                transport: Transport::PubSub,
+                stream_max_len: None,
+            },
+        );
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+        assert!(sink.publish(event).await.is_ok());
+    }
+
+    #[test_context(Context, skip_teardown)]
+    #[tokio::test]
+    async fn can_publish_events_to_a_stream(ctx: Context) {
+        let sink = RedisEventSink::new(
+            ctx.pool.clone(),
+            Config {
+                key: "test:events:stream".into(),
+                transport: Transport::Stream,
+                stream_max_len: Some(100),
+            },
+        );
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+        assert!(sink.publish(event).await.is_ok());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::events::port::{EventSink, PublishEventError, SessionEvent};
+
+/// Tags the events of a namespace with its tenant before publishing them, so the sinks shared
+/// by every tenant can route them.
+#[derive(Clone)]
+pub struct TenantEventSink<E> {
+    tenant: Option<String>,
+    inner: E,
+}
+
+impl<E> TenantEventSink<E> {
+    /// `tenant` is `None` for the root namespace.
+    pub fn new(tenant: Option<String>, inner: E) -> Self {
+        Self { tenant, inner }
+    }
+}
+
+impl<E> EventSink for TenantEventSink<E>
+where
+    E: EventSink,
+{
+    async fn publish(&self, event: SessionEvent) -> Result<(), PublishEventError> {
+        let event = SessionEvent {
+            tenant: self.tenant.clone(),
+            ..event
+        };
+        self.inner.publish(event).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TenantEventSink;
+    use crate::{
+        events::port::{EventSink, MockEventSink, SessionEvent, SessionEventKind},
+        sessions::role::Role,
+    };
+    use futures_util::FutureExt;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn events_are_tagged_with_their_tenant() {
+        let mut inner = MockEventSink::new();
+        inner
+            .expect_publish()
+            .withf(|event| event.tenant.as_deref() == Some("acme"))
+            .times(1)
+            .returning(|_| async { Ok(()) }.boxed());
+        let sink = TenantEventSink::new(Some("acme".into()), inner);
+
+        sink.publish(SessionEvent::new(
+            Uuid::nil(),
+            Role::Hub,
+            SessionEventKind::SessionStarted,
+        ))
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,610 @@
+use crate::{
+    events::port::{EventSink, PublishEventError, SessionEvent, SessionEventKind},
+    http,
+};
+use anyhow::anyhow;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError, Receiver, Sender},
+    OwnedSemaphorePermit, Semaphore,
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+pub const SIGNATURE_HEADER: &str = "x-intisync-signature";
+pub const EVENT_HEADER: &str = "x-intisync-event";
+pub const DELIVERY_HEADER: &str = "x-intisync-delivery";
+
+#[derive(Clone, Deserialize)]
+pub struct Config {
+    pub urls: Vec<String>,
+    /// Key used to sign every payload with HMAC-SHA256
+    pub secret: String,
+    /// Token required as `Authorization: Bearer <token>` to read the delivery log
+    pub api_token: String,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every following attempt
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Seconds to wait for an endpoint to answer
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout: u64,
+    #[serde(default = "default_delivery_log_size")]
+    pub delivery_log_size: usize,
+    /// Deliveries waiting to be sent, new events are dropped once it is full
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// Deliveries being sent at once, retries included. The others wait in the queue.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_request_timeout() -> u64 {
+    10
+}
+
+fn default_delivery_log_size() -> usize {
+    100
+}
+
+fn default_queue_size() -> usize {
+    1000
+}
+
+fn default_max_in_flight() -> usize {
+    10
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Delivered { status_code: u16 },
+    Failed { reason: String },
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DeliveryRecord {
+    pub delivery_id: Uuid,
+    pub url: String,
+    pub event: &'static str,
+    pub attempt: u32,
+    pub status: DeliveryStatus,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Bounded log of the most recent delivery attempts, newest last.
+#[derive(Clone)]
+pub struct DeliveryLog {
+    records: Arc<Mutex<VecDeque<DeliveryRecord>>>,
+    capacity: usize,
+}
+
+impl DeliveryLog {
+    fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    fn push(&self, record: DeliveryRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    pub fn records(&self) -> Vec<DeliveryRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+struct Delivery {
+    id: Uuid,
+    url: String,
+    event: &'static str,
+    payload: Arc<String>,
+}
+
+#[derive(Clone)]
+pub struct WebhookEventSink {
+    urls: Arc<[String]>,
+    tenant_urls: Arc<BTreeMap<String, Vec<String>>>,
+    api_token: Arc<str>,
+    queue: Sender<Delivery>,
+    log: DeliveryLog,
+}
+
+impl WebhookEventSink {
+    /// Creates the sink and spawns the task delivering queued events in the background.
+    pub fn new(config: Config) -> Self {
+        let (queue, receiver) = mpsc::channel(config.queue_size);
+        let log = DeliveryLog::new(config.delivery_log_size);
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.request_timeout))
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        let dispatcher = Dispatcher {
+            client,
+            secret: config.secret.into(),
+            max_attempts: config.max_attempts,
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight)),
+            log: log.clone(),
+        };
+        tokio::spawn(dispatcher.run(receiver));
+
+        Self {
+            urls: config.urls.into(),
+            tenant_urls: Default::default(),
+            api_token: config.api_token.into(),
+            queue,
+            log,
+        }
+    }
+
+    /// Sends the events of the tenants in `tenant_urls` to their own endpoints instead of the
+    /// configured ones.
+    pub fn with_tenant_urls(self, tenant_urls: BTreeMap<String, Vec<String>>) -> Self {
+        Self {
+            tenant_urls: Arc::new(tenant_urls),
+            ..self
+        }
+    }
+
+    fn urls(&self, event: &SessionEvent) -> &[String] {
+        event
+            .tenant
+            .as_ref()
+            .and_then(|tenant| self.tenant_urls.get(tenant))
+            .map_or(&self.urls, |urls| urls.as_slice())
+    }
+
+    pub fn delivery_log(&self) -> &DeliveryLog {
+        &self.log
+    }
+
+    /// Serves the delivery log at `/webhooks/deliveries` to the holders of the API token.
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/webhooks/deliveries", get(deliveries))
+            .with_state(self.clone())
+    }
+
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        http::authorize(headers, &self.api_token)
+    }
+}
+
+async fn deliveries(
+    State(sink): State<WebhookEventSink>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<DeliveryRecord>>, StatusCode> {
+    sink.authorize(&headers)?;
+    Ok(Json(sink.delivery_log().records()))
+}
+
+impl EventSink for WebhookEventSink {
+    async fn publish(&self, event: SessionEvent) -> Result<(), PublishEventError> {
+        if event.kind == SessionEventKind::ControllerLeft {
+            return Ok(());
+        }
+
+        let payload = Arc::new(serde_json::to_string(&event)?);
+        for url in self.urls(&event) {
+            self.queue
+                .try_send(Delivery {
+                    id: Uuid::new_v4(),
+                    url: url.clone(),
+                    event: event.kind.name(),
+                    payload: payload.clone(),
+                })
+                .map_err(|error| match error {
+                    TrySendError::Full(_) => anyhow!("Webhook delivery queue is full"),
+                    TrySendError::Closed(_) => anyhow!("Webhook delivery queue is closed"),
+                })?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Dispatcher {
+    client: reqwest::Client,
+    secret: Arc<str>,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    in_flight: Arc<Semaphore>,
+    log: DeliveryLog,
+}
+
+impl Dispatcher {
+    /// Takes a delivery off the queue whenever one of the in flight ones is done, so slow
+    /// endpoints fill the queue and new events get dropped instead of piling up.
+    async fn run(self, mut receiver: Receiver<Delivery>) {
+        loop {
+            let Ok(permit) = self.in_flight.clone().acquire_owned().await else {
+                return;
+            };
+            let Some(delivery) = receiver.recv().await else {
+                return;
+            };
+            tokio::spawn(self.clone().deliver(delivery, permit));
+        }
+    }
+
+    /// Sends the delivery until it succeeds or runs out of attempts, holding its permit
+    /// through the retries.
+    async fn deliver(self, delivery: Delivery, _permit: OwnedSemaphorePermit) {
+        for attempt in 1..=self.max_attempts {
+            let status = match self.send(&delivery).await {
+                Ok(status_code) => DeliveryStatus::Delivered { status_code },
+                Err(reason) => DeliveryStatus::Failed { reason },
+            };
+
+            self.log.push(DeliveryRecord {
+                delivery_id: delivery.id,
+                url: delivery.url.clone(),
+                event: delivery.event,
+                attempt,
+                status: status.clone(),
+                timestamp: now_millis(),
+            });
+
+            let DeliveryStatus::Failed { reason } = status else {
+                debug!(url = %delivery.url, "Webhook delivered");
+                return;
+            };
+
+            if attempt >= self.max_attempts {
+                warn!(url = %delivery.url, %reason, "Giving up on webhook delivery");
+                return;
+            }
+
+            let backoff = self.backoff(attempt);
+            warn!(url = %delivery.url, %reason, ?backoff, "Webhook delivery failed, retrying");
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    async fn send(&self, delivery: &Delivery) -> Result<u16, String> {
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&self.secret, &delivery.payload))
+            .header(EVENT_HEADER, delivery.event)
+            .header(DELIVERY_HEADER, delivery.id.to_string())
+            .body(delivery.payload.as_ref().clone())
+            .send()
+            .await
+            .map_err(|error| error.to_string())?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("Endpoint answered with status {status}"));
+        }
+        Ok(status.as_u16())
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+}
+
+/// Signature sent in the [`SIGNATURE_HEADER`] header, in the form `sha256=<hex digest>`.
+pub fn sign(secret: &str, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Local HTTP endpoint recording every webhook it receives.
+#[cfg(test)]
+pub mod receiver {
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    #[derive(Clone, Debug)]
+    pub struct ReceivedRequest {
+        pub headers: HashMap<String, String>,
+        pub body: String,
+    }
+
+    #[derive(Clone, Default)]
+    struct Inner {
+        requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+        failures: usize,
+    }
+
+    pub struct TestReceiver {
+        addr: SocketAddr,
+        inner: Inner,
+    }
+
+    impl TestReceiver {
+        /// Starts the receiver on an ephemeral port. The first `failures` requests are
+        /// answered with a server error, every following one with `200 OK`.
+        pub async fn start(failures: usize) -> Self {
+            let inner = Inner {
+                failures,
+                ..Default::default()
+            };
+            let app = Router::new()
+                .route("/", post(receive))
+                .with_state(inner.clone());
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            Self { addr, inner }
+        }
+
+        pub fn url(&self) -> String {
+            format!("http://{}/", self.addr)
+        }
+
+        pub fn requests(&self) -> Vec<ReceivedRequest> {
+            self.inner.requests.lock().unwrap().clone()
+        }
+
+        /// Waits up to five seconds for at least `count` requests to arrive.
+        pub async fn wait_for_requests(&self, count: usize) -> Vec<ReceivedRequest> {
+            for _ in 0..500 {
+                let requests = self.requests();
+                if requests.len() >= count {
+                    return requests;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("Timed out waiting for {count} webhook requests");
+        }
+    }
+
+    async fn receive(State(inner): State<Inner>, headers: HeaderMap, body: String) -> StatusCode {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    value.to_str().unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
+        let mut requests = inner.requests.lock().unwrap();
+        requests.push(ReceivedRequest { headers, body });
+        if requests.len() <= inner.failures {
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::OK
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        receiver::TestReceiver, sign, Config, DeliveryStatus, WebhookEventSink, EVENT_HEADER,
+        SIGNATURE_HEADER,
+    };
+    use crate::{
+        events::port::{EventSink, SessionEvent, SessionEventKind},
+        sessions::role::Role,
+    };
+    use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn config(url: String, max_attempts: u32) -> Config {
+        Config {
+            urls: vec![url],
+            secret: "potatoe".into(),
+            max_attempts,
+            initial_backoff_ms: 10,
+            api_token: "tomatoe".into(),
+            request_timeout: 1,
+            delivery_log_size: 10,
+            queue_size: 10,
+            max_in_flight: 10,
+        }
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        assert_eq!(
+            sign("key", "The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_events() {
+        let receiver = TestReceiver::start(0).await;
+        let sink = WebhookEventSink::new(config(receiver.url(), 1));
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+
+        sink.publish(event.clone()).await.unwrap();
+
+        let requests = receiver.wait_for_requests(1).await;
+        let request = &requests[0];
+        assert_eq!(
+            request.headers[SIGNATURE_HEADER],
+            sign("potatoe", &request.body)
+        );
+        assert_eq!(request.headers[EVENT_HEADER], "session_started");
+        assert_eq!(request.body, serde_json::to_string(&event).unwrap());
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries() {
+        let receiver = TestReceiver::start(2).await;
+        let sink = WebhookEventSink::new(config(receiver.url(), 3));
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+
+        sink.publish(event).await.unwrap();
+
+        receiver.wait_for_requests(3).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(receiver.requests().len(), 3);
+
+        let records = sink.delivery_log().records();
+        assert_eq!(records.len(), 3);
+        assert!(records
+            .iter()
+            .all(|record| record.delivery_id == records[0].delivery_id));
+        assert!(matches!(records[0].status, DeliveryStatus::Failed { .. }));
+        assert!(matches!(records[1].status, DeliveryStatus::Failed { .. }));
+        assert_eq!(
+            records[2].status,
+            DeliveryStatus::Delivered { status_code: 200 }
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let receiver = TestReceiver::start(usize::MAX).await;
+        let sink = WebhookEventSink::new(config(receiver.url(), 2));
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+
+        sink.publish(event).await.unwrap();
+
+        receiver.wait_for_requests(2).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(receiver.requests().len(), 2);
+        assert_eq!(sink.delivery_log().records().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn drops_events_once_the_queue_is_full() {
+        let receiver = TestReceiver::start(0).await;
+        let sink = WebhookEventSink::new(Config {
+            queue_size: 1,
+            ..config(receiver.url(), 1)
+        });
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+
+        let results =
+            futures_util::future::join_all((0..10).map(|_| sink.publish(event.clone()))).await;
+
+        assert!(results.iter().any(Result::is_err));
+    }
+
+    #[tokio::test]
+    async fn delivers_the_events_of_a_tenant_to_its_endpoints() {
+        let receiver = TestReceiver::start(0).await;
+        let acme_receiver = TestReceiver::start(0).await;
+        let sink = WebhookEventSink::new(config(receiver.url(), 1))
+            .with_tenant_urls([("acme".into(), vec![acme_receiver.url()])].into());
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+
+        sink.publish(SessionEvent {
+            tenant: Some("acme".into()),
+            ..event.clone()
+        })
+        .await
+        .unwrap();
+        sink.publish(SessionEvent {
+            tenant: Some("globex".into()),
+            ..event
+        })
+        .await
+        .unwrap();
+
+        let requests = acme_receiver.wait_for_requests(1).await;
+        assert!(requests[0].body.contains(r#""tenant":"acme""#));
+        let requests = receiver.wait_for_requests(1).await;
+        assert!(requests[0].body.contains(r#""tenant":"globex""#));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(acme_receiver.requests().len(), 1);
+        assert_eq!(receiver.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn queues_deliveries_while_the_endpoints_are_slow() {
+        // Accepts connections but never answers, so every delivery waits for its timeout
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let sink = WebhookEventSink::new(Config {
+            queue_size: 1,
+            max_in_flight: 1,
+            ..config(url, 1)
+        });
+        let event = SessionEvent::new(Uuid::nil(), Role::Hub, SessionEventKind::SessionStarted);
+
+        sink.publish(event.clone()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        sink.publish(event.clone()).await.unwrap();
+
+        assert!(sink.publish(event).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delivery_log_requires_the_api_token() {
+        let sink = WebhookEventSink::new(config("http://127.0.0.1:1/".into(), 1));
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(sink.authorize(&headers), Err(StatusCode::UNAUTHORIZED));
+
+        headers.insert(AUTHORIZATION, "Bearer potatoe".parse().unwrap());
+        assert_eq!(sink.authorize(&headers), Err(StatusCode::UNAUTHORIZED));
+
+        headers.insert(AUTHORIZATION, "Bearer tomatoe".parse().unwrap());
+        assert_eq!(sink.authorize(&headers), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn does_not_deliver_controller_left_events() {
+        let receiver = TestReceiver::start(0).await;
+        let sink = WebhookEventSink::new(config(receiver.url(), 1));
+        let event = SessionEvent::new(
+            Uuid::nil(),
+            Role::Controller,
+            SessionEventKind::ControllerLeft,
+        );
+
+        sink.publish(event).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(receiver.requests().is_empty());
+    }
+}
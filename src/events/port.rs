@@ -0,0 +1,151 @@
+use crate::sessions::role::Role;
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinOutcome {
+    Accepted,
+    Rejected,
+    HubResponseTimeout,
+    Cancelled,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    HubDisconnected,
+    Idle,
+    Expired,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEventKind {
+    SessionStarted,
+    JoinAttempt { outcome: JoinOutcome },
+    ControllerLeft,
+    SessionFinished { reason: FinishReason },
+}
+
+impl SessionEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SessionStarted => "session_started",
+            Self::JoinAttempt { .. } => "join_attempt",
+            Self::ControllerLeft => "controller_left",
+            Self::SessionFinished { .. } => "session_finished",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SessionEvent {
+    /// Tenant whose clients the session belongs to, missing for the root namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub session_id: Uuid,
+    pub role: Role,
+    #[serde(flatten)]
+    pub kind: SessionEventKind,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl SessionEvent {
+    pub fn new(session_id: Uuid, role: Role, kind: SessionEventKind) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            tenant: None,
+            session_id,
+            role,
+            kind,
+            timestamp,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PublishEventError {
+    #[error("Failed to serialize event: '{0}'")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Failed to publish event: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[cfg_attr(test, mockall::automock)]
+pub trait EventSink: Send + Sync {
+    fn publish(
+        &self,
+        event: SessionEvent,
+    ) -> impl std::future::Future<Output = Result<(), PublishEventError>> + std::marker::Send;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JoinOutcome, SessionEvent, SessionEventKind};
+    use crate::sessions::role::Role;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_serialize_session_event_to_json() {
+        let event = SessionEvent {
+            tenant: None,
+            session_id: Uuid::nil(),
+            role: Role::Controller,
+            kind: SessionEventKind::JoinAttempt {
+                outcome: JoinOutcome::Rejected,
+            },
+            timestamp: 42,
+        };
+        assert_eq!(
+            json!(event).to_string(),
+            format!(
+                r#"{{"outcome":"rejected","role":"controller","session_id":"{}","timestamp":42,"type":"join_attempt"}}"#,
+                Uuid::nil()
+            )
+        );
+    }
+
+    #[test]
+    fn test_serialize_session_event_without_payload_to_json() {
+        let event = SessionEvent {
+            tenant: None,
+            session_id: Uuid::nil(),
+            role: Role::Hub,
+            kind: SessionEventKind::SessionStarted,
+            timestamp: 42,
+        };
+        assert_eq!(
+            json!(event).to_string(),
+            format!(
+                r#"{{"role":"hub","session_id":"{}","timestamp":42,"type":"session_started"}}"#,
+                Uuid::nil()
+            )
+        );
+    }
+
+    #[test]
+    fn test_serialize_tenant_session_event_to_json() {
+        let event = SessionEvent {
+            tenant: Some("acme".into()),
+            session_id: Uuid::nil(),
+            role: Role::Hub,
+            kind: SessionEventKind::SessionStarted,
+            timestamp: 42,
+        };
+        assert_eq!(
+            json!(event).to_string(),
+            format!(
+                r#"{{"role":"hub","session_id":"{}","tenant":"acme","timestamp":42,"type":"session_started"}}"#,
+                Uuid::nil()
+            )
+        );
+    }
+}
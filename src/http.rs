@@ -0,0 +1,46 @@
+//! Helpers shared by the HTTP APIs served next to the socket.io server.
+
+use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+use subtle::ConstantTimeEq;
+
+/// Only lets through the requests carrying `token` as `Authorization: Bearer <token>`.
+///
+/// The token is compared in constant time so the time to answer doesn't tell how much of it a
+/// guess got right.
+pub fn authorize(headers: &HeaderMap, token: &str) -> Result<(), StatusCode> {
+    let expected = format!("Bearer {token}");
+    match headers.get(AUTHORIZATION) {
+        Some(value) if bool::from(value.as_bytes().ct_eq(expected.as_bytes())) => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::authorize;
+    use axum::http::{header::AUTHORIZATION, HeaderMap, StatusCode};
+
+    #[test]
+    fn only_the_bearer_of_the_token_is_authorized() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            authorize(&headers, "tomatoe"),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        headers.insert(AUTHORIZATION, "Bearer potatoe".parse().unwrap());
+        assert_eq!(
+            authorize(&headers, "tomatoe"),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        headers.insert(AUTHORIZATION, "tomatoe".parse().unwrap());
+        assert_eq!(
+            authorize(&headers, "tomatoe"),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        headers.insert(AUTHORIZATION, "Bearer tomatoe".parse().unwrap());
+        assert_eq!(authorize(&headers, "tomatoe"), Ok(()));
+    }
+}
@@ -0,0 +1,1428 @@
+//! End to end tests driving the whole application through real socket.io connections.
+
+mod client;
+mod rpc_client;
+
+use crate::{
+    actors::{identity, latency::unix_millis, Role},
+    app,
+    configuration::{
+        Config, ControllerConfig, HubConfig, PresenceConfig, RecordingsConfig, TenantConfig,
+    },
+    events::adapters::webhook::{self, receiver::TestReceiver, WebhookEventSink},
+    sessions::{
+        adapters::memory::InMemorySessionStore,
+        port::{SessionState, SessionStore},
+    },
+};
+use axum::Router;
+use client::TestClient;
+use rpc_client::RpcClient;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use test_context::{test_context, AsyncTestContext};
+use uuid::Uuid;
+
+/// Handle to the application under test.
+///
+/// socketioxide keeps its state in a process wide static that can only be set once, so a
+/// single server is shared by every test. It runs on its own runtime because each test
+/// gets a runtime of its own that is dropped when the test finishes.
+#[derive(Clone)]
+struct TestServer {
+    addr: SocketAddr,
+    sessions: InMemorySessionStore,
+    /// Receives the webhooks of every test.
+    webhooks: Arc<TestReceiver>,
+}
+
+static SERVER: OnceLock<TestServer> = OnceLock::new();
+
+impl AsyncTestContext for TestServer {
+    async fn setup() -> Self {
+        SERVER.get_or_init(TestServer::start).clone()
+    }
+}
+
+impl TestServer {
+    fn start() -> Self {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _runtime = runtime.enter();
+        // The runtime of the test calling this can't block, another thread has to
+        let webhooks = std::thread::scope(|scope| {
+            scope
+                .spawn(|| runtime.block_on(TestReceiver::start(0)))
+                .join()
+                .unwrap()
+        });
+        let webhooks_config = webhook::Config {
+            urls: vec![webhooks.url()],
+            secret: WEBHOOK_SECRET.into(),
+            api_token: "webhooks".into(),
+            max_attempts: 1,
+            initial_backoff_ms: 10,
+            request_timeout: 1,
+            delivery_log_size: 10,
+            queue_size: 100,
+            max_in_flight: 10,
+        };
+
+        let sessions = InMemorySessionStore::default();
+        let config = Config {
+            controller: ControllerConfig {
+                session_join_request_timeout: 2,
+                min_session_join_request_timeout: 1,
+                max_session_join_request_timeout: 5,
+                command_ack_timeout: 2,
+                join_message_max_len: 280,
+                join_attempt_min_interval_ms: 1000,
+            },
+            hub: HubConfig {
+                device_status_min_interval_ms: 1000,
+            },
+            redis: Default::default(),
+            redis_sentinel: None,
+            redis_cluster: None,
+            session_store: Default::default(),
+            redis_key_prefix: String::new(),
+            migrate_legacy_redis_keys: false,
+            session_ttl: None,
+            session_heartbeat_interval: 60,
+            presence_heartbeat_interval: 30,
+            session_idle_timeout: None,
+            latency_probe_interval: None,
+            events: None,
+            webhooks: Some(webhooks_config.clone()),
+            recordings: Some(RecordingsConfig {
+                api_token: "secret".into(),
+            }),
+            presence: Some(PresenceConfig {
+                api_token: PRESENCE_API_TOKEN.into(),
+            }),
+            metrics: None,
+            blocked_words: vec!["spam".into()],
+            auth_keys: vec![],
+            identity_secret: Some(IDENTITY_SECRET.into()),
+            tenants: BTreeMap::from([(
+                "acme".into(),
+                TenantConfig {
+                    auth_keys: vec!["acme-key".into()],
+                    identity_secret: None,
+                    webhook_urls: vec![],
+                    controller: None,
+                    hub: None,
+                },
+            )]),
+        };
+        let tenant_sessions = BTreeMap::from([("acme".into(), InMemorySessionStore::default())]);
+        let app = app::build(
+            Router::new(),
+            sessions.clone(),
+            tenant_sessions,
+            WebhookEventSink::new(webhooks_config),
+            config,
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                axum::serve(listener, app).await
+            })
+        });
+
+        Self {
+            addr,
+            sessions,
+            webhooks: Arc::new(webhooks),
+        }
+    }
+}
+
+impl TestServer {
+    async fn hub(&self) -> TestClient {
+        self.connect(json!({ "role": "hub" })).await
+    }
+
+    async fn controller(&self) -> TestClient {
+        self.connect(json!({ "role": "controller" })).await
+    }
+
+    /// Connects a client and waits for the server to confirm the protocol version.
+    async fn connect(&self, auth: Value) -> TestClient {
+        self.connect_to("/", auth).await
+    }
+
+    /// Connects a client to `namespace` and waits for the server to confirm the protocol
+    /// version.
+    async fn connect_to(&self, namespace: &str, auth: Value) -> TestClient {
+        let encoding = auth.get("encoding").cloned().unwrap_or(json!("json"));
+        let mut client = TestClient::connect_to(self.addr, namespace, auth).await;
+        let protocol = client.expect_event("protocol").await;
+        assert_eq!(
+            protocol.data,
+            json!({ "version": 1, "supported_versions": [1], "encoding": encoding })
+        );
+        client
+    }
+
+    async fn session_state(&self, session_id: Uuid) -> Option<SessionState> {
+        self.sessions.session_state(session_id).await.unwrap()
+    }
+
+    /// Sends a GET request to the HTTP API, returning the status and the JSON body if any.
+    async fn get(&self, path: &str, token: &str) -> (u16, Value) {
+        let response = reqwest::Client::new()
+            .get(format!("http://{}{path}", self.addr))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = response.text().await.unwrap();
+        (status, serde_json::from_str(&body).unwrap_or_default())
+    }
+
+    /// Polls the session store until the session reaches the given state.
+    async fn wait_for_state(&self, session_id: Uuid, state: Option<SessionState>) {
+        for _ in 0..100 {
+            if self.session_state(session_id).await == state {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Session never reached state {state:?}");
+    }
+
+    /// Polls the session store until a client is online.
+    async fn wait_until_online(&self, role: Role, client_id: &str) {
+        for _ in 0..100 {
+            let online = self
+                .sessions
+                .online(role, vec![client_id.to_owned()])
+                .await
+                .unwrap();
+            if online.contains(client_id) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Client never became online");
+    }
+
+    /// Waits for the webhook of an event of a session, returning its body.
+    async fn wait_for_webhook(&self, session_id: Uuid, event: &str) -> Value {
+        for _ in 0..100 {
+            let webhook = self.webhooks.requests().into_iter().find_map(|request| {
+                let body = serde_json::from_str::<Value>(&request.body).ok()?;
+                let matches = body["session_id"] == json!(session_id) && body["type"] == event;
+                assert_eq!(
+                    request.headers[webhook::SIGNATURE_HEADER],
+                    webhook::sign(WEBHOOK_SECRET, &request.body)
+                );
+                matches.then_some(body)
+            });
+            if let Some(webhook) = webhook {
+                return webhook;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Webhook {event} of session {session_id} never arrived");
+    }
+
+    /// Polls the presence API until the hub is online or offline.
+    async fn wait_for_hub_presence(&self, hub_id: &str, online: bool) {
+        for _ in 0..100 {
+            let (_, presence) = self
+                .get(&format!("/presence/hubs/{hub_id}"), PRESENCE_API_TOKEN)
+                .await;
+            if presence == json!({ "hub_id": hub_id, "online": online }) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!(
+            "Hub never became {}",
+            if online { "online" } else { "offline" }
+        );
+    }
+}
+
+impl TestServer {
+    /// Connects a JSON-RPC client and waits for the server to confirm the protocol version.
+    async fn rpc(&self, query: &str) -> RpcClient {
+        let encoding = if query.contains("encoding=msgpack") {
+            "msgpack"
+        } else {
+            "json"
+        };
+        let mut client = RpcClient::connect(self.addr, query).await;
+        let protocol = client.expect_method("protocol").await;
+        assert_eq!(
+            protocol["params"],
+            json!({ "version": 1, "supported_versions": [1], "encoding": encoding })
+        );
+        client
+    }
+}
+
+const IDENTITY_SECRET: &str = "identity-secret";
+const WEBHOOK_SECRET: &str = "webhook-secret";
+const PRESENCE_API_TOKEN: &str = "presence-api-token";
+
+/// Auth data of a client whose id is vouched for by the backend.
+fn identified(role: &str, client_id: &str) -> Value {
+    let token = identity::sign(
+        IDENTITY_SECRET,
+        client_id,
+        (unix_millis() / 1000 + 60) as u64,
+    );
+    json!({ "role": role, "client_id": client_id, "client_token": token })
+}
+
+async fn start_session(hub: &TestClient) -> Uuid {
+    let response = hub.emit_with_ack("start_session", ()).await;
+    assert_eq!(response["type"], "ok");
+    serde_json::from_value(response["session_id"].clone()).unwrap()
+}
+
+/// Sends a join request from the controller and answers it from the hub.
+async fn join_session(
+    hub: &mut TestClient,
+    controller: &TestClient,
+    session_id: Uuid,
+    answer: &str,
+) -> Value {
+    let (response, _) = tokio::join!(
+        controller.emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        ),
+        async {
+            let request = hub.expect_event("join_request").await;
+            assert_eq!(request.data["message"], "hello world");
+            hub.ack(request.ack_id.unwrap(), json!({ "type": answer }))
+                .await;
+        }
+    );
+    response
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn hub_can_start_a_session(server: TestServer) {
+    let hub = server.hub().await;
+
+    let session_id = start_session(&hub).await;
+
+    assert_eq!(
+        server.session_state(session_id).await,
+        Some(SessionState::WaitingForController)
+    );
+    assert_eq!(
+        hub.emit_with_ack("start_session", ()).await,
+        json!({ "type": "error", "kind": "already_in_a_session" })
+    );
+    let webhook = server.wait_for_webhook(session_id, "session_started").await;
+    assert_eq!(webhook["role"], "hub");
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controller_joins_a_session_and_relays_commands(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+
+    let response = join_session(&mut hub, &controller, session_id, "accept").await;
+
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+    assert_eq!(
+        server.session_state(session_id).await,
+        Some(SessionState::InProgress)
+    );
+
+    controller.emit("vibrate", json!({ "value": 0.5 })).await;
+    let command = hub.expect_event("vibrate").await;
+    assert_eq!(command.data, json!({ "value": 0.5 }));
+
+    controller.close().await;
+    hub.expect_event("controller_disconnected").await;
+    server
+        .wait_for_state(session_id, Some(SessionState::WaitingForController))
+        .await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controllers_join_open_sessions_without_asking_the_hub(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.controller().await;
+    let response = hub
+        .emit_with_ack(
+            "start_session",
+            json!({ "join_policy": { "type": "open" } }),
+        )
+        .await;
+    let session_id: Uuid = serde_json::from_value(response["session_id"].clone()).unwrap();
+
+    let response = controller
+        .emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        )
+        .await;
+
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn only_identified_controllers_join_allowlisted_sessions_without_asking(server: TestServer) {
+    let mut hub = server.hub().await;
+    let anonymous = server.controller().await;
+    let alice = server.connect(identified("controller", "alice")).await;
+    let response = hub
+        .emit_with_ack(
+            "start_session",
+            json!({ "join_policy": { "type": "allowlist", "controllers": ["alice"] } }),
+        )
+        .await;
+    let session_id: Uuid = serde_json::from_value(response["session_id"].clone()).unwrap();
+
+    let response = join_session(&mut hub, &anonymous, session_id, "reject").await;
+    assert_eq!(response, json!({ "type": "error", "kind": "rejected" }));
+
+    let response = alice
+        .emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        )
+        .await;
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn hubs_choose_how_long_they_have_to_answer_join_requests(server: TestServer) {
+    let mut hub = server.hub().await;
+    let mut controller = server.controller().await;
+    let response = hub
+        .emit_with_ack("start_session", json!({ "join_request_timeout": 3 }))
+        .await;
+    let session_id: Uuid = serde_json::from_value(response["session_id"].clone()).unwrap();
+
+    let (response, _) = tokio::join!(
+        controller.emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        ),
+        async {
+            let request = hub.expect_event("join_request").await;
+            assert_eq!(request.data["timeout_ms"], 3000);
+            hub.ack(request.ack_id.unwrap(), json!({ "type": "reject" }))
+                .await;
+        }
+    );
+
+    assert_eq!(response, json!({ "type": "error", "kind": "rejected" }));
+    let pending = controller.expect_event("join_pending").await;
+    assert!(pending.data["remaining_ms"].as_u64().unwrap() <= 3000);
+
+    let response = server
+        .hub()
+        .await
+        .emit_with_ack("start_session", json!({ "join_request_timeout": 3600 }))
+        .await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "invalid_join_request_timeout" })
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn hub_rooms_resolve_to_their_live_session(server: TestServer) {
+    let room = format!("room-{}", &Uuid::new_v4().simple().to_string()[..8]);
+    let auth = identified("hub", "alice-hub");
+    let mut hub = server.connect(auth.clone()).await;
+    let controller = server.controller().await;
+    let response = hub
+        .emit_with_ack("start_session", json!({ "room": room }))
+        .await;
+    let session_id: Uuid = serde_json::from_value(response["session_id"].clone()).unwrap();
+
+    let response = controller
+        .emit_with_ack("resolve_room", json!({ "room": room }))
+        .await;
+    assert_eq!(response, json!({ "type": "ok", "session_id": session_id }));
+    let response = join_session(&mut hub, &controller, session_id, "accept").await;
+    assert_eq!(response, json!({ "type": "ok" }));
+
+    let taken = server
+        .connect(identified("hub", "bob-hub"))
+        .await
+        .emit_with_ack("start_session", json!({ "room": room }))
+        .await;
+    assert_eq!(taken, json!({ "type": "error", "kind": "room_taken" }));
+    let anonymous = server
+        .hub()
+        .await
+        .emit_with_ack("start_session", json!({ "room": room }))
+        .await;
+    assert_eq!(
+        anonymous,
+        json!({ "type": "error", "kind": "room_requires_client_id" })
+    );
+
+    hub.close().await;
+    server.wait_for_state(session_id, None).await;
+    let response = controller
+        .emit_with_ack("resolve_room", json!({ "room": room }))
+        .await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "room_not_found" })
+    );
+
+    let hub = server.connect(auth).await;
+    let response = hub
+        .emit_with_ack("start_session", json!({ "room": room }))
+        .await;
+    let session_id = response["session_id"].clone();
+    let response = controller
+        .emit_with_ack("resolve_room", json!({ "room": room }))
+        .await;
+    assert_eq!(response, json!({ "type": "ok", "session_id": session_id }));
+
+    let response = hub
+        .emit_with_ack("release_room", json!({ "room": room }))
+        .await;
+    assert_eq!(response, json!({ "type": "ok" }));
+    let response = server
+        .connect(identified("hub", "bob-hub"))
+        .await
+        .emit_with_ack("start_session", json!({ "room": room }))
+        .await;
+    assert_eq!(response["type"], "ok");
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn trusted_controllers_join_without_asking_and_see_the_hub_presence(server: TestServer) {
+    let hub_id = Uuid::new_v4().to_string();
+    let controller_id = Uuid::new_v4().to_string();
+    let hub_auth = identified("hub", &hub_id);
+    let mut controller = server
+        .connect(identified("controller", &controller_id))
+        .await;
+    server
+        .wait_until_online(Role::Controller, &controller_id)
+        .await;
+    let hub = server.connect(hub_auth.clone()).await;
+
+    let response = hub
+        .emit_with_ack(
+            "trust_controller",
+            json!({ "controller_id": controller_id }),
+        )
+        .await;
+    assert_eq!(
+        response,
+        json!({
+            "type": "ok",
+            "controllers": [{ "controller_id": controller_id, "online": true }],
+        })
+    );
+
+    hub.close().await;
+    let presence = controller.expect_event("hub_presence").await;
+    assert_eq!(presence.data, json!({ "hub_id": hub_id, "online": false }));
+    let mut hub = server.connect(hub_auth).await;
+    let presence = controller.expect_event("hub_presence").await;
+    assert_eq!(presence.data, json!({ "hub_id": hub_id, "online": true }));
+
+    let session_id = start_session(&hub).await;
+    let response = controller
+        .emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        )
+        .await;
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    controller.close().await;
+    let presence = hub.expect_event("controller_presence").await;
+    assert_eq!(
+        presence.data,
+        json!({ "controller_id": controller_id, "online": false })
+    );
+    hub.expect_event("controller_disconnected").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn trust_is_only_kept_between_proven_client_ids(server: TestServer) {
+    // The tenant has no identity secret, so the client ids of its clients are ignored
+    let mut hub = RpcClient::connect_to(
+        server.addr,
+        "/acme/rpc",
+        "role=hub&key=acme-key&client_id=acme-hub",
+    )
+    .await;
+    hub.expect_method("protocol").await;
+    let response = hub
+        .call("trust_controller", json!({ "controller_id": "alice" }))
+        .await;
+    assert_eq!(
+        response["result"],
+        json!({ "type": "error", "kind": "requires_client_id" })
+    );
+
+    let token_of_bob = identity::sign(IDENTITY_SECRET, "bob", (unix_millis() / 1000 + 60) as u64);
+    let mut impostor = TestClient::connect(
+        server.addr,
+        json!({ "role": "controller", "client_id": "alice", "client_token": token_of_bob }),
+    )
+    .await;
+    let error = impostor.expect_event("connect_error").await;
+    assert_eq!(error.data, json!({ "reason": "unauthorized" }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn invited_controllers_join_scheduled_sessions_within_their_window(server: TestServer) {
+    let controller_id = Uuid::new_v4().to_string();
+    let now = unix_millis();
+    let mut hub = server.hub().await;
+    let response = hub
+        .emit_with_ack(
+            "start_session",
+            json!({ "schedule": {
+                "starts_at": now - 1_000,
+                "ends_at": now + 60_000,
+                "controller_id": controller_id,
+            } }),
+        )
+        .await;
+    assert_eq!(response["type"], "ok");
+    let session_id = response["session_id"].clone();
+    let invitation = response["invitation"].clone();
+
+    let request = json!({
+        "session_id": session_id,
+        "message": "hello world",
+        "invitation": invitation,
+    });
+    let response = server
+        .connect(identified("controller", "someone-else"))
+        .await
+        .emit_with_ack("join_session", request.clone())
+        .await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "invalid_invitation" })
+    );
+    let response = server
+        .connect(identified("controller", &controller_id))
+        .await
+        .emit_with_ack("join_session", request)
+        .await;
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    let later = server.hub().await;
+    let response = later
+        .emit_with_ack(
+            "start_session",
+            json!({ "schedule": {
+                "starts_at": now + 60_000,
+                "ends_at": now + 120_000,
+                "controller_id": controller_id,
+            } }),
+        )
+        .await;
+    let response = server
+        .controller()
+        .await
+        .emit_with_ack(
+            "join_session",
+            json!({ "session_id": response["session_id"], "message": "hello world" }),
+        )
+        .await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "outside_session_window" })
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controllers_can_see_which_hubs_are_online(server: TestServer) {
+    let hub_id = Uuid::new_v4().to_string();
+    let other_hub_id = Uuid::new_v4().to_string();
+    let controller_id = Uuid::new_v4().to_string();
+    let hub = server.connect(identified("hub", &hub_id)).await;
+    let _other_hub = server.connect(identified("hub", &other_hub_id)).await;
+    let controller = server
+        .connect(identified("controller", &controller_id))
+        .await;
+    let response = hub
+        .emit_with_ack(
+            "trust_controller",
+            json!({ "controller_id": controller_id }),
+        )
+        .await;
+    assert_eq!(response["type"], "ok");
+    server.wait_for_hub_presence(&hub_id, true).await;
+    server.wait_for_hub_presence(&other_hub_id, true).await;
+    let (status, _) = server.get(&format!("/presence/hubs/{hub_id}"), "").await;
+    assert_eq!(status, 401);
+
+    let response = controller
+        .emit_with_ack(
+            "hubs_online",
+            json!({ "hub_ids": [hub_id, other_hub_id, "nobody"] }),
+        )
+        .await;
+    assert_eq!(
+        response,
+        json!({
+            "type": "ok",
+            "hubs": [
+                { "hub_id": hub_id, "online": true },
+                { "hub_id": other_hub_id, "online": false },
+                { "hub_id": "nobody", "online": false },
+            ],
+        })
+    );
+    let response = server
+        .controller()
+        .await
+        .emit_with_ack("hubs_online", json!({ "hub_ids": [hub_id] }))
+        .await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "requires_client_id" })
+    );
+
+    hub.close().await;
+    server.wait_for_hub_presence(&hub_id, false).await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controller_can_not_join_if_the_hub_rejects(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+
+    let response = join_session(&mut hub, &controller, session_id, "reject").await;
+
+    assert_eq!(response, json!({ "type": "error", "kind": "rejected" }));
+    assert_eq!(
+        server.session_state(session_id).await,
+        Some(SessionState::WaitingForController)
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controllers_can_cancel_a_pending_join_request(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+
+    let (response, _) = tokio::join!(
+        controller.emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        ),
+        async {
+            hub.expect_event("join_request").await;
+            let response = controller.emit_with_ack("cancel_join", ()).await;
+            assert_eq!(response, json!({ "type": "ok" }));
+            hub.expect_event("join_request_cancelled").await;
+        }
+    );
+
+    assert_eq!(response, json!({ "type": "error", "kind": "cancelled" }));
+    let response = controller.emit_with_ack("cancel_join", ()).await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "no_pending_request" })
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn join_requests_are_withdrawn_when_the_controller_disconnects(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+
+    controller
+        .emit(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        )
+        .await;
+    let request = hub.expect_event("join_request").await;
+    controller.close().await;
+    hub.expect_event("join_request_cancelled").await;
+
+    hub.ack(request.ack_id.unwrap(), json!({ "type": "accept" }))
+        .await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        server.session_state(session_id).await,
+        Some(SessionState::WaitingForController)
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controller_can_not_join_an_unknown_session(server: TestServer) {
+    let controller = server.controller().await;
+
+    let response = controller
+        .emit_with_ack(
+            "join_session",
+            json!({ "session_id": Uuid::new_v4(), "message": "hello world" }),
+        )
+        .await;
+
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "session_not_found" })
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn hubs_can_see_the_join_attempts_on_their_sessions(server: TestServer) {
+    let hub_id = Uuid::new_v4();
+    let auth = identified("hub", &hub_id.to_string());
+    let mut hub = server.connect(auth.clone()).await;
+    let alice = server.connect(identified("controller", "alice")).await;
+    let bob = server.connect(identified("controller", "bob")).await;
+    let first_session = start_session(&hub).await;
+    join_session(&mut hub, &alice, first_session, "reject").await;
+    hub.close().await;
+
+    let mut hub = server.connect(auth).await;
+    let second_session = start_session(&hub).await;
+    join_session(&mut hub, &bob, second_session, "accept").await;
+    hub.expect_event("controller_joined").await;
+
+    let response = hub.emit_with_ack("join_attempts", json!({})).await;
+    assert_eq!(response["type"], "ok");
+    let attempts: Vec<_> = response["attempts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|attempt| {
+            assert_eq!(attempt["message"], "hello world");
+            (
+                attempt["session_id"].clone(),
+                attempt["controller_id"].clone(),
+                attempt["outcome"].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        attempts,
+        vec![
+            (json!(second_session), json!("bob"), json!("accepted")),
+            (json!(first_session), json!("alice"), json!("rejected")),
+        ]
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn join_requests_are_rate_limited_by_client_id(server: TestServer) {
+    let auth = identified("controller", &Uuid::new_v4().to_string());
+    let controller = server.connect(auth.clone()).await;
+    let request = json!({ "session_id": Uuid::new_v4(), "message": "hello world" });
+
+    assert_eq!(
+        controller.emit_with_ack("join_session", &request).await,
+        json!({ "type": "error", "kind": "session_not_found" })
+    );
+    // Reconnecting doesn't reset the limit of the identity
+    let controller = server.connect(auth).await;
+    assert_eq!(
+        controller.emit_with_ack("join_session", &request).await,
+        json!({ "type": "error", "kind": "rate_limited" })
+    );
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn join_requests_with_blocked_messages_are_refused(server: TestServer) {
+    let hub = server.hub().await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+
+    for message in ["buy my SPAM".to_string(), "a".repeat(281)] {
+        assert_eq!(
+            controller
+                .emit_with_ack(
+                    "join_session",
+                    json!({ "session_id": session_id, "message": message }),
+                )
+                .await,
+            json!({ "type": "error", "kind": "message_rejected" })
+        );
+    }
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn clients_with_an_invalid_client_id_are_disconnected(server: TestServer) {
+    let mut client =
+        TestClient::connect(server.addr, json!({ "role": "hub", "client_id": "" })).await;
+
+    let error = client.expect_event("connect_error").await;
+    assert_eq!(error.data, json!({ "reason": "unauthorized" }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn clients_can_not_claim_a_client_id_without_its_token(server: TestServer) {
+    let forged = identity::sign(
+        "guessed-secret",
+        "alice",
+        (unix_millis() / 1000 + 60) as u64,
+    );
+    for auth in [
+        json!({ "role": "hub", "client_id": "alice" }),
+        json!({ "role": "hub", "client_id": "alice", "client_token": forged }),
+    ] {
+        let mut client = TestClient::connect(server.addr, auth).await;
+
+        let error = client.expect_event("connect_error").await;
+        assert_eq!(error.data, json!({ "reason": "unauthorized" }));
+    }
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn session_finishes_when_the_hub_disconnects(server: TestServer) {
+    let mut hub = server.hub().await;
+    let mut controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+    join_session(&mut hub, &controller, session_id, "accept").await;
+    controller.expect_event("join_pending").await;
+
+    hub.close().await;
+
+    controller.expect_event("session_finished").await;
+    server.wait_for_state(session_id, None).await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn hubs_resume_their_scheduled_sessions_after_disconnecting(server: TestServer) {
+    let hub_auth = identified("hub", &Uuid::new_v4().to_string());
+    let now = unix_millis();
+    let hub = server.connect(hub_auth.clone()).await;
+    let response = hub
+        .emit_with_ack(
+            "start_session",
+            json!({ "schedule": {
+                "starts_at": now + 60_000,
+                "ends_at": now + 120_000,
+                "controller_id": "alice",
+            } }),
+        )
+        .await;
+    let session_id: Uuid = serde_json::from_value(response["session_id"].clone()).unwrap();
+
+    hub.close().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        server.session_state(session_id).await,
+        Some(SessionState::WaitingForController)
+    );
+
+    let resume = json!({ "resume": session_id });
+    let response = server
+        .connect(identified("hub", "someone-else"))
+        .await
+        .emit_with_ack("start_session", resume.clone())
+        .await;
+    assert_eq!(
+        response,
+        json!({ "type": "error", "kind": "session_not_found" })
+    );
+    let response = server
+        .connect(hub_auth)
+        .await
+        .emit_with_ack("start_session", resume)
+        .await;
+    assert_eq!(response, json!({ "type": "ok", "session_id": session_id }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn clients_with_invalid_auth_are_disconnected(server: TestServer) {
+    let mut client = TestClient::connect(server.addr, json!({ "role": "admin" })).await;
+
+    let error = client.expect_event("connect_error").await;
+
+    assert_eq!(error.data, json!({ "reason": "unauthorized" }));
+    client.expect_event("disconnect").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn clients_can_request_a_supported_protocol_version(server: TestServer) {
+    server
+        .connect(json!({ "role": "hub", "protocol_version": 1 }))
+        .await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn clients_with_an_unsupported_protocol_version_are_disconnected(server: TestServer) {
+    let mut client = TestClient::connect(
+        server.addr,
+        json!({ "role": "controller", "protocol_version": 2 }),
+    )
+    .await;
+
+    let error = client.expect_event("connect_error").await;
+
+    assert_eq!(
+        error.data,
+        json!({ "reason": "unsupported_protocol_version", "supported_versions": [1] })
+    );
+    client.expect_event("disconnect").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn rpc_hub_can_be_joined_by_a_socketio_controller(server: TestServer) {
+    let mut hub = server.rpc("role=hub").await;
+    let mut controller = server.controller().await;
+    let response = hub.call("start_session", ()).await;
+    assert_eq!(response["result"]["type"], "ok");
+    let session_id: Uuid =
+        serde_json::from_value(response["result"]["session_id"].clone()).unwrap();
+
+    let (response, _) = tokio::join!(
+        controller.emit_with_ack(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        ),
+        async {
+            let request = hub.expect_method("join_request").await;
+            assert_eq!(request["params"]["message"], "hello world");
+            hub.respond(request["id"].clone(), json!({ "type": "accept" }))
+                .await;
+        }
+    );
+
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_method("controller_joined").await;
+    controller.expect_event("join_pending").await;
+
+    controller.emit("vibrate", json!({ "value": 0.5 })).await;
+    let command = hub.expect_method("vibrate").await;
+    assert_eq!(command["params"], json!({ "value": 0.5 }));
+
+    hub.close().await;
+    controller.expect_event("session_finished").await;
+    server.wait_for_state(session_id, None).await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn tenant_clients_need_one_of_its_auth_keys(server: TestServer) {
+    for auth in [
+        json!({ "role": "hub" }),
+        json!({ "role": "hub", "key": "root-key" }),
+    ] {
+        let mut client = TestClient::connect_to(server.addr, "/acme", auth).await;
+        let error = client.expect_event("connect_error").await;
+        assert_eq!(error.data, json!({ "reason": "unauthorized" }));
+        client.expect_event("disconnect").await;
+    }
+
+    let url = format!("ws://{}/acme/rpc?role=hub", server.addr);
+    let error = tokio_tungstenite::connect_async(url).await.unwrap_err();
+    let tokio_tungstenite::tungstenite::Error::Http(response) = error else {
+        panic!("Unexpected error: {error}");
+    };
+    assert_eq!(response.status(), 401);
+
+    let mut hub = RpcClient::connect_to(server.addr, "/acme/rpc", "role=hub&key=acme-key").await;
+    hub.expect_method("protocol").await;
+    let response = hub.call("start_session", ()).await;
+    assert_eq!(response["result"]["type"], "ok");
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn tenants_only_see_their_own_sessions(server: TestServer) {
+    let auth = |role| json!({ "role": role, "key": "acme-key" });
+    let mut hub = server.connect_to("/acme", auth("hub")).await;
+    let controller = server.connect_to("/acme", auth("controller")).await;
+    let session_id = start_session(&hub).await;
+
+    assert_eq!(server.session_state(session_id).await, None);
+    assert_eq!(
+        server
+            .controller()
+            .await
+            .emit_with_ack(
+                "join_session",
+                json!({ "session_id": session_id, "message": "hello world" }),
+            )
+            .await,
+        json!({ "type": "error", "kind": "session_not_found" })
+    );
+
+    let response = join_session(&mut hub, &controller, session_id, "accept").await;
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    controller.emit("vibrate", json!({ "value": 0.5 })).await;
+    let command = hub.expect_event("vibrate").await;
+    assert_eq!(command.data, json!({ "value": 0.5 }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn rpc_controller_can_join_a_socketio_hub(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.rpc("role=controller").await;
+    let session_id = start_session(&hub).await;
+
+    let (response, _) = tokio::join!(
+        controller.call(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        ),
+        async {
+            let request = hub.expect_event("join_request").await;
+            hub.ack(request.ack_id.unwrap(), json!({ "type": "accept" }))
+                .await;
+        }
+    );
+
+    assert_eq!(response["result"], json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    let response = controller.call("vibrate", json!({ "value": 0.5 })).await;
+    assert_eq!(response["result"], Value::Null);
+    let command = hub.expect_event("vibrate").await;
+    assert_eq!(command.data, json!({ "value": 0.5 }));
+
+    controller.close().await;
+    hub.expect_event("controller_disconnected").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn rpc_clients_get_errors_for_invalid_calls(server: TestServer) {
+    let mut controller = server.rpc("role=controller").await;
+
+    let response = controller.call("start_session", ()).await;
+    assert_eq!(response["error"]["code"], -32601);
+
+    let response = controller
+        .call("join_session", json!({ "session_id": 1 }))
+        .await;
+    assert_eq!(response["error"]["code"], -32602);
+
+    controller.send_text("not json").await;
+    let response = controller.next_message().await;
+    assert_eq!(response["error"]["code"], -32700);
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn rpc_clients_with_invalid_auth_are_rejected(server: TestServer) {
+    for query in ["role=admin", "role=hub&protocol_version=2"] {
+        let url = format!("ws://{}/rpc?{query}", server.addr);
+        let error = tokio_tungstenite::connect_async(url).await.unwrap_err();
+        let tokio_tungstenite::tungstenite::Error::Http(response) = error else {
+            panic!("Unexpected error: {error}");
+        };
+        assert_eq!(response.status(), 400);
+    }
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn msgpack_hub_can_be_joined_by_a_json_controller(server: TestServer) {
+    let mut hub = server
+        .connect(json!({ "role": "hub", "encoding": "msgpack" }))
+        .await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+
+    let response = join_session(&mut hub, &controller, session_id, "accept").await;
+
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    controller.emit("vibrate", json!({ "value": 0.5 })).await;
+    let command = hub.expect_event("vibrate").await;
+    assert_eq!(command.data, json!({ "value": 0.5 }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn msgpack_controller_can_join_a_json_hub(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server
+        .connect(json!({ "role": "controller", "encoding": "msgpack" }))
+        .await;
+    let session_id = start_session(&hub).await;
+
+    let response = join_session(&mut hub, &controller, session_id, "accept").await;
+
+    assert_eq!(response, json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    controller.emit("vibrate", json!({ "value": 0.5 })).await;
+    let command = hub.expect_event("vibrate").await;
+    assert_eq!(command.data, json!({ "value": 0.5 }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn msgpack_rpc_clients_exchange_binary_frames(server: TestServer) {
+    let hub = server.rpc("role=hub&encoding=msgpack").await;
+
+    let response = hub.call("start_session", ()).await;
+
+    assert_eq!(response["result"]["type"], "ok");
+    assert!(response["result"]["session_id"].is_string());
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controller_can_wait_for_the_hub_to_apply_commands(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+    join_session(&mut hub, &controller, session_id, "accept").await;
+    hub.expect_event("controller_joined").await;
+
+    let (result, _) = tokio::join!(
+        controller.emit_with_ack("vibrate", json!({ "value": 0.5, "seq": 1 })),
+        async {
+            let command = hub.expect_event("vibrate").await;
+            assert_eq!(command.data, json!({ "value": 0.5, "seq": 1 }));
+            hub.ack(command.ack_id.unwrap(), json!({ "type": "applied" }))
+                .await;
+        }
+    );
+
+    assert_eq!(result, json!({ "seq": 1, "status": "applied" }));
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn acked_commands_time_out_if_the_hub_does_not_answer(server: TestServer) {
+    let mut hub = server.hub().await;
+    let controller = server.rpc("role=controller").await;
+    let session_id = start_session(&hub).await;
+    let (response, _) = tokio::join!(
+        controller.call(
+            "join_session",
+            json!({ "session_id": session_id, "message": "hello world" }),
+        ),
+        async {
+            let request = hub.expect_event("join_request").await;
+            hub.ack(request.ack_id.unwrap(), json!({ "type": "accept" }))
+                .await;
+        }
+    );
+    assert_eq!(response["result"], json!({ "type": "ok" }));
+    hub.expect_event("controller_joined").await;
+
+    let result = controller
+        .call(
+            "vibrate",
+            json!({ "value": 0.5, "seq": 2, "timeout_ms": 100 }),
+        )
+        .await;
+
+    assert_eq!(result["result"], json!({ "seq": 2, "status": "timed_out" }));
+    assert!(hub.expect_event("vibrate").await.ack_id.is_some());
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn controllers_joining_mid_session_get_the_latest_device_statuses(server: TestServer) {
+    let mut hub = server.hub().await;
+    let mut controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+    let status = json!({ "device_id": "toy", "battery": 0.8 });
+
+    assert_eq!(
+        hub.emit_with_ack("device_status", &status).await,
+        json!({ "type": "ok" })
+    );
+    assert_eq!(
+        hub.emit_with_ack("device_status", &status).await,
+        json!({ "type": "error", "kind": "rate_limited" })
+    );
+    assert_eq!(
+        hub.emit_with_ack(
+            "device_status",
+            json!({ "device_id": "toy", "battery": 2.0 })
+        )
+        .await,
+        json!({ "type": "error", "kind": "invalid_status" })
+    );
+
+    join_session(&mut hub, &controller, session_id, "accept").await;
+    controller.expect_event("join_pending").await;
+    assert_eq!(controller.expect_event("device_status").await.data, status);
+
+    let status = json!({ "device_id": "lamp", "rssi": -40 });
+    hub.emit("device_status", &status).await;
+    assert_eq!(controller.expect_event("device_status").await.data, status);
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn rpc_hubs_can_send_device_statuses(server: TestServer) {
+    let hub = server.rpc("role=hub").await;
+    let response = hub.call("start_session", ()).await;
+    assert_eq!(response["result"]["type"], "ok");
+
+    let response = hub
+        .call(
+            "device_status",
+            json!({ "device_id": "toy", "battery": 0.8 }),
+        )
+        .await;
+    assert_eq!(response["result"], json!({ "type": "ok" }));
+
+    let response = hub.call("device_status", json!({ "battery": 0.8 })).await;
+    assert_eq!(response["error"]["code"], -32602);
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn sessions_are_recorded_with_consent_and_can_be_replayed(server: TestServer) {
+    let hub_auth = identified("hub", &Uuid::new_v4().to_string());
+    let mut hub = server.connect(hub_auth.clone()).await;
+    let mut controller = server.controller().await;
+    let session_id = start_session(&hub).await;
+    join_session(&mut hub, &controller, session_id, "accept").await;
+    hub.expect_event("controller_joined").await;
+    controller.expect_event("join_pending").await;
+
+    // Commands are only recorded once both participants agreed
+    controller.emit("vibrate", json!({ "value": 0.1 })).await;
+    hub.expect_event("vibrate").await;
+    let status = json!({ "recording": false, "hub": true, "controller": false });
+    assert_eq!(
+        hub.emit_with_ack("recording_consent", json!({ "consent": true }))
+            .await,
+        status
+    );
+    assert_eq!(
+        controller.expect_event("recording_status").await.data,
+        status
+    );
+    let status = json!({ "recording": true, "hub": true, "controller": true });
+    assert_eq!(
+        controller
+            .emit_with_ack("recording_consent", json!({ "consent": true }))
+            .await,
+        status
+    );
+    assert_eq!(hub.expect_event("recording_status").await.data, status);
+    for value in [0.5, 1.0] {
+        controller.emit("vibrate", json!({ "value": value })).await;
+        hub.expect_event("vibrate").await;
+    }
+
+    hub.close().await;
+    controller.expect_event("session_finished").await;
+    server.wait_for_state(session_id, None).await;
+
+    let path = format!("/recordings/{session_id}");
+    assert_eq!(server.get(&path, "wrong").await.0, 401);
+    let (status, recording) = server.get(&path, "secret").await;
+    assert_eq!(status, 200);
+    let values: Vec<_> = recording["commands"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|command| command[1].clone())
+        .collect();
+    assert_eq!(values, vec![json!(0.5), json!(1.0)]);
+    let (status, recordings) = server.get("/recordings", "secret").await;
+    assert_eq!(status, 200);
+    assert!(recordings
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|summary| summary["id"] == json!(session_id) && summary["commands"] == 2));
+
+    // Only the hub of the recorded session can replay it
+    let other_hub = server.hub().await;
+    let response = other_hub
+        .emit_with_ack("start_replay", json!({ "recording_id": session_id }))
+        .await;
+    assert_eq!(response["kind"], "recording_not_found");
+
+    let mut hub = server.connect(hub_auth).await;
+    let response = hub
+        .emit_with_ack("start_replay", json!({ "recording_id": session_id }))
+        .await;
+    assert_eq!(response["type"], "ok");
+    let replay_id: Uuid = serde_json::from_value(response["session_id"].clone()).unwrap();
+    assert_eq!(
+        server.session_state(replay_id).await,
+        Some(SessionState::InProgress)
+    );
+    for value in [0.5, 1.0] {
+        let command = hub.expect_event("vibrate").await;
+        assert_eq!(command.data, json!({ "value": value }));
+    }
+    hub.expect_event("replay_finished").await;
+}
+
+#[test_context(TestServer, skip_teardown)]
+#[tokio::test]
+async fn unknown_recordings_can_not_be_replayed(server: TestServer) {
+    let hub = server.hub().await;
+    let recording_id = Uuid::new_v4();
+
+    assert_eq!(
+        hub.emit_with_ack("start_replay", json!({ "recording_id": recording_id }))
+            .await,
+        json!({ "type": "error", "kind": "recording_not_found" })
+    );
+    assert_eq!(
+        server
+            .get(&format!("/recordings/{recording_id}"), "secret")
+            .await
+            .0,
+        404
+    );
+}
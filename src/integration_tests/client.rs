@@ -0,0 +1,280 @@
+//! Minimal socket.io client speaking the Engine.IO v4 / Socket.IO v5 protocol over a websocket.
+
+use crate::socket::encoding;
+use futures_util::{
+    stream::{SplitSink, StreamExt},
+    SinkExt,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot, Mutex},
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Sink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+type Acks = Arc<Mutex<HashMap<i64, oneshot::Sender<Value>>>>;
+
+#[derive(Debug)]
+pub struct Event {
+    pub name: String,
+    pub data: Value,
+    pub ack_id: Option<i64>,
+}
+
+/// A binary event or acknowledgment waiting for its attachments.
+struct BinaryPacket {
+    is_ack: bool,
+    ack_id: Option<i64>,
+    payload: Vec<Value>,
+    attachments: usize,
+}
+
+pub struct TestClient {
+    sink: Arc<Mutex<Sink>>,
+    events: mpsc::UnboundedReceiver<Event>,
+    acks: Acks,
+    next_ack_id: AtomicI64,
+    msgpack: bool,
+    /// Prefix of the packets of a namespace other than the root one, such as `/acme,`.
+    namespace: String,
+}
+
+impl TestClient {
+    /// Connects to the root namespace, sending `auth` as the handshake payload.
+    ///
+    /// Payloads are sent as MessagePack attachments if `auth` asks for that encoding, and
+    /// received attachments are decoded back into JSON values.
+    pub async fn connect(addr: SocketAddr, auth: Value) -> Self {
+        Self::connect_to(addr, "/", auth).await
+    }
+
+    /// Connects to `namespace`, sending `auth` as the handshake payload.
+    pub async fn connect_to(addr: SocketAddr, namespace: &str, auth: Value) -> Self {
+        let url = format!("ws://{addr}/socket.io/?EIO=4&transport=websocket");
+        let (stream, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("Failed to open websocket");
+        let (sink, mut stream) = stream.split();
+        let sink = Arc::new(Mutex::new(sink));
+        let acks = Acks::default();
+        let (events_sender, events) = mpsc::unbounded_channel();
+        let (connected_sender, connected) = oneshot::channel();
+
+        tokio::spawn({
+            let sink = sink.clone();
+            let acks = acks.clone();
+            async move {
+                let mut connected_sender = Some(connected_sender);
+                let mut binary_packet: Option<BinaryPacket> = None;
+                while let Some(Ok(message)) = stream.next().await {
+                    let text = match message {
+                        Message::Text(text) => text,
+                        Message::Binary(bytes) => {
+                            let Some(mut packet) = binary_packet.take() else {
+                                continue;
+                            };
+                            packet.payload.push(encoding::from_msgpack(&bytes).unwrap());
+                            if packet.payload.len() < packet.attachments {
+                                binary_packet = Some(packet);
+                            } else if packet.is_ack {
+                                resolve_ack(&acks, packet.ack_id, packet.payload).await;
+                            } else {
+                                events_sender
+                                    .send(Event::new(packet.ack_id, packet.payload))
+                                    .ok();
+                            }
+                            continue;
+                        }
+                        _ => break,
+                    };
+
+                    match text.as_bytes() {
+                        // Engine.IO ping
+                        [b'2'] => {
+                            sink.lock().await.send(Message::Text("3".into())).await.ok();
+                        }
+                        // Socket.IO connect acknowledgment
+                        [b'4', b'0', ..] => {
+                            if let Some(sender) = connected_sender.take() {
+                                sender.send(()).ok();
+                            }
+                        }
+                        // Socket.IO disconnect
+                        [b'4', b'1', ..] => {
+                            let event = Event {
+                                name: "disconnect".into(),
+                                data: Value::Null,
+                                ack_id: None,
+                            };
+                            events_sender.send(event).ok();
+                        }
+                        // Socket.IO event
+                        [b'4', b'2', ..] => {
+                            let (ack_id, payload) = split_ack_id(&text[2..]);
+                            events_sender.send(Event::new(ack_id, payload)).ok();
+                        }
+                        // Socket.IO acknowledgment
+                        [b'4', b'3', ..] => {
+                            let (ack_id, payload) = split_ack_id(&text[2..]);
+                            resolve_ack(&acks, ack_id, payload).await;
+                        }
+                        // Socket.IO binary event or acknowledgment, attachments follow
+                        [b'4', kind @ (b'5' | b'6'), ..] => {
+                            let (attachments, packet) = text[2..].split_once('-').unwrap();
+                            let (ack_id, payload) = split_ack_id(packet);
+                            binary_packet = Some(BinaryPacket {
+                                is_ack: *kind == b'6',
+                                ack_id,
+                                payload: payload
+                                    .into_iter()
+                                    .filter(|value| value.get("_placeholder").is_none())
+                                    .collect(),
+                                attachments: attachments.parse().unwrap(),
+                            });
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        });
+
+        let client = Self {
+            sink,
+            events,
+            acks,
+            next_ack_id: AtomicI64::new(0),
+            msgpack: auth["encoding"] == "msgpack",
+            namespace: match namespace {
+                "/" => String::new(),
+                namespace => format!("{namespace},"),
+            },
+        };
+        client
+            .send(format!("40{}{}", client.namespace, json!(auth)))
+            .await;
+        tokio::time::timeout(TIMEOUT, connected)
+            .await
+            .expect("Timed out waiting for the connection")
+            .expect("Connection closed before being acknowledged");
+        client
+    }
+
+    pub async fn emit(&self, event: &str, data: impl Serialize) {
+        self.send_packet('2', "", json!([event]), data).await;
+    }
+
+    pub async fn emit_with_ack(&self, event: &str, data: impl Serialize) -> Value {
+        let ack_id = self.next_ack_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.acks.lock().await.insert(ack_id, sender);
+        self.send_packet('2', &ack_id.to_string(), json!([event]), data)
+            .await;
+        tokio::time::timeout(TIMEOUT, receiver)
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for '{event}' acknowledgment"))
+            .expect("Connection closed before receiving the acknowledgment")
+    }
+
+    /// Answers an event the server emitted expecting an acknowledgment.
+    pub async fn ack(&self, ack_id: i64, data: impl Serialize) {
+        self.send_packet('3', &ack_id.to_string(), json!([]), data)
+            .await;
+    }
+
+    pub async fn next_event(&mut self) -> Event {
+        tokio::time::timeout(TIMEOUT, self.events.recv())
+            .await
+            .expect("Timed out waiting for an event")
+            .expect("Connection closed")
+    }
+
+    /// Waits for the next event and checks that it has the expected name.
+    pub async fn expect_event(&mut self, name: &str) -> Event {
+        let event = self.next_event().await;
+        assert_eq!(event.name, name, "Unexpected event: {event:?}");
+        event
+    }
+
+    pub async fn close(self) {
+        self.sink.lock().await.close().await.ok();
+    }
+
+    /// Sends an event (`kind` 2) or acknowledgment (`kind` 3) with `data` as last argument.
+    async fn send_packet(&self, kind: char, ack_id: &str, mut args: Value, data: impl Serialize) {
+        let args_list = args.as_array_mut().unwrap();
+        if !self.msgpack {
+            args_list.push(json!(data));
+            self.send(format!("4{kind}{}{ack_id}{args}", self.namespace))
+                .await;
+            return;
+        }
+
+        args_list.push(json!({ "_placeholder": true, "num": 0 }));
+        let kind = if kind == '2' { '5' } else { '6' };
+        self.send(format!("4{kind}1-{}{ack_id}{args}", self.namespace))
+            .await;
+        self.sink
+            .lock()
+            .await
+            .send(Message::Binary(encoding::to_msgpack(&data).unwrap()))
+            .await
+            .expect("Failed to send attachment");
+    }
+
+    async fn send(&self, packet: String) {
+        self.sink
+            .lock()
+            .await
+            .send(Message::Text(packet))
+            .await
+            .expect("Failed to send packet");
+    }
+}
+
+impl Event {
+    fn new(ack_id: Option<i64>, payload: Vec<Value>) -> Self {
+        let mut payload = payload.into_iter();
+        let name = payload
+            .next()
+            .and_then(|name| name.as_str().map(Into::into));
+        Self {
+            name: name.unwrap_or_default(),
+            data: payload.next().unwrap_or_default(),
+            ack_id,
+        }
+    }
+}
+
+async fn resolve_ack(acks: &Acks, ack_id: Option<i64>, payload: Vec<Value>) {
+    let sender = acks.lock().await.remove(&ack_id.unwrap_or_default());
+    if let Some(sender) = sender {
+        sender
+            .send(payload.into_iter().next().unwrap_or_default())
+            .ok();
+    }
+}
+
+/// Splits a packet in the form `[/namespace,]<ack id>[...payload]` into its parts.
+fn split_ack_id(packet: &str) -> (Option<i64>, Vec<Value>) {
+    let packet = match packet.strip_prefix('/') {
+        Some(namespaced) => namespaced.split_once(',').map_or("", |(_, rest)| rest),
+        None => packet,
+    };
+    let payload_start = packet.find('[').unwrap_or(packet.len());
+    let ack_id = packet[..payload_start].parse().ok();
+    let payload = serde_json::from_str(&packet[payload_start..]).unwrap_or_default();
+    (ack_id, payload)
+}
@@ -0,0 +1,143 @@
+//! Minimal JSON-RPC 2.0 client talking to the `/rpc` websocket endpoint.
+
+use crate::socket::encoding;
+use futures_util::{
+    stream::{SplitSink, StreamExt},
+    SinkExt,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot, Mutex},
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+type Sink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+pub struct RpcClient {
+    sink: Arc<Mutex<Sink>>,
+    messages: mpsc::UnboundedReceiver<Value>,
+    responses: Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>,
+    next_id: AtomicU64,
+    msgpack: bool,
+}
+
+impl RpcClient {
+    /// Connects to the JSON-RPC endpoint, `query` carries the handshake parameters.
+    ///
+    /// Messages are sent as MessagePack binary frames if `query` asks for that encoding.
+    pub async fn connect(addr: SocketAddr, query: &str) -> Self {
+        Self::connect_to(addr, "/rpc", query).await
+    }
+
+    /// Connects to the JSON-RPC endpoint at `path`, such as the one of a tenant.
+    pub async fn connect_to(addr: SocketAddr, path: &str, query: &str) -> Self {
+        let url = format!("ws://{addr}{path}?{query}");
+        let (stream, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .expect("Failed to open websocket");
+        let (sink, mut stream) = stream.split();
+        let responses = Arc::new(Mutex::new(HashMap::<u64, oneshot::Sender<Value>>::new()));
+        let (messages_sender, messages) = mpsc::unbounded_channel();
+
+        tokio::spawn({
+            let responses = responses.clone();
+            async move {
+                while let Some(Ok(message)) = stream.next().await {
+                    let message: Value = match message {
+                        Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                        Message::Binary(bytes) => encoding::from_msgpack(&bytes).unwrap(),
+                        _ => break,
+                    };
+                    // Responses to our requests, anything else comes from the server
+                    if message.get("method").is_none() {
+                        let id = message["id"].as_u64().unwrap_or_default();
+                        if let Some(sender) = responses.lock().await.remove(&id) {
+                            sender.send(message).ok();
+                            continue;
+                        }
+                    }
+                    messages_sender.send(message).ok();
+                }
+            }
+        });
+
+        Self {
+            sink: Arc::new(Mutex::new(sink)),
+            messages,
+            responses,
+            next_id: AtomicU64::new(1),
+            msgpack: query.contains("encoding=msgpack"),
+        }
+    }
+
+    /// Sends a request and returns the whole response object.
+    pub async fn call(&self, method: &str, params: impl Serialize) -> Value {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.responses.lock().await.insert(id, sender);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await;
+        tokio::time::timeout(TIMEOUT, receiver)
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for '{method}' response"))
+            .expect("Connection closed before receiving the response")
+    }
+
+    /// Answers a request sent by the server.
+    pub async fn respond(&self, id: Value, result: impl Serialize) {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+            .await;
+    }
+
+    pub async fn send_text(&self, text: &str) {
+        self.sink
+            .lock()
+            .await
+            .send(Message::Text(text.into()))
+            .await
+            .expect("Failed to send message");
+    }
+
+    pub async fn next_message(&mut self) -> Value {
+        tokio::time::timeout(TIMEOUT, self.messages.recv())
+            .await
+            .expect("Timed out waiting for a message")
+            .expect("Connection closed")
+    }
+
+    /// Waits for the next message sent by the server and checks its method.
+    pub async fn expect_method(&mut self, method: &str) -> Value {
+        let message = self.next_message().await;
+        assert_eq!(message["method"], method, "Unexpected message: {message}");
+        message
+    }
+
+    pub async fn close(self) {
+        self.sink.lock().await.close().await.ok();
+    }
+
+    async fn send(&self, message: Value) {
+        if !self.msgpack {
+            return self.send_text(&message.to_string()).await;
+        }
+        self.sink
+            .lock()
+            .await
+            .send(Message::Binary(encoding::to_msgpack(&message).unwrap()))
+            .await
+            .expect("Failed to send message");
+    }
+}
@@ -1,15 +1,32 @@
 mod actors;
+mod app;
 mod configuration;
+mod events;
+mod http;
+#[cfg(test)]
+mod integration_tests;
+mod moderation;
 mod sessions;
 mod socket;
 
-use crate::{actors::Auth, configuration::Config};
-use axum::routing::get;
-use sessions::adapters::redis::{self, RedisSessionStore};
-use socketioxide::{
-    extract::{SocketRef, TryData},
-    SocketIoBuilder,
+use crate::{
+    configuration::Config,
+    events::adapters::{
+        fanout::FanoutEventSink, noop::NoopEventSink, redis::RedisEventSink,
+        webhook::WebhookEventSink,
+    },
 };
+use axum::{
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Json, Router,
+};
+use sessions::adapters::{
+    redis::{self, RedisSessionStore},
+    resilient::{ResilientSessionStore, StoreMetrics},
+};
+use std::{collections::BTreeMap, iter};
+use tracing::{info, warn};
 
 #[shuttle_runtime::main]
 async fn main() -> shuttle_axum::ShuttleAxum {
@@ -20,30 +37,113 @@ async fn main() -> shuttle_axum::ShuttleAxum {
     // We don't use our own tracing subscriber now since Shuttle provides its own.
     // tracing::subscriber::set_global_default(FmtSubscriber::default())?;
 
-    let pool = redis::pool::connect(&config.redis).expect("Couldn't connect to redis");
+    let pool = redis::pool::connect(
+        &config.redis,
+        config.redis_sentinel.as_ref(),
+        config.redis_cluster.as_ref(),
+    )
+    .expect("Couldn't connect to redis");
 
     let sessions = RedisSessionStore::new(
-        pool,
+        pool.clone(),
         redis::Config {
             session_ttl: config.session_ttl,
+            key_prefix: config.redis_key_prefix.clone(),
         },
     );
+    let tenant_sessions = config
+        .tenants
+        .keys()
+        .map(|name| {
+            let store = RedisSessionStore::new(
+                pool.clone(),
+                redis::Config {
+                    session_ttl: config.session_ttl,
+                    key_prefix: format!("{}{name}:", config.redis_key_prefix),
+                },
+            );
+            (name.clone(), store)
+        })
+        .collect::<BTreeMap<_, _>>();
 
-    let (layer, io) = SocketIoBuilder::new()
-        .with_state(sessions)
-        .with_state(config.clone())
-        .build_layer();
+    if config.migrate_legacy_redis_keys {
+        let moved = sessions
+            .migrate_legacy_keys()
+            .await
+            .expect("Couldn't migrate the legacy Redis keys");
+        info!(moved, "Moved the legacy Redis keys under the key prefix");
+    }
+    let legacy_keys = sessions
+        .legacy_keys()
+        .await
+        .expect("Couldn't look for legacy Redis keys");
+    if !legacy_keys.is_empty() {
+        warn!(
+            count = legacy_keys.len(),
+            keys = ?legacy_keys,
+            "Found Redis keys without the key prefix, move them under it with MIGRATE_LEGACY_REDIS_KEYS=true"
+        );
+    }
+    for store in iter::once(&sessions).chain(tenant_sessions.values()) {
+        store
+            .check_schema()
+            .await
+            .expect("Refusing to run against an incompatible Redis schema");
+    }
 
-    io.ns("/", {
-        let io = io.clone();
-        move |socket: SocketRef, auth: TryData<Auth>| {
-            actors::on_connect::<RedisSessionStore>(socket, io.clone(), auth);
-        }
+    let store_metrics = StoreMetrics::default();
+    let resilient = |store| {
+        ResilientSessionStore::new(store, config.session_store.clone(), store_metrics.clone())
+    };
+    let sessions = resilient(sessions);
+    let tenant_sessions = tenant_sessions
+        .into_iter()
+        .map(|(name, store)| (name, resilient(store)))
+        .collect::<BTreeMap<_, _>>();
+
+    let redis_events = config
+        .events
+        .clone()
+        .map(|events_config| RedisEventSink::new(pool, events_config));
+    let webhooks = config.webhooks.clone().map(|webhooks_config| {
+        let tenant_urls = config
+            .tenants
+            .iter()
+            .filter(|(_, tenant)| !tenant.webhook_urls.is_empty())
+            .map(|(name, tenant)| (name.clone(), tenant.webhook_urls.clone()))
+            .collect();
+        WebhookEventSink::new(webhooks_config).with_tenant_urls(tenant_urls)
     });
 
-    let app = axum::Router::new()
-        .route("/health-check", get(|| async { "ok" }))
-        .layer(layer);
+    let mut router = Router::new();
+    if let Some(metrics_config) = config.metrics.clone() {
+        router = router.route(
+            "/metrics/session-store",
+            get(move |headers: HeaderMap| async move {
+                http::authorize(&headers, &metrics_config.api_token)?;
+                Ok::<_, StatusCode>(Json(store_metrics.snapshot()))
+            }),
+        );
+    }
+
+    if let Some(webhooks) = webhooks.clone() {
+        router = router.merge(webhooks.router());
+    }
+
+    let app = match (redis_events, webhooks) {
+        (Some(redis_events), Some(webhooks)) => app::build(
+            router,
+            sessions,
+            tenant_sessions,
+            FanoutEventSink(redis_events, webhooks),
+            config,
+        ),
+        (Some(redis_events), None) => {
+            app::build(router, sessions, tenant_sessions, redis_events, config)
+        }
+        (None, Some(webhooks)) => app::build(router, sessions, tenant_sessions, webhooks, config),
+        (None, None) => app::build(router, sessions, tenant_sessions, NoopEventSink, config),
+    };
 
     Ok(app.into())
 }
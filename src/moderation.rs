@@ -0,0 +1,3 @@
+pub mod adapters;
+pub mod guard;
+pub mod port;
@@ -0,0 +1 @@
+pub mod word_list;
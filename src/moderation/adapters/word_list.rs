@@ -0,0 +1,55 @@
+use crate::moderation::port::MessageFilter;
+use std::collections::HashSet;
+
+/// Rejects messages containing any of a list of words, ignoring case.
+#[derive(Clone, Default)]
+pub struct WordListFilter {
+    words: HashSet<String>,
+}
+
+impl WordListFilter {
+    pub fn new<I, W>(words: I) -> Self
+    where
+        I: IntoIterator<Item = W>,
+        W: AsRef<str>,
+    {
+        let words = words
+            .into_iter()
+            .map(|word| word.as_ref().trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        Self { words }
+    }
+}
+
+impl MessageFilter for WordListFilter {
+    fn allows(&self, message: &str) -> bool {
+        message
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .all(|word| !self.words.contains(&word.to_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WordListFilter;
+    use crate::moderation::port::MessageFilter;
+
+    #[test]
+    fn rejects_messages_with_listed_words() {
+        let filter = WordListFilter::new(["Spam", " scam "]);
+
+        assert!(!filter.allows("Buy my SPAM!"));
+        assert!(!filter.allows("not a scam, promise"));
+        assert!(filter.allows("hello world"));
+    }
+
+    #[test]
+    fn only_matches_whole_words() {
+        let filter = WordListFilter::new(["ass"]);
+
+        assert!(filter.allows("let me pass"));
+        assert!(filter.allows(""));
+    }
+}
@@ -0,0 +1,118 @@
+use super::port::MessageFilter;
+use crate::{configuration::ControllerConfig, sessions::rate_limit::RateLimiter};
+use std::{net::IpAddr, time::Duration};
+
+/// Who a join attempt is rate limited by.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum JoinSource {
+    Ip(IpAddr),
+    Client(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JoinRefusal {
+    RateLimited,
+    MessageRejected,
+}
+
+/// Checks join requests before they reach a hub.
+#[derive(Clone)]
+pub struct JoinGuard<F> {
+    filter: F,
+    limiter: RateLimiter<JoinSource>,
+    max_message_len: usize,
+    min_interval: Duration,
+}
+
+impl<F: MessageFilter> JoinGuard<F> {
+    pub fn new(filter: F, config: &ControllerConfig) -> Self {
+        Self {
+            filter,
+            limiter: RateLimiter::default(),
+            max_message_len: config.join_message_max_len,
+            min_interval: Duration::from_millis(config.join_attempt_min_interval_ms),
+        }
+    }
+
+    /// Lets through one attempt per address and per client id every interval, and only
+    /// messages within the length limit that the filter allows.
+    pub fn check(
+        &self,
+        ip: Option<IpAddr>,
+        client_id: Option<String>,
+        message: &str,
+    ) -> Result<(), JoinRefusal> {
+        let sources = ip
+            .map(JoinSource::Ip)
+            .into_iter()
+            .chain(client_id.map(JoinSource::Client));
+        for source in sources {
+            if !self.limiter.check(source, self.min_interval) {
+                return Err(JoinRefusal::RateLimited);
+            }
+        }
+
+        if message.chars().count() > self.max_message_len || !self.filter.allows(message) {
+            return Err(JoinRefusal::MessageRejected);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JoinGuard, JoinRefusal};
+    use crate::{
+        configuration::ControllerConfig,
+        moderation::{adapters::word_list::WordListFilter, port::MockMessageFilter},
+    };
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn config() -> ControllerConfig {
+        ControllerConfig {
+            session_join_request_timeout: 60,
+            min_session_join_request_timeout: 10,
+            max_session_join_request_timeout: 300,
+            command_ack_timeout: 5,
+            join_message_max_len: 10,
+            join_attempt_min_interval_ms: 60_000,
+        }
+    }
+
+    #[test]
+    fn rate_limits_attempts_by_address_and_by_client_id() {
+        let guard = JoinGuard::new(WordListFilter::default(), &config());
+        let ip = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+
+        assert_eq!(guard.check(ip, None, "hi"), Ok(()));
+        assert_eq!(
+            guard.check(ip, Some("alice".into()), "hi"),
+            Err(JoinRefusal::RateLimited)
+        );
+        assert_eq!(guard.check(None, Some("alice".into()), "hi"), Ok(()));
+        assert_eq!(
+            guard.check(None, Some("alice".into()), "hi"),
+            Err(JoinRefusal::RateLimited)
+        );
+        assert_eq!(guard.check(None, None, "hi"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_long_or_filtered_messages() {
+        let mut filter = MockMessageFilter::new();
+        filter
+            .expect_allows()
+            .returning(|message| message != "blocked");
+        let guard = JoinGuard::new(filter, &config());
+
+        assert_eq!(
+            guard.check(None, None, "way too long"),
+            Err(JoinRefusal::MessageRejected)
+        );
+        assert_eq!(
+            guard.check(None, None, "blocked"),
+            Err(JoinRefusal::MessageRejected)
+        );
+        assert_eq!(guard.check(None, None, "ñññññññññ"), Ok(()));
+    }
+}
@@ -0,0 +1,5 @@
+/// Decides whether a message written by a controller can be forwarded to a hub.
+#[cfg_attr(test, mockall::automock)]
+pub trait MessageFilter: Send + Sync {
+    fn allows(&self, message: &str) -> bool;
+}
@@ -1,2 +1,13 @@
+pub mod activity;
 pub mod adapters;
+pub mod audit;
+pub mod commands;
+pub mod latency;
+pub mod pending;
+pub mod policy;
 pub mod port;
+pub mod rate_limit;
+pub mod recording;
+pub mod role;
+pub mod room;
+pub mod schedule;
@@ -0,0 +1,63 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Keeps track of the last time each session saw any command traffic.
+///
+/// Activity is recorded on every relayed command, so it lives in memory
+/// instead of going through the session store.
+#[derive(Clone, Default)]
+pub struct SessionActivity(Arc<Mutex<HashMap<Uuid, Instant>>>);
+
+impl SessionActivity {
+    pub fn record(&self, id: Uuid) {
+        self.0.lock().unwrap().insert(id, Instant::now());
+    }
+
+    pub fn remove(&self, id: Uuid) {
+        self.0.lock().unwrap().remove(&id);
+    }
+
+    /// Returns how long the session has been idle, if any activity was ever recorded for it.
+    pub fn idle_for(&self, id: Uuid) -> Option<Duration> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|last_activity| last_activity.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionActivity;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn unknown_sessions_have_no_idle_time() {
+        let activity = SessionActivity::default();
+        assert!(activity.idle_for(Uuid::nil()).is_none());
+    }
+
+    #[test]
+    fn recording_activity_resets_idle_time() {
+        let activity = SessionActivity::default();
+        activity.record(Uuid::nil());
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(activity.idle_for(Uuid::nil()).unwrap() >= Duration::from_millis(20));
+        activity.record(Uuid::nil());
+        assert!(activity.idle_for(Uuid::nil()).unwrap() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn removed_sessions_have_no_idle_time() {
+        let activity = SessionActivity::default();
+        activity.record(Uuid::nil());
+        activity.remove(Uuid::nil());
+        assert!(activity.idle_for(Uuid::nil()).is_none());
+    }
+}
@@ -1 +1,4 @@
+#[cfg(test)]
+pub mod memory;
 pub mod redis;
+pub mod resilient;
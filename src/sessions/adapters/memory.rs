@@ -0,0 +1,520 @@
+use crate::sessions::{
+    audit::{JoinAttempt, MAX_ATTEMPTS},
+    policy::JoinPolicy,
+    port::{
+        AppendJoinAttemptError, ClaimRoomError, CreateInvitationError, CreateSessionError,
+        DeleteSessionError, ExistsSessionError, GetDeviceStatusesError, GetInvitationError,
+        GetJoinAttemptsError, GetJoinPolicyError, GetJoinRequestTimeoutError, GetPresenceError,
+        GetRecordingError, GetRoomSessionError, GetSessionOwnerError, GetSessionStateError,
+        GetSessionWindowError, GetTrustedControllersError, GetTrustingHubsError,
+        ListRecordingsError, ReleaseRoomError, RoomClaim, SaveRecordingError, SessionState,
+        SessionStore, SetDeviceStatusError, SetJoinPolicyError, SetJoinRequestTimeoutError,
+        SetPresenceError, SetRoomSessionError, SetSessionOwnerError, SetSessionWindowError,
+        TouchSessionError, TrustControllerError, UntrustControllerError, UpdateSessionStateError,
+    },
+    recording::{Recording, RecordingSummary},
+    role::Role,
+    schedule::{Invitation, SessionWindow},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// When each connection of a client stops being online, by connection id.
+type Connections = HashMap<String, Instant>;
+
+/// Session store living in the process memory, used to run the server without Redis.
+/// Sessions never expire.
+#[derive(Clone, Default)]
+pub struct InMemorySessionStore {
+    sessions: Arc<Mutex<HashMap<Uuid, SessionState>>>,
+    devices: Arc<Mutex<HashMap<Uuid, BTreeMap<String, String>>>>,
+    recordings: Arc<Mutex<HashMap<Uuid, Recording>>>,
+    owners: Arc<Mutex<HashMap<Uuid, String>>>,
+    policies: Arc<Mutex<HashMap<Uuid, JoinPolicy>>>,
+    join_timeouts: Arc<Mutex<HashMap<Uuid, Duration>>>,
+    windows: Arc<Mutex<HashMap<Uuid, SessionWindow>>>,
+    invitations: Arc<Mutex<HashMap<String, (Invitation, Instant)>>>,
+    room_owners: Arc<Mutex<HashMap<String, String>>>,
+    room_sessions: Arc<Mutex<HashMap<String, Uuid>>>,
+    trust: Arc<Mutex<BTreeSet<(String, String)>>>,
+    presence: Arc<Mutex<HashMap<(Role, String), Connections>>>,
+    join_attempts: Arc<Mutex<HashMap<String, VecDeque<JoinAttempt>>>>,
+}
+
+impl SessionStore for InMemorySessionStore {
+    async fn create_session(&self) -> Result<Uuid, CreateSessionError> {
+        let id = Uuid::new_v4();
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(&id) {
+            return Err(CreateSessionError::UnexpectedSessionIdAlreadyInUse(id));
+        }
+        sessions.insert(id, SessionState::WaitingForController);
+        Ok(id)
+    }
+
+    async fn delete_session(&self, id: Uuid) -> Result<(), DeleteSessionError> {
+        self.sessions.lock().unwrap().remove(&id);
+        self.devices.lock().unwrap().remove(&id);
+        self.owners.lock().unwrap().remove(&id);
+        self.policies.lock().unwrap().remove(&id);
+        self.join_timeouts.lock().unwrap().remove(&id);
+        self.windows.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    async fn session_state(&self, id: Uuid) -> Result<Option<SessionState>, GetSessionStateError> {
+        Ok(self.sessions.lock().unwrap().get(&id).copied())
+    }
+
+    async fn exists_session(&self, id: Uuid) -> Result<bool, ExistsSessionError> {
+        Ok(self.sessions.lock().unwrap().contains_key(&id))
+    }
+
+    async fn update_session_state(
+        &self,
+        id: Uuid,
+        state: SessionState,
+    ) -> Result<(), UpdateSessionStateError> {
+        match self.sessions.lock().unwrap().get_mut(&id) {
+            Some(current) => {
+                *current = state;
+                Ok(())
+            }
+            None => Err(UpdateSessionStateError::UnknownSession(id)),
+        }
+    }
+
+    async fn touch(&self, id: Uuid) -> Result<(), TouchSessionError> {
+        if !self.sessions.lock().unwrap().contains_key(&id) {
+            return Err(TouchSessionError::UnknownSession(id));
+        }
+        Ok(())
+    }
+
+    async fn set_device_status(
+        &self,
+        id: Uuid,
+        device_id: String,
+        status: String,
+        max_devices: usize,
+    ) -> Result<bool, SetDeviceStatusError> {
+        let mut devices = self.devices.lock().unwrap();
+        let statuses = devices.entry(id).or_default();
+        if !statuses.contains_key(&device_id) && statuses.len() >= max_devices {
+            return Ok(false);
+        }
+        statuses.insert(device_id, status);
+        Ok(true)
+    }
+
+    async fn device_statuses(&self, id: Uuid) -> Result<Vec<String>, GetDeviceStatusesError> {
+        let devices = self.devices.lock().unwrap();
+        Ok(devices
+            .get(&id)
+            .map(|statuses| statuses.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn save_recording(&self, recording: Recording) -> Result<(), SaveRecordingError> {
+        self.recordings
+            .lock()
+            .unwrap()
+            .insert(recording.id, recording);
+        Ok(())
+    }
+
+    async fn recording(&self, id: Uuid) -> Result<Option<Recording>, GetRecordingError> {
+        Ok(self.recordings.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn recordings(&self) -> Result<Vec<RecordingSummary>, ListRecordingsError> {
+        let mut summaries: Vec<_> = self
+            .recordings
+            .lock()
+            .unwrap()
+            .values()
+            .map(Recording::summary)
+            .collect();
+        summaries.sort_by_key(|summary| summary.started_at);
+        Ok(summaries)
+    }
+
+    async fn set_session_owner(
+        &self,
+        id: Uuid,
+        hub_id: String,
+    ) -> Result<(), SetSessionOwnerError> {
+        self.owners.lock().unwrap().insert(id, hub_id);
+        Ok(())
+    }
+
+    async fn session_owner(&self, id: Uuid) -> Result<Option<String>, GetSessionOwnerError> {
+        Ok(self.owners.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn set_join_policy(
+        &self,
+        id: Uuid,
+        policy: JoinPolicy,
+    ) -> Result<(), SetJoinPolicyError> {
+        self.policies.lock().unwrap().insert(id, policy);
+        Ok(())
+    }
+
+    async fn join_policy(&self, id: Uuid) -> Result<Option<JoinPolicy>, GetJoinPolicyError> {
+        Ok(self.policies.lock().unwrap().get(&id).cloned())
+    }
+
+    async fn claim_room(
+        &self,
+        room: String,
+        hub_id: String,
+        max_rooms: usize,
+    ) -> Result<RoomClaim, ClaimRoomError> {
+        let mut owners = self.room_owners.lock().unwrap();
+        match owners.get(&room) {
+            Some(owner) if *owner == hub_id => return Ok(RoomClaim::Claimed),
+            Some(_) => return Ok(RoomClaim::Taken),
+            None => {}
+        }
+        if owners.values().filter(|owner| **owner == hub_id).count() >= max_rooms {
+            return Ok(RoomClaim::TooManyRooms);
+        }
+        owners.insert(room, hub_id);
+        Ok(RoomClaim::Claimed)
+    }
+
+    async fn release_room(&self, room: String, hub_id: String) -> Result<bool, ReleaseRoomError> {
+        let mut owners = self.room_owners.lock().unwrap();
+        if owners.get(&room) != Some(&hub_id) {
+            return Ok(false);
+        }
+        owners.remove(&room);
+        self.room_sessions.lock().unwrap().remove(&room);
+        Ok(true)
+    }
+
+    async fn set_room_session(
+        &self,
+        room: String,
+        session_id: Uuid,
+    ) -> Result<(), SetRoomSessionError> {
+        self.room_sessions.lock().unwrap().insert(room, session_id);
+        Ok(())
+    }
+
+    async fn room_session(&self, room: String) -> Result<Option<Uuid>, GetRoomSessionError> {
+        let session_id = self.room_sessions.lock().unwrap().get(&room).copied();
+        let sessions = self.sessions.lock().unwrap();
+        Ok(session_id.filter(|session_id| sessions.contains_key(session_id)))
+    }
+
+    async fn trust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> Result<(), TrustControllerError> {
+        self.trust.lock().unwrap().insert((hub_id, controller_id));
+        Ok(())
+    }
+
+    async fn untrust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> Result<(), UntrustControllerError> {
+        self.trust.lock().unwrap().remove(&(hub_id, controller_id));
+        Ok(())
+    }
+
+    async fn trusted_controllers(
+        &self,
+        hub_id: String,
+    ) -> Result<BTreeSet<String>, GetTrustedControllersError> {
+        let trust = self.trust.lock().unwrap();
+        Ok(trust
+            .iter()
+            .filter(|(hub, _)| *hub == hub_id)
+            .map(|(_, controller)| controller.clone())
+            .collect())
+    }
+
+    async fn trusting_hubs(
+        &self,
+        controller_id: String,
+    ) -> Result<BTreeSet<String>, GetTrustingHubsError> {
+        let trust = self.trust.lock().unwrap();
+        Ok(trust
+            .iter()
+            .filter(|(_, controller)| *controller == controller_id)
+            .map(|(hub, _)| hub.clone())
+            .collect())
+    }
+
+    async fn set_online(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+        ttl: Duration,
+    ) -> Result<(), SetPresenceError> {
+        let mut presence = self.presence.lock().unwrap();
+        let connections = presence.entry((role, client_id)).or_default();
+        connections.insert(connection_id, Instant::now() + ttl);
+        Ok(())
+    }
+
+    async fn set_offline(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+    ) -> Result<(), SetPresenceError> {
+        let mut presence = self.presence.lock().unwrap();
+        if let Some(connections) = presence.get_mut(&(role, client_id)) {
+            connections.remove(&connection_id);
+        }
+        Ok(())
+    }
+
+    async fn online(
+        &self,
+        role: Role,
+        client_ids: Vec<String>,
+    ) -> Result<BTreeSet<String>, GetPresenceError> {
+        let presence = self.presence.lock().unwrap();
+        let now = Instant::now();
+        Ok(client_ids
+            .into_iter()
+            .filter(|client_id| {
+                presence
+                    .get(&(role, client_id.clone()))
+                    .is_some_and(|connections| connections.values().any(|until| *until > now))
+            })
+            .collect())
+    }
+
+    async fn set_join_request_timeout(
+        &self,
+        id: Uuid,
+        timeout: Duration,
+    ) -> Result<(), SetJoinRequestTimeoutError> {
+        self.join_timeouts.lock().unwrap().insert(id, timeout);
+        Ok(())
+    }
+
+    async fn join_request_timeout(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<Duration>, GetJoinRequestTimeoutError> {
+        Ok(self.join_timeouts.lock().unwrap().get(&id).copied())
+    }
+
+    async fn set_session_window(
+        &self,
+        id: Uuid,
+        window: SessionWindow,
+    ) -> Result<(), SetSessionWindowError> {
+        self.windows.lock().unwrap().insert(id, window);
+        Ok(())
+    }
+
+    async fn session_window(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<SessionWindow>, GetSessionWindowError> {
+        Ok(self.windows.lock().unwrap().get(&id).copied())
+    }
+
+    async fn create_invitation(
+        &self,
+        token: String,
+        invitation: Invitation,
+        ttl: Duration,
+    ) -> Result<(), CreateInvitationError> {
+        let mut invitations = self.invitations.lock().unwrap();
+        let now = Instant::now();
+        invitations.retain(|_, (_, expires_at)| *expires_at > now);
+        invitations.insert(token, (invitation, now + ttl));
+        Ok(())
+    }
+
+    async fn invitation(&self, token: String) -> Result<Option<Invitation>, GetInvitationError> {
+        let invitations = self.invitations.lock().unwrap();
+        Ok(invitations
+            .get(&token)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(invitation, _)| invitation.clone()))
+    }
+
+    async fn append_join_attempt(
+        &self,
+        key: String,
+        attempt: JoinAttempt,
+    ) -> Result<(), AppendJoinAttemptError> {
+        let mut logs = self.join_attempts.lock().unwrap();
+        let log = logs.entry(key).or_default();
+        if log.len() >= MAX_ATTEMPTS {
+            log.pop_front();
+        }
+        log.push_back(attempt);
+        Ok(())
+    }
+
+    async fn join_attempts(
+        &self,
+        key: String,
+        limit: usize,
+    ) -> Result<Vec<JoinAttempt>, GetJoinAttemptsError> {
+        let logs = self.join_attempts.lock().unwrap();
+        Ok(logs
+            .get(&key)
+            .map(|log| log.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemorySessionStore;
+    use crate::sessions::{
+        port::{RoomClaim, SessionState, SessionStore, UpdateSessionStateError},
+        role::Role,
+        schedule::Invitation,
+    };
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn can_create_and_delete_sessions() {
+        let store = InMemorySessionStore::default();
+        let uuid = store.create_session().await.unwrap();
+        assert_eq!(
+            store.session_state(uuid).await.unwrap(),
+            Some(SessionState::WaitingForController)
+        );
+        store.delete_session(uuid).await.unwrap();
+        assert!(!store.exists_session(uuid).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rooms_resolve_to_the_live_session_of_their_owner() {
+        let store = InMemorySessionStore::default();
+        assert_eq!(
+            store
+                .claim_room("room".into(), "alice".into(), 1)
+                .await
+                .unwrap(),
+            RoomClaim::Claimed
+        );
+        assert_eq!(
+            store
+                .claim_room("room".into(), "bob".into(), 1)
+                .await
+                .unwrap(),
+            RoomClaim::Taken
+        );
+
+        let uuid = store.create_session().await.unwrap();
+        store.set_room_session("room".into(), uuid).await.unwrap();
+        assert_eq!(store.room_session("room".into()).await.unwrap(), Some(uuid));
+
+        store.delete_session(uuid).await.unwrap();
+        assert_eq!(store.room_session("room".into()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn hubs_own_a_bounded_number_of_rooms_until_they_release_them() {
+        let store = InMemorySessionStore::default();
+        store
+            .claim_room("first".into(), "alice".into(), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .claim_room("second".into(), "alice".into(), 1)
+                .await
+                .unwrap(),
+            RoomClaim::TooManyRooms
+        );
+
+        assert!(!store
+            .release_room("first".into(), "bob".into())
+            .await
+            .unwrap());
+        assert!(store
+            .release_room("first".into(), "alice".into())
+            .await
+            .unwrap());
+        assert_eq!(
+            store
+                .claim_room("second".into(), "alice".into(), 1)
+                .await
+                .unwrap(),
+            RoomClaim::Claimed
+        );
+        assert_eq!(
+            store
+                .claim_room("first".into(), "bob".into(), 1)
+                .await
+                .unwrap(),
+            RoomClaim::Claimed
+        );
+    }
+
+    #[tokio::test]
+    async fn connections_stop_being_online_without_heartbeats() {
+        let store = InMemorySessionStore::default();
+        let online = || store.online(Role::Hub, vec!["hub".into()]);
+        store
+            .set_online(Role::Hub, "hub".into(), "a".into(), Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(online().await.unwrap().is_empty());
+
+        store
+            .set_online(Role::Hub, "hub".into(), "a".into(), Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(online().await.unwrap(), ["hub".to_string()].into());
+    }
+
+    #[tokio::test]
+    async fn invitations_expire() {
+        let store = InMemorySessionStore::default();
+        let invitation = Invitation {
+            session_id: Uuid::nil(),
+            controller_id: "alice".into(),
+        };
+        store
+            .create_invitation("old".into(), invitation.clone(), Duration::ZERO)
+            .await
+            .unwrap();
+        store
+            .create_invitation("new".into(), invitation.clone(), Duration::from_secs(60))
+            .await
+            .unwrap();
+
+        assert_eq!(store.invitation("old".into()).await.unwrap(), None);
+        assert_eq!(
+            store.invitation("new".into()).await.unwrap(),
+            Some(invitation)
+        );
+        assert!(!store.invitations.lock().unwrap().contains_key("old"));
+    }
+
+    #[tokio::test]
+    async fn can_not_update_unknown_sessions() {
+        let store = InMemorySessionStore::default();
+        assert!(matches!(
+            store
+                .update_session_state(Uuid::nil(), SessionState::InProgress)
+                .await,
+            Err(UpdateSessionStateError::UnknownSession(_))
+        ));
+    }
+}
@@ -1,15 +1,56 @@
 pub mod pool;
 
 use self::pool::RedisPool;
-use crate::sessions::port::{
-    CreateSessionError, DeleteSessionError, ExistsSessionError, GetSessionStateError, SessionState,
-    SessionStore, UpdateSessionStateError,
+use crate::{
+    actors::latency::unix_millis,
+    sessions::{
+        audit::{JoinAttempt, MAX_ATTEMPTS},
+        policy::JoinPolicy,
+        port::{
+            AppendJoinAttemptError, ClaimRoomError, CreateInvitationError, CreateSessionError,
+            DeleteSessionError, ExistsSessionError, GetDeviceStatusesError, GetInvitationError,
+            GetJoinAttemptsError, GetJoinPolicyError, GetJoinRequestTimeoutError, GetPresenceError,
+            GetRecordingError, GetRoomSessionError, GetSessionOwnerError, GetSessionStateError,
+            GetSessionWindowError, GetTrustedControllersError, GetTrustingHubsError,
+            ListRecordingsError, ReleaseRoomError, RoomClaim, SaveRecordingError, SessionState,
+            SessionStore, SetDeviceStatusError, SetJoinPolicyError, SetJoinRequestTimeoutError,
+            SetPresenceError, SetRoomSessionError, SetSessionOwnerError, SetSessionWindowError,
+            TouchSessionError, TrustControllerError, UntrustControllerError,
+            UpdateSessionStateError,
+        },
+        recording::{Recording, RecordingSummary},
+        role::Role,
+        schedule::{Invitation, SessionWindow},
+    },
 };
+use std::{collections::BTreeSet, fmt::Display, time::Duration};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct Config {
     pub session_ttl: Option<i64>,
+    /// Prepended to every key, so tenants sharing a server don't see each other's data.
+    pub key_prefix: String,
+}
+
+/// Version of the layout of the keys, bumped whenever it changes in a way older servers can't
+/// read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Holds the version of the layout the keys under a prefix were written with.
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Matches the ids of the sessions written before keys had a prefix.
+const UUID_PATTERN: &str = "????????-????-????-????-????????????";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SchemaError {
+    #[error(
+        "Keys were written with schema version '{0}', this server uses version {SCHEMA_VERSION}"
+    )]
+    Incompatible(String),
+    #[error(transparent)]
+    IoError(#[from] anyhow::Error),
 }
 
 #[derive(Clone)]
@@ -22,12 +63,203 @@ impl RedisSessionStore {
     pub fn new(pool: RedisPool, config: Config) -> Self {
         Self { pool, config }
     }
+
+    fn key(&self, key: impl Display) -> String {
+        format!("{}{key}", self.config.key_prefix)
+    }
+
+    /// State of a session. Under a prefix the id is a hash tag, so the key shares its cluster
+    /// slot with the key the session had before the prefix was set.
+    fn session_key(&self, id: Uuid) -> String {
+        match self.config.key_prefix.is_empty() {
+            true => id.to_string(),
+            false => self.key(format!("{{{id}}}")),
+        }
+    }
+
+    /// TTL of the keys of a session, `None` if they don't expire.
+    async fn session_key_ttl(&self, id: Uuid) -> Result<Option<i64>, GetSessionWindowError> {
+        let Some(ttl) = self.config.session_ttl.filter(|ttl| *ttl > 0) else {
+            return Ok(None);
+        };
+        // Scheduled sessions last until their window ends, even without heartbeats
+        let ttl = match self.session_window(id).await? {
+            Some(window) => ttl.max((window.ends_at - unix_millis()) / 1000 + 1),
+            None => ttl,
+        };
+        Ok(Some(ttl))
+    }
+
+    /// Refuses to serve keys written with another layout, marking the keys with the current
+    /// one if they aren't yet.
+    pub async fn check_schema(&self) -> Result<(), SchemaError> {
+        let key = self.key(SCHEMA_VERSION_KEY);
+        pool::set_str_if_absent(&self.pool, key.clone(), SCHEMA_VERSION.to_string())
+            .await
+            .map_err(Into::into)
+            .map_err(SchemaError::IoError)?;
+        let version = pool::get_str(&self.pool, key)
+            .await
+            .map_err(Into::into)
+            .map_err(SchemaError::IoError)?
+            .unwrap_or_default();
+        match version.parse() {
+            Ok(SCHEMA_VERSION) => Ok(()),
+            _ => Err(SchemaError::Incompatible(version)),
+        }
+    }
+
+    /// Returns the keys written before the prefix was set, none if there is no prefix.
+    ///
+    /// Those are the sessions of the first layout, which only stored the state of each session
+    /// under its id. The database may be shared with other apps, so a key only counts if its
+    /// value is the state of a session.
+    pub async fn legacy_keys(&self) -> Result<Vec<String>, SchemaError> {
+        if self.config.key_prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+        let sessions = self.legacy_sessions().await?;
+        Ok(sessions.iter().map(Uuid::to_string).collect())
+    }
+
+    /// Sessions whose state was written without the prefix.
+    async fn legacy_sessions(&self) -> Result<BTreeSet<Uuid>, SchemaError> {
+        let mut sessions = BTreeSet::new();
+        for key in self.scan(UUID_PATTERN.to_owned()).await? {
+            let Ok(id) = Uuid::parse_str(&key) else {
+                continue;
+            };
+            if !self.has_type(&key, "string").await? {
+                continue;
+            }
+            let state = pool::get_str(&self.pool, key)
+                .await
+                .map_err(Into::into)
+                .map_err(SchemaError::IoError)?;
+            if state.is_some_and(|state| SessionState::try_from(state).is_ok()) {
+                sessions.insert(id);
+            }
+        }
+        Ok(sessions)
+    }
+
+    async fn scan(&self, pattern: String) -> Result<Vec<String>, SchemaError> {
+        pool::scan_keys(&self.pool, pattern)
+            .await
+            .map_err(Into::into)
+            .map_err(SchemaError::IoError)
+    }
+
+    async fn has_type(&self, key: &str, key_type: &str) -> Result<bool, SchemaError> {
+        let actual = pool::key_type(&self.pool, key.to_owned())
+            .await
+            .map_err(Into::into)
+            .map_err(SchemaError::IoError)?;
+        Ok(actual == key_type)
+    }
+
+    /// Moves the keys written before the prefix was set under it, returns how many were moved.
+    ///
+    /// Keys that already exist under the prefix are kept, the legacy ones are left in place.
+    pub async fn migrate_legacy_keys(&self) -> Result<usize, SchemaError> {
+        if self.config.key_prefix.is_empty() {
+            return Ok(0);
+        }
+        let mut moved = 0;
+        for id in self.legacy_sessions().await? {
+            if pool::rename_if_absent(&self.pool, id.to_string(), self.session_key(id))
+                .await
+                .map_err(Into::into)
+                .map_err(SchemaError::IoError)?
+            {
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+}
+
+/// Hash holding the latest status of each device of a session, by device id.
+fn devices_key(id: Uuid) -> String {
+    format!("{id}:devices")
+}
+
+/// Client id of the hub that started a session.
+fn owner_key(id: Uuid) -> String {
+    format!("{id}:owner")
+}
+
+/// Join policy of a session, as JSON.
+fn policy_key(id: Uuid) -> String {
+    format!("{id}:policy")
+}
+
+/// Seconds the hub of a session has to answer join requests.
+fn join_timeout_key(id: Uuid) -> String {
+    format!("{id}:join_timeout")
+}
+
+/// Window of a scheduled session, as JSON.
+fn window_key(id: Uuid) -> String {
+    format!("{id}:window")
+}
+
+/// Invitation to a scheduled session as JSON, it expires when the window ends.
+fn invitation_key(token: &str) -> String {
+    format!("invitation:{token}")
+}
+
+/// Stream holding an audit log of join attempts, it expires a while after the last attempt.
+fn join_attempts_key(key: &str) -> String {
+    format!("join_attempts:{key}")
+}
+
+const JOIN_ATTEMPTS_TTL: i64 = 30 * 24 * 60 * 60;
+
+/// Client id of the hub that owns a room, until it releases the room.
+fn room_owner_key(room: &str) -> String {
+    format!("room:{room}:owner")
+}
+
+/// Latest session of a room, only valid while that session exists.
+fn room_session_key(room: &str) -> String {
+    format!("room:{room}:session")
+}
+
+/// Rooms a hub owns, kept in sync with `room_owner_key`.
+fn hub_rooms_key(hub_id: &str) -> String {
+    format!("rooms:hub:{hub_id}")
+}
+
+/// Controllers a hub trusts, they never expire.
+fn trusted_controllers_key(hub_id: &str) -> String {
+    format!("trust:hub:{hub_id}")
+}
+
+/// Hubs that trust a controller, kept in sync with `trusted_controllers_key`.
+fn trusting_hubs_key(controller_id: &str) -> String {
+    format!("trust:controller:{controller_id}")
+}
+
+/// Connections of a client scored by when they stop being online, in Unix milliseconds.
+fn presence_key(role: Role, client_id: &str) -> String {
+    match role {
+        Role::Hub => format!("presence:hub:{client_id}"),
+        Role::Controller => format!("presence:controller:{client_id}"),
+    }
+}
+
+/// Hash holding the summary of every recording, by recording id.
+const RECORDINGS_KEY: &str = "recordings";
+
+fn recording_key(id: Uuid) -> String {
+    format!("{RECORDINGS_KEY}:{id}")
 }
 
 impl SessionStore for RedisSessionStore {
     async fn create_session(&self) -> Result<Uuid, CreateSessionError> {
         let id = Uuid::new_v4();
-        if pool::exists(&self.pool, id.into())
+        if pool::exists(&self.pool, self.session_key(id))
             .await
             .map_err(Into::into)
             .map_err(CreateSessionError::IoError)?
@@ -36,7 +268,7 @@ impl SessionStore for RedisSessionStore {
         }
         pool::set_str(
             &self.pool,
-            id.to_string(),
+            self.session_key(id),
             SessionState::WaitingForController.to_string(),
             self.config.session_ttl,
         )
@@ -47,15 +279,24 @@ impl SessionStore for RedisSessionStore {
     }
 
     async fn delete_session(&self, id: Uuid) -> Result<(), DeleteSessionError> {
-        pool::delete_key(&self.pool, id.into())
-            .await
-            .map_err(Into::into)
-            .map_err(DeleteSessionError::IoError)?;
+        for key in [
+            self.session_key(id),
+            self.key(devices_key(id)),
+            self.key(owner_key(id)),
+            self.key(policy_key(id)),
+            self.key(join_timeout_key(id)),
+            self.key(window_key(id)),
+        ] {
+            pool::delete_key(&self.pool, key)
+                .await
+                .map_err(Into::into)
+                .map_err(DeleteSessionError::IoError)?;
+        }
         Ok(())
     }
 
     async fn session_state(&self, id: Uuid) -> Result<Option<SessionState>, GetSessionStateError> {
-        let value = pool::get_str(&self.pool, id.into())
+        let value = pool::get_str(&self.pool, self.session_key(id))
             .await
             .map_err(Into::into)
             .map_err(GetSessionStateError::IoError)?
@@ -64,7 +305,7 @@ impl SessionStore for RedisSessionStore {
     }
 
     async fn exists_session(&self, id: Uuid) -> Result<bool, ExistsSessionError> {
-        let value = pool::exists(&self.pool, id.into())
+        let value = pool::exists(&self.pool, self.session_key(id))
             .await
             .map_err(Into::into)
             .map_err(ExistsSessionError::IoError)?;
@@ -76,47 +317,635 @@ impl SessionStore for RedisSessionStore {
         id: Uuid,
         state: SessionState,
     ) -> Result<(), UpdateSessionStateError> {
-        if !pool::exists(&self.pool, id.into())
+        if !pool::exists(&self.pool, self.session_key(id))
             .await
             .map_err(Into::into)
             .map_err(UpdateSessionStateError::IoError)?
         {
             return Err(UpdateSessionStateError::UnknownSession(id));
         };
+        let ttl = self
+            .session_key_ttl(id)
+            .await
+            .map_err(Into::into)
+            .map_err(UpdateSessionStateError::IoError)?;
+        pool::set_str(&self.pool, self.session_key(id), state.to_string(), ttl)
+            .await
+            .map_err(Into::into)
+            .map_err(UpdateSessionStateError::IoError)?;
+        Ok(())
+    }
+
+    async fn touch(&self, id: Uuid) -> Result<(), TouchSessionError> {
+        let ttl = self
+            .session_key_ttl(id)
+            .await
+            .map_err(Into::into)
+            .map_err(TouchSessionError::IoError)?;
+        let found = match ttl {
+            Some(ttl) => {
+                for key in [
+                    devices_key(id),
+                    owner_key(id),
+                    policy_key(id),
+                    join_timeout_key(id),
+                    window_key(id),
+                ] {
+                    pool::expire(&self.pool, self.key(key), ttl)
+                        .await
+                        .map_err(Into::into)
+                        .map_err(TouchSessionError::IoError)?;
+                }
+                pool::expire(&self.pool, self.session_key(id), ttl)
+                    .await
+                    .map_err(Into::into)
+                    .map_err(TouchSessionError::IoError)?
+            }
+            None => pool::exists(&self.pool, self.session_key(id))
+                .await
+                .map_err(Into::into)
+                .map_err(TouchSessionError::IoError)?,
+        };
+        if !found {
+            return Err(TouchSessionError::UnknownSession(id));
+        }
+        Ok(())
+    }
+
+    async fn set_device_status(
+        &self,
+        id: Uuid,
+        device_id: String,
+        status: String,
+        max_devices: usize,
+    ) -> Result<bool, SetDeviceStatusError> {
+        pool::hash_set_capped(
+            &self.pool,
+            self.key(devices_key(id)),
+            device_id,
+            status,
+            max_devices,
+            self.config.session_ttl,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetDeviceStatusError::IoError)
+    }
+
+    async fn device_statuses(&self, id: Uuid) -> Result<Vec<String>, GetDeviceStatusesError> {
+        let statuses = pool::hash_values(&self.pool, self.key(devices_key(id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetDeviceStatusesError::IoError)?;
+        Ok(statuses)
+    }
+
+    async fn save_recording(&self, recording: Recording) -> Result<(), SaveRecordingError> {
+        let summary = serde_json::to_string(&recording.summary())
+            .map_err(Into::into)
+            .map_err(SaveRecordingError::IoError)?;
+        let serialized = serde_json::to_string(&recording)
+            .map_err(Into::into)
+            .map_err(SaveRecordingError::IoError)?;
+        pool::set_str(
+            &self.pool,
+            self.key(recording_key(recording.id)),
+            serialized,
+            None,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SaveRecordingError::IoError)?;
+        pool::hash_set(
+            &self.pool,
+            self.key(RECORDINGS_KEY),
+            recording.id.into(),
+            summary,
+            None,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SaveRecordingError::IoError)?;
+        Ok(())
+    }
+
+    async fn recording(&self, id: Uuid) -> Result<Option<Recording>, GetRecordingError> {
+        let Some(recording) = pool::get_str(&self.pool, self.key(recording_key(id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetRecordingError::IoError)?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_str(&recording)
+            .map_err(Into::into)
+            .map_err(GetRecordingError::IoError)
+    }
+
+    async fn recordings(&self) -> Result<Vec<RecordingSummary>, ListRecordingsError> {
+        let summaries = pool::hash_values(&self.pool, self.key(RECORDINGS_KEY))
+            .await
+            .map_err(Into::into)
+            .map_err(ListRecordingsError::IoError)?;
+        let mut summaries = summaries
+            .iter()
+            .map(|summary| serde_json::from_str::<RecordingSummary>(summary))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Into::into)
+            .map_err(ListRecordingsError::IoError)?;
+        summaries.sort_by_key(|summary| summary.started_at);
+        Ok(summaries)
+    }
+
+    async fn set_session_owner(
+        &self,
+        id: Uuid,
+        hub_id: String,
+    ) -> Result<(), SetSessionOwnerError> {
+        pool::set_str(
+            &self.pool,
+            self.key(owner_key(id)),
+            hub_id,
+            self.config.session_ttl,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetSessionOwnerError::IoError)?;
+        Ok(())
+    }
+
+    async fn session_owner(&self, id: Uuid) -> Result<Option<String>, GetSessionOwnerError> {
+        let owner = pool::get_str(&self.pool, self.key(owner_key(id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetSessionOwnerError::IoError)?;
+        Ok(owner)
+    }
+
+    async fn set_join_policy(
+        &self,
+        id: Uuid,
+        policy: JoinPolicy,
+    ) -> Result<(), SetJoinPolicyError> {
+        let serialized = serde_json::to_string(&policy)
+            .map_err(Into::into)
+            .map_err(SetJoinPolicyError::IoError)?;
+        pool::set_str(
+            &self.pool,
+            self.key(policy_key(id)),
+            serialized,
+            self.config.session_ttl,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetJoinPolicyError::IoError)?;
+        Ok(())
+    }
+
+    async fn join_policy(&self, id: Uuid) -> Result<Option<JoinPolicy>, GetJoinPolicyError> {
+        let Some(policy) = pool::get_str(&self.pool, self.key(policy_key(id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetJoinPolicyError::IoError)?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_str(&policy)
+            .map(Some)
+            .map_err(Into::into)
+            .map_err(GetJoinPolicyError::IoError)
+    }
+
+    async fn claim_room(
+        &self,
+        room: String,
+        hub_id: String,
+        max_rooms: usize,
+    ) -> Result<RoomClaim, ClaimRoomError> {
+        let key = room_owner_key(&room);
+        let owner = pool::get_str(&self.pool, self.key(&key))
+            .await
+            .map_err(Into::into)
+            .map_err(ClaimRoomError::IoError)?;
+        match owner {
+            Some(owner) if owner == hub_id => return Ok(RoomClaim::Claimed),
+            Some(_) => return Ok(RoomClaim::Taken),
+            None => {}
+        }
+
+        let rooms_key = self.key(hub_rooms_key(&hub_id));
+        let rooms = pool::set_members(&self.pool, rooms_key.clone())
+            .await
+            .map_err(Into::into)
+            .map_err(ClaimRoomError::IoError)?;
+        if rooms.len() >= max_rooms {
+            return Ok(RoomClaim::TooManyRooms);
+        }
+        // Counted before it is claimed, so a failed claim can be retried without losing count
+        pool::set_add(&self.pool, rooms_key.clone(), room.clone())
+            .await
+            .map_err(Into::into)
+            .map_err(ClaimRoomError::IoError)?;
+        if pool::set_str_if_absent(&self.pool, self.key(&key), hub_id.clone())
+            .await
+            .map_err(Into::into)
+            .map_err(ClaimRoomError::IoError)?
+        {
+            return Ok(RoomClaim::Claimed);
+        }
+        let owner = pool::get_str(&self.pool, self.key(key))
+            .await
+            .map_err(Into::into)
+            .map_err(ClaimRoomError::IoError)?;
+        if owner == Some(hub_id) {
+            return Ok(RoomClaim::Claimed);
+        }
+        pool::set_remove(&self.pool, rooms_key, room)
+            .await
+            .map_err(Into::into)
+            .map_err(ClaimRoomError::IoError)?;
+        Ok(RoomClaim::Taken)
+    }
+
+    async fn release_room(&self, room: String, hub_id: String) -> Result<bool, ReleaseRoomError> {
+        let key = room_owner_key(&room);
+        let owner = pool::get_str(&self.pool, self.key(&key))
+            .await
+            .map_err(Into::into)
+            .map_err(ReleaseRoomError::IoError)?;
+        if owner.as_ref() != Some(&hub_id) {
+            return Ok(false);
+        }
+        // The owner goes last, so a failed release can be retried by the same hub
+        pool::set_remove(&self.pool, self.key(hub_rooms_key(&hub_id)), room.clone())
+            .await
+            .map_err(Into::into)
+            .map_err(ReleaseRoomError::IoError)?;
+        pool::delete_key(&self.pool, self.key(room_session_key(&room)))
+            .await
+            .map_err(Into::into)
+            .map_err(ReleaseRoomError::IoError)?;
+        pool::delete_key(&self.pool, self.key(key))
+            .await
+            .map_err(Into::into)
+            .map_err(ReleaseRoomError::IoError)?;
+        Ok(true)
+    }
+
+    async fn set_room_session(
+        &self,
+        room: String,
+        session_id: Uuid,
+    ) -> Result<(), SetRoomSessionError> {
+        pool::set_str(
+            &self.pool,
+            self.key(room_session_key(&room)),
+            session_id.to_string(),
+            None,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetRoomSessionError::IoError)?;
+        Ok(())
+    }
+
+    async fn room_session(&self, room: String) -> Result<Option<Uuid>, GetRoomSessionError> {
+        let Some(session_id) = pool::get_str(&self.pool, self.key(room_session_key(&room)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetRoomSessionError::IoError)?
+        else {
+            return Ok(None);
+        };
+        let session_id = Uuid::parse_str(&session_id)
+            .map_err(Into::into)
+            .map_err(GetRoomSessionError::IoError)?;
+        // The session may have finished or expired since
+        let live = pool::exists(&self.pool, self.session_key(session_id))
+            .await
+            .map_err(Into::into)
+            .map_err(GetRoomSessionError::IoError)?;
+        Ok(live.then_some(session_id))
+    }
+
+    async fn trust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> Result<(), TrustControllerError> {
+        pool::set_add(
+            &self.pool,
+            self.key(trusted_controllers_key(&hub_id)),
+            controller_id.clone(),
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(TrustControllerError::IoError)?;
+        pool::set_add(
+            &self.pool,
+            self.key(trusting_hubs_key(&controller_id)),
+            hub_id,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(TrustControllerError::IoError)?;
+        Ok(())
+    }
+
+    async fn untrust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> Result<(), UntrustControllerError> {
+        pool::set_remove(
+            &self.pool,
+            self.key(trusted_controllers_key(&hub_id)),
+            controller_id.clone(),
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(UntrustControllerError::IoError)?;
+        pool::set_remove(
+            &self.pool,
+            self.key(trusting_hubs_key(&controller_id)),
+            hub_id,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(UntrustControllerError::IoError)?;
+        Ok(())
+    }
+
+    async fn trusted_controllers(
+        &self,
+        hub_id: String,
+    ) -> Result<BTreeSet<String>, GetTrustedControllersError> {
+        pool::set_members(&self.pool, self.key(trusted_controllers_key(&hub_id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetTrustedControllersError::IoError)
+    }
+
+    async fn trusting_hubs(
+        &self,
+        controller_id: String,
+    ) -> Result<BTreeSet<String>, GetTrustingHubsError> {
+        pool::set_members(&self.pool, self.key(trusting_hubs_key(&controller_id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetTrustingHubsError::IoError)
+    }
+
+    async fn set_online(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+        ttl: Duration,
+    ) -> Result<(), SetPresenceError> {
+        let now = unix_millis();
+        pool::sorted_set_add(
+            &self.pool,
+            self.key(presence_key(role, &client_id)),
+            connection_id,
+            now + ttl.as_millis() as i64,
+            now,
+            ttl.as_secs().max(1) as i64,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetPresenceError::IoError)?;
+        Ok(())
+    }
+
+    async fn set_offline(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+    ) -> Result<(), SetPresenceError> {
+        pool::sorted_set_remove(
+            &self.pool,
+            self.key(presence_key(role, &client_id)),
+            connection_id,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetPresenceError::IoError)?;
+        Ok(())
+    }
+
+    async fn online(
+        &self,
+        role: Role,
+        client_ids: Vec<String>,
+    ) -> Result<BTreeSet<String>, GetPresenceError> {
+        let now = unix_millis();
+        let mut online = BTreeSet::new();
+        for client_id in client_ids {
+            let connections = pool::sorted_set_count_from(
+                &self.pool,
+                self.key(presence_key(role, &client_id)),
+                now,
+            )
+            .await
+            .map_err(Into::into)
+            .map_err(GetPresenceError::IoError)?;
+            if connections > 0 {
+                online.insert(client_id);
+            }
+        }
+        Ok(online)
+    }
+
+    async fn set_join_request_timeout(
+        &self,
+        id: Uuid,
+        timeout: Duration,
+    ) -> Result<(), SetJoinRequestTimeoutError> {
+        pool::set_str(
+            &self.pool,
+            self.key(join_timeout_key(id)),
+            timeout.as_secs().to_string(),
+            self.config.session_ttl,
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(SetJoinRequestTimeoutError::IoError)?;
+        Ok(())
+    }
+
+    async fn join_request_timeout(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<Duration>, GetJoinRequestTimeoutError> {
+        let Some(timeout) = pool::get_str(&self.pool, self.key(join_timeout_key(id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetJoinRequestTimeoutError::IoError)?
+        else {
+            return Ok(None);
+        };
+        timeout
+            .parse()
+            .map(|secs| Some(Duration::from_secs(secs)))
+            .map_err(Into::into)
+            .map_err(GetJoinRequestTimeoutError::IoError)
+    }
+
+    async fn set_session_window(
+        &self,
+        id: Uuid,
+        window: SessionWindow,
+    ) -> Result<(), SetSessionWindowError> {
+        let serialized = serde_json::to_string(&window)
+            .map_err(Into::into)
+            .map_err(SetSessionWindowError::IoError)?;
         pool::set_str(
             &self.pool,
-            id.to_string(),
-            state.to_string(),
+            self.key(window_key(id)),
+            serialized,
             self.config.session_ttl,
         )
         .await
         .map_err(Into::into)
-        .map_err(UpdateSessionStateError::IoError)?;
+        .map_err(SetSessionWindowError::IoError)?;
+        Ok(())
+    }
+
+    async fn session_window(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<SessionWindow>, GetSessionWindowError> {
+        let Some(window) = pool::get_str(&self.pool, self.key(window_key(id)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetSessionWindowError::IoError)?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_str(&window)
+            .map(Some)
+            .map_err(Into::into)
+            .map_err(GetSessionWindowError::IoError)
+    }
+
+    async fn create_invitation(
+        &self,
+        token: String,
+        invitation: Invitation,
+        ttl: Duration,
+    ) -> Result<(), CreateInvitationError> {
+        let serialized = serde_json::to_string(&invitation)
+            .map_err(Into::into)
+            .map_err(CreateInvitationError::IoError)?;
+        // Redis expires keys by the second
+        let ttl = (ttl.as_secs() as i64).max(1);
+        pool::set_str(
+            &self.pool,
+            self.key(invitation_key(&token)),
+            serialized,
+            Some(ttl),
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(CreateInvitationError::IoError)?;
+        Ok(())
+    }
+
+    async fn invitation(&self, token: String) -> Result<Option<Invitation>, GetInvitationError> {
+        let Some(invitation) = pool::get_str(&self.pool, self.key(invitation_key(&token)))
+            .await
+            .map_err(Into::into)
+            .map_err(GetInvitationError::IoError)?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_str(&invitation)
+            .map(Some)
+            .map_err(Into::into)
+            .map_err(GetInvitationError::IoError)
+    }
+
+    async fn append_join_attempt(
+        &self,
+        key: String,
+        attempt: JoinAttempt,
+    ) -> Result<(), AppendJoinAttemptError> {
+        let key = join_attempts_key(&key);
+        let serialized = serde_json::to_string(&attempt)
+            .map_err(Into::into)
+            .map_err(AppendJoinAttemptError::IoError)?;
+        pool::stream_add(
+            &self.pool,
+            self.key(&key),
+            &[("attempt", serialized)],
+            Some(MAX_ATTEMPTS),
+        )
+        .await
+        .map_err(Into::into)
+        .map_err(AppendJoinAttemptError::IoError)?;
+        pool::expire(&self.pool, self.key(key), JOIN_ATTEMPTS_TTL)
+            .await
+            .map_err(Into::into)
+            .map_err(AppendJoinAttemptError::IoError)?;
         Ok(())
     }
+
+    async fn join_attempts(
+        &self,
+        key: String,
+        limit: usize,
+    ) -> Result<Vec<JoinAttempt>, GetJoinAttemptsError> {
+        let entries = pool::stream_latest(&self.pool, self.key(join_attempts_key(&key)), limit)
+            .await
+            .map_err(Into::into)
+            .map_err(GetJoinAttemptsError::IoError)?;
+        entries
+            .iter()
+            .flatten()
+            .filter(|(field, _)| field == "attempt")
+            .map(|(_, attempt)| serde_json::from_str(attempt))
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+            .map_err(GetJoinAttemptsError::IoError)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use test_context::{test_context, AsyncTestContext};
 
-    use super::RedisSessionStore;
+    use super::{owner_key, RedisSessionStore, SchemaError, SCHEMA_VERSION_KEY};
     use crate::{
         configuration::Config,
         sessions::{
             adapters::redis::pool,
-            port::{SessionState, SessionStore},
+            audit::{JoinAttempt, JoinAttemptOutcome},
+            policy::JoinPolicy,
+            port::{RoomClaim, SessionState, SessionStore, TouchSessionError},
+            recording::{RecordedCommand, Recording},
+            role::Role,
+            schedule::{invitation_token, Invitation, SessionWindow},
         },
     };
+    use std::time::Duration;
+    use uuid::Uuid;
 
     impl AsyncTestContext for RedisSessionStore {
         async fn setup() -> Self {
             let config = Config::load();
-            let pool = pool::connect(&config.redis).expect("Can't connect to redis");
+            let pool = pool::connect(
+                &config.redis,
+                config.redis_sentinel.as_ref(),
+                config.redis_cluster.as_ref(),
+            )
+            .expect("Can't connect to redis");
             RedisSessionStore::new(
                 pool,
                 super::Config {
                     session_ttl: config.session_ttl,
+                    key_prefix: String::new(),
                 },
             )
         }
@@ -163,4 +992,357 @@ mod tests {
             SessionState::InProgress
         );
     }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn can_touch_an_existing_session(store: &mut RedisSessionStore) {
+        let uuid = store.create_session().await.unwrap();
+        assert!(store.touch(uuid).await.is_ok());
+        assert_eq!(
+            store.session_state(uuid).await.unwrap().unwrap(),
+            SessionState::WaitingForController
+        );
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn keeps_the_latest_status_of_each_device(store: &mut RedisSessionStore) {
+        let uuid = store.create_session().await.unwrap();
+        for (device_id, status) in [("a", "1"), ("b", "2"), ("a", "3")] {
+            assert!(store
+                .set_device_status(uuid, device_id.into(), status.into(), 2)
+                .await
+                .unwrap());
+        }
+        assert!(!store
+            .set_device_status(uuid, "c".into(), "4".into(), 2)
+            .await
+            .unwrap());
+
+        let mut statuses = store.device_statuses(uuid).await.unwrap();
+        statuses.sort();
+        assert_eq!(statuses, vec!["2".to_string(), "3".to_string()]);
+
+        store.delete_session(uuid).await.unwrap();
+        assert!(store.device_statuses(uuid).await.unwrap().is_empty());
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn recordings_outlive_their_session(store: &mut RedisSessionStore) {
+        let uuid = store.create_session().await.unwrap();
+        let recording = Recording {
+            id: uuid,
+            started_at: 1000,
+            commands: vec![RecordedCommand(0, 0.5), RecordedCommand(120, 1.0)],
+            owner: Some("hub".into()),
+        };
+        store.save_recording(recording.clone()).await.unwrap();
+        store.delete_session(uuid).await.unwrap();
+
+        assert_eq!(
+            store.recording(uuid).await.unwrap(),
+            Some(recording.clone())
+        );
+        assert!(store
+            .recordings()
+            .await
+            .unwrap()
+            .contains(&recording.summary()));
+        assert_eq!(store.recording(Uuid::new_v4()).await.unwrap(), None);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn join_policies_are_deleted_with_their_session(store: &mut RedisSessionStore) {
+        let uuid = store.create_session().await.unwrap();
+        assert_eq!(store.join_policy(uuid).await.unwrap(), None);
+
+        store.set_join_policy(uuid, JoinPolicy::Open).await.unwrap();
+        assert_eq!(
+            store.join_policy(uuid).await.unwrap(),
+            Some(JoinPolicy::Open)
+        );
+
+        store.delete_session(uuid).await.unwrap();
+        assert_eq!(store.join_policy(uuid).await.unwrap(), None);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn rooms_resolve_to_the_live_session_of_their_owner(store: &mut RedisSessionStore) {
+        let room = format!("room-{}", Uuid::new_v4());
+        let (alice, bob) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        for _ in 0..2 {
+            assert_eq!(
+                store
+                    .claim_room(room.clone(), alice.clone(), 1)
+                    .await
+                    .unwrap(),
+                RoomClaim::Claimed
+            );
+        }
+        assert_eq!(
+            store.claim_room(room.clone(), bob, 1).await.unwrap(),
+            RoomClaim::Taken
+        );
+        assert_eq!(store.room_session(room.clone()).await.unwrap(), None);
+
+        let uuid = store.create_session().await.unwrap();
+        store.set_room_session(room.clone(), uuid).await.unwrap();
+        assert_eq!(store.room_session(room.clone()).await.unwrap(), Some(uuid));
+
+        store.delete_session(uuid).await.unwrap();
+        assert_eq!(store.room_session(room).await.unwrap(), None);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn hubs_own_a_bounded_number_of_rooms_until_they_release_them(
+        store: &mut RedisSessionStore,
+    ) {
+        let (first, second) = (
+            format!("room-{}", Uuid::new_v4()),
+            format!("room-{}", Uuid::new_v4()),
+        );
+        let (alice, bob) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        store
+            .claim_room(first.clone(), alice.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .claim_room(second.clone(), alice.clone(), 1)
+                .await
+                .unwrap(),
+            RoomClaim::TooManyRooms
+        );
+
+        let uuid = store.create_session().await.unwrap();
+        store.set_room_session(first.clone(), uuid).await.unwrap();
+        assert!(!store
+            .release_room(first.clone(), bob.clone())
+            .await
+            .unwrap());
+        assert!(store
+            .release_room(first.clone(), alice.clone())
+            .await
+            .unwrap());
+        assert_eq!(store.room_session(first.clone()).await.unwrap(), None);
+        assert_eq!(
+            store.claim_room(second, alice, 1).await.unwrap(),
+            RoomClaim::Claimed
+        );
+        assert_eq!(
+            store.claim_room(first, bob, 1).await.unwrap(),
+            RoomClaim::Claimed
+        );
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn trust_is_seen_from_the_hub_and_the_controller(store: &mut RedisSessionStore) {
+        let hub_id = Uuid::new_v4().to_string();
+        let controller_id = Uuid::new_v4().to_string();
+        store
+            .trust_controller(hub_id.clone(), controller_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.trusted_controllers(hub_id.clone()).await.unwrap(),
+            [controller_id.clone()].into()
+        );
+        assert_eq!(
+            store.trusting_hubs(controller_id.clone()).await.unwrap(),
+            [hub_id.clone()].into()
+        );
+
+        store
+            .untrust_controller(hub_id.clone(), controller_id.clone())
+            .await
+            .unwrap();
+        assert!(store.trusted_controllers(hub_id).await.unwrap().is_empty());
+        assert!(store.trusting_hubs(controller_id).await.unwrap().is_empty());
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn clients_are_online_while_a_connection_is(store: &mut RedisSessionStore) {
+        let hub_id = Uuid::new_v4().to_string();
+        let ttl = Duration::from_secs(60);
+        for connection_id in ["a", "b"] {
+            store
+                .set_online(Role::Hub, hub_id.clone(), connection_id.into(), ttl)
+                .await
+                .unwrap();
+        }
+        let online = || store.online(Role::Hub, vec![hub_id.clone(), "nobody".into()]);
+        assert_eq!(online().await.unwrap(), [hub_id.clone()].into());
+
+        store
+            .set_offline(Role::Hub, hub_id.clone(), "a".into())
+            .await
+            .unwrap();
+        assert_eq!(online().await.unwrap(), [hub_id.clone()].into());
+        store
+            .set_offline(Role::Hub, hub_id.clone(), "b".into())
+            .await
+            .unwrap();
+        assert!(online().await.unwrap().is_empty());
+        assert!(store
+            .online(Role::Controller, vec![hub_id])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn join_request_timeouts_are_deleted_with_their_session(store: &mut RedisSessionStore) {
+        let uuid = store.create_session().await.unwrap();
+        assert_eq!(store.join_request_timeout(uuid).await.unwrap(), None);
+
+        let timeout = Duration::from_secs(30);
+        store.set_join_request_timeout(uuid, timeout).await.unwrap();
+        assert_eq!(
+            store.join_request_timeout(uuid).await.unwrap(),
+            Some(timeout)
+        );
+
+        store.delete_session(uuid).await.unwrap();
+        assert_eq!(store.join_request_timeout(uuid).await.unwrap(), None);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn scheduled_sessions_keep_their_window_and_invitations(store: &mut RedisSessionStore) {
+        let uuid = store.create_session().await.unwrap();
+        let window = SessionWindow {
+            starts_at: 1_000,
+            ends_at: 2_000,
+        };
+        store.set_session_window(uuid, window).await.unwrap();
+        assert_eq!(store.session_window(uuid).await.unwrap(), Some(window));
+
+        let token = invitation_token();
+        let invitation = Invitation {
+            session_id: uuid,
+            controller_id: "alice".into(),
+        };
+        store
+            .create_invitation(token.clone(), invitation.clone(), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.invitation(token.clone()).await.unwrap(),
+            Some(invitation)
+        );
+
+        store.delete_session(uuid).await.unwrap();
+        assert_eq!(store.session_window(uuid).await.unwrap(), None);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(store.invitation(token).await.unwrap(), None);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn keeps_the_latest_join_attempts(store: &mut RedisSessionStore) {
+        let key = format!("hub:{}", Uuid::new_v4());
+        for outcome in [JoinAttemptOutcome::Rejected, JoinAttemptOutcome::Accepted] {
+            let attempt = JoinAttempt::new(Uuid::nil(), Some("controller".into()), "hi", outcome);
+            store
+                .append_join_attempt(key.clone(), attempt)
+                .await
+                .unwrap();
+        }
+
+        let attempts = store.join_attempts(key.clone(), 1).await.unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].outcome, JoinAttemptOutcome::Accepted);
+        assert_eq!(attempts[0].controller_id.as_deref(), Some("controller"));
+        assert_eq!(store.join_attempts(key, 10).await.unwrap().len(), 2);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn can_not_touch_an_unknown_session(store: &mut RedisSessionStore) {
+        let uuid = Uuid::new_v4();
+        assert!(matches!(
+            store.touch(uuid).await,
+            Err(TouchSessionError::UnknownSession(id)) if id == uuid
+        ));
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn prefixed_stores_do_not_see_each_other(store: &mut RedisSessionStore) {
+        let tenant = RedisSessionStore::new(
+            store.pool.clone(),
+            super::Config {
+                key_prefix: "acme:".into(),
+                ..store.config.clone()
+            },
+        );
+
+        let uuid = tenant.create_session().await.unwrap();
+        assert!(tenant.exists_session(uuid).await.unwrap());
+        assert!(!store.exists_session(uuid).await.unwrap());
+        assert_eq!(store.session_state(uuid).await.unwrap(), None);
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn refuses_keys_written_with_another_schema(store: &mut RedisSessionStore) {
+        let tenant = RedisSessionStore::new(
+            store.pool.clone(),
+            super::Config {
+                key_prefix: format!("{}:", Uuid::new_v4()),
+                ..store.config.clone()
+            },
+        );
+
+        tenant.check_schema().await.unwrap();
+        tenant.check_schema().await.unwrap();
+
+        pool::set_str(
+            &store.pool,
+            tenant.key(SCHEMA_VERSION_KEY),
+            "0".into(),
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            tenant.check_schema().await,
+            Err(SchemaError::Incompatible(version)) if version == "0"
+        ));
+    }
+
+    #[test_context(RedisSessionStore)]
+    #[tokio::test]
+    async fn finds_the_keys_written_without_a_prefix(store: &mut RedisSessionStore) {
+        let prefixed = RedisSessionStore::new(
+            store.pool.clone(),
+            super::Config {
+                key_prefix: "intisync:".into(),
+                ..store.config.clone()
+            },
+        );
+
+        let id = store.create_session().await.unwrap();
+        store.set_session_owner(id, "hub".into()).await.unwrap();
+        let other_app = Uuid::new_v4();
+        pool::set_str(&store.pool, other_app.to_string(), "{}".into(), None)
+            .await
+            .unwrap();
+        pool::set_str(&store.pool, owner_key(other_app), "bob".into(), None)
+            .await
+            .unwrap();
+
+        assert!(store.legacy_keys().await.unwrap().is_empty());
+        let legacy_keys = prefixed.legacy_keys().await.unwrap();
+        assert!(legacy_keys.contains(&id.to_string()));
+        assert!(!legacy_keys.contains(&owner_key(id)));
+        assert!(!legacy_keys.contains(&other_app.to_string()));
+    }
 }
@@ -1,8 +1,182 @@
-use deadpool::managed::PoolError;
+use async_trait::async_trait;
+use deadpool::managed::{self, BuildError, Metrics, PoolError, RecycleError, RecycleResult};
 use deadpool::Runtime;
-use deadpool_redis::redis::{self, AsyncCommands, FromRedisValue, RedisError};
+use redis::{
+    aio::{ConnectionLike, MultiplexedConnection},
+    cluster::{ClusterClient, ClusterClientBuilder},
+    cluster_async::ClusterConnection,
+    cluster_routing::{Route, RoutingInfo, SingleNodeRoutingInfo, SlotAddr},
+    sentinel::{SentinelClient, SentinelNodeConnectionInfo, SentinelServerType},
+    AsyncCommands, Client, Cmd, ConnectionAddr, ConnectionInfo, FromRedisValue, IntoConnectionInfo,
+    Pipeline, RedisConnectionInfo, RedisError, RedisFuture, TlsMode, Value,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+use tokio::{sync::Mutex, time::Instant};
+use tracing::warn;
 
-pub type RedisPool = deadpool_redis::Pool;
+pub type RedisPool = managed::Pool<Manager>;
+
+/// Times a connection to the primary is attempted while Sentinel fails over to a replica.
+const FAILOVER_ATTEMPTS: u32 = 6;
+/// Wait before the second attempt, doubled after each one.
+const FAILOVER_BACKOFF: Duration = Duration::from_millis(250);
+/// How long a connection to the primary Sentinel pointed to is trusted to still reach it.
+const ROLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Finds the Redis primary through Sentinel instead of connecting to a single node.
+#[derive(Clone, Deserialize)]
+pub struct SentinelConfig {
+    /// URLs of the sentinels, such as `redis://localhost:26379`.
+    pub urls: Vec<String>,
+    /// Name the sentinels monitor the primary under.
+    pub master_name: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// Spreads the keys over the primaries of a Redis Cluster.
+#[derive(Clone, Deserialize)]
+pub struct ClusterConfig {
+    /// URLs of some nodes of the cluster, such as `redis://localhost:7000`, the others are
+    /// discovered from them.
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+enum Topology {
+    Single(Client),
+    Sentinel(Mutex<SentinelClient>),
+    Cluster(ClusterClient),
+}
+
+/// Connection to a single node, the primary Sentinel points to, or a whole cluster.
+pub enum Connection {
+    Node(MultiplexedConnection),
+    Primary {
+        connection: MultiplexedConnection,
+        /// When the node last said it was the primary.
+        checked_at: Instant,
+    },
+    Cluster(ClusterConnection),
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        match self {
+            Self::Node(connection) | Self::Primary { connection, .. } => {
+                connection.req_packed_command(cmd)
+            }
+            Self::Cluster(connection) => connection.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        pipeline: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        match self {
+            Self::Node(connection) | Self::Primary { connection, .. } => {
+                connection.req_packed_commands(pipeline, offset, count)
+            }
+            Self::Cluster(connection) => connection.req_packed_commands(pipeline, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Self::Node(connection) | Self::Primary { connection, .. } => connection.get_db(),
+            Self::Cluster(connection) => connection.get_db(),
+        }
+    }
+}
+
+/// Hands out multiplexed connections to a single node or to the primary Sentinel points to, or
+/// connections routing each command to the node of its key in a cluster.
+pub struct Manager(Topology);
+
+#[async_trait]
+impl managed::Manager for Manager {
+    type Type = Connection;
+    type Error = RedisError;
+
+    async fn create(&self) -> Result<Connection, RedisError> {
+        let sentinel = match &self.0 {
+            Topology::Single(client) => {
+                return client
+                    .get_multiplexed_tokio_connection()
+                    .await
+                    .map(Connection::Node)
+            }
+            // The cluster client retries with backoff itself while a replica is promoted
+            Topology::Cluster(client) => {
+                return client.get_async_connection().await.map(Connection::Cluster)
+            }
+            Topology::Sentinel(sentinel) => sentinel,
+        };
+        // The sentinels need a moment to promote a replica after the primary goes down
+        let mut backoff = FAILOVER_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            match sentinel.lock().await.get_async_connection().await {
+                Ok(connection) => {
+                    return Ok(Connection::Primary {
+                        connection,
+                        checked_at: Instant::now(),
+                    })
+                }
+                Err(error) if attempt < FAILOVER_ATTEMPTS => {
+                    warn!(%error, attempt, "Failed to connect to the Redis primary, retrying");
+                }
+                Err(error) => return Err(error),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    async fn recycle(&self, connection: &mut Connection, _: &Metrics) -> RecycleResult<RedisError> {
+        // Single nodes can't fail over, cluster connections follow the slots on their own
+        let Connection::Primary {
+            connection,
+            checked_at,
+        } = connection
+        else {
+            return Ok(());
+        };
+        if checked_at.elapsed() < ROLE_CHECK_INTERVAL {
+            return Ok(());
+        }
+        // A primary demoted by a failover keeps answering pings but refuses writes
+        let role: Vec<redis::Value> = redis::cmd("ROLE")
+            .query_async(connection)
+            .await
+            .map_err(RecycleError::Backend)?;
+        let role = role
+            .first()
+            .and_then(|role| String::from_redis_value(role).ok());
+        match role.as_deref() {
+            Some("master") => {
+                *checked_at = Instant::now();
+                Ok(())
+            }
+            _ => Err(RecycleError::StaticMessage(
+                "Redis node is no longer the primary",
+            )),
+        }
+    }
+}
 
 #[derive(thiserror::Error, Debug)]
 pub enum OperationError<T>
@@ -16,8 +190,12 @@ where
 }
 
 #[derive(thiserror::Error, Debug)]
-#[error("Failed to create Redis pool: '{0}'")]
-pub struct ConnectError(#[from] deadpool_redis::CreatePoolError);
+pub enum ConnectError {
+    #[error("Invalid Redis connection settings: '{0}'")]
+    Config(#[from] RedisError),
+    #[error("Failed to create Redis pool: '{0}'")]
+    Build(#[from] BuildError),
+}
 
 #[derive(thiserror::Error, Debug)]
 #[error("Failed to set value '{1}' to key '{0}': '{2}'")]
@@ -45,11 +223,145 @@ pub enum DeleteError {
     Delete(String, RedisError),
 }
 
-pub fn connect(config: &deadpool_redis::Config) -> Result<RedisPool, ConnectError> {
-    let pool = config.create_pool(Some(Runtime::Tokio1))?;
+#[derive(thiserror::Error, Debug)]
+pub enum ExpireError {
+    #[error("Failed to set the expiration of key '{0}': '{1}'")]
+    Expire(String, RedisError),
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to scan the keys matching '{0}': '{1}'")]
+pub struct ScanError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to read the type of key '{0}': '{1}'")]
+pub struct TypeError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to rename key '{0}' to '{1}': '{2}'")]
+pub struct RenameError(String, String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to set field '{1}' of hash '{0}': '{2}'")]
+pub struct HashSetError(String, String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to get the values of hash '{0}': '{1}'")]
+pub struct HashValuesError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to update the members of set '{0}': '{1}'")]
+pub struct SetMembersError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to update the sorted set '{0}': '{1}'")]
+pub struct SortedSetError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to publish message to channel '{0}': '{1}'")]
+pub struct PublishError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to add entry to stream '{0}': '{1}'")]
+pub struct StreamAddError(String, RedisError);
+
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to read the entries of stream '{0}': '{1}'")]
+pub struct StreamRangeError(String, RedisError);
+
+/// Creates a pool of connections to the node in `config`, to the primary `sentinel` points
+/// to, or to the nodes of `cluster`.
+pub fn connect(
+    config: &deadpool_redis::Config,
+    sentinel: Option<&SentinelConfig>,
+    cluster: Option<&ClusterConfig>,
+) -> Result<RedisPool, ConnectError> {
+    if let Some(cluster) = cluster {
+        let mut builder = ClusterClientBuilder::new(cluster.urls.clone())
+            .retries(FAILOVER_ATTEMPTS)
+            .min_retry_wait(FAILOVER_BACKOFF.as_millis() as u64);
+        if let Some(username) = &cluster.username {
+            builder = builder.username(username.clone());
+        }
+        if let Some(password) = &cluster.password {
+            builder = builder.password(password.clone());
+        }
+        return build_pool(config, Topology::Cluster(builder.build()?));
+    }
+    let topology = match (sentinel, &config.url, &config.connection) {
+        (Some(sentinel), _, _) => Topology::Sentinel(Mutex::new(SentinelClient::build(
+            sentinel.urls.clone(),
+            sentinel.master_name.clone(),
+            Some(SentinelNodeConnectionInfo {
+                tls_mode: tls_mode(&sentinel.urls)?,
+                redis_connection_info: Some(RedisConnectionInfo {
+                    db: 0,
+                    username: sentinel.username.clone(),
+                    password: sentinel.password.clone(),
+                }),
+            }),
+            SentinelServerType::Master,
+        )?)),
+        (None, Some(url), _) => Topology::Single(Client::open(url.as_str())?),
+        (None, None, Some(connection)) => {
+            Topology::Single(Client::open(connection_info(connection))?)
+        }
+        (None, None, None) => Topology::Single(Client::open("redis://127.0.0.1")?),
+    };
+    build_pool(config, topology)
+}
+
+fn build_pool(
+    config: &deadpool_redis::Config,
+    topology: Topology,
+) -> Result<RedisPool, ConnectError> {
+    let pool = RedisPool::builder(Manager(topology))
+        .config(config.get_pool_config())
+        .runtime(Runtime::Tokio1)
+        .build()?;
     Ok(pool)
 }
 
+/// Connects to the primary with TLS when the sentinels are reached with it, as `rediss://` URLs
+/// ask for.
+fn tls_mode(urls: &[String]) -> Result<Option<TlsMode>, RedisError> {
+    let Some(url) = urls.first() else {
+        return Ok(None);
+    };
+    let mode = match url.as_str().into_connection_info()?.addr {
+        ConnectionAddr::TcpTls { insecure: true, .. } => Some(TlsMode::Insecure),
+        ConnectionAddr::TcpTls { .. } => Some(TlsMode::Secure),
+        ConnectionAddr::Tcp(..) | ConnectionAddr::Unix(_) => None,
+    };
+    Ok(mode)
+}
+
+/// Converts the connection settings deadpool deserializes into those of the Redis client.
+fn connection_info(info: &deadpool_redis::ConnectionInfo) -> ConnectionInfo {
+    let addr = match &info.addr {
+        deadpool_redis::ConnectionAddr::Tcp(host, port) => ConnectionAddr::Tcp(host.clone(), *port),
+        deadpool_redis::ConnectionAddr::TcpTls {
+            host,
+            port,
+            insecure,
+        } => ConnectionAddr::TcpTls {
+            host: host.clone(),
+            port: *port,
+            insecure: *insecure,
+            tls_params: None,
+        },
+        deadpool_redis::ConnectionAddr::Unix(path) => ConnectionAddr::Unix(path.clone()),
+    };
+    ConnectionInfo {
+        addr,
+        redis: RedisConnectionInfo {
+            db: info.redis.db,
+            username: info.redis.username.clone(),
+            password: info.redis.password.clone(),
+        },
+    }
+}
+
 pub async fn set_str(
     pool: &RedisPool,
     key: String,
@@ -57,17 +369,184 @@ pub async fn set_str(
     ttl_seconds: Option<i64>,
 ) -> Result<(), OperationError<SetError>> {
     let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
-    con.set(&key, &value)
+    con.set::<_, _, ()>(&key, &value)
         .await
         .map_err(|err| SetError(key.clone(), value.clone(), err))?;
     if ttl_seconds.is_some_and(|x| x > 0) {
-        con.expire(&key, ttl_seconds.unwrap())
+        con.expire::<_, ()>(&key, ttl_seconds.unwrap())
             .await
             .map_err(|err| SetError(key, value, err))?;
     }
     Ok(())
 }
 
+/// Sets the value of a key that doesn't exist yet, returns whether it was set.
+pub async fn set_str_if_absent(
+    pool: &RedisPool,
+    key: String,
+    value: String,
+) -> Result<bool, OperationError<SetError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let set = con
+        .set_nx(&key, &value)
+        .await
+        .map_err(|err| SetError(key, value, err))?;
+    Ok(set)
+}
+
+pub async fn hash_set(
+    pool: &RedisPool,
+    key: String,
+    field: String,
+    value: String,
+    ttl_seconds: Option<i64>,
+) -> Result<(), OperationError<HashSetError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    con.hset::<_, _, _, ()>(&key, &field, &value)
+        .await
+        .map_err(|err| HashSetError(key.clone(), field.clone(), err))?;
+    if let Some(ttl) = ttl_seconds.filter(|ttl| *ttl > 0) {
+        con.expire::<_, ()>(&key, ttl)
+            .await
+            .map_err(|err| HashSetError(key, field, err))?;
+    }
+    Ok(())
+}
+
+/// Sets `field` of the hash unless it would make it have more than `max_fields` fields,
+/// returns whether it was set. Checked and set in one script so concurrent calls can't go over.
+pub async fn hash_set_capped(
+    pool: &RedisPool,
+    key: String,
+    field: String,
+    value: String,
+    max_fields: usize,
+    ttl_seconds: Option<i64>,
+) -> Result<bool, OperationError<HashSetError>> {
+    const SCRIPT: &str = r"
+        if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 0
+            and redis.call('HLEN', KEYS[1]) >= tonumber(ARGV[3]) then
+            return 0
+        end
+        redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+        if tonumber(ARGV[4]) > 0 then
+            redis.call('EXPIRE', KEYS[1], ARGV[4])
+        end
+        return 1
+    ";
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let set = redis::cmd("EVAL")
+        .arg(SCRIPT)
+        .arg(1)
+        .arg(&key)
+        .arg(&field)
+        .arg(&value)
+        .arg(max_fields)
+        .arg(ttl_seconds.unwrap_or_default())
+        .query_async(&mut *con)
+        .await
+        .map_err(|err| HashSetError(key, field, err))?;
+    Ok(set)
+}
+
+pub async fn hash_values(
+    pool: &RedisPool,
+    key: String,
+) -> Result<Vec<String>, OperationError<HashValuesError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let values = con
+        .hvals(&key)
+        .await
+        .map_err(|err| HashValuesError(key, err))?;
+    Ok(values)
+}
+
+pub async fn set_add(
+    pool: &RedisPool,
+    key: String,
+    member: String,
+) -> Result<(), OperationError<SetMembersError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    con.sadd::<_, _, ()>(&key, &member)
+        .await
+        .map_err(|err| SetMembersError(key, err))?;
+    Ok(())
+}
+
+pub async fn set_remove(
+    pool: &RedisPool,
+    key: String,
+    member: String,
+) -> Result<(), OperationError<SetMembersError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    con.srem::<_, _, ()>(&key, &member)
+        .await
+        .map_err(|err| SetMembersError(key, err))?;
+    Ok(())
+}
+
+pub async fn set_members(
+    pool: &RedisPool,
+    key: String,
+) -> Result<BTreeSet<String>, OperationError<SetMembersError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let members = con
+        .smembers(&key)
+        .await
+        .map_err(|err| SetMembersError(key, err))?;
+    Ok(members)
+}
+
+/// Adds a member to a sorted set, dropping the members scored below `min_score`.
+pub async fn sorted_set_add(
+    pool: &RedisPool,
+    key: String,
+    member: String,
+    score: i64,
+    min_score: i64,
+    ttl_seconds: i64,
+) -> Result<(), OperationError<SortedSetError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    redis::pipe()
+        .atomic()
+        .zrembyscore(&key, "-inf", format!("({min_score}"))
+        .ignore()
+        .zadd(&key, &member, score)
+        .ignore()
+        .expire(&key, ttl_seconds)
+        .ignore()
+        .query_async::<_, ()>(&mut *con)
+        .await
+        .map_err(|err| SortedSetError(key, err))?;
+    Ok(())
+}
+
+pub async fn sorted_set_remove(
+    pool: &RedisPool,
+    key: String,
+    member: String,
+) -> Result<(), OperationError<SortedSetError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    con.zrem::<_, _, ()>(&key, &member)
+        .await
+        .map_err(|err| SortedSetError(key, err))?;
+    Ok(())
+}
+
+/// Counts the members of a sorted set scored from `min_score`.
+pub async fn sorted_set_count_from(
+    pool: &RedisPool,
+    key: String,
+    min_score: i64,
+) -> Result<usize, OperationError<SortedSetError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let count = con
+        .zcount(&key, min_score, "+inf")
+        .await
+        .map_err(|err| SortedSetError(key, err))?;
+    Ok(count)
+}
+
 pub async fn get_str(
     pool: &RedisPool,
     key: String,
@@ -96,8 +575,305 @@ pub async fn exists(pool: &RedisPool, key: String) -> Result<bool, OperationErro
 
 pub async fn delete_key(pool: &RedisPool, key: String) -> Result<(), OperationError<DeleteError>> {
     let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
-    con.del(&key)
+    con.del::<_, ()>(&key)
         .await
         .map_err(|err| DeleteError::Delete(key.clone(), err))?;
     Ok(())
 }
+
+/// Returns every key matching a glob-style pattern, walking the keyspace with `SCAN`.
+///
+/// `SCAN` only walks the node it is sent to, so a cluster walks every primary with a cursor
+/// of its own.
+pub async fn scan_keys(
+    pool: &RedisPool,
+    pattern: String,
+) -> Result<Vec<String>, OperationError<ScanError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    if let Connection::Cluster(cluster) = &mut *con {
+        return scan_primaries(cluster, &pattern)
+            .await
+            .map_err(|err| ScanError(pattern, err).into());
+    }
+    let mut iter = con
+        .scan_match::<_, String>(&pattern)
+        .await
+        .map_err(|err| ScanError(pattern, err))?;
+    let mut keys = Vec::new();
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+async fn scan_primaries(
+    cluster: &mut ClusterConnection,
+    pattern: &str,
+) -> Result<Vec<String>, RedisError> {
+    let mut keys = Vec::new();
+    for slot in primary_slots(cluster).await? {
+        // Commands routed to a slot of a primary reach that primary
+        let route = RoutingInfo::SingleNode(SingleNodeRoutingInfo::SpecificNode(Route::new(
+            slot,
+            SlotAddr::Master,
+        )));
+        let mut cursor = 0;
+        loop {
+            let mut scan = redis::cmd("SCAN");
+            scan.arg(cursor).arg("MATCH").arg(pattern);
+            let reply = cluster.route_command(&scan, route.clone()).await?;
+            let (next, batch): (u64, Vec<String>) = FromRedisValue::from_redis_value(&reply)?;
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+    }
+    keys.sort_unstable();
+    keys.dedup();
+    Ok(keys)
+}
+
+/// Returns a slot served by each primary of a cluster.
+async fn primary_slots(cluster: &mut ClusterConnection) -> Result<Vec<u16>, RedisError> {
+    // Each range of slots is listed as its first and last slot, then its primary and replicas
+    let ranges: Vec<Vec<Value>> = redis::cmd("CLUSTER")
+        .arg("SLOTS")
+        .query_async(cluster)
+        .await?;
+    let mut primaries = BTreeMap::new();
+    for range in ranges {
+        let (Some(first_slot), Some(primary)) = (range.first(), range.get(2)) else {
+            continue;
+        };
+        let Value::Bulk(address) = primary else {
+            continue;
+        };
+        let (Some(host), Some(port)) = (address.first(), address.get(1)) else {
+            continue;
+        };
+        primaries
+            .entry((
+                String::from_redis_value(host)?,
+                u16::from_redis_value(port)?,
+            ))
+            .or_insert(u16::from_redis_value(first_slot)?);
+    }
+    Ok(primaries.into_values().collect())
+}
+
+/// Returns the type of the value at a key, `none` if there is no such key.
+pub async fn key_type(pool: &RedisPool, key: String) -> Result<String, OperationError<TypeError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let key_type = con
+        .key_type(&key)
+        .await
+        .map_err(|err| TypeError(key, err))?;
+    Ok(key_type)
+}
+
+/// Renames a key unless the new name is taken, returns whether it was renamed.
+///
+/// In a cluster both names must hash to the same slot, such as `id` and `prefix:{id}`.
+pub async fn rename_if_absent(
+    pool: &RedisPool,
+    key: String,
+    new_key: String,
+) -> Result<bool, OperationError<RenameError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let renamed = con
+        .rename_nx(&key, &new_key)
+        .await
+        .map_err(|err| RenameError(key, new_key, err))?;
+    Ok(renamed)
+}
+
+pub async fn expire(
+    pool: &RedisPool,
+    key: String,
+    ttl_seconds: i64,
+) -> Result<bool, OperationError<ExpireError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let updated = con
+        .expire(&key, ttl_seconds)
+        .await
+        .map_err(|err| ExpireError::Expire(key, err))?;
+    Ok(updated)
+}
+
+pub async fn publish(
+    pool: &RedisPool,
+    channel: String,
+    message: String,
+) -> Result<(), OperationError<PublishError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    con.publish::<_, _, ()>(&channel, message)
+        .await
+        .map_err(|err| PublishError(channel, err))?;
+    Ok(())
+}
+
+pub async fn stream_add(
+    pool: &RedisPool,
+    key: String,
+    fields: &[(&str, String)],
+    max_len: Option<usize>,
+) -> Result<(), OperationError<StreamAddError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let mut cmd = redis::cmd("XADD");
+    cmd.arg(&key);
+    if let Some(max_len) = max_len {
+        cmd.arg("MAXLEN").arg("~").arg(max_len);
+    }
+    cmd.arg("*").arg(fields);
+    cmd.query_async::<_, ()>(&mut *con)
+        .await
+        .map_err(|err| StreamAddError(key, err))?;
+    Ok(())
+}
+
+/// Returns the fields of the latest `count` entries of a stream, newest first.
+pub async fn stream_latest(
+    pool: &RedisPool,
+    key: String,
+    count: usize,
+) -> Result<Vec<Vec<(String, String)>>, OperationError<StreamRangeError>> {
+    let mut con = pool.get().await.map_err(OperationError::GetConnection)?;
+    let entries: Vec<redis::Value> = redis::cmd("XREVRANGE")
+        .arg(&key)
+        .arg("+")
+        .arg("-")
+        .arg("COUNT")
+        .arg(count)
+        .query_async(&mut *con)
+        .await
+        .map_err(|err| StreamRangeError(key.clone(), err))?;
+    // Each entry is `[id, [field, value, ...]]`
+    let entries = entries
+        .iter()
+        .map(|entry| {
+            let (_, fields): (String, Vec<String>) = FromRedisValue::from_redis_value(entry)?;
+            Ok(fields
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect())
+        })
+        .collect::<Result<_, RedisError>>()
+        .map_err(|err| StreamRangeError(key, err))?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect, get_str, rename_if_absent, scan_keys, set_str};
+    use crate::configuration::Config;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    #[ignore = "needs the Redis Sentinel setup of compose.yml, run it with `cargo make test-sentinel`"]
+    async fn sentinel_pools_follow_the_primary_across_failovers() {
+        let config = Config::load();
+        let sentinel = config
+            .redis_sentinel
+            .expect("Redis Sentinel isn't configured");
+        let pool = connect(&config.redis, Some(&sentinel), None).unwrap();
+        set_str(&pool, "failover".into(), "before".into(), None)
+            .await
+            .unwrap();
+
+        let client = redis::Client::open(sentinel.urls[0].as_str()).unwrap();
+        let mut con = client.get_multiplexed_tokio_connection().await.unwrap();
+        let primary = |mut con: redis::aio::MultiplexedConnection| {
+            let name = sentinel.master_name.clone();
+            async move {
+                redis::cmd("SENTINEL")
+                    .arg("GET-MASTER-ADDR-BY-NAME")
+                    .arg(name)
+                    .query_async::<_, (String, u16)>(&mut con)
+                    .await
+                    .unwrap()
+            }
+        };
+        let old_primary = primary(con.clone()).await;
+        redis::cmd("SENTINEL")
+            .arg("FAILOVER")
+            .arg(&sentinel.master_name)
+            .query_async::<_, ()>(&mut con)
+            .await
+            .unwrap();
+        for _ in 0..60 {
+            if primary(con.clone()).await != old_primary {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        assert_ne!(primary(con.clone()).await, old_primary);
+
+        // Connections to the demoted primary are refused writes until they are replaced
+        let mut written = false;
+        for _ in 0..20 {
+            if set_str(&pool, "failover".into(), "after".into(), None)
+                .await
+                .is_ok()
+            {
+                written = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        assert!(written);
+        assert_eq!(
+            get_str(&pool, "failover".into()).await.unwrap().as_deref(),
+            Some("after")
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs the Redis Cluster setup of compose.yml, run it with `cargo make test-cluster`"]
+    async fn cluster_pools_reach_the_keys_of_every_node() {
+        let config = Config::load();
+        let cluster = config
+            .redis_cluster
+            .expect("Redis Cluster isn't configured");
+        let pool = connect(&config.redis, None, Some(&cluster)).unwrap();
+        let prefix = Uuid::new_v4();
+
+        // Enough keys to land on the slots of every primary
+        for i in 0..20 {
+            set_str(&pool, format!("{prefix}:{i}"), i.to_string(), None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            scan_keys(&pool, format!("{prefix}:*")).await.unwrap().len(),
+            20
+        );
+
+        // The new name tags the old one, so both hash to the same slot
+        let new_key = format!("moved:{{{prefix}}}");
+        set_str(&pool, prefix.to_string(), "old".into(), None)
+            .await
+            .unwrap();
+        assert!(rename_if_absent(&pool, prefix.to_string(), new_key.clone())
+            .await
+            .unwrap());
+        set_str(&pool, prefix.to_string(), "new".into(), None)
+            .await
+            .unwrap();
+        assert!(
+            !rename_if_absent(&pool, prefix.to_string(), new_key.clone())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            get_str(&pool, new_key).await.unwrap().as_deref(),
+            Some("old")
+        );
+        assert_eq!(
+            get_str(&pool, prefix.to_string()).await.unwrap().as_deref(),
+            Some("new")
+        );
+    }
+}
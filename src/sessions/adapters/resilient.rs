@@ -0,0 +1,768 @@
+//! Session store decorator retrying transient failures and failing fast while its backend
+//! is down.
+//!
+//! Only `IoError`s are transient, errors like an unknown session are answers and returned
+//! right away. Calls are retried with exponential backoff and full jitter, and every failed
+//! call counts towards opening the circuit breaker. While open, calls fail without reaching
+//! the backend until the breaker lets a trial call through.
+
+use crate::sessions::{
+    audit::JoinAttempt,
+    policy::JoinPolicy,
+    port::{
+        AppendJoinAttemptError, ClaimRoomError, CreateInvitationError, CreateSessionError,
+        DeleteSessionError, ExistsSessionError, GetDeviceStatusesError, GetInvitationError,
+        GetJoinAttemptsError, GetJoinPolicyError, GetJoinRequestTimeoutError, GetPresenceError,
+        GetRecordingError, GetRoomSessionError, GetSessionOwnerError, GetSessionStateError,
+        GetSessionWindowError, GetTrustedControllersError, GetTrustingHubsError,
+        ListRecordingsError, ReleaseRoomError, RoomClaim, SaveRecordingError, SessionState,
+        SessionStore, SetDeviceStatusError, SetJoinPolicyError, SetJoinRequestTimeoutError,
+        SetPresenceError, SetRoomSessionError, SetSessionOwnerError, SetSessionWindowError,
+        TouchSessionError, TrustControllerError, UntrustControllerError, UpdateSessionStateError,
+    },
+    recording::{Recording, RecordingSummary},
+    role::Role,
+    schedule::{Invitation, SessionWindow},
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::warn;
+use uuid::Uuid;
+
+#[derive(Clone, Deserialize)]
+pub struct Config {
+    /// Retries of a call failing with a transient error.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Longest wait before the first retry, doubled on every following one.
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Failed calls in a row that open the circuit breaker.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Milliseconds the breaker stays open before letting a trial call through.
+    #[serde(default = "default_open_ms")]
+    pub open_ms: u64,
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    50
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_ms() -> u64 {
+    10_000
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_retries: default_max_retries(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            failure_threshold: default_failure_threshold(),
+            open_ms: default_open_ms(),
+        }
+    }
+}
+
+/// Counters of what the decorators sharing them did, since the server started.
+#[derive(Clone, Default)]
+pub struct StoreMetrics(Arc<Counters>);
+
+#[derive(Default)]
+struct Counters {
+    retries: AtomicU64,
+    exhausted: AtomicU64,
+    rejected: AtomicU64,
+    breaker_opened: AtomicU64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct StoreMetricsSnapshot {
+    /// Calls made again after a transient error.
+    pub retries: u64,
+    /// Calls that still failed after every retry.
+    pub exhausted: u64,
+    /// Calls failed right away because the breaker was open.
+    pub rejected: u64,
+    pub breaker_opened: u64,
+}
+
+impl StoreMetrics {
+    pub fn snapshot(&self) -> StoreMetricsSnapshot {
+        StoreMetricsSnapshot {
+            retries: self.0.retries.load(Ordering::Relaxed),
+            exhausted: self.0.exhausted.load(Ordering::Relaxed),
+            rejected: self.0.rejected.load(Ordering::Relaxed),
+            breaker_opened: self.0.breaker_opened.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+enum BreakerState {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A trial call is going on, the others are rejected until it finishes.
+    HalfOpen,
+}
+
+/// Call let through the breaker. A trial call that is dropped or panics before it settles
+/// leaves the breaker open, so the next call can try again.
+struct Admission<'a> {
+    trial: Option<&'a Mutex<BreakerState>>,
+}
+
+impl Admission<'_> {
+    /// Marks the outcome of the call as recorded in the breaker.
+    fn settle(mut self) {
+        self.trial = None;
+    }
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        let Some(breaker) = self.trial else {
+            return;
+        };
+        let mut breaker = breaker.lock().unwrap_or_else(PoisonError::into_inner);
+        if let BreakerState::HalfOpen = *breaker {
+            *breaker = BreakerState::Open {
+                until: Instant::now(),
+            };
+        }
+    }
+}
+
+/// Errors of the store, the transient ones may go away if the call is made again.
+trait StoreError: From<anyhow::Error> {
+    fn is_transient(&self) -> bool;
+}
+
+macro_rules! store_errors {
+    ($($error:ident),* $(,)?) => {
+        $(
+            impl StoreError for $error {
+                fn is_transient(&self) -> bool {
+                    matches!(self, Self::IoError(_))
+                }
+            }
+        )*
+    };
+}
+
+store_errors!(
+    AppendJoinAttemptError,
+    ClaimRoomError,
+    CreateInvitationError,
+    CreateSessionError,
+    DeleteSessionError,
+    ExistsSessionError,
+    GetDeviceStatusesError,
+    GetInvitationError,
+    GetJoinAttemptsError,
+    GetJoinPolicyError,
+    GetJoinRequestTimeoutError,
+    GetPresenceError,
+    GetRecordingError,
+    GetRoomSessionError,
+    GetSessionOwnerError,
+    GetSessionStateError,
+    GetSessionWindowError,
+    GetTrustedControllersError,
+    GetTrustingHubsError,
+    ListRecordingsError,
+    ReleaseRoomError,
+    SaveRecordingError,
+    SetDeviceStatusError,
+    SetJoinPolicyError,
+    SetJoinRequestTimeoutError,
+    SetPresenceError,
+    SetRoomSessionError,
+    SetSessionOwnerError,
+    SetSessionWindowError,
+    TouchSessionError,
+    TrustControllerError,
+    UntrustControllerError,
+    UpdateSessionStateError,
+);
+
+/// Whether a call can be made again after failing, which it can't if a failure may have
+/// left its effect behind.
+#[derive(Clone, Copy, PartialEq)]
+enum Retry {
+    Yes,
+    No,
+}
+
+#[derive(Clone)]
+pub struct ResilientSessionStore<T> {
+    inner: T,
+    config: Config,
+    breaker: Arc<Mutex<BreakerState>>,
+    metrics: StoreMetrics,
+}
+
+impl<T> ResilientSessionStore<T> {
+    pub fn new(inner: T, config: Config, metrics: StoreMetrics) -> Self {
+        Self {
+            inner,
+            config,
+            breaker: Arc::new(Mutex::new(BreakerState::Closed { failures: 0 })),
+            metrics,
+        }
+    }
+
+    async fn call<R, E, F, Fut>(&self, retry: Retry, operation: F) -> Result<R, E>
+    where
+        E: StoreError,
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+    {
+        let Some(admission) = self.admit() else {
+            StoreMetrics::count(&self.metrics.0.rejected);
+            return Err(
+                anyhow::anyhow!("Session store unavailable, circuit breaker is open").into(),
+            );
+        };
+
+        let mut attempt = 0;
+        loop {
+            let result = operation().await;
+            match &result {
+                Err(error) if error.is_transient() => {
+                    if retry == Retry::Yes && attempt < self.config.max_retries {
+                        attempt += 1;
+                        StoreMetrics::count(&self.metrics.0.retries);
+                        tokio::time::sleep(self.backoff(attempt)).await;
+                        continue;
+                    }
+                    if retry == Retry::Yes {
+                        StoreMetrics::count(&self.metrics.0.exhausted);
+                    }
+                    self.on_failure();
+                }
+                _ => self.on_success(),
+            }
+            admission.settle();
+            return result;
+        }
+    }
+
+    /// Random wait before a retry, up to the backoff of the attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let max = self
+            .config
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(attempt - 1));
+        Duration::from_millis(rand::thread_rng().gen_range(0..=max))
+    }
+
+    fn admit(&self) -> Option<Admission<'_>> {
+        let mut breaker = self.breaker.lock().unwrap();
+        match *breaker {
+            BreakerState::Closed { .. } => Some(Admission { trial: None }),
+            BreakerState::Open { until } if Instant::now() >= until => {
+                *breaker = BreakerState::HalfOpen;
+                Some(Admission {
+                    trial: Some(&self.breaker),
+                })
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => None,
+        }
+    }
+
+    fn on_success(&self) {
+        *self.breaker.lock().unwrap() = BreakerState::Closed { failures: 0 };
+    }
+
+    fn on_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap();
+        let failures = match *breaker {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::Open { .. } | BreakerState::HalfOpen => self.config.failure_threshold,
+        };
+        *breaker = if failures >= self.config.failure_threshold {
+            warn!(
+                failures,
+                "Session store keeps failing, opening the circuit breaker"
+            );
+            StoreMetrics::count(&self.metrics.0.breaker_opened);
+            BreakerState::Open {
+                until: Instant::now() + Duration::from_millis(self.config.open_ms),
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+    }
+}
+
+impl<T: SessionStore> SessionStore for ResilientSessionStore<T> {
+    /// Retried although a new id is generated on each attempt, a session created by a
+    /// failed attempt is never handed out and expires on its own.
+    async fn create_session(&self) -> Result<Uuid, CreateSessionError> {
+        self.call(Retry::Yes, || self.inner.create_session()).await
+    }
+
+    async fn delete_session(&self, id: Uuid) -> Result<(), DeleteSessionError> {
+        self.call(Retry::Yes, || self.inner.delete_session(id))
+            .await
+    }
+
+    async fn session_state(&self, id: Uuid) -> Result<Option<SessionState>, GetSessionStateError> {
+        self.call(Retry::Yes, || self.inner.session_state(id)).await
+    }
+
+    async fn exists_session(&self, id: Uuid) -> Result<bool, ExistsSessionError> {
+        self.call(Retry::Yes, || self.inner.exists_session(id))
+            .await
+    }
+
+    async fn update_session_state(
+        &self,
+        id: Uuid,
+        state: SessionState,
+    ) -> Result<(), UpdateSessionStateError> {
+        self.call(Retry::Yes, || self.inner.update_session_state(id, state))
+            .await
+    }
+
+    async fn touch(&self, id: Uuid) -> Result<(), TouchSessionError> {
+        self.call(Retry::Yes, || self.inner.touch(id)).await
+    }
+
+    async fn set_device_status(
+        &self,
+        id: Uuid,
+        device_id: String,
+        status: String,
+        max_devices: usize,
+    ) -> Result<bool, SetDeviceStatusError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .set_device_status(id, device_id.clone(), status.clone(), max_devices)
+        })
+        .await
+    }
+
+    async fn device_statuses(&self, id: Uuid) -> Result<Vec<String>, GetDeviceStatusesError> {
+        self.call(Retry::Yes, || self.inner.device_statuses(id))
+            .await
+    }
+
+    async fn save_recording(&self, recording: Recording) -> Result<(), SaveRecordingError> {
+        self.call(Retry::Yes, || self.inner.save_recording(recording.clone()))
+            .await
+    }
+
+    async fn recording(&self, id: Uuid) -> Result<Option<Recording>, GetRecordingError> {
+        self.call(Retry::Yes, || self.inner.recording(id)).await
+    }
+
+    async fn recordings(&self) -> Result<Vec<RecordingSummary>, ListRecordingsError> {
+        self.call(Retry::Yes, || self.inner.recordings()).await
+    }
+
+    async fn set_session_owner(
+        &self,
+        id: Uuid,
+        hub_id: String,
+    ) -> Result<(), SetSessionOwnerError> {
+        self.call(Retry::Yes, || {
+            self.inner.set_session_owner(id, hub_id.clone())
+        })
+        .await
+    }
+
+    async fn session_owner(&self, id: Uuid) -> Result<Option<String>, GetSessionOwnerError> {
+        self.call(Retry::Yes, || self.inner.session_owner(id)).await
+    }
+
+    async fn set_join_policy(
+        &self,
+        id: Uuid,
+        policy: JoinPolicy,
+    ) -> Result<(), SetJoinPolicyError> {
+        self.call(Retry::Yes, || {
+            self.inner.set_join_policy(id, policy.clone())
+        })
+        .await
+    }
+
+    async fn join_policy(&self, id: Uuid) -> Result<Option<JoinPolicy>, GetJoinPolicyError> {
+        self.call(Retry::Yes, || self.inner.join_policy(id)).await
+    }
+
+    /// Retried since a hub claiming a room it already owns still owns it.
+    async fn claim_room(
+        &self,
+        room: String,
+        hub_id: String,
+        max_rooms: usize,
+    ) -> Result<RoomClaim, ClaimRoomError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .claim_room(room.clone(), hub_id.clone(), max_rooms)
+        })
+        .await
+    }
+
+    /// Not retried, a release that went through would look like the hub never owned the room.
+    async fn release_room(&self, room: String, hub_id: String) -> Result<bool, ReleaseRoomError> {
+        self.call(Retry::No, || {
+            self.inner.release_room(room.clone(), hub_id.clone())
+        })
+        .await
+    }
+
+    async fn set_room_session(
+        &self,
+        room: String,
+        session_id: Uuid,
+    ) -> Result<(), SetRoomSessionError> {
+        self.call(Retry::Yes, || {
+            self.inner.set_room_session(room.clone(), session_id)
+        })
+        .await
+    }
+
+    async fn room_session(&self, room: String) -> Result<Option<Uuid>, GetRoomSessionError> {
+        self.call(Retry::Yes, || self.inner.room_session(room.clone()))
+            .await
+    }
+
+    async fn trust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> Result<(), TrustControllerError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .trust_controller(hub_id.clone(), controller_id.clone())
+        })
+        .await
+    }
+
+    async fn untrust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> Result<(), UntrustControllerError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .untrust_controller(hub_id.clone(), controller_id.clone())
+        })
+        .await
+    }
+
+    async fn trusted_controllers(
+        &self,
+        hub_id: String,
+    ) -> Result<BTreeSet<String>, GetTrustedControllersError> {
+        self.call(Retry::Yes, || {
+            self.inner.trusted_controllers(hub_id.clone())
+        })
+        .await
+    }
+
+    async fn trusting_hubs(
+        &self,
+        controller_id: String,
+    ) -> Result<BTreeSet<String>, GetTrustingHubsError> {
+        self.call(Retry::Yes, || {
+            self.inner.trusting_hubs(controller_id.clone())
+        })
+        .await
+    }
+
+    async fn set_online(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+        ttl: Duration,
+    ) -> Result<(), SetPresenceError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .set_online(role, client_id.clone(), connection_id.clone(), ttl)
+        })
+        .await
+    }
+
+    async fn set_offline(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+    ) -> Result<(), SetPresenceError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .set_offline(role, client_id.clone(), connection_id.clone())
+        })
+        .await
+    }
+
+    async fn online(
+        &self,
+        role: Role,
+        client_ids: Vec<String>,
+    ) -> Result<BTreeSet<String>, GetPresenceError> {
+        self.call(Retry::Yes, || self.inner.online(role, client_ids.clone()))
+            .await
+    }
+
+    async fn set_join_request_timeout(
+        &self,
+        id: Uuid,
+        timeout: Duration,
+    ) -> Result<(), SetJoinRequestTimeoutError> {
+        self.call(Retry::Yes, || {
+            self.inner.set_join_request_timeout(id, timeout)
+        })
+        .await
+    }
+
+    async fn join_request_timeout(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<Duration>, GetJoinRequestTimeoutError> {
+        self.call(Retry::Yes, || self.inner.join_request_timeout(id))
+            .await
+    }
+
+    async fn set_session_window(
+        &self,
+        id: Uuid,
+        window: SessionWindow,
+    ) -> Result<(), SetSessionWindowError> {
+        self.call(Retry::Yes, || self.inner.set_session_window(id, window))
+            .await
+    }
+
+    async fn session_window(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<SessionWindow>, GetSessionWindowError> {
+        self.call(Retry::Yes, || self.inner.session_window(id))
+            .await
+    }
+
+    async fn create_invitation(
+        &self,
+        token: String,
+        invitation: Invitation,
+        ttl: Duration,
+    ) -> Result<(), CreateInvitationError> {
+        self.call(Retry::Yes, || {
+            self.inner
+                .create_invitation(token.clone(), invitation.clone(), ttl)
+        })
+        .await
+    }
+
+    async fn invitation(&self, token: String) -> Result<Option<Invitation>, GetInvitationError> {
+        self.call(Retry::Yes, || self.inner.invitation(token.clone()))
+            .await
+    }
+
+    /// Not retried, an attempt that failed after appending would be logged twice.
+    async fn append_join_attempt(
+        &self,
+        key: String,
+        attempt: JoinAttempt,
+    ) -> Result<(), AppendJoinAttemptError> {
+        self.call(Retry::No, || {
+            self.inner.append_join_attempt(key.clone(), attempt.clone())
+        })
+        .await
+    }
+
+    async fn join_attempts(
+        &self,
+        key: String,
+        limit: usize,
+    ) -> Result<Vec<JoinAttempt>, GetJoinAttemptsError> {
+        self.call(Retry::Yes, || self.inner.join_attempts(key.clone(), limit))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ResilientSessionStore, StoreMetrics, StoreMetricsSnapshot};
+    use crate::sessions::{
+        audit::{JoinAttempt, JoinAttemptOutcome},
+        port::{
+            AppendJoinAttemptError, GetSessionStateError, MockSessionStore, SessionState,
+            SessionStore, TouchSessionError,
+        },
+    };
+    use futures_util::FutureExt;
+    use mockall::Sequence;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn config() -> Config {
+        Config {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            failure_threshold: 2,
+            open_ms: 50,
+        }
+    }
+
+    fn snapshot(
+        retries: u64,
+        exhausted: u64,
+        rejected: u64,
+        breaker_opened: u64,
+    ) -> StoreMetricsSnapshot {
+        StoreMetricsSnapshot {
+            retries,
+            exhausted,
+            rejected,
+            breaker_opened,
+        }
+    }
+
+    fn expect_session_state(
+        inner: &mut MockSessionStore,
+        seq: &mut Sequence,
+        times: usize,
+        state: Option<SessionState>,
+    ) {
+        inner
+            .expect_session_state()
+            .times(times)
+            .in_sequence(seq)
+            .returning(move |_| {
+                async move {
+                    match state {
+                        Some(state) => Ok(Some(state)),
+                        None => Err(GetSessionStateError::IoError(anyhow::anyhow!("down"))),
+                    }
+                }
+                .boxed()
+            });
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors() {
+        let mut inner = MockSessionStore::new();
+        let mut seq = Sequence::new();
+        expect_session_state(&mut inner, &mut seq, 2, None);
+        expect_session_state(&mut inner, &mut seq, 1, Some(SessionState::InProgress));
+        let metrics = StoreMetrics::default();
+        let store = ResilientSessionStore::new(inner, config(), metrics.clone());
+
+        let state = store.session_state(Uuid::nil()).await.unwrap();
+
+        assert_eq!(state, Some(SessionState::InProgress));
+        assert_eq!(metrics.snapshot(), snapshot(2, 0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn returns_answers_of_the_store_right_away() {
+        let mut inner = MockSessionStore::new();
+        inner
+            .expect_touch()
+            .times(1)
+            .returning(|id| async move { Err(TouchSessionError::UnknownSession(id)) }.boxed());
+        let metrics = StoreMetrics::default();
+        let store = ResilientSessionStore::new(inner, config(), metrics.clone());
+
+        let result = store.touch(Uuid::nil()).await;
+
+        assert!(matches!(result, Err(TouchSessionError::UnknownSession(_))));
+        assert_eq!(metrics.snapshot(), snapshot(0, 0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_appending_join_attempts() {
+        let mut inner = MockSessionStore::new();
+        inner
+            .expect_append_join_attempt()
+            .times(1)
+            .returning(|_, _| {
+                async { Err(AppendJoinAttemptError::IoError(anyhow::anyhow!("down"))) }.boxed()
+            });
+        let metrics = StoreMetrics::default();
+        let store = ResilientSessionStore::new(inner, config(), metrics.clone());
+        let attempt = JoinAttempt::new(Uuid::nil(), None, "hi", JoinAttemptOutcome::Accepted);
+
+        let result = store.append_join_attempt("hub:a".into(), attempt).await;
+
+        assert!(result.is_err());
+        assert_eq!(metrics.snapshot(), snapshot(0, 0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn fails_fast_while_the_breaker_is_open() {
+        let mut inner = MockSessionStore::new();
+        let mut seq = Sequence::new();
+        expect_session_state(&mut inner, &mut seq, 6, None);
+        expect_session_state(&mut inner, &mut seq, 1, Some(SessionState::InProgress));
+        let metrics = StoreMetrics::default();
+        let store = ResilientSessionStore::new(inner, config(), metrics.clone());
+
+        for _ in 0..3 {
+            assert!(store.session_state(Uuid::nil()).await.is_err());
+        }
+        assert_eq!(metrics.snapshot(), snapshot(4, 2, 1, 1));
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let state = store.session_state(Uuid::nil()).await.unwrap();
+        assert_eq!(state, Some(SessionState::InProgress));
+        assert_eq!(metrics.snapshot(), snapshot(4, 2, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn lets_another_trial_call_through_if_one_is_dropped() {
+        let mut inner = MockSessionStore::new();
+        let mut seq = Sequence::new();
+        expect_session_state(&mut inner, &mut seq, 6, None);
+        inner
+            .expect_session_state()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| futures_util::future::pending().boxed());
+        expect_session_state(&mut inner, &mut seq, 1, Some(SessionState::InProgress));
+        let store = ResilientSessionStore::new(inner, config(), StoreMetrics::default());
+
+        for _ in 0..2 {
+            assert!(store.session_state(Uuid::nil()).await.is_err());
+        }
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let trial = store.session_state(Uuid::nil());
+        assert!(tokio::time::timeout(Duration::from_millis(10), trial)
+            .await
+            .is_err());
+        let state = store.session_state(Uuid::nil()).await.unwrap();
+        assert_eq!(state, Some(SessionState::InProgress));
+    }
+}
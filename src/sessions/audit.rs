@@ -0,0 +1,80 @@
+use crate::actors::latency::unix_millis;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Attempts kept in each audit log, older ones are dropped.
+pub const MAX_ATTEMPTS: usize = 1000;
+
+/// Longest request message kept in the audit log, longer ones are truncated.
+const MAX_MESSAGE_LEN: usize = 256;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum JoinAttemptOutcome {
+    Accepted,
+    Rejected,
+    TimedOut,
+    SessionFull,
+    Cancelled,
+    OutsideSessionWindow,
+    InvalidInvitation,
+    RateLimited,
+    MessageRejected,
+}
+
+/// Request of a controller to join a session, and how it ended.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
+pub struct JoinAttempt {
+    pub session_id: Uuid,
+    /// When the attempt ended, in milliseconds since the Unix epoch.
+    pub timestamp: i64,
+    pub controller_id: Option<String>,
+    pub message: String,
+    pub outcome: JoinAttemptOutcome,
+}
+
+impl JoinAttempt {
+    pub fn new(
+        session_id: Uuid,
+        controller_id: Option<String>,
+        message: &str,
+        outcome: JoinAttemptOutcome,
+    ) -> Self {
+        let end = message
+            .char_indices()
+            .map(|(index, _)| index)
+            .nth(MAX_MESSAGE_LEN)
+            .unwrap_or(message.len());
+        JoinAttempt {
+            session_id,
+            timestamp: unix_millis(),
+            controller_id,
+            message: message[..end].to_owned(),
+            outcome,
+        }
+    }
+}
+
+/// Key of the audit log of a hub: its client id, or its session when it didn't send one.
+pub fn audit_key(hub_id: Option<String>, session_id: Option<Uuid>) -> Option<String> {
+    match (hub_id, session_id) {
+        (Some(hub_id), _) => Some(format!("hub:{hub_id}")),
+        (None, Some(session_id)) => Some(format!("session:{session_id}")),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JoinAttempt, JoinAttemptOutcome, MAX_MESSAGE_LEN};
+    use uuid::Uuid;
+
+    #[test]
+    fn long_messages_are_truncated() {
+        let message = "ñ".repeat(MAX_MESSAGE_LEN + 10);
+        let attempt = JoinAttempt::new(Uuid::nil(), None, &message, JoinAttemptOutcome::Rejected);
+        assert_eq!(attempt.message.chars().count(), MAX_MESSAGE_LEN);
+    }
+}
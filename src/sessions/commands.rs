@@ -0,0 +1,110 @@
+use crate::actors::controller::CommandStatus;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+/// What to do with an acknowledged command, given the ones already sent in its session.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum Sequenced {
+    /// The command is new, or the hub never answered it, and must be sent to the hub.
+    Relay,
+    /// The command is a replay, it is answered with this status without reaching the hub.
+    Answer(CommandStatus),
+}
+
+#[derive(Clone, Copy)]
+struct LastCommand {
+    seq: u64,
+    status: Option<CommandStatus>,
+}
+
+/// Keeps the last sequence number sent in each session, so replayed commands are dropped
+/// before reaching the hub.
+#[derive(Clone, Default)]
+pub struct CommandSequences(Arc<Mutex<HashMap<Uuid, LastCommand>>>);
+
+impl CommandSequences {
+    /// Checks the sequence number of a command and remembers it if it is new.
+    ///
+    /// Retrying the last command gives the answer of the hub if it answered, older sequence
+    /// numbers are rejected.
+    pub fn check(&self, session_id: Uuid, seq: u64) -> Sequenced {
+        let mut commands = self.0.lock().unwrap();
+        match commands.get(&session_id) {
+            Some(last) if seq < last.seq => Sequenced::Answer(CommandStatus::Rejected),
+            Some(LastCommand {
+                seq: last_seq,
+                status: Some(status),
+            }) if seq == *last_seq => Sequenced::Answer(*status),
+            _ => {
+                commands.insert(session_id, LastCommand { seq, status: None });
+                Sequenced::Relay
+            }
+        }
+    }
+
+    /// Stores the answer of the hub to a command. Commands that timed out may be retried.
+    pub fn record(&self, session_id: Uuid, seq: u64, status: CommandStatus) {
+        if !matches!(status, CommandStatus::Applied | CommandStatus::Rejected) {
+            return;
+        }
+        if let Some(last) = self.0.lock().unwrap().get_mut(&session_id) {
+            if last.seq == seq {
+                last.status = Some(status);
+            }
+        }
+    }
+
+    pub fn remove(&self, session_id: Uuid) {
+        self.0.lock().unwrap().remove(&session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandSequences, Sequenced};
+    use crate::actors::controller::CommandStatus;
+    use uuid::Uuid;
+
+    #[test]
+    fn answered_commands_are_not_relayed_again() {
+        let commands = CommandSequences::default();
+
+        assert_eq!(commands.check(Uuid::nil(), 1), Sequenced::Relay);
+        commands.record(Uuid::nil(), 1, CommandStatus::Applied);
+
+        assert_eq!(
+            commands.check(Uuid::nil(), 1),
+            Sequenced::Answer(CommandStatus::Applied)
+        );
+        assert_eq!(commands.check(Uuid::nil(), 2), Sequenced::Relay);
+        assert_eq!(
+            commands.check(Uuid::nil(), 1),
+            Sequenced::Answer(CommandStatus::Rejected)
+        );
+    }
+
+    #[test]
+    fn timed_out_commands_can_be_retried() {
+        let commands = CommandSequences::default();
+
+        assert_eq!(commands.check(Uuid::nil(), 1), Sequenced::Relay);
+        commands.record(Uuid::nil(), 1, CommandStatus::TimedOut);
+
+        assert_eq!(commands.check(Uuid::nil(), 1), Sequenced::Relay);
+    }
+
+    #[test]
+    fn sessions_have_their_own_sequence() {
+        let commands = CommandSequences::default();
+        let other = Uuid::new_v4();
+
+        assert_eq!(commands.check(Uuid::nil(), 5), Sequenced::Relay);
+        assert_eq!(commands.check(other, 1), Sequenced::Relay);
+
+        commands.remove(Uuid::nil());
+        assert_eq!(commands.check(Uuid::nil(), 1), Sequenced::Relay);
+    }
+}
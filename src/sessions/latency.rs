@@ -0,0 +1,119 @@
+use crate::sessions::role::Role;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// Round trip time and clock offset of a session participant.
+///
+/// The clock offset is how many milliseconds the clock of the participant is ahead of ours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub rtt: Duration,
+    pub clock_offset: i64,
+}
+
+/// Keeps track of the latency measured for the participants of each session.
+///
+/// Samples are smoothed like TCP does with round trip times, so a single slow
+/// ping doesn't make the estimates jump around.
+#[derive(Clone, Default)]
+pub struct SessionLatency(Arc<Mutex<HashMap<(Uuid, Role), Measurement>>>);
+
+impl SessionLatency {
+    /// Adds a sample for the participant and returns the updated estimate.
+    pub fn record(&self, id: Uuid, role: Role, sample: Measurement) -> Measurement {
+        let mut measurements = self.0.lock().unwrap();
+        let estimate = match measurements.get(&(id, role)) {
+            Some(previous) => Measurement {
+                rtt: (previous.rtt * 7 + sample.rtt) / 8,
+                clock_offset: previous.clock_offset
+                    + (sample.clock_offset - previous.clock_offset) / 8,
+            },
+            None => sample,
+        };
+        measurements.insert((id, role), estimate);
+        estimate
+    }
+
+    pub fn get(&self, id: Uuid, role: Role) -> Option<Measurement> {
+        self.0.lock().unwrap().get(&(id, role)).copied()
+    }
+
+    pub fn remove(&self, id: Uuid, role: Role) {
+        self.0.lock().unwrap().remove(&(id, role));
+    }
+
+    /// Converts a timestamp in milliseconds taken with the clock of `from` into the clock of
+    /// `to`, leaving it as is until the clocks of both have been measured.
+    pub fn convert_timestamp(&self, id: Uuid, timestamp: i64, from: Role, to: Role) -> i64 {
+        match (self.get(id, from), self.get(id, to)) {
+            (Some(from), Some(to)) => timestamp - from.clock_offset + to.clock_offset,
+            _ => timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Measurement, SessionLatency};
+    use crate::sessions::role::Role;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn measurement(rtt_ms: u64, clock_offset: i64) -> Measurement {
+        Measurement {
+            rtt: Duration::from_millis(rtt_ms),
+            clock_offset,
+        }
+    }
+
+    #[test]
+    fn first_sample_is_taken_as_is() {
+        let latency = SessionLatency::default();
+
+        let estimate = latency.record(Uuid::nil(), Role::Hub, measurement(80, 16));
+
+        assert_eq!(estimate, measurement(80, 16));
+        assert_eq!(latency.get(Uuid::nil(), Role::Hub), Some(estimate));
+        assert_eq!(latency.get(Uuid::nil(), Role::Controller), None);
+    }
+
+    #[test]
+    fn following_samples_are_smoothed() {
+        let latency = SessionLatency::default();
+        latency.record(Uuid::nil(), Role::Hub, measurement(80, 16));
+
+        let estimate = latency.record(Uuid::nil(), Role::Hub, measurement(160, 0));
+
+        assert_eq!(estimate, measurement(90, 14));
+    }
+
+    #[test]
+    fn converts_timestamps_between_participant_clocks() {
+        let latency = SessionLatency::default();
+        latency.record(Uuid::nil(), Role::Controller, measurement(40, 500));
+        latency.record(Uuid::nil(), Role::Hub, measurement(40, -200));
+
+        assert_eq!(
+            latency.convert_timestamp(Uuid::nil(), 10_000, Role::Controller, Role::Hub),
+            9_300
+        );
+    }
+
+    #[test]
+    fn timestamps_are_not_converted_until_both_clocks_are_measured() {
+        let latency = SessionLatency::default();
+        latency.record(Uuid::nil(), Role::Controller, measurement(40, 500));
+
+        assert_eq!(
+            latency.convert_timestamp(Uuid::nil(), 10_000, Role::Controller, Role::Hub),
+            10_000
+        );
+
+        latency.remove(Uuid::nil(), Role::Controller);
+        assert_eq!(latency.get(Uuid::nil(), Role::Controller), None);
+    }
+}
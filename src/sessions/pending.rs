@@ -0,0 +1,175 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{oneshot, Mutex as TurnLock, OwnedMutexGuard};
+use uuid::Uuid;
+
+/// The join request was cancelled by its controller, or the controller disconnected.
+#[derive(Debug, PartialEq)]
+pub struct Cancelled;
+
+/// Keeps track of the join requests waiting for an answer.
+///
+/// Requests to join the same session take turns, the next one isn't looked at until the
+/// previous one is finished. Each connection has at most one pending request.
+#[derive(Clone, Default)]
+pub struct PendingJoins(Arc<Mutex<Registry>>);
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    turns: HashMap<Uuid, Arc<TurnLock<()>>>,
+    requests: HashMap<String, Registration>,
+}
+
+struct Registration {
+    id: u64,
+    session_id: Uuid,
+    cancel: oneshot::Sender<()>,
+}
+
+impl PendingJoins {
+    /// Registers a join request of a connection, unless it already has one pending.
+    pub fn register(&self, connection_id: String, session_id: Uuid) -> Option<PendingJoin> {
+        let mut registry = self.0.lock().unwrap();
+        if registry.requests.contains_key(&connection_id) {
+            return None;
+        }
+
+        registry.next_id += 1;
+        let id = registry.next_id;
+        let (cancel, cancelled) = oneshot::channel();
+        registry.requests.insert(
+            connection_id.clone(),
+            Registration {
+                id,
+                session_id,
+                cancel,
+            },
+        );
+        let turn = registry.turns.entry(session_id).or_default().clone();
+
+        Some(PendingJoin {
+            id,
+            connection_id,
+            session_id,
+            turn,
+            guard: None,
+            cancelled,
+            registry: self.clone(),
+        })
+    }
+
+    /// Cancels the pending request of a connection, returns the session it wanted to join.
+    pub fn cancel(&self, connection_id: &str) -> Option<Uuid> {
+        let registration = self.0.lock().unwrap().requests.remove(connection_id)?;
+        registration.cancel.send(()).ok();
+        Some(registration.session_id)
+    }
+}
+
+/// Join request of a connection, unregistered when dropped.
+pub struct PendingJoin {
+    id: u64,
+    connection_id: String,
+    session_id: Uuid,
+    turn: Arc<TurnLock<()>>,
+    guard: Option<OwnedMutexGuard<()>>,
+    cancelled: oneshot::Receiver<()>,
+    registry: PendingJoins,
+}
+
+impl PendingJoin {
+    /// Waits until the requests made before this one to the same session are finished.
+    pub async fn wait_turn(&mut self) -> Result<(), Cancelled> {
+        let guard = self.until_cancelled(self.turn.clone().lock_owned()).await?;
+        self.guard = Some(guard);
+        Ok(())
+    }
+
+    /// Runs `future` unless the request is cancelled first.
+    pub async fn until_cancelled<F>(&mut self, future: F) -> Result<F::Output, Cancelled>
+    where
+        F: Future,
+    {
+        tokio::select! {
+            output = future => Ok(output),
+            _ = &mut self.cancelled => Err(Cancelled),
+        }
+    }
+
+    /// Unregisters the request and runs `on_success`, unless it was cancelled before.
+    ///
+    /// Both happen while holding the registry, so a request is either cancelled or finished.
+    pub fn finish(&self, on_success: impl FnOnce()) -> Result<(), Cancelled> {
+        let mut registry = self.registry.0.lock().unwrap();
+        match registry.requests.get(&self.connection_id) {
+            Some(registration) if registration.id == self.id => {
+                registry.requests.remove(&self.connection_id);
+                on_success();
+                Ok(())
+            }
+            _ => Err(Cancelled),
+        }
+    }
+}
+
+impl Drop for PendingJoin {
+    fn drop(&mut self) {
+        self.guard.take();
+        let mut registry = self.registry.0.lock().unwrap();
+        if registry
+            .requests
+            .get(&self.connection_id)
+            .is_some_and(|registration| registration.id == self.id)
+        {
+            registry.requests.remove(&self.connection_id);
+        }
+        // Nobody else is waiting for a turn in this session
+        if Arc::strong_count(&self.turn) == 2 {
+            registry.turns.remove(&self.session_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cancelled, PendingJoins};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn requests_to_the_same_session_take_turns() {
+        let pending = PendingJoins::default();
+        let mut first = pending.register("first".into(), Uuid::nil()).unwrap();
+        let mut second = pending.register("second".into(), Uuid::nil()).unwrap();
+
+        assert_eq!(first.wait_turn().await, Ok(()));
+        let waiting = tokio::time::timeout(Duration::from_millis(20), second.wait_turn()).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        assert_eq!(second.wait_turn().await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_stop_waiting_and_can_not_finish() {
+        let pending = PendingJoins::default();
+        let mut first = pending.register("first".into(), Uuid::nil()).unwrap();
+        first.wait_turn().await.unwrap();
+
+        let mut second = pending.register("second".into(), Uuid::nil()).unwrap();
+        assert!(pending.register("second".into(), Uuid::nil()).is_none());
+        assert_eq!(pending.cancel("second"), Some(Uuid::nil()));
+        assert_eq!(second.wait_turn().await, Err(Cancelled));
+        assert_eq!(
+            second.finish(|| panic!("cancelled request finished")),
+            Err(Cancelled)
+        );
+
+        assert!(pending.register("second".into(), Uuid::nil()).is_some());
+        assert_eq!(pending.cancel("unknown"), None);
+    }
+}
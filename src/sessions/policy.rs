@@ -0,0 +1,73 @@
+use crate::actors::MAX_CLIENT_ID_LEN;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Most controllers a hub can let join its session without asking.
+const MAX_ALLOWED_CONTROLLERS: usize = 100;
+
+/// How a session decides whether a controller can join it, chosen by the hub that starts it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JoinPolicy {
+    /// The hub is asked about every join request.
+    #[default]
+    Manual,
+    /// Controllers that proved one of these client ids join without asking the hub, the hub is
+    /// asked about the rest.
+    Allowlist { controllers: BTreeSet<String> },
+    /// Any controller joins without asking the hub.
+    Open,
+}
+
+impl JoinPolicy {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Allowlist { controllers } => {
+                controllers.len() <= MAX_ALLOWED_CONTROLLERS
+                    && controllers
+                        .iter()
+                        .all(|id| !id.is_empty() && id.len() <= MAX_CLIENT_ID_LEN)
+            }
+            Self::Manual | Self::Open => true,
+        }
+    }
+
+    /// Whether a controller joins without asking the hub, given the client id it proved.
+    pub fn auto_accepts(&self, controller_id: Option<&str>) -> bool {
+        match self {
+            Self::Manual => false,
+            Self::Allowlist { controllers } => {
+                controller_id.is_some_and(|id| controllers.contains(id))
+            }
+            Self::Open => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JoinPolicy;
+
+    #[test]
+    fn allowlists_only_accept_listed_controllers() {
+        let policy = JoinPolicy::Allowlist {
+            controllers: ["alice".to_string()].into(),
+        };
+
+        assert!(policy.auto_accepts(Some("alice")));
+        assert!(!policy.auto_accepts(Some("bob")));
+        assert!(!policy.auto_accepts(None));
+        assert!(!JoinPolicy::Manual.auto_accepts(Some("alice")));
+        assert!(JoinPolicy::Open.auto_accepts(None));
+    }
+
+    #[test]
+    fn allowlists_can_not_have_empty_ids() {
+        let policy = JoinPolicy::Allowlist {
+            controllers: [String::new()].into(),
+        };
+
+        assert!(!policy.is_valid());
+    }
+}
@@ -1,5 +1,12 @@
+use super::{
+    audit::JoinAttempt,
+    policy::JoinPolicy,
+    recording::{Recording, RecordingSummary},
+    schedule::{Invitation, SessionWindow},
+};
+use crate::sessions::role::Role;
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::{collections::BTreeSet, fmt::Display, time::Duration};
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
@@ -17,6 +24,17 @@ impl Display for SessionState {
     }
 }
 
+/// Outcome of a hub claiming a room.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoomClaim {
+    /// The hub owns the room, it may have owned it already.
+    Claimed,
+    /// Another hub owns the room.
+    Taken,
+    /// The hub owns as many rooms as it can.
+    TooManyRooms,
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Failed to parse SessionState from a string: '{0}'")]
 pub struct ParseSessionStateError(String);
@@ -66,6 +84,176 @@ pub enum ExistsSessionError {
     IoError(#[from] anyhow::Error),
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum TouchSessionError {
+    #[error("Session with id '{0}' does not exist")]
+    UnknownSession(Uuid),
+    #[error("Failed to refresh the session TTL: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetDeviceStatusError {
+    #[error("Failed to store the device status: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetDeviceStatusesError {
+    #[error("Failed to get the device statuses: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SaveRecordingError {
+    #[error("Failed to save the recording: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetRecordingError {
+    #[error("Failed to get the recording: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListRecordingsError {
+    #[error("Failed to list the recordings: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetSessionOwnerError {
+    #[error("Failed to store the session owner: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetSessionOwnerError {
+    #[error("Failed to get the session owner: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AppendJoinAttemptError {
+    #[error("Failed to append the join attempt: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetJoinAttemptsError {
+    #[error("Failed to get the join attempts: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetJoinPolicyError {
+    #[error("Failed to store the join policy: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetJoinPolicyError {
+    #[error("Failed to get the join policy: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ClaimRoomError {
+    #[error("Failed to claim the room: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReleaseRoomError {
+    #[error("Failed to release the room: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetRoomSessionError {
+    #[error("Failed to point the room to its session: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetRoomSessionError {
+    #[error("Failed to get the session of the room: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrustControllerError {
+    #[error("Failed to trust the controller: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UntrustControllerError {
+    #[error("Failed to stop trusting the controller: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetTrustedControllersError {
+    #[error("Failed to get the trusted controllers: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetTrustingHubsError {
+    #[error("Failed to get the hubs trusting the controller: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetPresenceError {
+    #[error("Failed to update the presence of the client: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetPresenceError {
+    #[error("Failed to get the presence of the clients: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetJoinRequestTimeoutError {
+    #[error("Failed to store the join request timeout: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetJoinRequestTimeoutError {
+    #[error("Failed to get the join request timeout: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SetSessionWindowError {
+    #[error("Failed to store the session window: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetSessionWindowError {
+    #[error("Failed to get the session window: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CreateInvitationError {
+    #[error("Failed to store the invitation: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetInvitationError {
+    #[error("Failed to get the invitation: '{0}'")]
+    IoError(#[from] anyhow::Error),
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait SessionStore: Send + Sync {
     fn create_session(
@@ -93,4 +281,211 @@ pub trait SessionStore: Send + Sync {
         id: Uuid,
         state: SessionState,
     ) -> impl std::future::Future<Output = Result<(), UpdateSessionStateError>> + std::marker::Send;
+
+    /// Refreshes the TTL of a session, scheduled sessions last at least until their window ends.
+    fn touch(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<(), TouchSessionError>> + std::marker::Send;
+
+    /// Stores the latest status reported for a device, replacing the previous one.
+    ///
+    /// Returns `false` without storing anything if the device is new and the session already
+    /// has `max_devices` devices. Statuses are deleted along with the session.
+    fn set_device_status(
+        &self,
+        id: Uuid,
+        device_id: String,
+        status: String,
+        max_devices: usize,
+    ) -> impl std::future::Future<Output = Result<bool, SetDeviceStatusError>> + std::marker::Send;
+
+    fn device_statuses(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Vec<String>, GetDeviceStatusesError>> + std::marker::Send;
+
+    /// Stores a recording, it is kept after the recorded session finishes.
+    fn save_recording(
+        &self,
+        recording: Recording,
+    ) -> impl std::future::Future<Output = Result<(), SaveRecordingError>> + std::marker::Send;
+
+    fn recording(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Option<Recording>, GetRecordingError>>
+           + std::marker::Send;
+
+    fn recordings(
+        &self,
+    ) -> impl std::future::Future<Output = Result<Vec<RecordingSummary>, ListRecordingsError>>
+           + std::marker::Send;
+
+    /// Stores the client id of the hub that started a session, it is deleted along with the
+    /// session.
+    fn set_session_owner(
+        &self,
+        id: Uuid,
+        hub_id: String,
+    ) -> impl std::future::Future<Output = Result<(), SetSessionOwnerError>> + std::marker::Send;
+
+    fn session_owner(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Option<String>, GetSessionOwnerError>>
+           + std::marker::Send;
+
+    /// Stores how controllers can join a session, it is deleted along with the session.
+    fn set_join_policy(
+        &self,
+        id: Uuid,
+        policy: JoinPolicy,
+    ) -> impl std::future::Future<Output = Result<(), SetJoinPolicyError>> + std::marker::Send;
+
+    /// Returns the join policy of a session, `None` if it has the default one.
+    fn join_policy(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Option<JoinPolicy>, GetJoinPolicyError>>
+           + std::marker::Send;
+
+    /// Gives a room to a hub unless another one owns it or the hub already owns `max_rooms`.
+    ///
+    /// Rooms are kept across sessions until their owner releases them.
+    fn claim_room(
+        &self,
+        room: String,
+        hub_id: String,
+        max_rooms: usize,
+    ) -> impl std::future::Future<Output = Result<RoomClaim, ClaimRoomError>> + std::marker::Send;
+
+    /// Lets any hub claim a room, returns whether `hub_id` owned it.
+    fn release_room(
+        &self,
+        room: String,
+        hub_id: String,
+    ) -> impl std::future::Future<Output = Result<bool, ReleaseRoomError>> + std::marker::Send;
+
+    /// Points a room to the current session of its owner.
+    fn set_room_session(
+        &self,
+        room: String,
+        session_id: Uuid,
+    ) -> impl std::future::Future<Output = Result<(), SetRoomSessionError>> + std::marker::Send;
+
+    /// Returns the session of a room, `None` if its owner has no session going on.
+    fn room_session(
+        &self,
+        room: String,
+    ) -> impl std::future::Future<Output = Result<Option<Uuid>, GetRoomSessionError>> + std::marker::Send;
+
+    /// Lets a controller join the sessions of a hub without asking it.
+    fn trust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> impl std::future::Future<Output = Result<(), TrustControllerError>> + std::marker::Send;
+
+    fn untrust_controller(
+        &self,
+        hub_id: String,
+        controller_id: String,
+    ) -> impl std::future::Future<Output = Result<(), UntrustControllerError>> + std::marker::Send;
+
+    fn trusted_controllers(
+        &self,
+        hub_id: String,
+    ) -> impl std::future::Future<Output = Result<BTreeSet<String>, GetTrustedControllersError>>
+           + std::marker::Send;
+
+    /// Returns the hubs that trust a controller, the other side of `trusted_controllers`.
+    fn trusting_hubs(
+        &self,
+        controller_id: String,
+    ) -> impl std::future::Future<Output = Result<BTreeSet<String>, GetTrustingHubsError>>
+           + std::marker::Send;
+
+    /// Marks a connection of a client as online until `ttl` passes without marking it again.
+    fn set_online(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+        ttl: Duration,
+    ) -> impl std::future::Future<Output = Result<(), SetPresenceError>> + std::marker::Send;
+
+    fn set_offline(
+        &self,
+        role: Role,
+        client_id: String,
+        connection_id: String,
+    ) -> impl std::future::Future<Output = Result<(), SetPresenceError>> + std::marker::Send;
+
+    /// Returns which of the clients have a connection online.
+    fn online(
+        &self,
+        role: Role,
+        client_ids: Vec<String>,
+    ) -> impl std::future::Future<Output = Result<BTreeSet<String>, GetPresenceError>> + std::marker::Send;
+
+    /// Stores how long the hub of a session has to answer join requests, it is deleted along
+    /// with the session.
+    fn set_join_request_timeout(
+        &self,
+        id: Uuid,
+        timeout: Duration,
+    ) -> impl std::future::Future<Output = Result<(), SetJoinRequestTimeoutError>> + std::marker::Send;
+
+    /// Returns the join request timeout of a session, `None` if it has the default one.
+    fn join_request_timeout(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Option<Duration>, GetJoinRequestTimeoutError>>
+           + std::marker::Send;
+
+    /// Stores when controllers can join a scheduled session, it is deleted along with the
+    /// session.
+    fn set_session_window(
+        &self,
+        id: Uuid,
+        window: SessionWindow,
+    ) -> impl std::future::Future<Output = Result<(), SetSessionWindowError>> + std::marker::Send;
+
+    /// Returns the window of a scheduled session, `None` if it can be joined any time.
+    fn session_window(
+        &self,
+        id: Uuid,
+    ) -> impl std::future::Future<Output = Result<Option<SessionWindow>, GetSessionWindowError>>
+           + std::marker::Send;
+
+    /// Stores an invitation under its token, it is deleted once `ttl` passes.
+    fn create_invitation(
+        &self,
+        token: String,
+        invitation: Invitation,
+        ttl: Duration,
+    ) -> impl std::future::Future<Output = Result<(), CreateInvitationError>> + std::marker::Send;
+
+    /// Returns the invitation of a token, `None` if it doesn't exist or expired.
+    fn invitation(
+        &self,
+        token: String,
+    ) -> impl std::future::Future<Output = Result<Option<Invitation>, GetInvitationError>>
+           + std::marker::Send;
+
+    /// Appends a join attempt to an audit log, only the latest attempts of each log are kept.
+    fn append_join_attempt(
+        &self,
+        key: String,
+        attempt: JoinAttempt,
+    ) -> impl std::future::Future<Output = Result<(), AppendJoinAttemptError>> + std::marker::Send;
+
+    /// Returns the latest attempts of an audit log, newest first.
+    fn join_attempts(
+        &self,
+        key: String,
+        limit: usize,
+    ) -> impl std::future::Future<Output = Result<Vec<JoinAttempt>, GetJoinAttemptsError>>
+           + std::marker::Send;
 }
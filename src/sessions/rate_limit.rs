@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Entries kept before forgetting the ones that can't limit anything anymore.
+const MAX_ENTRIES: usize = 1024;
+
+/// Lets through at most one event per key every `min_interval`.
+#[derive(Clone)]
+pub struct RateLimiter<K>(Arc<Mutex<HashMap<K, Instant>>>);
+
+impl<K> Default for RateLimiter<K> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    /// Returns whether an event for `key` is allowed now, recording it if it is.
+    pub fn check(&self, key: K, min_interval: Duration) -> bool {
+        let mut last_events = self.0.lock().unwrap();
+        if !allows(&last_events, &key, min_interval) {
+            return false;
+        }
+        record(&mut last_events, key, min_interval);
+        true
+    }
+
+    /// Returns whether an event for `key` is allowed now, without recording it.
+    pub fn allows(&self, key: &K, min_interval: Duration) -> bool {
+        allows(&self.0.lock().unwrap(), key, min_interval)
+    }
+
+    /// Records an event for `key` that [`RateLimiter::allows`] let through.
+    pub fn record(&self, key: K, min_interval: Duration) {
+        record(&mut self.0.lock().unwrap(), key, min_interval)
+    }
+}
+
+fn allows<K: Hash + Eq>(
+    last_events: &HashMap<K, Instant>,
+    key: &K,
+    min_interval: Duration,
+) -> bool {
+    last_events
+        .get(key)
+        .is_none_or(|last_event| last_event.elapsed() >= min_interval)
+}
+
+fn record<K: Hash + Eq>(last_events: &mut HashMap<K, Instant>, key: K, min_interval: Duration) {
+    if last_events.len() >= MAX_ENTRIES {
+        last_events.retain(|_, last_event| last_event.elapsed() < min_interval);
+    }
+    last_events.insert(key, Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+
+    #[test]
+    fn allows_one_event_per_interval() {
+        let limiter = RateLimiter::default();
+
+        assert!(limiter.check("a", Duration::from_secs(60)));
+        assert!(!limiter.check("a", Duration::from_secs(60)));
+        assert!(limiter.check("b", Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn only_limits_recorded_events() {
+        let limiter = RateLimiter::default();
+
+        assert!(limiter.allows(&"a", Duration::from_secs(60)));
+        assert!(limiter.allows(&"a", Duration::from_secs(60)));
+        limiter.record("a", Duration::from_secs(60));
+        assert!(!limiter.allows(&"a", Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn allows_events_once_the_interval_passed() {
+        let limiter = RateLimiter::default();
+
+        assert!(limiter.check("a", Duration::from_millis(10)));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.check("a", Duration::from_millis(10)));
+    }
+}
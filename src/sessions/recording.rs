@@ -0,0 +1,194 @@
+use crate::{actors::latency::unix_millis, sessions::role::Role};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Commands kept per recording, later ones are dropped.
+const MAX_COMMANDS: usize = 100_000;
+
+/// Command relayed while recording, as `[offset_ms, value]` from the start of the recording.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RecordedCommand(pub u64, pub f32);
+
+/// Commands relayed in a session while both participants agreed to record it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Recording {
+    /// Id of the recorded session.
+    pub id: Uuid,
+    /// When the recording started, in milliseconds since the Unix epoch.
+    pub started_at: i64,
+    pub commands: Vec<RecordedCommand>,
+    /// Client id of the hub of the recorded session, the only one allowed to replay it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RecordingSummary {
+    pub id: Uuid,
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub commands: usize,
+}
+
+impl Recording {
+    pub fn summary(&self) -> RecordingSummary {
+        RecordingSummary {
+            id: self.id,
+            started_at: self.started_at,
+            duration_ms: self.commands.last().map_or(0, |command| command.0),
+            commands: self.commands.len(),
+        }
+    }
+}
+
+/// Whether each participant of a session agreed to record it.
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Consents {
+    pub hub: bool,
+    pub controller: bool,
+}
+
+impl Consents {
+    pub fn recording(&self) -> bool {
+        self.hub && self.controller
+    }
+}
+
+#[derive(Default)]
+struct SessionRecording {
+    consents: Consents,
+    started_at: Option<i64>,
+    /// Time recorded before the current run, consent can be withdrawn and given again.
+    recorded: Duration,
+    running_since: Option<Instant>,
+    commands: Vec<RecordedCommand>,
+}
+
+impl SessionRecording {
+    fn elapsed(&self) -> Duration {
+        self.recorded
+            + self
+                .running_since
+                .map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+/// Recordings of the sessions in progress, kept in memory until the session finishes.
+#[derive(Clone, Default)]
+pub struct SessionRecordings(Arc<Mutex<HashMap<Uuid, SessionRecording>>>);
+
+impl SessionRecordings {
+    /// Records the consent of a participant, starting or pausing the recording accordingly.
+    pub fn set_consent(&self, id: Uuid, role: Role, consent: bool) -> Consents {
+        let mut recordings = self.0.lock().unwrap();
+        let recording = recordings.entry(id).or_default();
+        match role {
+            Role::Hub => recording.consents.hub = consent,
+            Role::Controller => recording.consents.controller = consent,
+        }
+
+        match (recording.consents.recording(), recording.running_since) {
+            (true, None) => {
+                recording.started_at.get_or_insert_with(unix_millis);
+                recording.running_since = Some(Instant::now());
+            }
+            (false, Some(since)) => {
+                recording.recorded += since.elapsed();
+                recording.running_since = None;
+            }
+            _ => (),
+        }
+        recording.consents
+    }
+
+    /// Adds a relayed command to the recording of the session, if it is being recorded.
+    pub fn record(&self, id: Uuid, value: f32) {
+        let mut recordings = self.0.lock().unwrap();
+        let Some(recording) = recordings.get_mut(&id) else {
+            return;
+        };
+        if recording.running_since.is_none() || recording.commands.len() >= MAX_COMMANDS {
+            return;
+        }
+        let offset = recording.elapsed().as_millis() as u64;
+        recording.commands.push(RecordedCommand(offset, value));
+    }
+
+    /// Forgets the session, returning what was recorded of it.
+    pub fn finish(&self, id: Uuid) -> Option<Recording> {
+        let recording = self.0.lock().unwrap().remove(&id)?;
+        if recording.commands.is_empty() {
+            return None;
+        }
+        Some(Recording {
+            id,
+            started_at: recording.started_at?,
+            commands: recording.commands,
+            owner: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecordedCommand, SessionRecordings};
+    use crate::sessions::role::Role;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn records_only_with_the_consent_of_both_participants() {
+        let recordings = SessionRecordings::default();
+
+        assert!(!recordings
+            .set_consent(Uuid::nil(), Role::Hub, true)
+            .recording());
+        recordings.record(Uuid::nil(), 0.1);
+        assert!(recordings
+            .set_consent(Uuid::nil(), Role::Controller, true)
+            .recording());
+        recordings.record(Uuid::nil(), 0.2);
+        recordings.set_consent(Uuid::nil(), Role::Hub, false);
+        recordings.record(Uuid::nil(), 0.3);
+
+        let recording = recordings.finish(Uuid::nil()).unwrap();
+        assert_eq!(recording.id, Uuid::nil());
+        assert_eq!(
+            recording
+                .commands
+                .iter()
+                .map(|command| command.1)
+                .collect::<Vec<_>>(),
+            vec![0.2]
+        );
+        assert!(recordings.finish(Uuid::nil()).is_none());
+    }
+
+    #[test]
+    fn paused_time_is_not_recorded() {
+        let recordings = SessionRecordings::default();
+        recordings.set_consent(Uuid::nil(), Role::Hub, true);
+        recordings.set_consent(Uuid::nil(), Role::Controller, true);
+        recordings.set_consent(Uuid::nil(), Role::Controller, false);
+        std::thread::sleep(Duration::from_millis(50));
+        recordings.set_consent(Uuid::nil(), Role::Controller, true);
+        recordings.record(Uuid::nil(), 0.5);
+
+        let RecordedCommand(offset, _) = recordings.finish(Uuid::nil()).unwrap().commands[0];
+        assert!(offset < 50);
+    }
+
+    #[test]
+    fn sessions_without_commands_have_no_recording() {
+        let recordings = SessionRecordings::default();
+        recordings.set_consent(Uuid::nil(), Role::Hub, true);
+        recordings.set_consent(Uuid::nil(), Role::Controller, true);
+
+        assert!(recordings.finish(Uuid::nil()).is_none());
+    }
+}
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// Side of a session a client connects as.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Hub,
+    Controller,
+}
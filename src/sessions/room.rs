@@ -0,0 +1,30 @@
+/// Longest name of a room, so it fits in a bookmarked link.
+pub const MAX_ROOM_LEN: usize = 32;
+const MIN_ROOM_LEN: usize = 3;
+
+/// Whether `room` can name a room: lowercase letters, digits and dashes between them.
+pub fn is_valid_room(room: &str) -> bool {
+    (MIN_ROOM_LEN..=MAX_ROOM_LEN).contains(&room.len())
+        && room
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !room.starts_with('-')
+        && !room.ends_with('-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_room, MAX_ROOM_LEN};
+
+    #[test]
+    fn rooms_are_lowercase_slugs() {
+        assert!(is_valid_room("alice-and-bob"));
+        assert!(is_valid_room("room-42"));
+        assert!(!is_valid_room("ab"));
+        assert!(!is_valid_room(&"a".repeat(MAX_ROOM_LEN + 1)));
+        assert!(!is_valid_room("Alice"));
+        assert!(!is_valid_room("alice bob"));
+        assert!(!is_valid_room("-alice"));
+        assert!(!is_valid_room("ñandú"));
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How far ahead a scheduled session can end, in milliseconds.
+const MAX_SCHEDULE_AHEAD_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+/// Unix milliseconds between which controllers can join a scheduled session.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SessionWindow {
+    pub starts_at: i64,
+    pub ends_at: i64,
+}
+
+impl SessionWindow {
+    /// Whether the window can be scheduled at `now`: it can't be empty, over or too far ahead.
+    pub fn is_valid(&self, now: i64) -> bool {
+        self.starts_at < self.ends_at
+            && now < self.ends_at
+            && self.ends_at - now <= MAX_SCHEDULE_AHEAD_MS
+    }
+
+    pub fn contains(&self, now: i64) -> bool {
+        (self.starts_at..self.ends_at).contains(&now)
+    }
+}
+
+/// Lets a controller join a scheduled session without asking its hub, until the window ends.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Invitation {
+    pub session_id: Uuid,
+    pub controller_id: String,
+}
+
+impl Invitation {
+    pub fn admits(&self, session_id: Uuid, controller_id: Option<&str>) -> bool {
+        self.session_id == session_id && controller_id == Some(self.controller_id.as_str())
+    }
+}
+
+/// Random token a hub hands to the controller it invites.
+pub fn invitation_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Invitation, SessionWindow};
+    use uuid::Uuid;
+
+    #[test]
+    fn windows_can_only_be_joined_while_open() {
+        let window = SessionWindow {
+            starts_at: 1_000,
+            ends_at: 2_000,
+        };
+
+        assert!(window.is_valid(0));
+        assert!(window.is_valid(1_500));
+        assert!(!window.is_valid(2_000));
+        assert!(!window.contains(999));
+        assert!(window.contains(1_000));
+        assert!(!window.contains(2_000));
+
+        let far_ahead = SessionWindow {
+            starts_at: 0,
+            ends_at: 30 * 24 * 60 * 60 * 1000,
+        };
+        assert!(!far_ahead.is_valid(0));
+    }
+
+    #[test]
+    fn invitations_only_admit_their_controller_to_their_session() {
+        let invitation = Invitation {
+            session_id: Uuid::nil(),
+            controller_id: "alice".into(),
+        };
+
+        assert!(invitation.admits(Uuid::nil(), Some("alice")));
+        assert!(!invitation.admits(Uuid::nil(), Some("bob")));
+        assert!(!invitation.admits(Uuid::nil(), None));
+        assert!(!invitation.admits(Uuid::new_v4(), Some("alice")));
+    }
+}
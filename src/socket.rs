@@ -1,2 +1,4 @@
 pub mod adapters;
+pub mod encoding;
+pub mod ip;
 pub mod port;
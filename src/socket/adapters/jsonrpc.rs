@@ -0,0 +1,780 @@
+use super::local;
+use crate::{
+    actors::protocol::{ProtocolVersion, DEFAULT_VERSION},
+    socket::{
+        encoding::{self, Encoding},
+        port::{ClientSocket, MessageWithAck},
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use socketioxide::{adapter::LocalAdapter, SocketIo};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+pub const VERSION: &str = "2.0";
+
+pub type PeerId = u64;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Incoming {
+    Request(Request),
+    Response(Response),
+}
+
+#[derive(Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Deserialize)]
+pub struct Response {
+    pub id: Value,
+    #[serde(default)]
+    pub result: Value,
+    #[serde(default)]
+    pub error: Option<ErrorObject>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
+impl ErrorObject {
+    pub fn parse_error() -> Self {
+        Self::new(-32700, "Parse error")
+    }
+
+    pub fn invalid_request() -> Self {
+        Self::new(-32600, "Invalid request")
+    }
+
+    pub fn method_not_found() -> Self {
+        Self::new(-32601, "Method not found")
+    }
+
+    pub fn invalid_params(error: serde_json::Error) -> Self {
+        Self::new(-32602, format!("Invalid params: {error}"))
+    }
+
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Outcome {
+    Result { result: Value },
+    Error { error: ErrorObject },
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Message<'a> {
+    Request {
+        jsonrpc: &'static str,
+        id: u64,
+        method: &'a str,
+        params: &'a Value,
+    },
+    Notification {
+        jsonrpc: &'static str,
+        method: &'a str,
+        params: &'a Value,
+    },
+    Response {
+        jsonrpc: &'static str,
+        id: Value,
+        #[serde(flatten)]
+        outcome: Outcome,
+    },
+}
+
+impl Message<'_> {
+    fn encode(&self, encoding: Encoding) -> Outgoing {
+        match encoding {
+            Encoding::Json => Outgoing::Text(
+                serde_json::to_string(self).expect("JSON-RPC messages are always serializable"),
+            ),
+            Encoding::MessagePack => Outgoing::Binary(
+                encoding::to_msgpack(self).expect("JSON-RPC messages are always serializable"),
+            ),
+        }
+    }
+}
+
+/// What the connection task of a peer should do next.
+pub enum Outgoing {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendError {
+    #[error("Peer '{0}' is not connected")]
+    Disconnected(PeerId),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RequestError {
+    #[error("No peer in room '{0}'")]
+    EmptyRoom(String),
+    #[error("Peer '{0}' is not connected")]
+    Disconnected(PeerId),
+    #[error("Timed out waiting for a response")]
+    Timeout,
+    #[error("Peer answered with an error: '{}'", .0.message)]
+    ErrorResponse(ErrorObject),
+}
+
+type PendingResponse = oneshot::Receiver<Result<Value, ErrorObject>>;
+
+/// A request waiting for a response from one of the peers it was sent to.
+struct Pending {
+    sender: oneshot::Sender<Result<Value, ErrorObject>>,
+    targets: HashSet<PeerId>,
+}
+
+struct Peer {
+    sender: mpsc::UnboundedSender<Outgoing>,
+    encoding: Encoding,
+    version: ProtocolVersion,
+    rooms: HashSet<String>,
+    client_id: Option<String>,
+    ip: Option<IpAddr>,
+    value: Option<Uuid>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_peer_id: PeerId,
+    next_request_id: u64,
+    peers: HashMap<PeerId, Peer>,
+    rooms: HashMap<String, HashSet<PeerId>>,
+    pending: HashMap<u64, Pending>,
+}
+
+impl Inner {
+    fn send(&self, peer: PeerId, message: &Message) -> Result<(), SendError> {
+        self.peers
+            .get(&peer)
+            .and_then(|peer| peer.sender.send(message.encode(peer.encoding)).ok())
+            .ok_or(SendError::Disconnected(peer))
+    }
+
+    fn members(&self, room: &str) -> impl Iterator<Item = PeerId> + '_ {
+        self.rooms.get(room).into_iter().flatten().copied()
+    }
+}
+
+/// Registry of the clients connected through the JSON-RPC transport and the rooms they are in.
+#[derive(Clone, Default)]
+pub struct Peers(Arc<Mutex<Inner>>);
+
+impl Peers {
+    pub fn connect(
+        &self,
+        encoding: Encoding,
+        version: ProtocolVersion,
+    ) -> (PeerId, mpsc::UnboundedReceiver<Outgoing>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut inner = self.0.lock().unwrap();
+        inner.next_peer_id += 1;
+        let id = inner.next_peer_id;
+        inner.peers.insert(
+            id,
+            Peer {
+                sender,
+                encoding,
+                version,
+                rooms: HashSet::new(),
+                client_id: None,
+                ip: None,
+                value: None,
+            },
+        );
+        (id, receiver)
+    }
+
+    pub fn disconnect(&self, peer: PeerId) {
+        let mut inner = self.0.lock().unwrap();
+        let Some(removed) = inner.peers.remove(&peer) else {
+            return;
+        };
+        for room in removed.rooms {
+            if let Some(members) = inner.rooms.get_mut(&room) {
+                members.remove(&peer);
+                if members.is_empty() {
+                    inner.rooms.remove(&room);
+                }
+            }
+        }
+    }
+
+    pub fn is_connected(&self, peer: PeerId) -> bool {
+        self.0.lock().unwrap().peers.contains_key(&peer)
+    }
+
+    /// Asks the connection task of the peer to close the websocket.
+    pub fn close(&self, peer: PeerId) {
+        if let Some(peer) = self.0.lock().unwrap().peers.get(&peer) {
+            peer.sender.send(Outgoing::Close).ok();
+        }
+    }
+
+    pub fn join(&self, peer: PeerId, room: String) {
+        let mut inner = self.0.lock().unwrap();
+        let Some(state) = inner.peers.get_mut(&peer) else {
+            return;
+        };
+        state.rooms.insert(room.clone());
+        inner.rooms.entry(room).or_default().insert(peer);
+    }
+
+    pub fn leave(&self, peer: PeerId, room: &str) {
+        let mut inner = self.0.lock().unwrap();
+        if let Some(state) = inner.peers.get_mut(&peer) {
+            state.rooms.remove(room);
+        }
+        if let Some(members) = inner.rooms.get_mut(room) {
+            members.remove(&peer);
+            if members.is_empty() {
+                inner.rooms.remove(room);
+            }
+        }
+    }
+
+    pub fn notify(&self, peer: PeerId, method: &str, params: &Value) -> Result<(), SendError> {
+        let message = Message::Notification {
+            jsonrpc: VERSION,
+            method,
+            params,
+        };
+        self.0.lock().unwrap().send(peer, &message)
+    }
+
+    /// Notifies every peer in the room except `except`.
+    pub fn notify_room(&self, room: &str, except: Option<PeerId>, method: &str, params: &Value) {
+        let message = Message::Notification {
+            jsonrpc: VERSION,
+            method,
+            params,
+        };
+        let inner = self.0.lock().unwrap();
+        for peer in inner.members(room).filter(|peer| Some(*peer) != except) {
+            inner.send(peer, &message).ok();
+        }
+    }
+
+    pub fn respond(&self, peer: PeerId, id: Value, outcome: Result<Value, ErrorObject>) {
+        let outcome = match outcome {
+            Ok(result) => Outcome::Result { result },
+            Err(error) => Outcome::Error { error },
+        };
+        let message = Message::Response {
+            jsonrpc: VERSION,
+            id,
+            outcome,
+        };
+        self.0.lock().unwrap().send(peer, &message).ok();
+    }
+
+    /// Sends a request to every peer in the room and waits for the first response.
+    pub async fn request_room(
+        &self,
+        room: &str,
+        method: &str,
+        params: &Value,
+        timeout: Duration,
+    ) -> Result<Value, RequestError> {
+        let (id, receiver) = self
+            .send_request(method, params, |inner| inner.members(room).collect())
+            .ok_or_else(|| RequestError::EmptyRoom(room.into()))?;
+        self.wait_response(id, receiver, timeout).await
+    }
+
+    /// Sends a request to a single peer and waits for its response.
+    pub async fn request(
+        &self,
+        peer: PeerId,
+        method: &str,
+        params: &Value,
+        timeout: Duration,
+    ) -> Result<Value, RequestError> {
+        let (id, receiver) = self
+            .send_request(method, params, |_| vec![peer])
+            .ok_or(RequestError::Disconnected(peer))?;
+        self.wait_response(id, receiver, timeout).await
+    }
+
+    /// Sends a request to the given peers, returns `None` if none of them could be reached.
+    fn send_request(
+        &self,
+        method: &str,
+        params: &Value,
+        targets: impl FnOnce(&Inner) -> Vec<PeerId>,
+    ) -> Option<(u64, PendingResponse)> {
+        let (sender, receiver) = oneshot::channel();
+        let mut inner = self.0.lock().unwrap();
+        inner.next_request_id += 1;
+        let id = inner.next_request_id;
+        let message = Message::Request {
+            jsonrpc: VERSION,
+            id,
+            method,
+            params,
+        };
+        let targets: HashSet<PeerId> = targets(&inner)
+            .into_iter()
+            .filter(|peer| inner.send(*peer, &message).is_ok())
+            .collect();
+        if targets.is_empty() {
+            return None;
+        }
+        inner.pending.insert(id, Pending { sender, targets });
+        Some((id, receiver))
+    }
+
+    async fn wait_response(
+        &self,
+        id: u64,
+        receiver: PendingResponse,
+        timeout: Duration,
+    ) -> Result<Value, RequestError> {
+        let response = tokio::time::timeout(timeout, receiver).await;
+        self.0.lock().unwrap().pending.remove(&id);
+
+        match response {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(error))) => Err(RequestError::ErrorResponse(error)),
+            Ok(Err(_)) | Err(_) => Err(RequestError::Timeout),
+        }
+    }
+
+    /// Resolves a pending request with the response sent by `peer`.
+    ///
+    /// Responses from peers the request wasn't sent to are ignored.
+    pub fn resolve(&self, peer: PeerId, response: Response) {
+        let Some(id) = response.id.as_u64() else {
+            return;
+        };
+        let mut inner = self.0.lock().unwrap();
+        if !inner
+            .pending
+            .get(&id)
+            .is_some_and(|pending| pending.targets.contains(&peer))
+        {
+            return;
+        }
+        let Some(Pending { sender, .. }) = inner.pending.remove(&id) else {
+            return;
+        };
+        drop(inner);
+        let outcome = match response.error {
+            Some(error) => Err(error),
+            None => Ok(response.result),
+        };
+        sender.send(outcome).ok();
+    }
+
+    /// Stores the identity the peer proved when connecting and its address.
+    pub fn identify(&self, peer: PeerId, client_id: Option<String>, ip: Option<IpAddr>) {
+        if let Some(peer) = self.0.lock().unwrap().peers.get_mut(&peer) {
+            peer.client_id = client_id;
+            peer.ip = ip;
+        }
+    }
+
+    fn client_id(&self, peer: PeerId) -> Option<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .peers
+            .get(&peer)
+            .and_then(|peer| peer.client_id.clone())
+    }
+
+    fn protocol_version(&self, peer: PeerId) -> Option<ProtocolVersion> {
+        self.0
+            .lock()
+            .unwrap()
+            .peers
+            .get(&peer)
+            .map(|peer| peer.version)
+    }
+
+    fn ip(&self, peer: PeerId) -> Option<IpAddr> {
+        self.0
+            .lock()
+            .unwrap()
+            .peers
+            .get(&peer)
+            .and_then(|peer| peer.ip)
+    }
+
+    fn value(&self, peer: PeerId) -> Option<Uuid> {
+        self.0
+            .lock()
+            .unwrap()
+            .peers
+            .get(&peer)
+            .and_then(|peer| peer.value)
+    }
+
+    fn set_value(&self, peer: PeerId, value: Option<Uuid>) {
+        if let Some(peer) = self.0.lock().unwrap().peers.get_mut(&peer) {
+            peer.value = value;
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmitError {
+    #[error("Failed to serialize message: '{0}'")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Failed to send message: '{0}'")]
+    SendError(#[from] SendError),
+    #[error("Failed to emit message to socket.io clients: '{0}'")]
+    SocketIo(#[from] local::EmitError),
+}
+
+/// A client connected through the JSON-RPC transport.
+///
+/// Rooms are shared with socket.io clients, so messages sent to a room reach peers on both
+/// transports.
+pub struct ClientSocketImpl {
+    peers: Peers,
+    io: SocketIo<LocalAdapter>,
+    /// Namespace of the socket.io clients sharing rooms with the peer.
+    namespace: String,
+    id: PeerId,
+}
+
+impl ClientSocketImpl {
+    pub fn new(peers: Peers, io: SocketIo<LocalAdapter>, namespace: String, id: PeerId) -> Self {
+        Self {
+            peers,
+            io,
+            namespace,
+            id,
+        }
+    }
+}
+
+impl ClientSocket for ClientSocketImpl {
+    type Error = Infallible;
+    type EmitError = EmitError;
+    type EmitWithAckError = local::EmitWithAckError;
+    type StoreItem = Uuid;
+
+    fn disconnect(self) {
+        self.peers.close(self.id);
+    }
+
+    fn join(&self, room: String) -> Result<(), Self::Error> {
+        self.peers.join(self.id, room);
+        Ok(())
+    }
+
+    fn leave(&self, room: String) -> Result<(), Self::Error> {
+        self.peers.leave(self.id, &room);
+        Ok(())
+    }
+
+    fn emit_to_room<T>(&self, room: String, event: String, data: T) -> Result<(), Self::EmitError>
+    where
+        T: Serialize + Send,
+    {
+        let params = serde_json::to_value(data)?;
+        self.peers
+            .notify_room(&room, Some(self.id), &event, &params);
+        for socket in local::sockets_in(&self.io, &self.namespace, room) {
+            local::emit_encoded(&socket, &event, &params)?;
+        }
+        Ok(())
+    }
+
+    fn emit<T>(&self, event: String, data: T) -> Result<(), Self::EmitError>
+    where
+        T: Serialize + Send,
+    {
+        let params = serde_json::to_value(data)?;
+        self.peers.notify(self.id, &event, &params)?;
+        Ok(())
+    }
+
+    async fn emit_with_ack<T>(
+        &self,
+        event: String,
+        value: T,
+        timeout: Duration,
+    ) -> Result<T::Ack, Self::EmitWithAckError>
+    where
+        T: MessageWithAck,
+    {
+        let params = serde_json::to_value(value)?;
+        let response = self
+            .peers
+            .request(self.id, &event, &params, timeout)
+            .await?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    fn connection_id(&self) -> String {
+        format!("rpc:{}", self.id)
+    }
+
+    fn client_id(&self) -> Option<String> {
+        self.peers.client_id(self.id)
+    }
+
+    fn protocol_version(&self) -> ProtocolVersion {
+        self.peers
+            .protocol_version(self.id)
+            .unwrap_or(DEFAULT_VERSION)
+    }
+
+    fn ip(&self) -> Option<IpAddr> {
+        self.peers.ip(self.id)
+    }
+
+    fn get_stored_value(&self) -> Option<Self::StoreItem> {
+        self.peers.value(self.id)
+    }
+
+    fn remove_value(&self) {
+        self.peers.set_value(self.id, None);
+    }
+
+    fn store_value(&self, value: Self::StoreItem) {
+        self.peers.set_value(self.id, Some(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorObject, Incoming, Outgoing, Peers, RequestError, Response};
+    use crate::{
+        actors::protocol::ProtocolVersion,
+        socket::encoding::{self, Encoding},
+    };
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    fn next_message(receiver: &mut UnboundedReceiver<Outgoing>) -> Value {
+        match receiver.try_recv() {
+            Ok(Outgoing::Text(text)) => serde_json::from_str(&text).unwrap(),
+            Ok(Outgoing::Binary(bytes)) => encoding::from_msgpack(&bytes).unwrap(),
+            _ => panic!("Expected a message"),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_request_from_json() {
+        let incoming = r#"{"jsonrpc":"2.0","id":1,"method":"start_session"}"#;
+        let Ok(Incoming::Request(request)) = serde_json::from_str(incoming) else {
+            panic!("Expected a request");
+        };
+        assert_eq!(request.id, Some(json!(1)));
+        assert_eq!(request.method, "start_session");
+        assert_eq!(request.params, Value::Null);
+    }
+
+    #[test]
+    fn test_deserialize_response_from_json() {
+        let incoming = r#"{"jsonrpc":"2.0","id":1,"result":{"type":"accept"}}"#;
+        let Ok(Incoming::Response(response)) = serde_json::from_str(incoming) else {
+            panic!("Expected a response");
+        };
+        assert_eq!(response.result, json!({ "type": "accept" }));
+        assert_eq!(response.error, None);
+    }
+
+    #[test]
+    fn notifies_room_members_except_the_sender() {
+        let peers = Peers::default();
+        let (sender, mut sender_messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        let (receiver, mut receiver_messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        peers.join(sender, "room".into());
+        peers.join(receiver, "room".into());
+
+        peers.notify_room("room", Some(sender), "vibrate", &json!({ "value": 0.5 }));
+
+        assert_eq!(
+            next_message(&mut receiver_messages),
+            json!({ "jsonrpc": "2.0", "method": "vibrate", "params": { "value": 0.5 } })
+        );
+        assert!(sender_messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn messages_are_encoded_with_the_encoding_of_each_peer() {
+        let peers = Peers::default();
+        let (json, mut json_messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        let (msgpack, mut msgpack_messages) =
+            peers.connect(Encoding::MessagePack, ProtocolVersion::V1);
+        peers.join(json, "room".into());
+        peers.join(msgpack, "room".into());
+
+        peers.notify_room("room", None, "vibrate", &json!({ "value": 0.5 }));
+
+        assert!(matches!(json_messages.try_recv(), Ok(Outgoing::Text(_))));
+        let Ok(Outgoing::Binary(bytes)) = msgpack_messages.try_recv() else {
+            panic!("Expected a binary message");
+        };
+        assert_eq!(
+            encoding::from_msgpack::<Value>(&bytes).unwrap(),
+            json!({ "jsonrpc": "2.0", "method": "vibrate", "params": { "value": 0.5 } })
+        );
+    }
+
+    #[test]
+    fn disconnected_peers_leave_their_rooms() {
+        let peers = Peers::default();
+        let (peer, _messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        peers.join(peer, "room".into());
+
+        peers.disconnect(peer);
+
+        assert!(!peers.0.lock().unwrap().rooms.contains_key("room"));
+        assert!(!peers.is_connected(peer));
+    }
+
+    #[tokio::test]
+    async fn requests_are_resolved_by_the_first_response() {
+        let peers = Peers::default();
+        let (peer, mut messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        peers.join(peer, "room".into());
+
+        let params = json!({ "message": "hello" });
+        let request = peers.request_room("room", "join_request", &params, Duration::from_secs(1));
+        let respond = async {
+            tokio::task::yield_now().await;
+            let message = next_message(&mut messages);
+            assert_eq!(message["method"], "join_request");
+            peers.resolve(
+                peer,
+                Response {
+                    id: message["id"].clone(),
+                    result: json!({ "type": "accept" }),
+                    error: None,
+                },
+            );
+        };
+
+        let (response, _) = tokio::join!(request, respond);
+
+        assert_eq!(response.unwrap(), json!({ "type": "accept" }));
+    }
+
+    #[tokio::test]
+    async fn responses_from_other_peers_are_ignored() {
+        let peers = Peers::default();
+        let (hub, mut messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        let (intruder, _messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        peers.join(hub, "room".into());
+
+        let request = peers.request_room(
+            "room",
+            "join_request",
+            &Value::Null,
+            Duration::from_millis(100),
+        );
+        let respond = async {
+            tokio::task::yield_now().await;
+            let message = next_message(&mut messages);
+            peers.resolve(
+                intruder,
+                Response {
+                    id: message["id"].clone(),
+                    result: json!({ "type": "accept" }),
+                    error: None,
+                },
+            );
+        };
+
+        let (response, _) = tokio::join!(request, respond);
+
+        assert!(matches!(response, Err(RequestError::Timeout)));
+    }
+
+    #[tokio::test]
+    async fn requests_to_a_disconnected_peer_fail() {
+        let peers = Peers::default();
+        let (peer, _messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        peers.disconnect(peer);
+
+        let response = peers
+            .request(peer, "ping", &Value::Null, Duration::from_secs(1))
+            .await;
+
+        assert!(matches!(response, Err(RequestError::Disconnected(_))));
+    }
+
+    #[tokio::test]
+    async fn requests_to_an_empty_room_fail() {
+        let peers = Peers::default();
+
+        let response = peers
+            .request_room("room", "join_request", &Value::Null, Duration::from_secs(1))
+            .await;
+
+        assert!(matches!(response, Err(RequestError::EmptyRoom(_))));
+    }
+
+    #[tokio::test]
+    async fn requests_time_out_without_a_response() {
+        let peers = Peers::default();
+        let (peer, _messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+        peers.join(peer, "room".into());
+
+        let response = peers
+            .request_room(
+                "room",
+                "join_request",
+                &Value::Null,
+                Duration::from_millis(10),
+            )
+            .await;
+
+        assert!(matches!(response, Err(RequestError::Timeout)));
+    }
+
+    #[test]
+    fn test_serialize_error_response_to_json() {
+        let peers = Peers::default();
+        let (peer, mut messages) = peers.connect(Encoding::Json, ProtocolVersion::V1);
+
+        peers.respond(peer, json!(7), Err(ErrorObject::method_not_found()));
+
+        assert_eq!(
+            next_message(&mut messages),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "error": { "code": -32601, "message": "Method not found" }
+            })
+        );
+    }
+}
@@ -1,16 +1,39 @@
-use crate::socket::port::{ClientSocket, GlobalSocket, MessageWithAck};
-use serde::Serialize;
+use super::jsonrpc::{self, Peers};
+use crate::{
+    actors::protocol::{ProtocolVersion, DEFAULT_VERSION},
+    socket::{
+        encoding::{self, Encoding},
+        ip::client_ip,
+        port::{ClientSocket, GlobalSocket, MessageWithAck},
+    },
+};
+use axum::extract::ConnectInfo;
+use futures_util::future::select_ok;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use socketioxide::{
-    adapter::LocalAdapter, extract::SocketRef, AckError, BroadcastError, SendError, SocketIo,
+    adapter::{Adapter, LocalAdapter},
+    extract::{AckSender, Data, SocketRef},
+    handler::FromMessageParts,
+    socket::{Sid, Socket},
+    AckError, BroadcastError, SendError, SocketIo,
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
 };
-use std::{convert::Infallible, time::Duration};
 use uuid::Uuid;
 
-pub struct ClientSocketImpl(SocketRef<LocalAdapter>);
+/// A client connected through socket.io.
+///
+/// Messages sent to a room are also delivered to the peers connected through JSON-RPC.
+pub struct ClientSocketImpl(SocketRef<LocalAdapter>, Peers);
 
-impl From<SocketRef> for ClientSocketImpl {
-    fn from(value: SocketRef) -> Self {
-        Self(value)
+impl ClientSocketImpl {
+    pub fn new(socket: SocketRef, peers: Peers) -> Self {
+        Self(socket, peers)
     }
 }
 
@@ -18,6 +41,16 @@ impl From<SocketRef> for ClientSocketImpl {
 pub enum EmitWithAckError {
     #[error("Failed to receive acknowledgment: '{0}'")]
     AckError(#[from] AckError),
+    #[error("Failed to serialize message: '{0}'")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Failed to encode message: '{0}'")]
+    Encode(#[from] rmp_serde::encode::Error),
+    #[error("Failed to decode acknowledgment: '{0}'")]
+    Decode(#[from] rmp_serde::decode::Error),
+    #[error("Failed to receive JSON-RPC response: '{0}'")]
+    JsonRpc(#[from] jsonrpc::RequestError),
+    #[error("No client in room '{0}'")]
+    EmptyRoom(String),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -26,11 +59,130 @@ pub enum EmitError {
     BroadcastError(#[from] BroadcastError),
     #[error("Failed to send message: '{0}'")]
     SendError(#[from] SendError),
+    #[error("Failed to serialize message: '{0}'")]
+    Serialize(#[from] serde_json::Error),
+    #[error("Failed to encode message: '{0}'")]
+    Encode(#[from] rmp_serde::encode::Error),
+}
+
+/// Stores the payload encoding negotiated by the client.
+pub fn set_encoding(socket: &SocketRef, encoding: Encoding) {
+    socket.extensions.insert(encoding);
+}
+
+/// Stores the protocol version negotiated by the client.
+pub fn set_protocol_version(socket: &SocketRef, version: ProtocolVersion) {
+    socket.extensions.insert(version);
+}
+
+/// Identity proven by the client, see [`ClientSocket::client_id`].
+#[derive(Clone)]
+struct ClientId(String);
+
+/// Stores the identity the client proved when connecting.
+pub fn set_client_id(socket: &SocketRef, client_id: String) {
+    socket.extensions.insert(ClientId(client_id));
+}
+
+fn encoding_of<A: Adapter>(socket: &Socket<A>) -> Encoding {
+    socket
+        .extensions
+        .get::<Encoding>()
+        .map(|encoding| *encoding)
+        .unwrap_or_default()
+}
+
+/// Emits an event to the socket with the encoding it negotiated.
+pub fn emit_encoded<T>(socket: &SocketRef, event: &str, data: &T) -> Result<(), EmitError>
+where
+    T: Serialize + ?Sized,
+{
+    match encoding_of(socket) {
+        Encoding::Json => socket.emit(event.to_owned(), data)?,
+        Encoding::MessagePack => socket
+            .bin(vec![encoding::to_msgpack(data)?])
+            .emit(event.to_owned(), [(); 0])?,
+    }
+    Ok(())
+}
+
+/// Extracts the data of an event, decoded with the encoding negotiated by the client.
+///
+/// Clients that negotiated MessagePack send the data as a binary attachment. Events sent
+/// without data are read as `null`, so optional payloads can be extracted as `Option<T>`.
+pub struct Payload<T>(pub T);
+
+#[derive(thiserror::Error, Debug)]
+pub enum PayloadError {
+    #[error("Failed to deserialize payload: '{0}'")]
+    Deserialize(#[from] serde_json::Error),
+    #[error("Failed to decode payload: '{0}'")]
+    Decode(#[from] rmp_serde::decode::Error),
+}
+
+impl<T, A> FromMessageParts<A> for Payload<T>
+where
+    T: DeserializeOwned,
+    A: Adapter,
+{
+    type Error = PayloadError;
+
+    fn from_message_parts(
+        socket: &Arc<Socket<A>>,
+        value: &mut Value,
+        binary: &mut Vec<Vec<u8>>,
+        ack_id: &Option<i64>,
+    ) -> Result<Self, Self::Error> {
+        match (encoding_of(socket), binary.first()) {
+            (Encoding::MessagePack, Some(bytes)) => Ok(Self(encoding::from_msgpack(bytes)?)),
+            _ => {
+                if value.as_array().is_some_and(Vec::is_empty) {
+                    *value = Value::Null;
+                }
+                let Data(data) = Data::from_message_parts(socket, value, binary, ack_id)?;
+                Ok(Self(data))
+            }
+        }
+    }
+}
+
+/// Sends acknowledgments with the encoding negotiated by the client.
+pub struct Ack(AckSender, Encoding);
+
+impl<A: Adapter> FromMessageParts<A> for Ack
+where
+    AckSender: FromMessageParts<A, Error = Infallible>,
+{
+    type Error = Infallible;
+
+    fn from_message_parts(
+        socket: &Arc<Socket<A>>,
+        value: &mut Value,
+        binary: &mut Vec<Vec<u8>>,
+        ack_id: &Option<i64>,
+    ) -> Result<Self, Self::Error> {
+        let sender = AckSender::from_message_parts(socket, value, binary, ack_id)?;
+        Ok(Self(sender, encoding_of(socket)))
+    }
+}
+
+impl Ack {
+    pub fn send<T: Serialize>(self, data: T) -> Result<(), EmitError> {
+        match self.1 {
+            Encoding::Json => self.0.send(data)?,
+            Encoding::MessagePack => self
+                .0
+                .bin(vec![encoding::to_msgpack(&data)?])
+                .send([(); 0])?,
+        }
+        Ok(())
+    }
 }
 
 impl ClientSocket for ClientSocketImpl {
     type Error = Infallible;
     type EmitError = EmitError;
+    type EmitWithAckError = EmitWithAckError;
     type StoreItem = Uuid;
 
     fn join(&self, room: String) -> Result<(), Self::Error> {
@@ -47,7 +199,11 @@ impl ClientSocket for ClientSocketImpl {
     where
         T: Serialize + Send,
     {
-        self.0.to(room).emit(event, data)?;
+        let data = serde_json::to_value(data)?;
+        self.1.notify_room(&room, None, &event, &data);
+        for socket in self.0.to(room).sockets().unwrap_or_default() {
+            emit_encoded(&socket, &event, &data)?;
+        }
         Ok(())
     }
 
@@ -55,14 +211,51 @@ impl ClientSocket for ClientSocketImpl {
     where
         T: Serialize + Send,
     {
-        self.0.emit(event, data)?;
-        Ok(())
+        emit_encoded(&self.0, &event, &data)
+    }
+
+    async fn emit_with_ack<T>(
+        &self,
+        event: String,
+        value: T,
+        timeout: Duration,
+    ) -> Result<T::Ack, Self::EmitWithAckError>
+    where
+        T: MessageWithAck,
+    {
+        let value = serde_json::to_value(value)?;
+        emit_with_ack(&self.0, event, &value, timeout).await
     }
 
     fn disconnect(self) {
         let _ = self.0.disconnect();
     }
 
+    fn connection_id(&self) -> String {
+        self.0.id.to_string()
+    }
+
+    fn client_id(&self) -> Option<String> {
+        self.0.extensions.get::<ClientId>().map(|id| id.0.clone())
+    }
+
+    fn protocol_version(&self) -> ProtocolVersion {
+        self.0
+            .extensions
+            .get::<ProtocolVersion>()
+            .map(|version| *version)
+            .unwrap_or(DEFAULT_VERSION)
+    }
+
+    fn ip(&self) -> Option<IpAddr> {
+        let parts = self.0.req_parts();
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0);
+        client_ip(&parts.headers, peer)
+    }
+
     fn get_stored_value(&self) -> Option<Self::StoreItem> {
         self.0
             .extensions
@@ -79,11 +272,50 @@ impl ClientSocket for ClientSocketImpl {
     }
 }
 
-pub struct GlobalSocketImpl(SocketIo<LocalAdapter>);
+/// Reaches the clients of a namespace, on both transports.
+pub struct GlobalSocketImpl(SocketIo<LocalAdapter>, String, Peers);
+
+impl GlobalSocketImpl {
+    pub fn new(io: SocketIo<LocalAdapter>, namespace: String, peers: Peers) -> Self {
+        Self(io, namespace, peers)
+    }
+}
+
+/// Returns a socket.io client of a namespace.
+pub fn socket_of(io: &SocketIo<LocalAdapter>, namespace: &str, sid: Sid) -> Option<SocketRef> {
+    io.of(namespace)?.get_socket(sid)
+}
+
+/// Returns the socket.io clients in a room of a namespace.
+pub fn sockets_in(io: &SocketIo<LocalAdapter>, namespace: &str, room: String) -> Vec<SocketRef> {
+    io.of(namespace)
+        .and_then(|sockets| sockets.within(room).sockets().ok())
+        .unwrap_or_default()
+}
+
+/// Emits an event to a single socket and waits for its acknowledgment.
+async fn emit_with_ack<T>(
+    socket: &SocketRef,
+    event: String,
+    data: &Value,
+    timeout: Duration,
+) -> Result<T, EmitWithAckError>
+where
+    T: DeserializeOwned,
+{
+    let response = match encoding_of(socket) {
+        Encoding::Json => socket.timeout(timeout).emit_with_ack::<Value>(event, data),
+        Encoding::MessagePack => socket
+            .bin(vec![encoding::to_msgpack(data)?])
+            .timeout(timeout)
+            .emit_with_ack::<Value>(event, [(); 0]),
+    }?
+    .await?;
 
-impl From<SocketIo<LocalAdapter>> for GlobalSocketImpl {
-    fn from(value: SocketIo<LocalAdapter>) -> Self {
-        Self(value)
+    match response.binary.first() {
+        Some(bytes) => Ok(encoding::from_msgpack(bytes)?),
+        // Clients send acknowledgments as a list of arguments, we only expect one
+        None => Ok(serde_json::from_value::<(T,)>(response.data)?.0),
     }
 }
 
@@ -100,14 +332,22 @@ impl GlobalSocket for GlobalSocketImpl {
     where
         T: MessageWithAck,
     {
-        let response = self
-            .0
-            .to(room)
-            .timeout(timeout)
-            .emit_with_ack::<T::Ack>(event, value)
-            .unwrap()
-            .await?;
+        let value = serde_json::to_value(value)?;
+
+        match self.2.request_room(&room, &event, &value, timeout).await {
+            Err(jsonrpc::RequestError::EmptyRoom(_)) => (),
+            response => return Ok(serde_json::from_value(response?)?),
+        }
+
+        let sockets = sockets_in(&self.0, &self.1, room.clone());
+        if sockets.is_empty() {
+            return Err(EmitWithAckError::EmptyRoom(room));
+        }
 
-        Ok(response.data)
+        let requests = sockets
+            .iter()
+            .map(|socket| Box::pin(emit_with_ack(socket, event.clone(), &value, timeout)));
+        let (response, _) = select_ok(requests).await?;
+        Ok(response)
     }
 }
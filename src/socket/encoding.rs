@@ -0,0 +1,81 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Encoding of the payloads exchanged with a client, negotiated when it connects.
+///
+/// JSON payloads travel as regular text messages, MessagePack ones as binary attachments on
+/// socket.io and as binary frames on the JSON-RPC transport.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(test, derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+}
+
+/// Encodes a value as MessagePack, structs are written as maps keyed by field name and values
+/// like UUIDs as strings, so payloads have the same shape as their JSON counterparts.
+pub fn to_msgpack<T>(value: &T) -> Result<Vec<u8>, rmp_serde::encode::Error>
+where
+    T: Serialize + ?Sized,
+{
+    let mut buffer = Vec::new();
+    let mut serializer = rmp_serde::Serializer::new(&mut buffer)
+        .with_struct_map()
+        .with_human_readable();
+    value.serialize(&mut serializer)?;
+    Ok(buffer)
+}
+
+pub fn from_msgpack<T>(bytes: &[u8]) -> Result<T, rmp_serde::decode::Error>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = rmp_serde::Deserializer::new(bytes).with_human_readable();
+    T::deserialize(&mut deserializer)
+}
+
+#[cfg(test)]
+pub use test_support::*;
+
+#[cfg(test)]
+mod test_support {
+    use super::{from_msgpack, to_msgpack};
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::fmt::Debug;
+
+    pub fn assert_msgpack_round_trip<T>(value: T)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let bytes = to_msgpack(&value).unwrap();
+        assert_eq!(from_msgpack::<T>(&bytes).unwrap(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_msgpack, to_msgpack};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    #[test]
+    fn msgpack_payloads_have_the_same_shape_as_json() {
+        let value = json!({ "type": "ok", "session_id": Uuid::nil(), "value": 0.5 });
+
+        let decoded: Value = from_msgpack(&to_msgpack(&value).unwrap()).unwrap();
+
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn uuids_are_encoded_as_strings() {
+        let bytes = to_msgpack(&Uuid::nil()).unwrap();
+
+        assert_eq!(
+            from_msgpack::<String>(&bytes).unwrap(),
+            Uuid::nil().to_string()
+        );
+    }
+}
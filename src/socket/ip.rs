@@ -0,0 +1,44 @@
+use axum::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Address of the client: the peer address of the connection when the server knows it, or
+/// else the last hop of `X-Forwarded-For`, added by the proxy in front of the server.
+pub fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>) -> Option<IpAddr> {
+    if let Some(peer) = peer {
+        return Some(peer.ip());
+    }
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .next_back()?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client_ip;
+    use axum::http::HeaderMap;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+    #[test]
+    fn uses_the_hop_added_by_the_proxy() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+
+        assert_eq!(
+            client_ip(&headers, None),
+            Some(IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)))
+        );
+        assert_eq!(
+            client_ip(&headers, Some(SocketAddr::from(([3, 3, 3, 3], 80)))),
+            Some(IpAddr::V4(Ipv4Addr::new(3, 3, 3, 3)))
+        );
+        assert_eq!(client_ip(&HeaderMap::new(), None), None);
+    }
+}
@@ -1,9 +1,10 @@
+use crate::actors::protocol::ProtocolVersion;
 #[cfg(test)]
 pub use mocks::*;
 use serde::{de::DeserializeOwned, Serialize};
 #[cfg(test)]
 use std::convert::Infallible;
-use std::{future::Future, time::Duration};
+use std::{future::Future, net::IpAddr, time::Duration};
 #[cfg(test)]
 use uuid::Uuid;
 
@@ -11,10 +12,11 @@ pub trait MessageWithAck: Serialize + Send + 'static {
     type Ack: DeserializeOwned + Send;
 }
 
-#[cfg_attr(test, mockall::automock(type Error = Infallible; type EmitWithAckError = Infallible; type EmitError = Infallible; type StoreItem = Uuid;))]
+#[cfg_attr(test, mockall::automock(type Error = Infallible; type EmitWithAckError = DummyMockError; type EmitError = Infallible; type StoreItem = Uuid;))]
 pub trait ClientSocket: Send {
     type Error: std::error::Error + Send;
     type EmitError: std::error::Error + Send;
+    type EmitWithAckError: std::error::Error + Send;
     type StoreItem: Send + Sync + Copy + 'static;
 
     fn disconnect(self);
@@ -31,6 +33,26 @@ pub trait ClientSocket: Send {
     where
         T: Serialize + Send + 'static;
 
+    fn emit_with_ack<T>(
+        &self,
+        event: String,
+        value: T,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T::Ack, Self::EmitWithAckError>> + Send
+    where
+        T: MessageWithAck;
+
+    /// Identifies this connection among all the clients connected to the server.
+    fn connection_id(&self) -> String;
+
+    /// Identity the client proved with a token signed by its backend when connecting.
+    fn client_id(&self) -> Option<String>;
+
+    /// Version of the protocol negotiated when the client connected.
+    fn protocol_version(&self) -> ProtocolVersion;
+
+    fn ip(&self) -> Option<IpAddr>;
+
     fn get_stored_value(&self) -> Option<Self::StoreItem>;
 
     fn remove_value(&self);